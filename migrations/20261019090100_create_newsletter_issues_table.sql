@@ -0,0 +1,17 @@
+-- Add migration script here
+CREATE TABLE newsletter_issues(
+    newsletter_issue_id uuid NOT NULL,
+    title TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    -- One of `scheduled`, `sending`, `sent` or `cancelled`
+    status TEXT NOT NULL,
+    send_at timestamptz NOT NULL,
+    published_at timestamptz NULL,
+    created_at timestamptz NOT NULL,
+    PRIMARY KEY(newsletter_issue_id)
+);
+
+CREATE INDEX newsletter_issues_due_idx
+    ON newsletter_issues (send_at)
+    WHERE status = 'scheduled';
@@ -0,0 +1,48 @@
+-- Add migration script here
+-- Drafts have no send time until they are scheduled, and cancelling a
+-- scheduled issue now turns it back into an editable draft.
+BEGIN;
+    ALTER TABLE newsletter_issues ALTER COLUMN send_at DROP NOT NULL;
+    UPDATE newsletter_issues
+        SET status = 'draft', send_at = NULL
+        WHERE status = 'cancelled';
+
+    ALTER TABLE newsletter_issues ADD COLUMN updated_at timestamptz NULL;
+    UPDATE newsletter_issues SET updated_at = created_at;
+    ALTER TABLE newsletter_issues ALTER COLUMN updated_at SET NOT NULL;
+
+    ALTER TABLE newsletter_issues ADD COLUMN current_revision INT NOT NULL DEFAULT 1;
+    ALTER TABLE newsletter_issues ALTER COLUMN current_revision DROP DEFAULT;
+
+    CREATE TABLE newsletter_issue_revisions(
+        newsletter_issue_id uuid NOT NULL
+            REFERENCES newsletter_issues (newsletter_issue_id) ON DELETE CASCADE,
+        revision INT NOT NULL,
+        title TEXT NOT NULL,
+        text_content TEXT NOT NULL,
+        html_content TEXT NOT NULL,
+        created_by uuid NULL REFERENCES users (user_id),
+        created_at timestamptz NOT NULL,
+        -- Set when the revision was created by restoring an earlier one
+        restored_from INT NULL,
+        PRIMARY KEY (newsletter_issue_id, revision)
+    );
+
+    INSERT INTO newsletter_issue_revisions (
+        newsletter_issue_id, revision, title, text_content, html_content, created_at
+    )
+    SELECT newsletter_issue_id, 1, title, text_content, html_content, created_at
+    FROM newsletter_issues;
+
+    -- Revisions are append-only: they disappear along with their draft, but
+    -- are never edited in place.
+    CREATE FUNCTION forbid_revision_updates() RETURNS trigger AS $$
+    BEGIN
+        RAISE EXCEPTION 'newsletter_issue_revisions is append-only';
+    END;
+    $$ LANGUAGE plpgsql;
+
+    CREATE TRIGGER newsletter_issue_revisions_append_only
+        BEFORE UPDATE ON newsletter_issue_revisions
+        FOR EACH ROW EXECUTE FUNCTION forbid_revision_updates();
+COMMIT;
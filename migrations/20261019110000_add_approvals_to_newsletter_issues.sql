@@ -0,0 +1,26 @@
+-- Add migration script here
+BEGIN;
+    -- One of `pending`, `approved` or `rejected`, always about `current_revision`
+    ALTER TABLE newsletter_issues ADD COLUMN approval_status TEXT NOT NULL DEFAULT 'pending';
+    ALTER TABLE newsletter_issues ALTER COLUMN approval_status DROP DEFAULT;
+    ALTER TABLE newsletter_issues ADD COLUMN approved_revision INT NULL;
+    ALTER TABLE newsletter_issues ADD COLUMN approved_by uuid NULL REFERENCES users (user_id);
+
+    -- Issues that already went out predate the approval workflow.
+    UPDATE newsletter_issues
+        SET approval_status = 'approved', approved_revision = current_revision
+        WHERE status IN ('sending', 'sent');
+
+    -- No foreign key on the issue: the trail must outlive deleted drafts.
+    CREATE TABLE issue_audit_log(
+        id BIGSERIAL PRIMARY KEY,
+        newsletter_issue_id uuid NOT NULL,
+        revision INT NULL,
+        -- NULL when the action was taken by the application itself
+        actor uuid NULL REFERENCES users (user_id),
+        action TEXT NOT NULL,
+        note TEXT NULL,
+        created_at timestamptz NOT NULL
+    );
+    CREATE INDEX issue_audit_log_issue_idx ON issue_audit_log (newsletter_issue_id, created_at);
+COMMIT;
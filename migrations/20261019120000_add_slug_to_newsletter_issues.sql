@@ -0,0 +1,13 @@
+-- Add migration script here
+BEGIN;
+    ALTER TABLE newsletter_issues ADD COLUMN slug TEXT NULL;
+    UPDATE newsletter_issues
+        SET slug = trim(both '-' from lower(regexp_replace(title, '[^a-zA-Z0-9]+', '-', 'g')))
+            || '-' || left(newsletter_issue_id::text, 8);
+    ALTER TABLE newsletter_issues ALTER COLUMN slug SET NOT NULL;
+    ALTER TABLE newsletter_issues ADD CONSTRAINT newsletter_issues_slug_key UNIQUE (slug);
+
+    CREATE INDEX newsletter_issues_published_idx
+        ON newsletter_issues (published_at DESC)
+        WHERE published_at IS NOT NULL;
+COMMIT;
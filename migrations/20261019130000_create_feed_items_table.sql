@@ -0,0 +1,11 @@
+-- Add migration script here
+-- The feed items the poller already turned into issues (or deliberately
+-- skipped), so that each post becomes at most one issue.
+CREATE TABLE feed_items(
+    feed_source TEXT NOT NULL,
+    guid TEXT NOT NULL,
+    newsletter_issue_id uuid NULL
+        REFERENCES newsletter_issues (newsletter_issue_id) ON DELETE SET NULL,
+    processed_at timestamptz NOT NULL,
+    PRIMARY KEY (feed_source, guid)
+);
@@ -0,0 +1,19 @@
+-- Add migration script here
+-- Deliveries that failed for a transient reason are retried later.
+ALTER TABLE issue_delivery_queue
+    ADD COLUMN n_retries SMALLINT NOT NULL DEFAULT 0,
+    ADD COLUMN execute_after timestamptz NOT NULL DEFAULT now();
+
+-- Deliveries that failed for good, kept until an admin retries or
+-- discards them.
+CREATE TABLE failed_deliveries(
+    failed_delivery_id uuid PRIMARY KEY,
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id) ON DELETE CASCADE,
+    subscriber_email TEXT NOT NULL,
+    error_kind TEXT NOT NULL,
+    last_error TEXT NOT NULL,
+    n_attempts SMALLINT NOT NULL,
+    failed_at timestamptz NOT NULL,
+    UNIQUE (newsletter_issue_id, subscriber_email)
+);
@@ -0,0 +1,22 @@
+-- Add migration script here
+-- One row per recipient of each issue, kept after the delivery left the
+-- queue so that we can tell who an issue actually reached.
+CREATE TABLE issue_deliveries(
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id) ON DELETE CASCADE,
+    subscriber_email TEXT NOT NULL,
+    subscriber_id uuid NULL
+        REFERENCES subscriptions (id) ON DELETE SET NULL,
+    -- 'queued', 'sent', 'failed' or 'bounced'
+    status TEXT NOT NULL,
+    provider_message_id TEXT NULL,
+    queued_at timestamptz NOT NULL,
+    sent_at timestamptz NULL,
+    failed_at timestamptz NULL,
+    bounced_at timestamptz NULL,
+    PRIMARY KEY (newsletter_issue_id, subscriber_email)
+);
+
+CREATE INDEX issue_deliveries_subscriber_id_idx ON issue_deliveries (subscriber_id);
+CREATE UNIQUE INDEX issue_deliveries_provider_message_id_idx
+    ON issue_deliveries (provider_message_id);
@@ -0,0 +1,12 @@
+-- Add migration script here
+-- Addresses we must not send issues to anymore: they hard bounced or their
+-- owner reported us as spam.
+CREATE TABLE suppressed_emails(
+    email TEXT PRIMARY KEY,
+    -- 'hard_bounce' or 'spam_complaint'
+    reason TEXT NOT NULL,
+    -- What the provider told us, e.g. the bounce description
+    detail TEXT NULL,
+    provider_message_id TEXT NULL,
+    suppressed_at timestamptz NOT NULL
+);
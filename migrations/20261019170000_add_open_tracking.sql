@@ -0,0 +1,13 @@
+-- Add migration script here
+ALTER TABLE newsletter_issues ADD COLUMN track_opens BOOLEAN NOT NULL DEFAULT false;
+
+-- Subscribers can refuse to be tracked, whatever the issue says.
+ALTER TABLE subscriptions ADD COLUMN tracking_opt_out BOOLEAN NOT NULL DEFAULT false;
+
+-- The token is embedded in the tracking pixel of the email sent to the
+-- recipient. It is NULL when the delivery is not tracked.
+ALTER TABLE issue_deliveries
+    ADD COLUMN open_token TEXT NULL UNIQUE,
+    ADD COLUMN first_opened_at timestamptz NULL,
+    ADD COLUMN last_opened_at timestamptz NULL,
+    ADD COLUMN open_count INT NOT NULL DEFAULT 0;
@@ -0,0 +1,19 @@
+-- Add migration script here
+ALTER TABLE newsletter_issues ADD COLUMN track_clicks BOOLEAN NOT NULL DEFAULT false;
+
+-- Identifies the delivery in its click-tracking links. NULL when the
+-- delivery is not tracked.
+ALTER TABLE issue_deliveries ADD COLUMN click_token TEXT NULL UNIQUE;
+
+CREATE TABLE link_clicks(
+    link_click_id BIGSERIAL PRIMARY KEY,
+    newsletter_issue_id uuid NOT NULL,
+    subscriber_email TEXT NOT NULL,
+    url TEXT NOT NULL,
+    clicked_at timestamptz NOT NULL,
+    FOREIGN KEY (newsletter_issue_id, subscriber_email)
+        REFERENCES issue_deliveries (newsletter_issue_id, subscriber_email)
+        ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+CREATE INDEX link_clicks_newsletter_issue_id_idx ON link_clicks (newsletter_issue_id);
@@ -0,0 +1,7 @@
+-- Add migration script here
+ALTER TABLE newsletter_issues ADD COLUMN utm_tagging BOOLEAN NOT NULL DEFAULT false;
+
+-- NULL falls back to the defaults: `newsletter`, `email` and the issue slug.
+ALTER TABLE newsletter_issues ADD COLUMN utm_source TEXT NULL;
+ALTER TABLE newsletter_issues ADD COLUMN utm_medium TEXT NULL;
+ALTER TABLE newsletter_issues ADD COLUMN utm_campaign TEXT NULL;
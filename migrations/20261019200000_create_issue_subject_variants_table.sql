@@ -0,0 +1,23 @@
+-- Add migration script here
+-- A/B tests of the subject line: a sample of the recipients gets one of the
+-- variants, the rest get the winner once the test window closes.
+ALTER TABLE newsletter_issues ADD COLUMN ab_test_percentage SMALLINT NULL
+    CHECK (ab_test_percentage BETWEEN 1 AND 99);
+ALTER TABLE newsletter_issues ADD COLUMN ab_test_wait_minutes INTEGER NULL;
+-- `open_rate` or `click_rate`.
+ALTER TABLE newsletter_issues ADD COLUMN ab_test_metric TEXT NULL;
+-- Set when the sample gets queued.
+ALTER TABLE newsletter_issues ADD COLUMN ab_test_decide_at timestamptz NULL;
+ALTER TABLE newsletter_issues ADD COLUMN winning_variant SMALLINT NULL;
+
+CREATE TABLE issue_subject_variants(
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id) ON DELETE CASCADE,
+    variant SMALLINT NOT NULL,
+    subject TEXT NOT NULL,
+    PRIMARY KEY (newsletter_issue_id, variant)
+);
+
+-- The subject variant sent to the recipient, NULL outside of A/B tests.
+ALTER TABLE issue_delivery_queue ADD COLUMN variant SMALLINT NULL;
+ALTER TABLE issue_deliveries ADD COLUMN variant SMALLINT NULL;
@@ -0,0 +1,43 @@
+-- Add migration script here
+BEGIN;
+    CREATE TABLE lists(
+        list_id uuid PRIMARY KEY,
+        slug TEXT NOT NULL UNIQUE,
+        name TEXT NOT NULL,
+        -- NULL falls back to the sender configured for the application.
+        sender_email TEXT NULL,
+        sender_name TEXT NULL,
+        -- Whether joining the list takes a click on a confirmation link.
+        double_opt_in BOOLEAN NOT NULL DEFAULT true,
+        confirmation_subject TEXT NOT NULL DEFAULT 'Welcome',
+        created_at timestamptz NOT NULL
+    );
+
+    -- Every subscriber and issue predating lists belongs to this one.
+    INSERT INTO lists (list_id, slug, name, created_at)
+    VALUES (gen_random_uuid(), 'newsletter', 'Newsletter', now());
+
+    CREATE TABLE list_subscriptions(
+        list_id uuid NOT NULL REFERENCES lists (list_id) ON DELETE CASCADE,
+        subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+        -- `pending_confirmation`, `confirmed` or `unsubscribed`.
+        status TEXT NOT NULL,
+        subscribed_at timestamptz NOT NULL,
+        confirmed_at timestamptz NULL,
+        PRIMARY KEY (list_id, subscriber_id)
+    );
+    CREATE INDEX list_subscriptions_subscriber_id_idx ON list_subscriptions (subscriber_id);
+
+    INSERT INTO list_subscriptions (list_id, subscriber_id, status, subscribed_at, confirmed_at)
+    SELECT
+        (SELECT list_id FROM lists WHERE slug = 'newsletter'),
+        id,
+        status,
+        subscribed_at,
+        CASE WHEN status = 'confirmed' THEN subscribed_at END
+    FROM subscriptions;
+
+    ALTER TABLE newsletter_issues ADD COLUMN list_id uuid NULL REFERENCES lists (list_id);
+    UPDATE newsletter_issues SET list_id = (SELECT list_id FROM lists WHERE slug = 'newsletter');
+    ALTER TABLE newsletter_issues ALTER COLUMN list_id SET NOT NULL;
+COMMIT;
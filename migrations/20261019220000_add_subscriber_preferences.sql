@@ -0,0 +1,28 @@
+-- Add migration script here
+-- `every_issue`, `weekly` or `monthly`.
+ALTER TABLE subscriptions ADD COLUMN frequency TEXT NOT NULL DEFAULT 'every_issue';
+
+-- The subscribers an issue goes out to: the confirmed, non-suppressed
+-- members of its list, less the ones who got another issue more recently
+-- than their frequency allows.
+CREATE FUNCTION issue_recipients(uuid) RETURNS TABLE (subscriber_email TEXT) AS $$
+    SELECT s.email
+    FROM subscriptions s
+    JOIN list_subscriptions l ON l.subscriber_id = s.id
+    WHERE
+        l.list_id = (SELECT i.list_id FROM newsletter_issues i WHERE i.newsletter_issue_id = $1) AND
+        l.status = 'confirmed' AND
+        NOT EXISTS (SELECT 1 FROM suppressed_emails e WHERE e.email = s.email) AND
+        NOT EXISTS (
+            SELECT 1 FROM issue_deliveries d
+            WHERE
+                d.subscriber_email = s.email AND
+                d.newsletter_issue_id <> $1 AND
+                d.status IN ('queued', 'sent', 'bounced') AND
+                d.queued_at > now() - CASE s.frequency
+                    WHEN 'weekly' THEN interval '7 days'
+                    WHEN 'monthly' THEN interval '30 days'
+                    ELSE interval '0'
+                END
+        )
+$$ LANGUAGE sql STABLE;
@@ -0,0 +1,50 @@
+-- Add migration script here
+CREATE TABLE segments(
+    segment_id uuid PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    -- An expression of the filter language, see `src/segments.rs`.
+    filter TEXT NOT NULL,
+    created_at timestamptz NOT NULL
+);
+
+-- NULL sends the issue to its whole list.
+ALTER TABLE newsletter_issues ADD COLUMN segment_id uuid NULL REFERENCES segments (segment_id);
+
+-- The members of the segment of an issue, as they were when it went out.
+CREATE TABLE issue_segment_members(
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id) ON DELETE CASCADE,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    PRIMARY KEY (newsletter_issue_id, subscriber_id)
+);
+
+-- As before, restricted to the members of the issue's segment if it has one.
+CREATE OR REPLACE FUNCTION issue_recipients(uuid) RETURNS TABLE (subscriber_email TEXT) AS $$
+    SELECT s.email
+    FROM subscriptions s
+    JOIN list_subscriptions l ON l.subscriber_id = s.id
+    JOIN newsletter_issues i ON i.newsletter_issue_id = $1
+    WHERE
+        l.list_id = i.list_id AND
+        l.status = 'confirmed' AND
+        (
+            i.segment_id IS NULL OR
+            EXISTS (
+                SELECT 1 FROM issue_segment_members m
+                WHERE m.newsletter_issue_id = $1 AND m.subscriber_id = s.id
+            )
+        ) AND
+        NOT EXISTS (SELECT 1 FROM suppressed_emails e WHERE e.email = s.email) AND
+        NOT EXISTS (
+            SELECT 1 FROM issue_deliveries d
+            WHERE
+                d.subscriber_email = s.email AND
+                d.newsletter_issue_id <> $1 AND
+                d.status IN ('queued', 'sent', 'bounced') AND
+                d.queued_at > now() - CASE s.frequency
+                    WHEN 'weekly' THEN interval '7 days'
+                    WHEN 'monthly' THEN interval '30 days'
+                    ELSE interval '0'
+                END
+        )
+$$ LANGUAGE sql STABLE;
@@ -0,0 +1,9 @@
+-- Add migration script here
+-- The custom attributes a list collects, as a map from their name to their
+-- type: `text`, `number` or `boolean`.
+ALTER TABLE lists ADD COLUMN attribute_schema JSONB NOT NULL DEFAULT '{}';
+
+-- Free-form labels and custom data, for segments to filter on.
+ALTER TABLE subscriptions
+    ADD COLUMN tags TEXT[] NOT NULL DEFAULT '{}',
+    ADD COLUMN attributes JSONB NOT NULL DEFAULT '{}';
@@ -0,0 +1,8 @@
+-- Add migration script here
+-- Address changes waiting for the new address to be confirmed.
+CREATE TABLE email_change_tokens(
+    email_change_token TEXT NOT NULL PRIMARY KEY,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    new_email TEXT NOT NULL,
+    requested_at timestamptz NOT NULL
+);
@@ -0,0 +1,11 @@
+-- Add migration script here
+-- Back the admin listing of subscribers: one index per sortable column, for
+-- keyset pagination, and trigram indexes for substring search.
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+CREATE INDEX subscriptions_subscribed_at_idx ON subscriptions (subscribed_at, id);
+CREATE INDEX subscriptions_email_idx ON subscriptions (email, id);
+CREATE INDEX subscriptions_name_idx ON subscriptions (name, id);
+CREATE INDEX subscriptions_status_idx ON subscriptions (status);
+CREATE INDEX subscriptions_tags_idx ON subscriptions USING GIN (tags);
+CREATE INDEX subscriptions_email_trgm_idx ON subscriptions USING GIN (email gin_trgm_ops);
+CREATE INDEX subscriptions_name_trgm_idx ON subscriptions USING GIN (name gin_trgm_ops);
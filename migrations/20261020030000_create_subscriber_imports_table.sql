@@ -0,0 +1,24 @@
+-- Add migration script here
+-- Bulk imports of subscribers, run in the background, and the rows they
+-- could not import.
+CREATE TABLE subscriber_imports(
+    import_id uuid NOT NULL PRIMARY KEY,
+    list_id uuid NOT NULL REFERENCES lists (list_id),
+    confirmed BOOLEAN NOT NULL,
+    -- `running`, `completed` or `failed`.
+    status TEXT NOT NULL,
+    processed_rows INTEGER NOT NULL DEFAULT 0,
+    imported_rows INTEGER NOT NULL DEFAULT 0,
+    duplicate_rows INTEGER NOT NULL DEFAULT 0,
+    failed_rows INTEGER NOT NULL DEFAULT 0,
+    -- Why the import stopped, when it failed.
+    error TEXT NULL,
+    created_at timestamptz NOT NULL,
+    finished_at timestamptz NULL
+);
+CREATE TABLE subscriber_import_errors(
+    import_id uuid NOT NULL REFERENCES subscriber_imports (import_id) ON DELETE CASCADE,
+    line BIGINT NOT NULL,
+    error TEXT NOT NULL
+);
+CREATE INDEX subscriber_import_errors_import_id_idx ON subscriber_import_errors (import_id, line);
@@ -0,0 +1,37 @@
+-- Add migration script here
+-- No foreign key on the subscriber: the trail must outlive deleted
+-- subscribers.
+CREATE TABLE subscriber_audit_log(
+    id BIGSERIAL PRIMARY KEY,
+    subscriber_id uuid NOT NULL,
+    -- NULL when the action was taken by the application itself
+    actor uuid NULL REFERENCES users (user_id),
+    action TEXT NOT NULL,
+    note TEXT NULL,
+    created_at timestamptz NOT NULL
+);
+CREATE INDEX subscriber_audit_log_subscriber_idx ON subscriber_audit_log (subscriber_id, created_at);
+
+-- Actions applied to many subscribers at once, in the background.
+CREATE TABLE bulk_actions(
+    bulk_action_id uuid NOT NULL PRIMARY KEY,
+    actor uuid NOT NULL REFERENCES users (user_id),
+    -- `unsubscribe`, `delete`, `tag`, `untag`, `resend_confirmation` or
+    -- `move_to_list`.
+    action TEXT NOT NULL,
+    -- The tag, or the slug of the list, the action is about.
+    argument TEXT NULL,
+    -- `running`, `completed` or `failed`.
+    status TEXT NOT NULL,
+    total_subscribers INTEGER NOT NULL,
+    processed_subscribers INTEGER NOT NULL DEFAULT 0,
+    -- Why the action stopped, when it failed.
+    error TEXT NULL,
+    created_at timestamptz NOT NULL,
+    finished_at timestamptz NULL
+);
+CREATE TABLE bulk_action_subscribers(
+    bulk_action_id uuid NOT NULL REFERENCES bulk_actions (bulk_action_id) ON DELETE CASCADE,
+    subscriber_id uuid NOT NULL,
+    PRIMARY KEY (bulk_action_id, subscriber_id)
+);
@@ -0,0 +1,13 @@
+-- Add migration script here
+-- What is left of subscribers who asked for their data to be erased: a hash
+-- of their address, so that importing it again can be refused without
+-- keeping the address itself.
+CREATE TABLE erased_subscribers(
+    email_hash TEXT PRIMARY KEY,
+    erased_at timestamptz NOT NULL
+);
+
+-- Addresses are compared regardless of case.
+CREATE FUNCTION email_hash(TEXT) RETURNS TEXT AS $$
+    SELECT encode(sha256(convert_to(lower($1), 'UTF8')), 'hex')
+$$ LANGUAGE SQL IMMUTABLE STRICT;
@@ -0,0 +1,22 @@
+-- Add migration script here
+-- How and when subscribers agreed to hear from us. Records outlive the
+-- subscriber once their data is erased, see
+-- 20261020090000_keep_consent_records_of_erased_subscribers.sql.
+CREATE TABLE consent_records(
+    consent_record_id BIGSERIAL PRIMARY KEY,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    -- `subscribe` when the form was submitted, `confirm` when the
+    -- confirmation link was followed.
+    action TEXT NOT NULL,
+    -- The slugs of the lists consented to.
+    lists TEXT[] NOT NULL,
+    -- The address of the client, behind any trusted proxy.
+    ip_address TEXT NULL,
+    user_agent TEXT NULL,
+    -- Where the subscription form was, as the form tells.
+    source TEXT NULL,
+    -- The privacy policy in effect at the time.
+    policy_version TEXT NOT NULL,
+    created_at timestamptz NOT NULL
+);
+CREATE INDEX consent_records_subscriber_idx ON consent_records (subscriber_id, created_at);
@@ -0,0 +1,4 @@
+-- Add migration script here
+-- The admin who last changed how or to whom an issue is sent, other than by
+-- writing a revision: they cannot sign off on their own change.
+ALTER TABLE newsletter_issues ADD COLUMN settings_changed_by uuid NULL REFERENCES users (user_id);
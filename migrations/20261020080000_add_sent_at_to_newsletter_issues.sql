@@ -0,0 +1,13 @@
+-- Add migration script here
+BEGIN;
+    -- When delivery of the issue first finished. Retrying failed deliveries
+    -- puts the issue back into `sending` but leaves it set.
+    ALTER TABLE newsletter_issues ADD COLUMN sent_at timestamptz NULL;
+    UPDATE newsletter_issues SET sent_at = published_at WHERE status = 'sent';
+
+    -- The archive shows the issues sent to a whole list.
+    DROP INDEX newsletter_issues_published_idx;
+    CREATE INDEX newsletter_issues_archived_idx
+        ON newsletter_issues (published_at DESC)
+        WHERE sent_at IS NOT NULL AND segment_id IS NULL;
+COMMIT;
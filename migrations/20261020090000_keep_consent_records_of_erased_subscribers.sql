@@ -0,0 +1,5 @@
+-- Add migration script here
+-- No foreign key on the subscriber any more: like their audit trail, the
+-- consent records of erased subscribers stay as proof of what was agreed to,
+-- stripped of who agreed to it.
+ALTER TABLE consent_records DROP CONSTRAINT consent_records_subscriber_id_fkey;
@@ -0,0 +1,4 @@
+-- Add migration script here
+-- When the process running an import last told it was alive. Imports whose
+-- heartbeat stopped are failed, leaving alone the ones other instances run.
+ALTER TABLE subscriber_imports ADD COLUMN heartbeat_at timestamptz NOT NULL DEFAULT now();
@@ -0,0 +1,4 @@
+-- Add migration script here
+-- When the process running a bulk action last told it was alive, as for
+-- imports.
+ALTER TABLE bulk_actions ADD COLUMN heartbeat_at timestamptz NOT NULL DEFAULT now();
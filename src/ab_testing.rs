@@ -0,0 +1,296 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    audit::record_issue_event, delivery_log::record_queued_issue,
+    issue_delivery_worker::mark_issue_as_sent_if_done, scheduler::try_lock_issue,
+};
+
+/// What decides the winner of an A/B test of subject lines.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AbTestMetric {
+    OpenRate,
+    ClickRate,
+}
+
+impl AbTestMetric {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AbTestMetric::OpenRate => "open_rate",
+            AbTestMetric::ClickRate => "click_rate",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "open_rate" => Some(AbTestMetric::OpenRate),
+            "click_rate" => Some(AbTestMetric::ClickRate),
+            _ => None,
+        }
+    }
+
+    /// Whether an issue tracked this way measures the metric. Otherwise every
+    /// variant scores zero and the test decides nothing.
+    pub fn is_measured(&self, track_opens: bool, track_clicks: bool) -> bool {
+        match self {
+            AbTestMetric::OpenRate => track_opens,
+            AbTestMetric::ClickRate => track_clicks,
+        }
+    }
+}
+
+/// How a subject variant fared with its share of the sample.
+#[derive(Serialize)]
+pub struct VariantResults {
+    pub variant: i16,
+    pub subject: String,
+    pub sent: i64,
+    pub opened: i64,
+    pub clicked: i64,
+}
+
+impl VariantResults {
+    pub fn rate(&self, metric: AbTestMetric) -> f64 {
+        let hits = match metric {
+            AbTestMetric::OpenRate => self.opened,
+            AbTestMetric::ClickRate => self.clicked,
+        };
+        if self.sent == 0 {
+            0.0
+        } else {
+            hits as f64 / self.sent as f64
+        }
+    }
+}
+
+/// The best variant by `metric`. Ties go to the earliest variant, so that a
+/// test without any tracked activity sends the first subject.
+pub fn pick_winner(results: &[VariantResults], metric: AbTestMetric) -> Option<i16> {
+    results
+        .iter()
+        .fold(
+            None,
+            |best: Option<&VariantResults>, candidate| match best {
+                Some(best) if best.rate(metric) >= candidate.rate(metric) => Some(best),
+                _ => Some(candidate),
+            },
+        )
+        .map(|winner| winner.variant)
+}
+
+/// The results of the subject variants of an issue so far, by variant.
+pub async fn variant_results<'e>(
+    executor: impl PgExecutor<'e>,
+    issue_id: Uuid,
+) -> Result<Vec<VariantResults>, sqlx::Error> {
+    sqlx::query_as!(
+        VariantResults,
+        r#"
+        SELECT
+            v.variant,
+            v.subject,
+            COUNT(d.sent_at) AS "sent!",
+            COUNT(d.first_opened_at) AS "opened!",
+            COUNT(*) FILTER (
+                WHERE EXISTS (
+                    SELECT 1 FROM link_clicks c
+                    WHERE
+                        c.newsletter_issue_id = d.newsletter_issue_id AND
+                        c.subscriber_email = d.subscriber_email
+                )
+            ) AS "clicked!"
+        FROM issue_subject_variants v
+        LEFT JOIN issue_deliveries d ON
+            d.newsletter_issue_id = v.newsletter_issue_id AND
+            d.variant = v.variant
+        WHERE v.newsletter_issue_id = $1
+        GROUP BY v.variant, v.subject
+        ORDER BY v.variant
+        "#,
+        issue_id
+    )
+    .fetch_all(executor)
+    .await
+}
+
+/// Queue an issue under A/B test for its test sample only.
+///
+/// Recipients are ranked by a hash of their address salted with the issue id:
+/// the sample and the variant each recipient gets look random, yet come out
+/// the same every time for a given list.
+pub async fn queue_test_sample(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    test_percentage: i16,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        WITH recipients AS (
+            SELECT
+                subscriber_email,
+                ROW_NUMBER() OVER (ORDER BY md5($1::uuid::TEXT || subscriber_email)) - 1 AS position,
+                COUNT(*) OVER () AS total
+            FROM issue_recipients($1)
+        ), variants AS (
+            SELECT COUNT(*) AS n FROM issue_subject_variants WHERE newsletter_issue_id = $1
+        )
+        INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email, variant)
+        SELECT $1, r.subscriber_email, (r.position % v.n)::SMALLINT
+        FROM recipients r, variants v
+        WHERE r.position < GREATEST(CEIL(r.total * $2::INTEGER / 100.0), v.n)
+        "#,
+        issue_id,
+        i32::from(test_percentage)
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+/// Pick the winning subject of every A/B test whose window has closed, and
+/// queue the issue for the rest of its recipients.
+///
+/// Returns the number of tests decided by this call. Tests that failed to be
+/// decided are logged and tried again on the next call.
+#[tracing::instrument(skip_all)]
+pub async fn decide_due_ab_tests(pool: &PgPool) -> Result<usize, sqlx::Error> {
+    let due_issues = sqlx::query_scalar!(
+        r#"
+        SELECT newsletter_issue_id
+        FROM newsletter_issues
+        WHERE
+            status = 'sending' AND
+            ab_test_percentage IS NOT NULL AND
+            winning_variant IS NULL AND
+            ab_test_decide_at <= now()
+        ORDER BY ab_test_decide_at
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut decided = 0;
+    for issue_id in due_issues {
+        // One test that cannot be decided must not hold back the others.
+        match decide_ab_test(pool, issue_id).await {
+            Ok(true) => decided += 1,
+            Ok(false) => {}
+            Err(e) => tracing::error!(
+                newsletter_issue_id = %issue_id,
+                "Failed to decide an A/B test: {:?}",
+                e
+            ),
+        }
+    }
+    Ok(decided)
+}
+
+#[tracing::instrument(skip(pool), fields(newsletter_issue_id = %issue_id))]
+async fn decide_ab_test(pool: &PgPool, issue_id: Uuid) -> Result<bool, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    if !try_lock_issue(&mut transaction, issue_id).await? {
+        return Ok(false);
+    }
+    let metric = sqlx::query_scalar!(
+        r#"
+        SELECT ab_test_metric AS "ab_test_metric!"
+        FROM newsletter_issues
+        WHERE
+            newsletter_issue_id = $1 AND
+            status = 'sending' AND
+            ab_test_percentage IS NOT NULL AND
+            winning_variant IS NULL AND
+            ab_test_decide_at <= now()
+        FOR UPDATE
+        "#,
+        issue_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    let Some(metric) = metric else {
+        return Ok(false);
+    };
+    let metric = AbTestMetric::parse(&metric).unwrap_or(AbTestMetric::OpenRate);
+
+    let results = variant_results(&mut *transaction, issue_id).await?;
+    let winner = pick_winner(&results, metric).unwrap_or(0);
+    sqlx::query!(
+        "UPDATE newsletter_issues SET winning_variant = $2 WHERE newsletter_issue_id = $1",
+        issue_id,
+        winner
+    )
+    .execute(&mut *transaction)
+    .await?;
+    // Subscribers who confirmed during the test get the winner as well.
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email, variant)
+        SELECT $1, r.subscriber_email, $2
+        FROM issue_recipients($1) r
+        WHERE NOT EXISTS (
+            SELECT 1 FROM issue_deliveries d
+            WHERE d.newsletter_issue_id = $1 AND d.subscriber_email = r.subscriber_email
+        )
+        ON CONFLICT DO NOTHING
+        "#,
+        issue_id,
+        winner
+    )
+    .execute(&mut *transaction)
+    .await?;
+    record_queued_issue(&mut *transaction, issue_id).await?;
+    record_issue_event(
+        &mut *transaction,
+        issue_id,
+        None,
+        None,
+        "ab_test_decided",
+        Some(&format!("Variant {} won on {}", winner, metric.as_str())),
+    )
+    .await?;
+    // The whole list might have been in the sample.
+    mark_issue_as_sent_if_done(&mut transaction, issue_id).await?;
+    transaction.commit().await?;
+    tracing::info!(winning_variant = winner, "A/B test decided");
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AbTestMetric, VariantResults, pick_winner};
+
+    fn results(variant: i16, sent: i64, opened: i64, clicked: i64) -> VariantResults {
+        VariantResults {
+            variant,
+            subject: format!("Subject {}", variant),
+            sent,
+            opened,
+            clicked,
+        }
+    }
+
+    #[test]
+    fn the_best_rate_wins() {
+        let results = [results(0, 10, 2, 1), results(1, 8, 4, 0)];
+        assert_eq!(pick_winner(&results, AbTestMetric::OpenRate), Some(1));
+        assert_eq!(pick_winner(&results, AbTestMetric::ClickRate), Some(0));
+    }
+
+    #[test]
+    fn ties_go_to_the_first_variant() {
+        let results = [
+            results(0, 0, 0, 0),
+            results(1, 0, 0, 0),
+            results(2, 4, 0, 0),
+        ];
+        assert_eq!(pick_winner(&results, AbTestMetric::OpenRate), Some(0));
+    }
+
+    #[test]
+    fn rates_account_for_the_size_of_each_share() {
+        let results = [results(0, 10, 5, 0), results(1, 3, 2, 0)];
+        assert_eq!(pick_winner(&results, AbTestMetric::OpenRate), Some(1));
+    }
+}
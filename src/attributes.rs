@@ -0,0 +1,212 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{segments::is_attribute_name, utils::escape_html};
+
+/// Subscribers carry at most this many tags.
+pub const MAX_TAGS: usize = 50;
+
+/// The type of a custom attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AttributeType {
+    Text,
+    Number,
+    Boolean,
+}
+
+/// The custom attributes a list collects, by name.
+pub type AttributeSchema = BTreeMap<String, AttributeType>;
+
+impl AttributeType {
+    pub fn accepts(self, value: &Value) -> bool {
+        match self {
+            Self::Text => value.is_string(),
+            Self::Number => value.is_number(),
+            Self::Boolean => value.is_boolean(),
+        }
+    }
+
+    /// Parse the value of a form field.
+    pub fn parse(self, s: &str) -> Result<Value, String> {
+        let s = s.trim();
+        match self {
+            Self::Text => Ok(Value::String(s.to_owned())),
+            Self::Number => s
+                .parse::<i64>()
+                .map(Value::from)
+                .or_else(|_| s.parse::<f64>().map(Value::from))
+                .ok()
+                .filter(Value::is_number)
+                .ok_or_else(|| format!("{} is not a number.", s)),
+            Self::Boolean => match s {
+                "true" | "on" => Ok(Value::Bool(true)),
+                "false" | "off" => Ok(Value::Bool(false)),
+                _ => Err(format!("{} is not a boolean.", s)),
+            },
+        }
+    }
+}
+
+pub fn is_valid_schema(schema: &AttributeSchema) -> bool {
+    schema.keys().all(|name| is_attribute_name(name))
+}
+
+/// Check that every attribute is declared, with the type of its value, by
+/// one of `schemas`.
+pub fn validate_attributes(
+    attributes: &Map<String, Value>,
+    schemas: &[AttributeSchema],
+) -> Result<(), String> {
+    for (name, value) in attributes {
+        let declared = schemas
+            .iter()
+            .filter_map(|schema| schema.get(name))
+            .any(|attribute_type| attribute_type.accepts(value));
+        if !declared {
+            return Err(format!("{} = {} is not a declared attribute.", name, value));
+        }
+    }
+    Ok(())
+}
+
+/// Trim, lowercase, sort and deduplicate tags. Tags are made of letters,
+/// digits, `-` and `_`.
+pub fn normalize_tags(tags: Vec<String>) -> Result<Vec<String>, String> {
+    let mut tags: Vec<String> = tags.iter().map(|tag| tag.trim().to_lowercase()).collect();
+    tags.sort();
+    tags.dedup();
+    if tags.len() > MAX_TAGS {
+        return Err(format!("Subscribers carry at most {} tags.", MAX_TAGS));
+    }
+    match tags.iter().find(|tag| {
+        tag.is_empty()
+            || tag.len() > 50
+            || !tag
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+    }) {
+        Some(tag) => Err(format!("{:?} is not a valid tag.", tag)),
+        None => Ok(tags),
+    }
+}
+
+/// The variables issues of a list can use to address a subscriber: `name`,
+/// `email` and `attributes.<name>` for each attribute declared by the list's
+/// `schema`, empty when the subscriber has no value for it. Values are
+/// HTML-escaped when `html` is set.
+pub fn template_variables(
+    name: &str,
+    email: &str,
+    attributes: &Map<String, Value>,
+    schema: &AttributeSchema,
+    html: bool,
+) -> HashMap<String, String> {
+    let escape = |s: &str| if html { escape_html(s) } else { s.to_owned() };
+    let mut variables = HashMap::from([
+        ("name".to_owned(), escape(name)),
+        ("email".to_owned(), escape(email)),
+    ]);
+    for key in schema.keys() {
+        let value = match attributes.get(key) {
+            Some(Value::String(s)) => escape(s),
+            None | Some(Value::Null) => String::new(),
+            Some(value) => value.to_string(),
+        };
+        variables.insert(format!("attributes.{}", key), value);
+    }
+    variables
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::assert_err;
+    use serde_json::json;
+
+    use super::{
+        AttributeSchema, AttributeType, normalize_tags, template_variables, validate_attributes,
+    };
+
+    fn schema(attributes: &[(&str, AttributeType)]) -> AttributeSchema {
+        attributes
+            .iter()
+            .map(|(name, attribute_type)| (name.to_string(), *attribute_type))
+            .collect()
+    }
+
+    #[test]
+    fn form_values_are_parsed_by_type() {
+        assert_eq!(AttributeType::Number.parse(" 12 "), Ok(json!(12)));
+        assert_eq!(AttributeType::Number.parse("1.5"), Ok(json!(1.5)));
+        assert_eq!(AttributeType::Boolean.parse("on"), Ok(json!(true)));
+        assert_eq!(AttributeType::Text.parse("Acme"), Ok(json!("Acme")));
+        assert_err!(AttributeType::Number.parse("twelve"));
+        assert_err!(AttributeType::Number.parse("NaN"));
+        assert_err!(AttributeType::Boolean.parse("yes"));
+    }
+
+    #[test]
+    fn attributes_must_be_declared_with_their_type() {
+        let schemas = [
+            schema(&[("company", AttributeType::Text)]),
+            schema(&[("seats", AttributeType::Number)]),
+        ];
+        let valid = json!({"company": "Acme", "seats": 3});
+        assert_eq!(
+            validate_attributes(valid.as_object().unwrap(), &schemas),
+            Ok(())
+        );
+        for invalid in [json!({"seats": "3"}), json!({"plan": "pro"})] {
+            assert_err!(validate_attributes(invalid.as_object().unwrap(), &schemas));
+        }
+    }
+
+    #[test]
+    fn tags_are_normalized() {
+        assert_eq!(
+            normalize_tags(vec![" Beta".into(), "beta".into(), "early-bird".into()]),
+            Ok(vec!["beta".to_owned(), "early-bird".to_owned()])
+        );
+        assert_err!(normalize_tags(vec!["".into()]));
+        assert_err!(normalize_tags(vec!["two words".into()]));
+        assert_err!(normalize_tags((0..51).map(|i| i.to_string()).collect()));
+    }
+
+    #[test]
+    fn html_variables_are_escaped() {
+        let attributes = json!({"company": "<Acme & co>", "seats": 3});
+        let variables = template_variables(
+            "Ursula",
+            "u@example.com",
+            attributes.as_object().unwrap(),
+            &schema(&[
+                ("company", AttributeType::Text),
+                ("seats", AttributeType::Number),
+            ]),
+            true,
+        );
+        assert_eq!(variables["attributes.company"], "&lt;Acme &amp; co&gt;");
+        assert_eq!(variables["attributes.seats"], "3");
+        assert_eq!(variables["name"], "Ursula");
+    }
+
+    #[test]
+    fn only_declared_attributes_are_variables() {
+        let attributes = json!({"company": "Acme", "plan": "pro"});
+        let variables = template_variables(
+            "Ursula",
+            "u@example.com",
+            attributes.as_object().unwrap(),
+            &schema(&[
+                ("company", AttributeType::Text),
+                ("seats", AttributeType::Number),
+            ]),
+            false,
+        );
+        assert_eq!(variables["attributes.company"], "Acme");
+        assert_eq!(variables["attributes.seats"], "");
+        assert!(!variables.contains_key("attributes.plan"));
+    }
+}
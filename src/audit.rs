@@ -0,0 +1,56 @@
+use sqlx::PgExecutor;
+use uuid::Uuid;
+
+/// Append an entry to the audit trail of a newsletter issue.
+///
+/// `actor` is `None` for actions taken by the application itself, e.g. the
+/// scheduler publishing an issue.
+pub async fn record_issue_event<'e>(
+    executor: impl PgExecutor<'e>,
+    issue_id: Uuid,
+    revision: Option<i32>,
+    actor: Option<Uuid>,
+    action: &str,
+    note: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_audit_log (
+            newsletter_issue_id, revision, actor, action, note, created_at
+        )
+        VALUES ($1, $2, $3, $4, $5, now())
+        "#,
+        issue_id,
+        revision,
+        actor,
+        action,
+        note
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Append the same entry to the audit trail of each of `subscriber_ids`.
+pub async fn record_subscriber_events<'e>(
+    executor: impl PgExecutor<'e>,
+    subscriber_ids: &[Uuid],
+    actor: Option<Uuid>,
+    action: &str,
+    note: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_audit_log (subscriber_id, actor, action, note, created_at)
+        SELECT subscriber_id, $2, $3, $4, now()
+        FROM UNNEST($1::uuid[]) AS subscriber_id
+        "#,
+        subscriber_ids,
+        actor,
+        action,
+        note
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,275 @@
+use std::sync::Arc;
+
+use argon2::{
+    Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version,
+    password_hash::{SaltString, rand_core::OsRng},
+};
+use axum::{
+    extract::FromRequestParts,
+    http::{HeaderMap, HeaderValue, StatusCode, header, request::Parts},
+    response::{IntoResponse, Response},
+};
+use base64::Engine;
+use secrecy::{ExposeSecret, SecretString};
+use sqlx::PgPool;
+use subtle::ConstantTimeEq;
+use uuid::Uuid;
+
+use crate::startup::ApplicationState;
+
+/// The bounds on the length of an admin password, in characters.
+const MIN_PASSWORD_LENGTH: usize = 12;
+const MAX_PASSWORD_LENGTH: usize = 128;
+
+pub struct Credentials {
+    pub username: String,
+    pub password: SecretString,
+}
+
+#[derive(Debug)]
+pub enum AuthError {
+    InvalidCredentials(String),
+    UnexpectedError(String),
+}
+
+/// An admin who successfully authenticated with HTTP Basic credentials.
+///
+/// Add it as an argument to a handler to restrict the route to admins.
+pub struct AdminUser {
+    pub user_id: Uuid,
+    pub username: String,
+}
+
+impl FromRequestParts<Arc<ApplicationState>> for AdminUser {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        app_state: &Arc<ApplicationState>,
+    ) -> Result<Self, Self::Rejection> {
+        let credentials = basic_authentication(&parts.headers).map_err(|e| {
+            tracing::warn!("Rejected admin request: {}", e);
+            unauthorized("admin")
+        })?;
+        let username = credentials.username.clone();
+        match validate_credentials(credentials, &app_state.pool).await {
+            Ok(user_id) => Ok(Self { user_id, username }),
+            Err(AuthError::InvalidCredentials(e)) => {
+                tracing::warn!("Rejected admin request: {}", e);
+                Err(unauthorized("admin"))
+            }
+            Err(AuthError::UnexpectedError(e)) => {
+                tracing::error!("Failed to authenticate admin: {}", e);
+                Err(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+            }
+        }
+    }
+}
+
+/// A webhook call made by Postmark, authenticated with the HTTP Basic
+/// credentials configured on both sides.
+pub struct PostmarkWebhook;
+
+impl FromRequestParts<Arc<ApplicationState>> for PostmarkWebhook {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        app_state: &Arc<ApplicationState>,
+    ) -> Result<Self, Self::Rejection> {
+        let expected = &app_state.postmark_webhook;
+        let credentials = basic_authentication(&parts.headers).map_err(|e| {
+            tracing::warn!("Rejected webhook call: {}", e);
+            unauthorized("webhooks")
+        })?;
+        // Compared in constant time, not to reveal how much of a guess is right.
+        let username_matches = credentials
+            .username
+            .as_bytes()
+            .ct_eq(expected.username.as_bytes());
+        let password_matches = credentials
+            .password
+            .expose_secret()
+            .as_bytes()
+            .ct_eq(expected.password.expose_secret().as_bytes());
+        if !bool::from(username_matches & password_matches) {
+            tracing::warn!("Rejected webhook call: invalid credentials");
+            return Err(unauthorized("webhooks"));
+        }
+        Ok(Self)
+    }
+}
+
+fn unauthorized(realm: &str) -> Response {
+    let mut response = StatusCode::UNAUTHORIZED.into_response();
+    response.headers_mut().insert(
+        header::WWW_AUTHENTICATE,
+        HeaderValue::from_str(&format!(r#"Basic realm="{}""#, realm)).unwrap(),
+    );
+    response
+}
+
+pub fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, String> {
+    let header_value = headers
+        .get(header::AUTHORIZATION)
+        .ok_or("The 'Authorization' header was missing")?
+        .to_str()
+        .map_err(|_| "The 'Authorization' header was not a valid UTF8 string.")?;
+    let base64encoded_segment = header_value
+        .strip_prefix("Basic ")
+        .ok_or("The authorization scheme was not 'Basic'.")?;
+    let decoded_bytes = base64::engine::general_purpose::STANDARD
+        .decode(base64encoded_segment)
+        .map_err(|_| "Failed to base64-decode 'Basic' credentials.")?;
+    let decoded_credentials = String::from_utf8(decoded_bytes)
+        .map_err(|_| "The decoded credential string is not valid UTF8.")?;
+
+    let (username, password) = decoded_credentials
+        .split_once(':')
+        .ok_or("A password must be provided in 'Basic' auth.")?;
+
+    Ok(Credentials {
+        username: username.to_string(),
+        password: SecretString::new(password.into()),
+    })
+}
+
+#[tracing::instrument(name = "Validate credentials", skip(credentials, pool))]
+pub async fn validate_credentials(
+    credentials: Credentials,
+    pool: &PgPool,
+) -> Result<Uuid, AuthError> {
+    let mut user_id = None;
+    // Verify against a dummy hash when the user does not exist, so that
+    // response times do not reveal which usernames are valid.
+    let mut expected_password_hash = SecretString::new(
+        "$argon2id$v=19$m=15000,t=2,p=1$\
+        gZiV/M1gPc22ElAH/Jh1Hw$\
+        CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno"
+            .into(),
+    );
+
+    if let Some((stored_user_id, stored_password_hash)) =
+        get_stored_credentials(&credentials.username, pool).await?
+    {
+        user_id = Some(stored_user_id);
+        expected_password_hash = stored_password_hash;
+    }
+
+    tokio::task::spawn_blocking(move || {
+        verify_password_hash(expected_password_hash, credentials.password)
+    })
+    .await
+    .map_err(|e| AuthError::UnexpectedError(e.to_string()))??;
+
+    user_id.ok_or_else(|| AuthError::InvalidCredentials("Unknown username.".into()))
+}
+
+fn verify_password_hash(
+    expected_password_hash: SecretString,
+    password_candidate: SecretString,
+) -> Result<(), AuthError> {
+    let expected_password_hash = PasswordHash::new(expected_password_hash.expose_secret())
+        .map_err(|e| AuthError::UnexpectedError(e.to_string()))?;
+    Argon2::default()
+        .verify_password(
+            password_candidate.expose_secret().as_bytes(),
+            &expected_password_hash,
+        )
+        .map_err(|_| AuthError::InvalidCredentials("Invalid password.".into()))
+}
+
+#[tracing::instrument(name = "Get stored credentials", skip(username, pool))]
+async fn get_stored_credentials(
+    username: &str,
+    pool: &PgPool,
+) -> Result<Option<(Uuid, SecretString)>, AuthError> {
+    let row = sqlx::query!(
+        r#"SELECT user_id, password_hash FROM users WHERE username = $1"#,
+        username,
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| AuthError::UnexpectedError(e.to_string()))?
+    .map(|row| (row.user_id, SecretString::new(row.password_hash.into())));
+    Ok(row)
+}
+
+/// Check that a new admin password is long enough, without being so long
+/// that hashing it becomes a burden.
+pub fn validate_new_password(password: &SecretString) -> Result<(), String> {
+    let length = password.expose_secret().chars().count();
+    if length < MIN_PASSWORD_LENGTH {
+        return Err(format!(
+            "The password must be at least {} characters long.",
+            MIN_PASSWORD_LENGTH
+        ));
+    }
+    if length > MAX_PASSWORD_LENGTH {
+        return Err(format!(
+            "The password must be at most {} characters long.",
+            MAX_PASSWORD_LENGTH
+        ));
+    }
+    Ok(())
+}
+
+/// Hash a password for storage, with the parameters of the dummy hash
+/// [`validate_credentials`] verifies against.
+async fn compute_password_hash(password: SecretString) -> Result<String, AuthError> {
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::new(
+            Algorithm::Argon2id,
+            Version::V0x13,
+            Params::new(15000, 2, 1, None).unwrap(),
+        )
+        .hash_password(password.expose_secret().as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| AuthError::UnexpectedError(e.to_string()))
+    })
+    .await
+    .map_err(|e| AuthError::UnexpectedError(e.to_string()))?
+}
+
+/// Add an admin, returning their id, or `None` when the username is taken.
+#[tracing::instrument(name = "Create an admin", skip(password, pool))]
+pub async fn create_admin(
+    username: &str,
+    password: SecretString,
+    pool: &PgPool,
+) -> Result<Option<Uuid>, AuthError> {
+    let password_hash = compute_password_hash(password).await?;
+    sqlx::query_scalar!(
+        r#"
+        INSERT INTO users (user_id, username, password_hash)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (username) DO NOTHING
+        RETURNING user_id
+        "#,
+        Uuid::new_v4(),
+        username,
+        password_hash
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| AuthError::UnexpectedError(e.to_string()))
+}
+
+#[tracing::instrument(name = "Change password", skip(password, pool))]
+pub async fn change_password(
+    user_id: Uuid,
+    password: SecretString,
+    pool: &PgPool,
+) -> Result<(), AuthError> {
+    let password_hash = compute_password_hash(password).await?;
+    sqlx::query!(
+        "UPDATE users SET password_hash = $2 WHERE user_id = $1",
+        user_id,
+        password_hash
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| AuthError::UnexpectedError(e.to_string()))?;
+    Ok(())
+}
@@ -0,0 +1,568 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, QueryBuilder, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    attributes::MAX_TAGS,
+    audit::record_subscriber_events,
+    domain::{NewSubscriber, SubscriberEmail, SubscriberName},
+    email_client::EmailClient,
+    erasure::erase_subscribers,
+    heartbeat::{HEARTBEAT_TIMEOUT, keep_alive},
+    lists::find_lists,
+    routes::subscriptions::send_confirmation_email,
+    segments::Filter,
+    utils::generate_token,
+};
+
+/// How many subscribers are handled, in a single transaction, at once.
+const BATCH_SIZE: i64 = 500;
+
+/// What to do to every subscriber of a bulk action.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum BulkAction {
+    /// Leave every list.
+    Unsubscribe,
+    /// Forget the subscriber altogether, see [`erase_subscribers`].
+    Delete,
+    /// Subscribers carrying [`MAX_TAGS`] tags already are left alone.
+    Tag {
+        tag: String,
+    },
+    Untag {
+        tag: String,
+    },
+    /// Send a new confirmation link to subscribers who have not confirmed
+    /// one of their lists yet.
+    ResendConfirmation,
+    /// Join the list with the given slug and leave every other one. Lists
+    /// with double opt-in send a confirmation link to the subscribers who
+    /// join them.
+    MoveToList {
+        list: String,
+    },
+}
+
+impl BulkAction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Unsubscribe => "unsubscribe",
+            Self::Delete => "delete",
+            Self::Tag { .. } => "tag",
+            Self::Untag { .. } => "untag",
+            Self::ResendConfirmation => "resend_confirmation",
+            Self::MoveToList { .. } => "move_to_list",
+        }
+    }
+
+    /// The tag, or the slug of the list, the action is about.
+    pub fn argument(&self) -> Option<&str> {
+        match self {
+            Self::Tag { tag } | Self::Untag { tag } => Some(tag),
+            Self::MoveToList { list } => Some(list),
+            _ => None,
+        }
+    }
+}
+
+/// The subscribers a bulk action applies to.
+pub enum BulkTargets {
+    /// Ids of subscribers we do not know are skipped.
+    Ids(Vec<Uuid>),
+    Filter(Filter),
+}
+
+#[derive(Serialize)]
+pub struct BulkActionReport {
+    pub bulk_action_id: Uuid,
+    pub actor: Uuid,
+    pub action: String,
+    pub argument: Option<String>,
+    /// `running`, `completed` or `failed`.
+    pub status: String,
+    pub total_subscribers: i32,
+    pub processed_subscribers: i32,
+    /// Why the action stopped, when it failed.
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+/// Record a bulk action, to be run with [`run_bulk_action`], along with the
+/// subscribers it applies to: subscribers matching a filter later on are
+/// left out.
+#[tracing::instrument(skip(pool, targets), fields(action = action.name()))]
+pub async fn create_bulk_action(
+    pool: &PgPool,
+    actor: Uuid,
+    action: &BulkAction,
+    targets: &BulkTargets,
+) -> Result<Uuid, sqlx::Error> {
+    let bulk_action_id = Uuid::new_v4();
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO bulk_actions (
+            bulk_action_id, actor, action, argument, status, total_subscribers, created_at
+        )
+        VALUES ($1, $2, $3, $4, 'running', 0, now())
+        "#,
+        bulk_action_id,
+        actor,
+        action.name(),
+        action.argument()
+    )
+    .execute(&mut *transaction)
+    .await?;
+    let mut query = QueryBuilder::new(
+        "INSERT INTO bulk_action_subscribers (bulk_action_id, subscriber_id) SELECT ",
+    );
+    query
+        .push_bind(bulk_action_id)
+        .push(", s.id FROM subscriptions s WHERE ");
+    match targets {
+        BulkTargets::Ids(ids) => {
+            query.push("s.id = ANY(").push_bind(ids).push(")");
+        }
+        BulkTargets::Filter(filter) => filter.push_sql(&mut query),
+    }
+    let total = query
+        .build()
+        .execute(&mut *transaction)
+        .await?
+        .rows_affected();
+    sqlx::query!(
+        "UPDATE bulk_actions SET total_subscribers = $2 WHERE bulk_action_id = $1",
+        bulk_action_id,
+        total as i32
+    )
+    .execute(&mut *transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(bulk_action_id)
+}
+
+/// Apply a bulk action a batch of subscribers at a time, each batch in its
+/// own transaction along with its progress and the audit trail of the
+/// subscribers it changed.
+///
+/// The action is marked as failed if it cannot complete; the batches applied
+/// until then stay. It keeps a heartbeat while it runs, see
+/// [`fail_stale_bulk_actions`].
+#[tracing::instrument(skip(pool, email_client, base_url, action), fields(action = action.name()))]
+pub async fn run_bulk_action(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    bulk_action_id: Uuid,
+    action: &BulkAction,
+) -> Result<(), sqlx::Error> {
+    let runner = Runner {
+        pool,
+        email_client,
+        base_url,
+        bulk_action_id,
+        action,
+    };
+    let error = tokio::select! {
+        result = runner.run() => result.err(),
+        () = keep_alive(|| heartbeat(pool, bulk_action_id)) => {
+            tracing::warn!("Stopped a bulk action that was failed in the meantime");
+            return Ok(());
+        }
+    };
+    if let Some(e) = &error {
+        tracing::error!("Failed to apply a bulk action: {:?}", e);
+    }
+    // The action may have been failed in the meantime, and must stay so.
+    sqlx::query!(
+        r#"
+        UPDATE bulk_actions
+        SET
+            status = CASE WHEN $2::TEXT IS NULL THEN 'completed' ELSE 'failed' END,
+            error = $2,
+            finished_at = now()
+        WHERE bulk_action_id = $1 AND status = 'running'
+        "#,
+        bulk_action_id,
+        error.map(|e| e.to_string())
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Record that a bulk action is still running, returning whether it is.
+async fn heartbeat(pool: &PgPool, bulk_action_id: Uuid) -> Result<bool, sqlx::Error> {
+    let updated = sqlx::query!(
+        r#"
+        UPDATE bulk_actions
+        SET heartbeat_at = now()
+        WHERE bulk_action_id = $1 AND status = 'running'
+        "#,
+        bulk_action_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(updated.rows_affected() > 0)
+}
+
+/// Mark the running bulk actions whose heartbeat stopped as failed: the
+/// process running them stopped. The batches they applied stay.
+#[tracing::instrument(skip(pool))]
+pub async fn fail_stale_bulk_actions(pool: &PgPool) -> Result<(), sqlx::Error> {
+    let stale = sqlx::query!(
+        r#"
+        UPDATE bulk_actions
+        SET
+            status = 'failed',
+            error = 'The application stopped before the bulk action completed.',
+            finished_at = now()
+        WHERE
+            status = 'running' AND
+            heartbeat_at < now() - make_interval(secs => $1)
+        "#,
+        HEARTBEAT_TIMEOUT.as_secs_f64()
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    if stale > 0 {
+        tracing::warn!(stale, "Failed the bulk actions whose heartbeat stopped");
+    }
+    Ok(())
+}
+
+/// The progress of a bulk action, or its outcome once it is over.
+pub async fn bulk_action_report(
+    pool: &PgPool,
+    bulk_action_id: Uuid,
+) -> Result<Option<BulkActionReport>, sqlx::Error> {
+    sqlx::query_as!(
+        BulkActionReport,
+        r#"
+        SELECT
+            bulk_action_id, actor, action, argument, status, total_subscribers,
+            processed_subscribers, error, created_at, finished_at
+        FROM bulk_actions
+        WHERE bulk_action_id = $1
+        "#,
+        bulk_action_id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+struct Runner<'a> {
+    pool: &'a PgPool,
+    email_client: &'a EmailClient,
+    base_url: &'a str,
+    bulk_action_id: Uuid,
+    action: &'a BulkAction,
+}
+
+/// A confirmation email to send once its token is stored.
+struct Confirmation {
+    subscriber_id: Uuid,
+    subscriber: NewSubscriber,
+    pending_lists: Vec<String>,
+    token: String,
+}
+
+impl Runner<'_> {
+    async fn run(&self) -> Result<(), sqlx::Error> {
+        let actor = sqlx::query_scalar!(
+            "SELECT actor FROM bulk_actions WHERE bulk_action_id = $1",
+            self.bulk_action_id
+        )
+        .fetch_one(self.pool)
+        .await?;
+        let mut last_id = Uuid::nil();
+        loop {
+            let ids = sqlx::query_scalar!(
+                r#"
+                SELECT subscriber_id
+                FROM bulk_action_subscribers
+                WHERE bulk_action_id = $1 AND subscriber_id > $2
+                ORDER BY subscriber_id
+                LIMIT $3
+                "#,
+                self.bulk_action_id,
+                last_id,
+                BATCH_SIZE
+            )
+            .fetch_all(self.pool)
+            .await?;
+            let Some(last) = ids.last() else {
+                return Ok(());
+            };
+            last_id = *last;
+
+            let mut transaction = self.pool.begin().await?;
+            let (affected, confirmations) = self.apply(&mut transaction, &ids).await?;
+            record_subscriber_events(
+                &mut *transaction,
+                &affected,
+                Some(actor),
+                self.action.name(),
+                self.action.argument(),
+            )
+            .await?;
+            sqlx::query!(
+                r#"
+                UPDATE bulk_actions
+                SET processed_subscribers = processed_subscribers + $2
+                WHERE bulk_action_id = $1
+                "#,
+                self.bulk_action_id,
+                ids.len() as i32
+            )
+            .execute(&mut *transaction)
+            .await?;
+            transaction.commit().await?;
+
+            self.send_confirmations(confirmations).await?;
+            tracing::info!(
+                bulk_action_id = %self.bulk_action_id,
+                subscribers = ids.len(),
+                "Applied a bulk action to a batch of subscribers"
+            );
+        }
+    }
+
+    /// Apply the action to `ids`, returning the subscribers it changed and
+    /// the confirmation emails to send once the changes are committed.
+    async fn apply(
+        &self,
+        transaction: &mut Transaction<'_, Postgres>,
+        ids: &[Uuid],
+    ) -> Result<(Vec<Uuid>, Vec<Confirmation>), sqlx::Error> {
+        let mut confirmations = Vec::new();
+        let mut affected = match self.action {
+            BulkAction::Unsubscribe => {
+                sqlx::query_scalar!(
+                    r#"
+                    UPDATE list_subscriptions
+                    SET status = 'unsubscribed'
+                    WHERE subscriber_id = ANY($1) AND status <> 'unsubscribed'
+                    RETURNING subscriber_id
+                    "#,
+                    ids
+                )
+                .fetch_all(&mut **transaction)
+                .await?
+            }
+            BulkAction::Delete => erase_subscribers(transaction, ids).await?,
+            BulkAction::Tag { tag } => {
+                sqlx::query_scalar!(
+                    r#"
+                    UPDATE subscriptions
+                    SET tags = ARRAY(SELECT DISTINCT UNNEST(tags || $2::TEXT) ORDER BY 1)
+                    WHERE id = ANY($1) AND NOT ($2 = ANY(tags)) AND cardinality(tags) < $3
+                    RETURNING id
+                    "#,
+                    ids,
+                    tag,
+                    MAX_TAGS as i32
+                )
+                .fetch_all(&mut **transaction)
+                .await?
+            }
+            BulkAction::Untag { tag } => {
+                sqlx::query_scalar!(
+                    r#"
+                    UPDATE subscriptions
+                    SET tags = array_remove(tags, $2)
+                    WHERE id = ANY($1) AND $2 = ANY(tags)
+                    RETURNING id
+                    "#,
+                    ids,
+                    tag
+                )
+                .fetch_all(&mut **transaction)
+                .await?
+            }
+            BulkAction::ResendConfirmation => {
+                confirmations = pending_confirmations(transaction, ids).await?;
+                confirmations.iter().map(|c| c.subscriber_id).collect()
+            }
+            BulkAction::MoveToList { list } => {
+                let (affected, pending) = move_to_list(transaction, ids, list).await?;
+                confirmations = pending_confirmations(transaction, &pending).await?;
+                affected
+            }
+        };
+        affected.sort();
+        affected.dedup();
+        Ok((affected, confirmations))
+    }
+
+    /// Emails that cannot be sent are logged: their tokens are stored, and
+    /// the action can be applied again.
+    async fn send_confirmations(
+        &self,
+        confirmations: Vec<Confirmation>,
+    ) -> Result<(), sqlx::Error> {
+        for confirmation in confirmations {
+            let lists = find_lists(self.pool, &confirmation.pending_lists).await?;
+            if lists.is_empty() {
+                continue;
+            }
+            if let Err(e) = send_confirmation_email(
+                self.email_client,
+                confirmation.subscriber,
+                &lists,
+                self.base_url,
+                &confirmation.token,
+            )
+            .await
+            {
+                tracing::error!("Failed to resend a confirmation email: {:?}", e);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Store a new confirmation token for each of `ids` with a list left to
+/// confirm, returning the emails to send.
+async fn pending_confirmations(
+    transaction: &mut Transaction<'_, Postgres>,
+    ids: &[Uuid],
+) -> Result<Vec<Confirmation>, sqlx::Error> {
+    let subscribers = sqlx::query!(
+        r#"
+        SELECT
+            s.id, s.email, s.name,
+            ARRAY_AGG(l.slug ORDER BY l.created_at, l.slug) AS "pending_lists!"
+        FROM subscriptions s
+        JOIN list_subscriptions ls ON ls.subscriber_id = s.id
+        JOIN lists l ON l.list_id = ls.list_id
+        WHERE s.id = ANY($1) AND ls.status = 'pending_confirmation'
+        GROUP BY s.id
+        "#,
+        ids
+    )
+    .fetch_all(&mut **transaction)
+    .await?;
+    let mut confirmations = Vec::new();
+    for subscriber in subscribers {
+        let (Ok(email), Ok(name)) = (
+            SubscriberEmail::parse(subscriber.email),
+            SubscriberName::parse(subscriber.name),
+        ) else {
+            tracing::warn!(subscriber_id = %subscriber.id, "Skipped an invalid subscriber");
+            continue;
+        };
+        confirmations.push(Confirmation {
+            subscriber_id: subscriber.id,
+            subscriber: NewSubscriber { email, name },
+            pending_lists: subscriber.pending_lists,
+            token: generate_token(),
+        });
+    }
+    let (subscriber_ids, tokens): (Vec<Uuid>, Vec<String>) = confirmations
+        .iter()
+        .map(|c| (c.subscriber_id, c.token.clone()))
+        .unzip();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription_tokens (subscription_token, subscriber_id)
+        SELECT * FROM UNNEST($1::TEXT[], $2::uuid[])
+        "#,
+        &tokens,
+        &subscriber_ids
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(confirmations)
+}
+
+/// Make `ids` members of the list with the given slug, and of no other one.
+/// Subscribers keep the status they had in the list if they were members of
+/// it already; they need to confirm it otherwise, as joining a list with
+/// double opt-in always takes a confirmation.
+///
+/// Returns the subscribers it changed, and the ones left to confirm the list.
+async fn move_to_list(
+    transaction: &mut Transaction<'_, Postgres>,
+    ids: &[Uuid],
+    slug: &str,
+) -> Result<(Vec<Uuid>, Vec<Uuid>), sqlx::Error> {
+    let joined = sqlx::query!(
+        r#"
+        INSERT INTO list_subscriptions (
+            list_id, subscriber_id, status, subscribed_at, confirmed_at
+        )
+        SELECT
+            l.list_id, s.id,
+            CASE WHEN l.double_opt_in THEN 'pending_confirmation' ELSE 'confirmed' END,
+            now(),
+            CASE WHEN l.double_opt_in THEN NULL ELSE now() END
+        FROM subscriptions s, lists l
+        WHERE s.id = ANY($1) AND l.slug = $2
+        ON CONFLICT (list_id, subscriber_id) DO UPDATE
+        SET
+            status = EXCLUDED.status,
+            subscribed_at = EXCLUDED.subscribed_at,
+            confirmed_at = EXCLUDED.confirmed_at
+        WHERE list_subscriptions.status = 'unsubscribed'
+        RETURNING subscriber_id, status
+        "#,
+        ids,
+        slug
+    )
+    .fetch_all(&mut **transaction)
+    .await?;
+    let pending = joined
+        .iter()
+        .filter(|r| r.status == "pending_confirmation")
+        .map(|r| r.subscriber_id)
+        .collect();
+    let mut affected: Vec<Uuid> = joined.into_iter().map(|r| r.subscriber_id).collect();
+    affected.extend(
+        sqlx::query_scalar!(
+            r#"
+            UPDATE list_subscriptions ls
+            SET status = 'unsubscribed'
+            FROM lists l
+            WHERE
+                l.list_id = ls.list_id AND
+                ls.subscriber_id = ANY($1) AND
+                l.slug <> $2 AND
+                ls.status <> 'unsubscribed'
+            RETURNING ls.subscriber_id
+            "#,
+            ids,
+            slug
+        )
+        .fetch_all(&mut **transaction)
+        .await?,
+    );
+    Ok((affected, pending))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BulkAction;
+
+    #[test]
+    fn actions_are_tagged_by_name() {
+        let action: BulkAction =
+            serde_json::from_value(serde_json::json!({"action": "tag", "tag": "vip"})).unwrap();
+        assert_eq!(action, BulkAction::Tag { tag: "vip".into() });
+        assert_eq!(action.name(), "tag");
+        assert_eq!(action.argument(), Some("vip"));
+
+        let action: BulkAction =
+            serde_json::from_value(serde_json::json!({"action": "resend_confirmation"})).unwrap();
+        assert_eq!(action.name(), "resend_confirmation");
+        assert_eq!(action.argument(), None);
+
+        assert!(
+            serde_json::from_value::<BulkAction>(serde_json::json!({"action": "tag"})).is_err()
+        );
+    }
+}
@@ -0,0 +1,148 @@
+use std::{fs::File, io::BufRead};
+
+use secrecy::SecretString;
+use tokio::{io::AsyncWriteExt, sync::mpsc};
+
+use crate::{
+    authentication::{create_admin, validate_new_password},
+    configuration::Settings,
+    lists::{DEFAULT_LIST, find_lists},
+    startup::get_connection_pool,
+    subscriber_export::{ExportFilter, ExportFormat, stream_subscribers},
+    subscriber_import::{create_import, csv_reader, import_report, run_import},
+};
+
+const USAGE: &str = "\
+Usage:
+    email_newsletter                      Serve requests
+    email_newsletter import-subscribers <file.csv> [--confirmed] [--list <slug>]
+    email_newsletter export-subscribers [--format csv|jsonl] [--status <status>] [--list <slug>]
+    email_newsletter create-admin <username>    Reads the password from stdin";
+
+/// Run the command named by `args`, the command line arguments after the
+/// program name.
+pub async fn run_command(configuration: Settings, args: &[String]) -> Result<(), String> {
+    match args.first().map(String::as_str) {
+        Some("import-subscribers") => import_subscribers(configuration, &args[1..]).await,
+        Some("export-subscribers") => export_subscribers(configuration, &args[1..]).await,
+        Some("create-admin") => create_admin_user(configuration, &args[1..]).await,
+        _ => Err(USAGE.into()),
+    }
+}
+
+/// Import a CSV file of subscribers, printing the rows that could not be
+/// imported once done. Progress is logged batch after batch.
+async fn import_subscribers(configuration: Settings, args: &[String]) -> Result<(), String> {
+    let mut path = None;
+    let mut confirmed = false;
+    let mut list = DEFAULT_LIST.to_owned();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--confirmed" => confirmed = true,
+            "--list" => list = args.next().ok_or(USAGE)?.clone(),
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(USAGE.into()),
+        }
+    }
+    let path = path.ok_or(USAGE)?;
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    let reader = csv_reader(file)?;
+
+    let pool = get_connection_pool(&configuration.database);
+    let email_client = configuration.email_client.client();
+    let list = find_lists(&pool, &[list.clone()])
+        .await
+        .map_err(|e| e.to_string())?
+        .pop()
+        .ok_or_else(|| format!("There is no `{}` list.", list))?;
+    let import_id = create_import(&pool, &list, confirmed)
+        .await
+        .map_err(|e| e.to_string())?;
+    run_import(
+        &pool,
+        &email_client,
+        &configuration.application.base_url,
+        import_id,
+        &list,
+        reader,
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let report = import_report(&pool, import_id)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or("The import vanished.")?;
+    for error in &report.errors {
+        println!("line {}: {}", error.line, error.error);
+    }
+    println!(
+        "{} rows: {} imported, {} already subscribed, {} failed",
+        report.processed_rows, report.imported_rows, report.duplicate_rows, report.failed_rows
+    );
+    match report.error {
+        Some(error) => Err(format!("The import stopped: {}", error)),
+        None => Ok(()),
+    }
+}
+
+/// Write the subscribers to stdout as they are read from the database.
+async fn export_subscribers(configuration: Settings, args: &[String]) -> Result<(), String> {
+    let mut format = ExportFormat::default();
+    let mut filter = ExportFilter::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = args.next().ok_or(USAGE)?.clone();
+        match arg.as_str() {
+            "--format" => format = ExportFormat::parse(&value)?,
+            "--status" => filter.status = Some(value),
+            "--list" => filter.list = Some(value),
+            _ => return Err(USAGE.into()),
+        }
+    }
+
+    let pool = get_connection_pool(&configuration.database);
+    if let Some(list) = &filter.list {
+        let lists = find_lists(&pool, std::slice::from_ref(list))
+            .await
+            .map_err(|e| e.to_string())?;
+        if lists.is_empty() {
+            return Err(format!("There is no `{}` list.", list));
+        }
+    }
+    let (sender, mut receiver) = mpsc::channel(4);
+    let export = tokio::spawn(async move {
+        stream_subscribers(&pool, &filter, format, sender).await;
+    });
+    let mut stdout = tokio::io::stdout();
+    while let Some(chunk) = receiver.recv().await {
+        let chunk = chunk.map_err(|e| format!("The export stopped: {}", e))?;
+        stdout.write_all(&chunk).await.map_err(|e| e.to_string())?;
+    }
+    stdout.flush().await.map_err(|e| e.to_string())?;
+    export.await.map_err(|e| e.to_string())
+}
+
+/// Add an admin, reading their password from the first line of stdin rather
+/// than the command line, where it would be kept in the shell history.
+async fn create_admin_user(configuration: Settings, args: &[String]) -> Result<(), String> {
+    let [username] = args else {
+        return Err(USAGE.into());
+    };
+    let mut password = String::new();
+    std::io::stdin()
+        .lock()
+        .read_line(&mut password)
+        .map_err(|e| format!("Failed to read the password: {}", e))?;
+    let password = SecretString::from(password.trim_end_matches(['\r', '\n']));
+    validate_new_password(&password)?;
+
+    let pool = get_connection_pool(&configuration.database);
+    create_admin(username, password, &pool)
+        .await
+        .map_err(|e| format!("{:?}", e))?
+        .ok_or_else(|| format!("There is an admin named `{}` already.", username))?;
+    println!("Created the `{}` admin.", username);
+    Ok(())
+}
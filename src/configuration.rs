@@ -1,3 +1,5 @@
+use std::net::IpAddr;
+
 use config::{Config, ConfigError};
 use secrecy::{ExposeSecret, SecretString};
 use serde::Deserialize;
@@ -5,13 +7,43 @@ use serde_aux::field_attributes::deserialize_number_from_string;
 use sqlx::ConnectOptions;
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
 
-use crate::domain::SubscriberEmail;
+use crate::{domain::SubscriberEmail, email_client::EmailClient, throttle::Throttle};
 
 #[derive(Deserialize, Clone)]
 pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    pub archive: ArchiveSettings,
+    /// The credentials Postmark sends along with its webhook calls.
+    pub postmark_webhook: WebhookCredentials,
+    /// Turns the posts of an RSS feed into issues. Disabled when missing.
+    pub feed_poller: Option<FeedPollerSettings>,
+    pub consent: ConsentSettings,
+}
+
+/// What we record when subscribers give their consent.
+#[derive(Deserialize, Clone)]
+pub struct ConsentSettings {
+    /// The version of the privacy policy subscribers agree to.
+    pub privacy_policy_version: String,
+    /// The reverse proxies trusted to tell the address of the client in
+    /// `X-Forwarded-For`. The header is ignored on other requests.
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct WebhookCredentials {
+    pub username: String,
+    pub password: SecretString,
+}
+
+/// How the newsletter presents itself in the public archive and its feeds.
+#[derive(Deserialize, Clone)]
+pub struct ArchiveSettings {
+    pub title: String,
+    pub description: String,
 }
 
 #[derive(Deserialize, Clone)]
@@ -20,6 +52,15 @@ pub struct EmailClientSettings {
     pub sender_email: String,
     pub authorization_token: SecretString,
     pub timeout_milliseconds: u64,
+    /// The sending quota of the provider, shared by everything sending email.
+    pub rate_limit: RateLimitSettings,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct RateLimitSettings {
+    pub messages_per_second: f64,
+    /// How many messages can go out at once after a quiet period.
+    pub burst: u32,
 }
 
 impl EmailClientSettings {
@@ -30,6 +71,62 @@ impl EmailClientSettings {
     pub fn timeout(&self) -> std::time::Duration {
         std::time::Duration::from_millis(self.timeout_milliseconds)
     }
+
+    pub fn throttle(&self) -> Throttle {
+        Throttle::new(self.rate_limit.messages_per_second, self.rate_limit.burst)
+    }
+
+    pub fn client(&self) -> EmailClient {
+        let sender_email = self.sender().expect("Invalid sender email address");
+        EmailClient::new(
+            self.base_url.clone(),
+            sender_email,
+            self.authorization_token.clone(),
+            self.timeout(),
+            self.throttle(),
+        )
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct FeedPollerSettings {
+    /// An `http(s)://` URL, or the path of a local file.
+    pub source: String,
+    pub interval_seconds: u64,
+    pub mode: FeedIssueMode,
+    /// How long after a post is detected its issue is scheduled to go out.
+    /// Only used in `scheduled` mode.
+    #[serde(default)]
+    pub send_delay_minutes: i64,
+    /// Create issues for the posts already in the feed on the first poll,
+    /// instead of only remembering them.
+    #[serde(default)]
+    pub import_existing_items: bool,
+    #[serde(default = "default_feed_html_template")]
+    pub html_template: String,
+    #[serde(default = "default_feed_text_template")]
+    pub text_template: String,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum FeedIssueMode {
+    Draft,
+    Scheduled,
+}
+
+fn default_feed_html_template() -> String {
+    r#"<h1>{{ title }}</h1>{{ content }}<p><a href="{{ link }}">Read it on the web</a></p>"#.into()
+}
+
+fn default_feed_text_template() -> String {
+    "{{ title }}\n\n{{ content }}\n\nRead it on the web: {{ link }}".into()
+}
+
+impl FeedPollerSettings {
+    pub fn interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.interval_seconds)
+    }
 }
 
 #[derive(Deserialize, Clone)]
@@ -38,6 +135,8 @@ pub struct ApplicationSettings {
     pub port: u16,
     pub host: String,
     pub base_url: String,
+    /// The key signing the links we hand out, e.g. click-tracking redirects.
+    pub hmac_secret: SecretString,
 }
 
 #[derive(Deserialize, Clone)]
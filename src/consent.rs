@@ -0,0 +1,205 @@
+use std::{
+    convert::Infallible,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+
+use axum::{
+    extract::{ConnectInfo, FromRequestParts},
+    http::{HeaderMap, header, request::Parts},
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::PgExecutor;
+use uuid::Uuid;
+
+use crate::startup::ApplicationState;
+
+/// User agents are cut to this many characters.
+const MAX_USER_AGENT_LENGTH: usize = 512;
+
+/// What a request tells about the consent it carries: who sent it, from
+/// where, under which privacy policy.
+pub struct ConsentContext {
+    pub ip_address: Option<IpAddr>,
+    pub user_agent: Option<String>,
+    pub policy_version: String,
+}
+
+impl FromRequestParts<Arc<ApplicationState>> for ConsentContext {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        app_state: &Arc<ApplicationState>,
+    ) -> Result<Self, Self::Rejection> {
+        let settings = &app_state.consent;
+        let ip_address =
+            parts
+                .extensions
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(peer)| {
+                    client_ip(peer.ip(), &parts.headers, &settings.trusted_proxies)
+                });
+        let user_agent = parts
+            .headers
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.chars().take(MAX_USER_AGENT_LENGTH).collect());
+        Ok(Self {
+            ip_address,
+            user_agent,
+            policy_version: settings.privacy_policy_version.clone(),
+        })
+    }
+}
+
+/// The address of the client a request comes from.
+///
+/// Requests relayed by a trusted proxy come from the last address the chain
+/// of trusted proxies in `X-Forwarded-For` was told about: anything before it
+/// could have been made up by the client.
+pub fn client_ip(peer: IpAddr, headers: &HeaderMap, trusted_proxies: &[IpAddr]) -> IpAddr {
+    let mut client = peer;
+    let forwarded_for = headers
+        .get_all("X-Forwarded-For")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .collect::<Vec<_>>();
+    for hop in forwarded_for.iter().rev() {
+        if !trusted_proxies.contains(&client) {
+            break;
+        }
+        match hop.trim().parse() {
+            Ok(address) => client = address,
+            Err(_) => break,
+        }
+    }
+    client
+}
+
+#[derive(Serialize)]
+pub struct ConsentRecord {
+    /// `subscribe` or `confirm`. Anyone can submit the form with someone
+    /// else's address: only `confirm` proves consent to a list that asks for
+    /// confirmation.
+    pub action: String,
+    /// The slugs of the lists consented to.
+    pub lists: Vec<String>,
+    /// Missing once the subscriber had their data erased, as is `user_agent`.
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    /// Where the subscription form was, as the form tells.
+    pub source: Option<String>,
+    pub policy_version: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Record that a subscriber agreed to receive `lists`.
+#[tracing::instrument(skip(executor, context))]
+pub async fn record_consent<'e>(
+    executor: impl PgExecutor<'e>,
+    subscriber_id: Uuid,
+    action: &str,
+    lists: &[String],
+    source: Option<&str>,
+    context: &ConsentContext,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO consent_records (
+            subscriber_id, action, lists, ip_address, user_agent, source,
+            policy_version, created_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, now())
+        "#,
+        subscriber_id,
+        action,
+        lists,
+        context.ip_address.map(|address| address.to_string()),
+        context.user_agent,
+        source,
+        context.policy_version
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// The consent records of a subscriber, oldest first.
+pub async fn consent_records<'e>(
+    executor: impl PgExecutor<'e>,
+    subscriber_id: Uuid,
+) -> Result<Vec<ConsentRecord>, sqlx::Error> {
+    sqlx::query_as!(
+        ConsentRecord,
+        r#"
+        SELECT action, lists, ip_address, user_agent, source, policy_version, created_at
+        FROM consent_records
+        WHERE subscriber_id = $1
+        ORDER BY created_at, consent_record_id
+        "#,
+        subscriber_id
+    )
+    .fetch_all(executor)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use axum::http::HeaderMap;
+
+    use super::client_ip;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn forwarded_for(values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append("X-Forwarded-For", value.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn the_header_is_ignored_unless_a_trusted_proxy_sent_it() {
+        let headers = forwarded_for(&["203.0.113.7"]);
+        assert_eq!(
+            client_ip(ip("198.51.100.1"), &headers, &[]),
+            ip("198.51.100.1")
+        );
+        assert_eq!(
+            client_ip(ip("198.51.100.1"), &headers, &[ip("10.0.0.2")]),
+            ip("198.51.100.1")
+        );
+    }
+
+    #[test]
+    fn chains_of_trusted_proxies_are_walked_back() {
+        let trusted = [ip("10.0.0.2"), ip("10.0.0.3")];
+        let headers = forwarded_for(&["192.0.2.66, 203.0.113.7", "10.0.0.3"]);
+        assert_eq!(
+            client_ip(ip("10.0.0.2"), &headers, &trusted),
+            ip("203.0.113.7")
+        );
+    }
+
+    #[test]
+    fn invalid_hops_stop_the_walk() {
+        let trusted = [ip("10.0.0.2")];
+        let headers = forwarded_for(&["203.0.113.7, not-an-ip"]);
+        assert_eq!(
+            client_ip(ip("10.0.0.2"), &headers, &trusted),
+            ip("10.0.0.2")
+        );
+        assert_eq!(
+            client_ip(ip("10.0.0.2"), &forwarded_for(&[]), &trusted),
+            ip("10.0.0.2")
+        );
+    }
+}
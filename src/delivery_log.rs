@@ -0,0 +1,120 @@
+use sqlx::PgExecutor;
+use uuid::Uuid;
+
+/// Log every delivery queued for an issue, for each of its recipients.
+///
+/// Call it in the transaction filling `issue_delivery_queue`.
+pub async fn record_queued_issue<'e>(
+    executor: impl PgExecutor<'e>,
+    issue_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_deliveries (
+            newsletter_issue_id, subscriber_email, subscriber_id, variant, status, queued_at
+        )
+        SELECT q.newsletter_issue_id, q.subscriber_email, s.id, q.variant, 'queued', now()
+        FROM issue_delivery_queue q
+        LEFT JOIN subscriptions s ON s.email = q.subscriber_email
+        WHERE q.newsletter_issue_id = $1
+        ON CONFLICT (newsletter_issue_id, subscriber_email) DO NOTHING
+        "#,
+        issue_id
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Mark deliveries that were queued again, e.g. by an admin retrying them.
+pub async fn record_requeued<'e>(
+    executor: impl PgExecutor<'e>,
+    issue_ids: &[Uuid],
+    emails: &[String],
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE issue_deliveries d
+        SET status = 'queued', queued_at = now(), failed_at = NULL
+        FROM UNNEST($1::uuid[], $2::TEXT[]) AS r (newsletter_issue_id, subscriber_email)
+        WHERE
+            d.newsletter_issue_id = r.newsletter_issue_id AND
+            d.subscriber_email = r.subscriber_email
+        "#,
+        issue_ids,
+        emails
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn record_sent<'e>(
+    executor: impl PgExecutor<'e>,
+    issue_id: Uuid,
+    email: &str,
+    provider_message_id: Option<&str>,
+    open_token: Option<&str>,
+    click_token: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE issue_deliveries
+        SET
+            status = 'sent',
+            sent_at = now(),
+            provider_message_id = $3,
+            open_token = $4,
+            click_token = $5
+        WHERE newsletter_issue_id = $1 AND subscriber_email = $2
+        "#,
+        issue_id,
+        email,
+        provider_message_id,
+        open_token,
+        click_token
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn record_failed<'e>(
+    executor: impl PgExecutor<'e>,
+    issue_id: Uuid,
+    email: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE issue_deliveries
+        SET status = 'failed', failed_at = now()
+        WHERE newsletter_issue_id = $1 AND subscriber_email = $2
+        "#,
+        issue_id,
+        email
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Mark a delivery dropped because its recipient was suppressed after it
+/// got queued.
+pub async fn record_suppressed<'e>(
+    executor: impl PgExecutor<'e>,
+    issue_id: Uuid,
+    email: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE issue_deliveries
+        SET status = 'suppressed'
+        WHERE newsletter_issue_id = $1 AND subscriber_email = $2
+        "#,
+        issue_id,
+        email
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
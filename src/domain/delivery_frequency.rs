@@ -0,0 +1,57 @@
+/// How often a subscriber is willing to hear from us, across all lists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryFrequency {
+    EveryIssue,
+    /// At most one issue a week.
+    Weekly,
+    /// At most one issue a month.
+    Monthly,
+}
+
+impl DeliveryFrequency {
+    pub const ALL: [DeliveryFrequency; 3] = [Self::EveryIssue, Self::Weekly, Self::Monthly];
+
+    pub fn parse(s: String) -> Result<DeliveryFrequency, String> {
+        Self::ALL
+            .into_iter()
+            .find(|frequency| frequency.as_str() == s)
+            .ok_or_else(|| format!("{} is not a valid delivery frequency.", s))
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::EveryIssue => "every_issue",
+            Self::Weekly => "weekly",
+            Self::Monthly => "monthly",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::EveryIssue => "Every issue",
+            Self::Weekly => "At most once a week",
+            Self::Monthly => "At most once a month",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DeliveryFrequency;
+
+    #[test]
+    fn frequencies_round_trip() {
+        for frequency in DeliveryFrequency::ALL {
+            assert_eq!(
+                DeliveryFrequency::parse(frequency.as_str().into()),
+                Ok(frequency)
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_frequencies_are_rejected() {
+        assert!(DeliveryFrequency::parse("daily".into()).is_err());
+        assert!(DeliveryFrequency::parse("".into()).is_err());
+    }
+}
@@ -0,0 +1,70 @@
+/// The URL-safe identifier of an issue in the public archive.
+#[derive(Debug, Clone)]
+pub struct IssueSlug(String);
+
+impl IssueSlug {
+    const MAX_LENGTH: usize = 60;
+
+    /// Derive a slug from an issue title, keeping ASCII letters and digits
+    /// and collapsing everything else into single dashes.
+    pub fn from_title(title: &str) -> IssueSlug {
+        let mut slug = String::new();
+        for c in title.chars() {
+            if c.is_ascii_alphanumeric() {
+                slug.push(c.to_ascii_lowercase());
+            } else if !slug.is_empty() && !slug.ends_with('-') {
+                slug.push('-');
+            }
+            if slug.len() >= Self::MAX_LENGTH {
+                break;
+            }
+        }
+        let slug = slug.trim_end_matches('-');
+        if slug.is_empty() {
+            Self("issue".into())
+        } else {
+            Self(slug.into())
+        }
+    }
+
+    /// Disambiguate a slug that is already taken.
+    pub fn with_suffix(&self, suffix: &str) -> IssueSlug {
+        Self(format!("{}-{}", self.0, suffix))
+    }
+}
+
+impl AsRef<str> for IssueSlug {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IssueSlug;
+
+    #[test]
+    fn titles_are_lowercased_and_dasherized() {
+        let slug = IssueSlug::from_title("Hello, World: Issue #42!");
+        assert_eq!(slug.as_ref(), "hello-world-issue-42");
+    }
+
+    #[test]
+    fn titles_without_ascii_alphanumerics_fall_back_to_issue() {
+        let slug = IssueSlug::from_title("✉️ — ✉️");
+        assert_eq!(slug.as_ref(), "issue");
+    }
+
+    #[test]
+    fn long_titles_are_truncated() {
+        let slug = IssueSlug::from_title(&"a ".repeat(100));
+        assert!(slug.as_ref().len() <= 60);
+        assert!(!slug.as_ref().ends_with('-'));
+    }
+
+    #[test]
+    fn suffixes_are_appended_with_a_dash() {
+        let slug = IssueSlug::from_title("Weekly digest").with_suffix("1a2b3c4d");
+        assert_eq!(slug.as_ref(), "weekly-digest-1a2b3c4d");
+    }
+}
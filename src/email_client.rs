@@ -1,7 +1,7 @@
-use crate::domain::SubscriberEmail;
-use reqwest::Client;
+use crate::{domain::SubscriberEmail, throttle::Throttle};
+use reqwest::{Client, StatusCode};
 use secrecy::{ExposeSecret, SecretString};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 #[derive(Clone)]
 pub struct EmailClient {
@@ -9,6 +9,7 @@ pub struct EmailClient {
     http_client: Client,
     base_url: String,
     authorization_token: SecretString,
+    throttle: Throttle,
 }
 
 impl EmailClient {
@@ -17,6 +18,7 @@ impl EmailClient {
         sender: SubscriberEmail,
         authorization_token: SecretString,
         timeout: std::time::Duration,
+        throttle: Throttle,
     ) -> Self {
         let http_client = Client::builder().timeout(timeout).build().unwrap();
         Self {
@@ -24,24 +26,52 @@ impl EmailClient {
             http_client,
             base_url,
             authorization_token,
+            throttle,
         }
     }
+
+    /// The rate limit shared by every clone of this client.
+    pub fn throttle(&self) -> &Throttle {
+        &self.throttle
+    }
+
+    /// Send an email, waiting first for the provider's sending quota to
+    /// allow it.
+    ///
+    /// Returns the `MessageID` the provider assigned to the email, when its
+    /// response carries one.
     pub async fn send_email(
         &self,
         recipient: SubscriberEmail,
         subject: &str,
         html_content: &str,
         text_content: &str,
-    ) -> Result<(), reqwest::Error> {
+    ) -> Result<Option<String>, reqwest::Error> {
+        self.send_email_from(None, recipient, subject, html_content, text_content)
+            .await
+    }
+
+    /// Like [`EmailClient::send_email`], from `sender` rather than the
+    /// configured sender when it is set.
+    pub async fn send_email_from(
+        &self,
+        sender: Option<&str>,
+        recipient: SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<Option<String>, reqwest::Error> {
+        self.throttle.acquire().await;
         let url = format!("{}/email", self.base_url);
         let request_body = SendEmailRequest {
-            from: self.sender.as_ref(),
+            from: sender.unwrap_or(self.sender.as_ref()),
             to: recipient.as_ref(),
             subject,
             html_body: html_content,
             text_body: text_content,
         };
-        self.http_client
+        let response = self
+            .http_client
             .post(&url)
             .header(
                 "X-Postmark-Server-Token",
@@ -51,7 +81,61 @@ impl EmailClient {
             .send()
             .await?
             .error_for_status()?;
-        Ok(())
+        let message_id = response
+            .json::<SendEmailResponse>()
+            .await
+            .ok()
+            .and_then(|r| r.message_id);
+        Ok(message_id)
+    }
+}
+
+/// Why the provider did not take a message, as far as we can tell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendErrorKind {
+    Timeout,
+    Connection,
+    RateLimited,
+    /// The provider failed on its side (5xx).
+    ProviderError,
+    /// The provider refused the message itself (4xx).
+    Rejected,
+    /// The stored recipient address is not a valid email address.
+    InvalidRecipient,
+    Unknown,
+}
+
+impl SendErrorKind {
+    pub fn classify(e: &reqwest::Error) -> Self {
+        if e.is_timeout() {
+            return Self::Timeout;
+        }
+        if e.is_connect() {
+            return Self::Connection;
+        }
+        match e.status() {
+            Some(StatusCode::TOO_MANY_REQUESTS) => Self::RateLimited,
+            Some(status) if status.is_server_error() => Self::ProviderError,
+            Some(status) if status.is_client_error() => Self::Rejected,
+            _ => Self::Unknown,
+        }
+    }
+
+    /// Whether sending the same message again later might succeed.
+    pub fn is_retryable(self) -> bool {
+        !matches!(self, Self::Rejected | Self::InvalidRecipient)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Timeout => "timeout",
+            Self::Connection => "connection",
+            Self::RateLimited => "rate_limited",
+            Self::ProviderError => "provider_error",
+            Self::Rejected => "rejected",
+            Self::InvalidRecipient => "invalid_recipient",
+            Self::Unknown => "unknown",
+        }
     }
 }
 
@@ -65,9 +149,19 @@ struct SendEmailRequest<'a> {
     text_body: &'a str,
 }
 
+#[derive(Deserialize)]
+struct SendEmailResponse {
+    #[serde(rename = "MessageID")]
+    message_id: Option<String>,
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::{domain::SubscriberEmail, email_client::EmailClient};
+    use crate::{
+        domain::SubscriberEmail,
+        email_client::{EmailClient, SendErrorKind},
+        throttle::Throttle,
+    };
     use claim::{assert_err, assert_ok};
     use fake::{
         Fake, Faker,
@@ -123,6 +217,7 @@ mod tests {
             email(),
             SecretString::new(random_token.into()),
             std::time::Duration::from_millis(200),
+            Throttle::new(100.0, 10),
         )
     }
 
@@ -197,4 +292,68 @@ mod tests {
             .send_email(email(), &subject(), &content(), &content())
             .await;
     }
+
+    #[tokio::test]
+    async fn send_errors_are_classified_from_the_response() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        for (status, kind) in [
+            (429, SendErrorKind::RateLimited),
+            (503, SendErrorKind::ProviderError),
+            (422, SendErrorKind::Rejected),
+        ] {
+            let _guard = Mock::given(any())
+                .respond_with(ResponseTemplate::new(status))
+                .mount_as_scoped(&mock_server)
+                .await;
+            let e = email_client
+                .send_email(email(), &subject(), &content(), &content())
+                .await
+                .unwrap_err();
+            assert_eq!(SendErrorKind::classify(&e), kind);
+        }
+    }
+
+    #[tokio::test]
+    async fn timeouts_are_classified_as_retryable() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let response = ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(180));
+        Mock::given(any())
+            .respond_with(response)
+            .mount(&mock_server)
+            .await;
+        let e = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await
+            .unwrap_err();
+        let kind = SendErrorKind::classify(&e);
+        assert_eq!(kind, SendErrorKind::Timeout);
+        assert!(kind.is_retryable());
+        assert!(!SendErrorKind::Rejected.is_retryable());
+    }
+
+    #[tokio::test]
+    async fn send_email_returns_the_message_id_assigned_by_the_provider() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "ErrorCode": 0,
+                "Message": "OK",
+                "MessageID": "b7bc2f4a-e38e-4336-af7d-e6c392c2f817",
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let message_id = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            message_id.as_deref(),
+            Some("b7bc2f4a-e38e-4336-af7d-e6c392c2f817")
+        );
+    }
 }
@@ -0,0 +1,152 @@
+use std::collections::HashSet;
+
+use sqlx::{Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::issue_delivery_worker::mark_issue_as_sent_if_done;
+
+/// Erase subscribers for good, returning the ids of the ones we knew.
+///
+/// Their personal data is deleted. What we sent them stays, to keep the
+/// statistics of past issues right, but under an address made up for each
+/// delivery and tied to no subscriber. Their consent records stay too, as
+/// proof of what they agreed to, without the address and browser they agreed
+/// from. Issues not sent to them yet are dropped. A hash of their address is
+/// kept for imports to refuse it, see [`erased_addresses`].
+///
+/// Their audit trail stays as well, without the notes that could repeat
+/// their tags or attributes.
+#[tracing::instrument(skip(transaction, subscriber_ids))]
+pub async fn erase_subscribers(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_ids: &[Uuid],
+) -> Result<Vec<Uuid>, sqlx::Error> {
+    let (ids, emails): (Vec<Uuid>, Vec<String>) = sqlx::query!(
+        "SELECT id, email FROM subscriptions WHERE id = ANY($1) FOR UPDATE",
+        subscriber_ids
+    )
+    .fetch_all(&mut **transaction)
+    .await?
+    .into_iter()
+    .map(|r| (r.id, r.email))
+    .unzip();
+    if ids.is_empty() {
+        return Ok(ids);
+    }
+    sqlx::query!(
+        r#"
+        INSERT INTO erased_subscribers (email_hash, erased_at)
+        SELECT email_hash(email), now()
+        FROM UNNEST($1::TEXT[]) AS email
+        ON CONFLICT (email_hash) DO NOTHING
+        "#,
+        &emails
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    let mut issue_ids = sqlx::query_scalar!(
+        r#"
+        DELETE FROM issue_delivery_queue
+        WHERE subscriber_email = ANY($1)
+        RETURNING newsletter_issue_id
+        "#,
+        &emails
+    )
+    .fetch_all(&mut **transaction)
+    .await?;
+    // Deliveries are matched by address too for the ones recorded before
+    // they were tied to subscribers.
+    sqlx::query!(
+        r#"
+        DELETE FROM issue_deliveries
+        WHERE
+            (subscriber_id = ANY($1) OR (subscriber_id IS NULL AND subscriber_email = ANY($2))) AND
+            status = 'queued'
+        "#,
+        &ids,
+        &emails
+    )
+    .execute(&mut **transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        UPDATE issue_deliveries
+        SET
+            subscriber_email = 'erased-' || gen_random_uuid() || '@invalid',
+            subscriber_id = NULL,
+            provider_message_id = NULL,
+            open_token = NULL,
+            click_token = NULL
+        WHERE subscriber_id = ANY($1) OR (subscriber_id IS NULL AND subscriber_email = ANY($2))
+        "#,
+        &ids,
+        &emails
+    )
+    .execute(&mut **transaction)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM failed_deliveries WHERE subscriber_email = ANY($1)",
+        &emails
+    )
+    .execute(&mut **transaction)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM suppressed_emails WHERE email = ANY($1)",
+        &emails
+    )
+    .execute(&mut **transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        UPDATE consent_records
+        SET ip_address = NULL, user_agent = NULL
+        WHERE subscriber_id = ANY($1)
+        "#,
+        &ids
+    )
+    .execute(&mut **transaction)
+    .await?;
+    sqlx::query!(
+        "UPDATE subscriber_audit_log SET note = NULL WHERE subscriber_id = ANY($1)",
+        &ids
+    )
+    .execute(&mut **transaction)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM subscription_tokens WHERE subscriber_id = ANY($1)",
+        &ids
+    )
+    .execute(&mut **transaction)
+    .await?;
+    // Memberships and pending changes of address go with the subscriber.
+    sqlx::query!("DELETE FROM subscriptions WHERE id = ANY($1)", &ids)
+        .execute(&mut **transaction)
+        .await?;
+
+    // The erased subscribers may have been the last ones an issue waited for.
+    issue_ids.sort();
+    issue_ids.dedup();
+    for issue_id in issue_ids {
+        mark_issue_as_sent_if_done(transaction, issue_id).await?;
+    }
+    Ok(ids)
+}
+
+/// The addresses among `emails` whose owner had their data erased.
+pub async fn erased_addresses(
+    transaction: &mut Transaction<'_, Postgres>,
+    emails: &[&str],
+) -> Result<HashSet<String>, sqlx::Error> {
+    let erased = sqlx::query_scalar!(
+        r#"
+        SELECT email AS "email!"
+        FROM UNNEST($1::TEXT[]) AS email
+        WHERE EXISTS (SELECT 1 FROM erased_subscribers WHERE email_hash = email_hash(email))
+        "#,
+        emails as &[&str]
+    )
+    .fetch_all(&mut **transaction)
+    .await?;
+    Ok(erased.into_iter().collect())
+}
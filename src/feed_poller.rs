@@ -0,0 +1,263 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use chrono::{Duration, Utc};
+use reqwest::Client;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    configuration::{FeedIssueMode, FeedPollerSettings},
+    routes::admin::issues::{IssueContent, insert_issue},
+    templates::{render, strip_tags},
+    utils::escape_html,
+};
+
+#[derive(Debug)]
+pub enum FeedError {
+    Fetch(String),
+    Parse(String),
+    Database(sqlx::Error),
+}
+
+impl From<sqlx::Error> for FeedError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Database(e)
+    }
+}
+
+/// Where the poller gets the raw feed from.
+pub trait FeedFetcher: Send + Sync {
+    fn fetch(&self) -> impl Future<Output = Result<String, FeedError>> + Send;
+}
+
+pub struct HttpFeedFetcher {
+    http_client: Client,
+    url: String,
+}
+
+impl HttpFeedFetcher {
+    pub fn new(url: String, timeout: std::time::Duration) -> Self {
+        let http_client = Client::builder().timeout(timeout).build().unwrap();
+        Self { http_client, url }
+    }
+}
+
+impl FeedFetcher for HttpFeedFetcher {
+    async fn fetch(&self) -> Result<String, FeedError> {
+        self.http_client
+            .get(&self.url)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| FeedError::Fetch(e.to_string()))?
+            .text()
+            .await
+            .map_err(|e| FeedError::Fetch(e.to_string()))
+    }
+}
+
+pub struct FileFeedFetcher {
+    path: PathBuf,
+}
+
+impl FileFeedFetcher {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl FeedFetcher for FileFeedFetcher {
+    async fn fetch(&self) -> Result<String, FeedError> {
+        tokio::fs::read_to_string(&self.path)
+            .await
+            .map_err(|e| FeedError::Fetch(e.to_string()))
+    }
+}
+
+/// The fetcher picked by the `source` of the configuration.
+pub enum ConfiguredFetcher {
+    Http(HttpFeedFetcher),
+    File(FileFeedFetcher),
+}
+
+impl ConfiguredFetcher {
+    pub fn from_source(source: &str) -> Self {
+        if source.starts_with("http://") || source.starts_with("https://") {
+            Self::Http(HttpFeedFetcher::new(
+                source.to_owned(),
+                std::time::Duration::from_secs(30),
+            ))
+        } else {
+            Self::File(FileFeedFetcher::new(source))
+        }
+    }
+}
+
+impl FeedFetcher for ConfiguredFetcher {
+    async fn fetch(&self) -> Result<String, FeedError> {
+        match self {
+            Self::Http(fetcher) => fetcher.fetch().await,
+            Self::File(fetcher) => fetcher.fetch().await,
+        }
+    }
+}
+
+pub struct FeedPoller<F> {
+    fetcher: F,
+    settings: FeedPollerSettings,
+}
+
+impl<F: FeedFetcher> FeedPoller<F> {
+    pub fn new(fetcher: F, settings: FeedPollerSettings) -> Self {
+        Self { fetcher, settings }
+    }
+
+    /// Fetch the feed once and turn the items we have not seen yet into
+    /// issues. Returns the ids of the issues created.
+    #[tracing::instrument(skip_all, fields(feed_source = %self.settings.source))]
+    pub async fn poll(&self, pool: &PgPool) -> Result<Vec<Uuid>, FeedError> {
+        let raw = self.fetcher.fetch().await?;
+        let channel =
+            rss::Channel::read_from(raw.as_bytes()).map_err(|e| FeedError::Parse(e.to_string()))?;
+
+        let mut transaction = pool.begin().await?;
+        // Replicas polling the same feed would otherwise both create an
+        // issue for a new item.
+        sqlx::query!(
+            "SELECT pg_advisory_xact_lock(hashtext($1))",
+            self.settings.source
+        )
+        .execute(&mut *transaction)
+        .await?;
+        let first_poll = !self.has_seen_items(&mut transaction).await?;
+        let mut created = Vec::new();
+        // Feeds list the most recent posts first: create their issues in
+        // publication order instead.
+        for item in channel.items().iter().rev() {
+            let Some(guid) = item_guid(item) else {
+                tracing::warn!("Skipping a feed item without guid, link or title");
+                continue;
+            };
+            if self.is_processed(&mut transaction, &guid).await? {
+                continue;
+            }
+            let issue_id = if first_poll && !self.settings.import_existing_items {
+                None
+            } else {
+                Some(self.create_issue(&mut transaction, item).await?)
+            };
+            self.mark_processed(&mut transaction, &guid, issue_id)
+                .await?;
+            created.extend(issue_id);
+        }
+        transaction.commit().await?;
+        if !created.is_empty() {
+            tracing::info!("Created {} issue(s) from new feed items", created.len());
+        }
+        Ok(created)
+    }
+
+    async fn create_issue(
+        &self,
+        transaction: &mut Transaction<'_, Postgres>,
+        item: &rss::Item,
+    ) -> Result<Uuid, FeedError> {
+        let title = item.title().unwrap_or("New post").to_owned();
+        let link = item.link().unwrap_or_default();
+        // Prefer the full post over its summary when the feed has both.
+        let content = item.content().or(item.description()).unwrap_or_default();
+
+        let html_variables = HashMap::from([
+            ("title", escape_html(&title)),
+            ("link", escape_html(link)),
+            ("content", content.to_owned()),
+        ]);
+        let text_variables = HashMap::from([
+            ("title", title.clone()),
+            ("link", link.to_owned()),
+            ("content", strip_tags(content)),
+        ]);
+        let issue = IssueContent {
+            html_content: render(&self.settings.html_template, &html_variables),
+            text_content: render(&self.settings.text_template, &text_variables),
+            title,
+        };
+        let send_at = match self.settings.mode {
+            FeedIssueMode::Draft => None,
+            FeedIssueMode::Scheduled => {
+                Some(Utc::now() + Duration::minutes(self.settings.send_delay_minutes))
+            }
+        };
+        Ok(insert_issue(transaction, &issue, None, send_at, None).await?)
+    }
+
+    async fn has_seen_items(
+        &self,
+        transaction: &mut Transaction<'_, Postgres>,
+    ) -> Result<bool, sqlx::Error> {
+        let seen = sqlx::query_scalar!(
+            r#"SELECT EXISTS(SELECT 1 FROM feed_items WHERE feed_source = $1) AS "seen!""#,
+            self.settings.source
+        )
+        .fetch_one(&mut **transaction)
+        .await?;
+        Ok(seen)
+    }
+
+    async fn is_processed(
+        &self,
+        transaction: &mut Transaction<'_, Postgres>,
+        guid: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let processed = sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS(
+                SELECT 1 FROM feed_items WHERE feed_source = $1 AND guid = $2
+            ) AS "processed!"
+            "#,
+            self.settings.source,
+            guid
+        )
+        .fetch_one(&mut **transaction)
+        .await?;
+        Ok(processed)
+    }
+
+    async fn mark_processed(
+        &self,
+        transaction: &mut Transaction<'_, Postgres>,
+        guid: &str,
+        issue_id: Option<Uuid>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO feed_items (feed_source, guid, newsletter_issue_id, processed_at)
+            VALUES ($1, $2, $3, now())
+            "#,
+            self.settings.source,
+            guid,
+            issue_id
+        )
+        .execute(&mut **transaction)
+        .await?;
+        Ok(())
+    }
+}
+
+/// Not every feed sets `<guid>`: fall back on the link, then on the title.
+fn item_guid(item: &rss::Item) -> Option<String> {
+    item.guid()
+        .map(|guid| guid.value())
+        .or(item.link())
+        .or(item.title())
+        .map(str::to_owned)
+}
+
+pub async fn run_feed_poller_until_stopped<F: FeedFetcher>(pool: PgPool, poller: FeedPoller<F>) {
+    loop {
+        if let Err(e) = poller.poll(&pool).await {
+            tracing::error!("Failed to poll the feed: {:?}", e);
+        }
+        tokio::time::sleep(poller.settings.interval()).await;
+    }
+}
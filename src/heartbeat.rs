@@ -0,0 +1,29 @@
+use std::{future::Future, time::Duration};
+
+/// How often a background job records that it is still running.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+/// How long a job can go without a heartbeat before it is taken for dead:
+/// the process running it stopped. Generous, as a busy database can hold a
+/// heartbeat back.
+pub const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Call `beat` every [`HEARTBEAT_INTERVAL`] for as long as it tells the job
+/// is still ours to run. Failed heartbeats are logged and tried again.
+///
+/// Run it alongside a job, stopping the job when this returns: the job was
+/// given up on by another process in the meantime.
+pub async fn keep_alive<F, Fut>(mut beat: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<bool, sqlx::Error>>,
+{
+    loop {
+        tokio::time::sleep(HEARTBEAT_INTERVAL).await;
+        match beat().await {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(e) => tracing::error!("Failed to record a heartbeat: {:?}", e),
+        }
+    }
+}
@@ -0,0 +1,423 @@
+use std::{collections::HashMap, time::Duration};
+
+use sqlx::{PgPool, Postgres, Transaction, types::Json};
+use tracing::{Span, field::display};
+use uuid::Uuid;
+
+use crate::{
+    attributes::{AttributeSchema, template_variables},
+    delivery_log::{record_failed, record_sent, record_suppressed},
+    domain::SubscriberEmail,
+    email_client::{EmailClient, SendErrorKind},
+    lists::sender,
+    templates::substitute,
+    tracking::{Tracker, inject_open_pixel},
+    utils::generate_token,
+    utm::{DEFAULT_MEDIUM, DEFAULT_SOURCE, UtmParameters},
+};
+
+/// How many times a delivery is attempted before it is dead-lettered.
+const MAX_ATTEMPTS: i16 = 5;
+/// The delay before the first retry, doubled on each following one.
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+pub enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue,
+}
+
+pub async fn run_worker_until_stopped(pool: PgPool, email_client: EmailClient, tracker: Tracker) {
+    loop {
+        match try_execute_task(&pool, &email_client, &tracker).await {
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+            }
+            Err(_) => {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+        }
+    }
+}
+
+#[tracing::instrument(
+    skip_all,
+    fields(newsletter_issue_id=tracing::field::Empty, subscriber_email=tracing::field::Empty),
+    err(Debug)
+)]
+pub async fn try_execute_task(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    tracker: &Tracker,
+) -> Result<ExecutionOutcome, sqlx::Error> {
+    let task = dequeue_task(pool).await?;
+    let Some((mut transaction, task)) = task else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    Span::current()
+        .record("newsletter_issue_id", display(task.newsletter_issue_id))
+        .record("subscriber_email", display(&task.subscriber_email));
+
+    // The address might have bounced or complained since the issue was queued.
+    if is_suppressed(pool, &task.subscriber_email).await? {
+        tracing::info!("Skipping a suppressed email address");
+        record_suppressed(
+            &mut *transaction,
+            task.newsletter_issue_id,
+            &task.subscriber_email,
+        )
+        .await?;
+        delete_task(&mut transaction, &task).await?;
+        mark_issue_as_sent_if_done(&mut transaction, task.newsletter_issue_id).await?;
+        transaction.commit().await?;
+        return Ok(ExecutionOutcome::TaskCompleted);
+    }
+
+    let issue = get_issue(pool, task.newsletter_issue_id, task.variant).await?;
+    let recipient = get_recipient(pool, &task.subscriber_email).await?;
+    // Subscribers unknown to us (e.g. removed since the issue was queued) are
+    // not tracked either.
+    let tracked = (issue.track_opens || issue.track_clicks)
+        && recipient
+            .as_ref()
+            .is_some_and(|recipient| !recipient.tracking_opt_out);
+    let open_token = (tracked && issue.track_opens).then(generate_token);
+    let click_token = (tracked && issue.track_clicks).then(generate_token);
+    let outcome = match SubscriberEmail::parse(task.subscriber_email.clone()) {
+        Ok(email) => {
+            let (name, attributes) = match &recipient {
+                Some(recipient) => (recipient.name.as_str(), recipient.attributes.clone()),
+                None => ("", Default::default()),
+            };
+            let mut html_content = personalise(
+                &issue.html_content,
+                template_variables(
+                    name,
+                    email.as_ref(),
+                    &attributes,
+                    &issue.attribute_schema,
+                    true,
+                ),
+            );
+            let mut text_content = personalise(
+                &issue.text_content,
+                template_variables(
+                    name,
+                    email.as_ref(),
+                    &attributes,
+                    &issue.attribute_schema,
+                    false,
+                ),
+            );
+            if let Some(utm) = &issue.utm {
+                html_content = utm.tag_html(&html_content, tracker.base_url());
+                text_content = utm.tag_text(&text_content, tracker.base_url());
+            }
+            if let Some(token) = &click_token {
+                html_content = tracker.track_clicks(&html_content, token);
+            }
+            if let Some(token) = &open_token {
+                html_content = inject_open_pixel(&html_content, &tracker.open_pixel_url(token));
+            }
+            email_client
+                .send_email_from(
+                    issue.sender.as_deref(),
+                    email,
+                    &issue.subject,
+                    &html_content,
+                    &text_content,
+                )
+                .await
+                .map_err(|e| {
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "Failed to deliver issue to a confirmed subscriber.",
+                    );
+                    (SendErrorKind::classify(&e), e.to_string())
+                })
+        }
+        Err(e) => {
+            tracing::error!(
+                error.message = %e,
+                "Skipping a confirmed subscriber. Their stored contact details are invalid",
+            );
+            Err((SendErrorKind::InvalidRecipient, e))
+        }
+    };
+    match outcome {
+        Ok(message_id) => {
+            record_sent(
+                &mut *transaction,
+                task.newsletter_issue_id,
+                &task.subscriber_email,
+                message_id.as_deref(),
+                open_token.as_deref(),
+                click_token.as_deref(),
+            )
+            .await?;
+            delete_task(&mut transaction, &task).await?;
+        }
+        Err((kind, _)) if kind.is_retryable() && task.n_retries + 1 < MAX_ATTEMPTS => {
+            postpone_task(&mut transaction, &task).await?
+        }
+        Err((kind, error)) => {
+            dead_letter_task(&mut transaction, &task, kind, &error).await?;
+            record_failed(
+                &mut *transaction,
+                task.newsletter_issue_id,
+                &task.subscriber_email,
+            )
+            .await?;
+            delete_task(&mut transaction, &task).await?;
+        }
+    }
+    mark_issue_as_sent_if_done(&mut transaction, task.newsletter_issue_id).await?;
+    transaction.commit().await?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+struct Task {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+    n_retries: i16,
+    /// The subject variant to send, when the issue is being A/B tested.
+    variant: Option<i16>,
+}
+
+type PgTransaction = Transaction<'static, Postgres>;
+
+#[tracing::instrument(skip_all)]
+async fn dequeue_task(pool: &PgPool) -> Result<Option<(PgTransaction, Task)>, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let task = sqlx::query_as!(
+        Task,
+        r#"
+        SELECT newsletter_issue_id, subscriber_email, n_retries, variant
+        FROM issue_delivery_queue
+        WHERE execute_after <= now()
+        FOR UPDATE
+        SKIP LOCKED
+        LIMIT 1
+        "#,
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    Ok(task.map(|task| (transaction, task)))
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_task(transaction: &mut PgTransaction, task: &Task) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+/// Put a task back in the queue, to be attempted again after an exponential
+/// backoff.
+#[tracing::instrument(skip_all)]
+async fn postpone_task(transaction: &mut PgTransaction, task: &Task) -> Result<(), sqlx::Error> {
+    let delay = FIRST_RETRY_DELAY * 2u32.pow(task.n_retries as u32);
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET
+            n_retries = n_retries + 1,
+            execute_after = now() + make_interval(secs => $3)
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        delay.as_secs_f64()
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+/// Record a delivery that will not be attempted again unless an admin asks.
+#[tracing::instrument(skip_all)]
+async fn dead_letter_task(
+    transaction: &mut PgTransaction,
+    task: &Task,
+    kind: SendErrorKind,
+    error: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO failed_deliveries (
+            failed_delivery_id,
+            newsletter_issue_id,
+            subscriber_email,
+            error_kind,
+            last_error,
+            n_attempts,
+            failed_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, now())
+        ON CONFLICT (newsletter_issue_id, subscriber_email) DO UPDATE
+        SET
+            error_kind = EXCLUDED.error_kind,
+            last_error = EXCLUDED.last_error,
+            n_attempts = failed_deliveries.n_attempts + EXCLUDED.n_attempts,
+            failed_at = EXCLUDED.failed_at
+        "#,
+        Uuid::new_v4(),
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        kind.as_str(),
+        error,
+        task.n_retries + 1
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+/// Flip an issue from `sending` to `sent` once its last queued delivery is
+/// gone. An issue under A/B test still has to go out to the rest of its
+/// recipients.
+#[tracing::instrument(skip_all)]
+pub async fn mark_issue_as_sent_if_done(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'sent', sent_at = COALESCE(sent_at, now())
+        WHERE
+            newsletter_issue_id = $1 AND
+            status = 'sending' AND
+            (ab_test_percentage IS NULL OR winning_variant IS NOT NULL) AND
+            NOT EXISTS (
+                SELECT 1 FROM issue_delivery_queue WHERE newsletter_issue_id = $1
+            )
+        "#,
+        issue_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn is_suppressed(pool: &PgPool, email: &str) -> Result<bool, sqlx::Error> {
+    let suppressed = sqlx::query_scalar!(
+        r#"SELECT EXISTS(SELECT 1 FROM suppressed_emails WHERE email = $1) AS "suppressed!""#,
+        email
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(suppressed)
+}
+
+/// Address the content of an issue to a subscriber. Anything that looks like
+/// a placeholder but names no variable is part of the content and kept.
+pub fn personalise(content: &str, variables: HashMap<String, String>) -> String {
+    let variables: HashMap<&str, String> = variables
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.clone()))
+        .collect();
+    substitute(content, &variables)
+}
+
+/// What the content of an issue is personalised with.
+struct Recipient {
+    name: String,
+    tracking_opt_out: bool,
+    attributes: serde_json::Map<String, serde_json::Value>,
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_recipient(pool: &PgPool, email: &str) -> Result<Option<Recipient>, sqlx::Error> {
+    let recipient = sqlx::query!(
+        "SELECT name, tracking_opt_out, attributes FROM subscriptions WHERE email = $1",
+        email
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(recipient.map(|r| Recipient {
+        name: r.name,
+        tracking_opt_out: r.tracking_opt_out,
+        attributes: match r.attributes {
+            serde_json::Value::Object(attributes) => attributes,
+            _ => Default::default(),
+        },
+    }))
+}
+
+struct NewsletterIssue {
+    /// `None` when the list uses the application's sender.
+    sender: Option<String>,
+    subject: String,
+    text_content: String,
+    html_content: String,
+    track_opens: bool,
+    track_clicks: bool,
+    /// `None` when the links of the issue are not tagged.
+    utm: Option<UtmParameters>,
+    /// The attributes declared by the list the issue goes to.
+    attribute_schema: AttributeSchema,
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_issue(
+    pool: &PgPool,
+    issue_id: Uuid,
+    variant: Option<i16>,
+) -> Result<NewsletterIssue, sqlx::Error> {
+    let issue = sqlx::query!(
+        r#"
+        SELECT
+            i.slug,
+            l.sender_email,
+            l.sender_name,
+            COALESCE(
+                (
+                    SELECT subject FROM issue_subject_variants
+                    WHERE newsletter_issue_id = $1 AND variant = $2
+                ),
+                i.title
+            ) AS "subject!",
+            i.text_content, i.html_content, i.track_opens, i.track_clicks,
+            i.utm_tagging, i.utm_source, i.utm_medium, i.utm_campaign,
+            l.attribute_schema AS "attribute_schema: Json<AttributeSchema>"
+        FROM newsletter_issues i
+        JOIN lists l ON l.list_id = i.list_id
+        WHERE
+            i.newsletter_issue_id = $1
+        "#,
+        issue_id,
+        variant
+    )
+    .fetch_one(pool)
+    .await?;
+    let utm = issue.utm_tagging.then(|| UtmParameters {
+        source: issue.utm_source.unwrap_or_else(|| DEFAULT_SOURCE.into()),
+        medium: issue.utm_medium.unwrap_or_else(|| DEFAULT_MEDIUM.into()),
+        campaign: issue.utm_campaign.unwrap_or(issue.slug),
+    });
+    Ok(NewsletterIssue {
+        sender: sender(issue.sender_email.as_deref(), issue.sender_name.as_deref()),
+        subject: issue.subject,
+        text_content: issue.text_content,
+        html_content: issue.html_content,
+        track_opens: issue.track_opens,
+        track_clicks: issue.track_clicks,
+        utm,
+        attribute_schema: issue.attribute_schema.0,
+    })
+}
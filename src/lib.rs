@@ -1,6 +1,32 @@
+pub mod ab_testing;
+pub mod attributes;
+pub mod audit;
+pub mod authentication;
+pub mod bulk_actions;
+pub mod cli;
 pub mod configuration;
+pub mod consent;
+pub mod delivery_log;
 pub mod domain;
 pub mod email_client;
+pub mod erasure;
+pub mod feed_poller;
+pub mod heartbeat;
+pub mod issue_delivery_worker;
+pub mod links;
+pub mod lists;
+pub mod preferences;
 pub mod routes;
+pub mod scheduler;
+pub mod segments;
+pub mod signing;
 pub mod startup;
+pub mod subscriber_data;
+pub mod subscriber_export;
+pub mod subscriber_import;
 pub mod telemetry;
+pub mod templates;
+pub mod throttle;
+pub mod tracking;
+pub mod utils;
+pub mod utm;
@@ -0,0 +1,167 @@
+use crate::utils::escape_html;
+
+/// Rewrite the value of every quoted `href` attribute of an HTML document.
+///
+/// `rewrite` is given the URL with its HTML entities decoded, and returns its
+/// replacement, or `None` to leave the attribute untouched.
+pub fn rewrite_hrefs(html: &str, mut rewrite: impl FnMut(&str) -> Option<String>) -> String {
+    let lowercase = html.to_ascii_lowercase();
+    let mut rewritten = String::with_capacity(html.len());
+    let mut position = 0;
+    while let Some(offset) = lowercase[position..].find("href") {
+        let start = position + offset;
+        let Some((value_start, value_end)) = attribute_value(html, start) else {
+            rewritten.push_str(&html[position..start + 4]);
+            position = start + 4;
+            continue;
+        };
+        rewritten.push_str(&html[position..value_start]);
+        let value = &html[value_start..value_end];
+        match rewrite(&unescape_html(value)) {
+            Some(replacement) => rewritten.push_str(&escape_html(&replacement)),
+            None => rewritten.push_str(value),
+        }
+        position = value_end;
+    }
+    rewritten.push_str(&html[position..]);
+    rewritten
+}
+
+/// The bounds of the quoted value of the attribute whose name starts at
+/// `name_start`, if it is an `href` attribute.
+fn attribute_value(html: &str, name_start: usize) -> Option<(usize, usize)> {
+    let bytes = html.as_bytes();
+    // `data-href` or `hreflang` are other attributes.
+    if name_start == 0 || !bytes[name_start - 1].is_ascii_whitespace() {
+        return None;
+    }
+    let mut i = name_start + 4;
+    while bytes.get(i)?.is_ascii_whitespace() {
+        i += 1;
+    }
+    if bytes[i] != b'=' {
+        return None;
+    }
+    i += 1;
+    while bytes.get(i)?.is_ascii_whitespace() {
+        i += 1;
+    }
+    let quote = bytes[i];
+    if quote != b'"' && quote != b'\'' {
+        return None;
+    }
+    let value_start = i + 1;
+    let value_end = value_start + html[value_start..].find(quote as char)?;
+    Some((value_start, value_end))
+}
+
+fn unescape_html(input: &str) -> String {
+    input
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+/// Rewrite every web URL appearing in a plain-text document.
+///
+/// URLs end at whitespace or at characters that cannot appear in them
+/// unescaped; trailing punctuation is taken to belong to the sentence.
+pub fn rewrite_text_urls(text: &str, mut rewrite: impl FnMut(&str) -> Option<String>) -> String {
+    let lowercase = text.to_ascii_lowercase();
+    let mut rewritten = String::with_capacity(text.len());
+    let mut position = 0;
+    while let Some(start) = next_url_start(&lowercase, position) {
+        let length = text[start..]
+            .find(|c: char| c.is_whitespace() || matches!(c, '<' | '>' | '"'))
+            .unwrap_or(text.len() - start);
+        let url = text[start..start + length].trim_end_matches(|c| {
+            matches!(c, '.' | ',' | ';' | ':' | '!' | '?' | ')' | ']' | '\'')
+        });
+        rewritten.push_str(&text[position..start]);
+        match rewrite(url) {
+            Some(replacement) => rewritten.push_str(&replacement),
+            None => rewritten.push_str(url),
+        }
+        position = start + url.len();
+    }
+    rewritten.push_str(&text[position..]);
+    rewritten
+}
+
+fn next_url_start(lowercase: &str, from: usize) -> Option<usize> {
+    let http = lowercase[from..].find("http://");
+    let https = lowercase[from..].find("https://");
+    let offset = match (http, https) {
+        (Some(a), Some(b)) => a.min(b),
+        (a, b) => a.or(b)?,
+    };
+    Some(from + offset)
+}
+
+/// Whether a link points to a web page, as opposed to e.g. `mailto:`.
+pub fn is_web_url(url: &str) -> bool {
+    let lowercase = url.trim_start().to_ascii_lowercase();
+    lowercase.starts_with("http://") || lowercase.starts_with("https://")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_web_url, rewrite_hrefs, rewrite_text_urls};
+
+    #[test]
+    fn every_href_is_rewritten() {
+        let html = r#"<a href="https://a.example">A</a> <A HREF = 'https://b.example'>B</A>"#;
+        let rewritten = rewrite_hrefs(html, |url| Some(format!("{}/x", url)));
+        assert_eq!(
+            rewritten,
+            r#"<a href="https://a.example/x">A</a> <A HREF = 'https://b.example/x'>B</A>"#
+        );
+    }
+
+    #[test]
+    fn urls_are_unescaped_then_escaped_again() {
+        let html = r#"<a href="https://a.example/?a=1&amp;b=2">A</a>"#;
+        let mut seen = Vec::new();
+        let rewritten = rewrite_hrefs(html, |url| {
+            seen.push(url.to_owned());
+            Some(format!("{}&c=3", url))
+        });
+        assert_eq!(seen, ["https://a.example/?a=1&b=2"]);
+        assert_eq!(
+            rewritten,
+            r#"<a href="https://a.example/?a=1&amp;b=2&amp;c=3">A</a>"#
+        );
+    }
+
+    #[test]
+    fn other_attributes_and_text_are_left_alone() {
+        let html = r#"<a data-href="https://a.example" hreflang="en">href = "x"</a>"#;
+        assert_eq!(rewrite_hrefs(html, |_| Some("changed".into())), html);
+    }
+
+    #[test]
+    fn links_can_be_kept() {
+        let html = r#"<a href="mailto:me@example.com">Mail</a>"#;
+        assert_eq!(rewrite_hrefs(html, |_| None), html);
+    }
+
+    #[test]
+    fn urls_are_found_in_plain_text() {
+        let text = "Read https://a.example/post?id=1. Or (http://b.example), not ftp://c.example";
+        let rewritten = rewrite_text_urls(text, |url| Some(format!("<{}>", url)));
+        assert_eq!(
+            rewritten,
+            "Read <https://a.example/post?id=1>. Or (<http://b.example>), not ftp://c.example"
+        );
+    }
+
+    #[test]
+    fn only_http_links_are_web_urls() {
+        assert!(is_web_url("https://example.com"));
+        assert!(is_web_url("HTTP://example.com"));
+        assert!(!is_web_url("mailto:me@example.com"));
+        assert!(!is_web_url("#top"));
+    }
+}
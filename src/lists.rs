@@ -0,0 +1,81 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{PgExecutor, types::Json};
+use uuid::Uuid;
+
+use crate::attributes::AttributeSchema;
+
+/// The list subscribers join, and issues go out to, when none is named.
+pub const DEFAULT_LIST: &str = "newsletter";
+
+/// A publication people subscribe to.
+#[derive(Serialize)]
+pub struct List {
+    pub list_id: Uuid,
+    pub slug: String,
+    pub name: String,
+    pub sender_email: Option<String>,
+    pub sender_name: Option<String>,
+    pub double_opt_in: bool,
+    pub confirmation_subject: String,
+    /// The custom attributes the list collects from its subscribers.
+    pub attribute_schema: Json<AttributeSchema>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl List {
+    /// The sender of the emails sent for this list, if it does not use the
+    /// application's.
+    pub fn sender(&self) -> Option<String> {
+        sender(self.sender_email.as_deref(), self.sender_name.as_deref())
+    }
+}
+
+/// Format a `From` address, e.g. `Weekly <weekly@example.com>`.
+pub fn sender(email: Option<&str>, name: Option<&str>) -> Option<String> {
+    let email = email?;
+    Some(match name {
+        Some(name) => format!("{} <{}>", name, email),
+        None => email.to_owned(),
+    })
+}
+
+/// The lists with the given slugs, skipping unknown ones.
+pub async fn find_lists<'e>(
+    executor: impl PgExecutor<'e>,
+    slugs: &[String],
+) -> Result<Vec<List>, sqlx::Error> {
+    sqlx::query_as!(
+        List,
+        r#"
+        SELECT
+            list_id, slug, name, sender_email, sender_name,
+            double_opt_in, confirmation_subject,
+            attribute_schema AS "attribute_schema: Json<AttributeSchema>", created_at
+        FROM lists
+        WHERE slug = ANY($1)
+        ORDER BY created_at, slug
+        "#,
+        slugs
+    )
+    .fetch_all(executor)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::sender;
+
+    #[test]
+    fn the_sender_name_is_optional() {
+        assert_eq!(
+            sender(Some("weekly@example.com"), Some("Weekly")),
+            Some("Weekly <weekly@example.com>".into())
+        );
+        assert_eq!(
+            sender(Some("weekly@example.com"), None),
+            Some("weekly@example.com".into())
+        );
+        assert_eq!(sender(None, Some("Weekly")), None);
+    }
+}
@@ -1,14 +1,26 @@
 use email_newsletter::{
+    cli::run_command,
     configuration::get_configuration,
     startup::Application,
     telemetry::{get_subscriber, init_subscriber},
 };
 #[tokio::main]
 async fn main() {
+    let configuration = get_configuration().expect("Failed to read configuration!");
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        // Commands write their output to stdout: keep the logs out of it.
+        let subscriber = get_subscriber("email_newsletter".into(), "info".into(), std::io::stderr);
+        init_subscriber(subscriber);
+        if let Err(e) = run_command(configuration, &args).await {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let subscriber = get_subscriber("email_newsletter".into(), "info".into(), std::io::stdout);
     init_subscriber(subscriber);
-
-    let configuration = get_configuration().expect("Failed to read configuration!");
     let application = Application::build(configuration)
         .await
         .expect("Failed to build application");
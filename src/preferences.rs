@@ -0,0 +1,113 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::Deserialize;
+use url::Url;
+use uuid::Uuid;
+
+use crate::signing::Signer;
+
+/// How long a link to the preference center stays valid.
+const LINK_LIFETIME: Duration = Duration::hours(24);
+
+/// What a link to the preference center carries: whose preferences they
+/// are, until when, and the signature vouching for both.
+#[derive(Deserialize)]
+pub struct PreferenceToken {
+    pub subscriber_id: Uuid,
+    pub expires: i64,
+    pub sig: String,
+}
+
+/// Builds the magic links giving subscribers access to their preferences,
+/// and checks the ones that come back.
+#[derive(Clone)]
+pub struct PreferenceLinks {
+    base_url: String,
+    signer: Signer,
+}
+
+impl PreferenceLinks {
+    pub fn new(base_url: String, signer: Signer) -> Self {
+        Self { base_url, signer }
+    }
+
+    /// A link to the preferences of a subscriber, valid from `now` on for
+    /// [`LINK_LIFETIME`].
+    pub fn link(&self, subscriber_id: Uuid, now: DateTime<Utc>) -> String {
+        let expires = (now + LINK_LIFETIME).timestamp();
+        let mut link = Url::parse(&format!("{}/preferences", self.base_url))
+            .expect("The base URL is not a valid URL");
+        link.query_pairs_mut()
+            .append_pair("subscriber_id", &subscriber_id.to_string())
+            .append_pair("expires", &expires.to_string())
+            .append_pair("sig", &self.sign(subscriber_id, expires));
+        link.into()
+    }
+
+    /// The token of a link we issued that has not expired yet.
+    pub fn verify(&self, token: &PreferenceToken, now: DateTime<Utc>) -> bool {
+        now.timestamp() < token.expires
+            && self.signer.verify(
+                &preferences_message(token.subscriber_id, token.expires),
+                &token.sig,
+            )
+    }
+
+    fn sign(&self, subscriber_id: Uuid, expires: i64) -> String {
+        self.signer
+            .sign(&preferences_message(subscriber_id, expires))
+    }
+}
+
+fn preferences_message(subscriber_id: Uuid, expires: i64) -> String {
+    format!("preferences\n{}\n{}", subscriber_id, expires)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use secrecy::SecretString;
+    use uuid::Uuid;
+
+    use super::{PreferenceLinks, PreferenceToken};
+    use crate::signing::Signer;
+
+    fn token_of(link: &str) -> PreferenceToken {
+        let query = url::Url::parse(link).unwrap().query().unwrap().to_owned();
+        serde_urlencoded::from_str(&query).unwrap()
+    }
+
+    fn links() -> PreferenceLinks {
+        PreferenceLinks::new(
+            "https://news.example".into(),
+            Signer::new(SecretString::from("secret")),
+        )
+    }
+
+    #[test]
+    fn links_are_valid_until_they_expire() {
+        let links = links();
+        let now = Utc::now();
+        let token = token_of(&links.link(Uuid::new_v4(), now));
+
+        assert!(links.verify(&token, now + Duration::hours(23)));
+        assert!(!links.verify(&token, now + Duration::hours(25)));
+    }
+
+    #[test]
+    fn tampered_links_are_rejected() {
+        let links = links();
+        let now = Utc::now();
+        let token = token_of(&links.link(Uuid::new_v4(), now));
+
+        let other_subscriber = PreferenceToken {
+            subscriber_id: Uuid::new_v4(),
+            ..token_of(&links.link(token.subscriber_id, now))
+        };
+        let extended = PreferenceToken {
+            expires: token.expires + 3600,
+            ..token_of(&links.link(token.subscriber_id, now))
+        };
+        assert!(!links.verify(&other_subscriber, now));
+        assert!(!links.verify(&extended, now));
+    }
+}
@@ -0,0 +1,224 @@
+use std::sync::Arc;
+
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    ab_testing::{AbTestMetric, VariantResults, variant_results},
+    authentication::AdminUser,
+    routes::admin::issues::{ensure_unpublished, record_settings_change},
+    startup::ApplicationState,
+    utils::e500,
+};
+
+/// Beyond this, each variant's share of the sample gets too thin to tell
+/// anything.
+const MAX_VARIANTS: usize = 10;
+
+#[derive(Deserialize)]
+pub struct AbTestSettings {
+    pub subjects: Vec<String>,
+    /// The share of the list, in percent, receiving the variants.
+    pub test_percentage: i16,
+    /// How long to wait after the sample is queued before picking a winner.
+    pub wait_minutes: i32,
+    pub metric: AbTestMetric,
+}
+
+impl AbTestSettings {
+    pub fn is_valid(&self) -> bool {
+        (2..=MAX_VARIANTS).contains(&self.subjects.len())
+            && self.subjects.iter().all(|s| !s.trim().is_empty())
+            && (1..=99).contains(&self.test_percentage)
+            && self.wait_minutes > 0
+    }
+}
+
+#[derive(Serialize)]
+pub struct AbTestReport {
+    pub test_percentage: i16,
+    pub wait_minutes: i32,
+    pub metric: AbTestMetric,
+    /// When the winner gets picked, once the issue is in delivery.
+    pub decide_at: Option<DateTime<Utc>>,
+    pub winning_variant: Option<i16>,
+    pub variants: Vec<VariantResults>,
+}
+
+#[tracing::instrument(
+    name = "Set up an A/B test of subject lines",
+    skip(admin, app_state, body),
+    fields(username = %admin.username, test_percentage = body.test_percentage)
+)]
+pub async fn set_ab_test(
+    admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Path(issue_id): Path<Uuid>,
+    Json(body): Json<AbTestSettings>,
+) -> Result<Json<AbTestReport>, StatusCode> {
+    if !body.is_valid() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let mut transaction = app_state.pool.begin().await.map_err(e500)?;
+    ensure_unpublished(&mut transaction, issue_id).await?;
+    let tracking = sqlx::query!(
+        "SELECT track_opens, track_clicks FROM newsletter_issues WHERE newsletter_issue_id = $1",
+        issue_id
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .map_err(e500)?;
+    if !body
+        .metric
+        .is_measured(tracking.track_opens, tracking.track_clicks)
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            ab_test_percentage = $2,
+            ab_test_wait_minutes = $3,
+            ab_test_metric = $4,
+            updated_at = now()
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id,
+        body.test_percentage,
+        body.wait_minutes,
+        body.metric.as_str()
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(e500)?;
+    delete_variants(&mut transaction, issue_id)
+        .await
+        .map_err(e500)?;
+    let subjects: Vec<String> = body.subjects.iter().map(|s| s.trim().to_owned()).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_subject_variants (newsletter_issue_id, variant, subject)
+        SELECT $1, (ordinality - 1)::SMALLINT, subject
+        FROM UNNEST($2::TEXT[]) WITH ORDINALITY AS s (subject, ordinality)
+        "#,
+        issue_id,
+        &subjects
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(e500)?;
+    record_settings_change(&mut transaction, issue_id, admin.user_id, "A/B test")
+        .await
+        .map_err(e500)?;
+    let report = fetch_report(&mut transaction, issue_id)
+        .await
+        .map_err(e500)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    transaction.commit().await.map_err(e500)?;
+    Ok(Json(report))
+}
+
+#[tracing::instrument(
+    name = "Remove the A/B test of an issue",
+    skip(admin, app_state),
+    fields(username = %admin.username)
+)]
+pub async fn remove_ab_test(
+    admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Path(issue_id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let mut transaction = app_state.pool.begin().await.map_err(e500)?;
+    ensure_unpublished(&mut transaction, issue_id).await?;
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            ab_test_percentage = NULL,
+            ab_test_wait_minutes = NULL,
+            ab_test_metric = NULL,
+            updated_at = now()
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(e500)?;
+    delete_variants(&mut transaction, issue_id)
+        .await
+        .map_err(e500)?;
+    record_settings_change(&mut transaction, issue_id, admin.user_id, "A/B test")
+        .await
+        .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[tracing::instrument(name = "Get the A/B test of an issue", skip(_admin, app_state))]
+pub async fn get_ab_test(
+    _admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Path(issue_id): Path<Uuid>,
+) -> Result<Json<AbTestReport>, StatusCode> {
+    let mut transaction = app_state.pool.begin().await.map_err(e500)?;
+    let report = fetch_report(&mut transaction, issue_id)
+        .await
+        .map_err(e500)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(report))
+}
+
+async fn delete_variants(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM issue_subject_variants WHERE newsletter_issue_id = $1",
+        issue_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+/// `None` when the issue does not exist or is not under A/B test.
+async fn fetch_report(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+) -> Result<Option<AbTestReport>, sqlx::Error> {
+    let settings = sqlx::query!(
+        r#"
+        SELECT
+            ab_test_percentage AS "test_percentage!",
+            ab_test_wait_minutes AS "wait_minutes!",
+            ab_test_metric AS "metric!",
+            ab_test_decide_at,
+            winning_variant
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1 AND ab_test_percentage IS NOT NULL
+        "#,
+        issue_id
+    )
+    .fetch_optional(&mut **transaction)
+    .await?;
+    let Some(settings) = settings else {
+        return Ok(None);
+    };
+    Ok(Some(AbTestReport {
+        test_percentage: settings.test_percentage,
+        wait_minutes: settings.wait_minutes,
+        metric: AbTestMetric::parse(&settings.metric).unwrap_or(AbTestMetric::OpenRate),
+        decide_at: settings.ab_test_decide_at,
+        winning_variant: settings.winning_variant,
+        variants: variant_results(&mut **transaction, issue_id).await?,
+    }))
+}
@@ -0,0 +1,190 @@
+use std::sync::Arc;
+
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    audit::record_issue_event,
+    authentication::AdminUser,
+    routes::admin::issues::{IssueSummary, fetch_issue_summary},
+    scheduler::lock_issue,
+    startup::ApplicationState,
+    utils::e500,
+};
+
+/// A sign-off decision always names the revision it is about, so that an
+/// editor cannot approve content they have not seen.
+#[derive(Deserialize)]
+pub struct Decision {
+    pub revision: i32,
+    pub note: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct AuditEntry {
+    pub revision: Option<i32>,
+    pub actor: Option<String>,
+    pub action: String,
+    pub note: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(
+    name = "Approve a newsletter issue",
+    skip(admin, app_state, body),
+    fields(username = %admin.username, revision = body.revision)
+)]
+pub async fn approve_issue(
+    admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Path(issue_id): Path<Uuid>,
+    Json(body): Json<Decision>,
+) -> Result<Json<IssueSummary>, StatusCode> {
+    let mut transaction = app_state.pool.begin().await.map_err(e500)?;
+    let involved = ensure_pending_decision(&mut transaction, issue_id, body.revision).await?;
+    // The second pair of eyes must belong to someone else. Issues the
+    // application wrote itself, out of a feed, have no author: the admin
+    // approving them is the one reviewing what the feed published.
+    if involved.contains(&admin.user_id) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET approval_status = 'approved', approved_revision = $2, approved_by = $3
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id,
+        body.revision,
+        admin.user_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(e500)?;
+    record_issue_event(
+        &mut *transaction,
+        issue_id,
+        Some(body.revision),
+        Some(admin.user_id),
+        "approved",
+        body.note.as_deref(),
+    )
+    .await
+    .map_err(e500)?;
+    let issue = fetch_issue_summary(&mut transaction, issue_id)
+        .await
+        .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    Ok(Json(issue))
+}
+
+#[tracing::instrument(
+    name = "Reject a newsletter issue",
+    skip(admin, app_state, body),
+    fields(username = %admin.username, revision = body.revision)
+)]
+pub async fn reject_issue(
+    admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Path(issue_id): Path<Uuid>,
+    Json(body): Json<Decision>,
+) -> Result<Json<IssueSummary>, StatusCode> {
+    let mut transaction = app_state.pool.begin().await.map_err(e500)?;
+    ensure_pending_decision(&mut transaction, issue_id, body.revision).await?;
+
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET approval_status = 'rejected', approved_revision = NULL, approved_by = NULL
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(e500)?;
+    record_issue_event(
+        &mut *transaction,
+        issue_id,
+        Some(body.revision),
+        Some(admin.user_id),
+        "rejected",
+        body.note.as_deref(),
+    )
+    .await
+    .map_err(e500)?;
+    let issue = fetch_issue_summary(&mut transaction, issue_id)
+        .await
+        .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    Ok(Json(issue))
+}
+
+#[tracing::instrument(name = "Get the audit trail of an issue", skip(_admin, app_state))]
+pub async fn get_audit_trail(
+    _admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Path(issue_id): Path<Uuid>,
+) -> Result<Json<Vec<AuditEntry>>, StatusCode> {
+    let entries = sqlx::query_as!(
+        AuditEntry,
+        r#"
+        SELECT a.revision, u.username AS "actor?", a.action, a.note, a.created_at
+        FROM issue_audit_log a
+        LEFT JOIN users u ON u.user_id = a.actor
+        WHERE a.newsletter_issue_id = $1
+        ORDER BY a.created_at, a.id
+        "#,
+        issue_id
+    )
+    .fetch_all(&app_state.pool)
+    .await
+    .map_err(e500)?;
+    Ok(Json(entries))
+}
+
+/// Lock the issue and check that a decision about `revision` can still be
+/// taken. Returns the admins who shaped what would be sent: the author of that
+/// revision and whoever last changed the issue's settings.
+async fn ensure_pending_decision(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    revision: i32,
+) -> Result<Vec<Uuid>, StatusCode> {
+    lock_issue(transaction, issue_id).await.map_err(e500)?;
+    let issue = sqlx::query!(
+        r#"
+        SELECT i.status, i.current_revision, i.settings_changed_by, r.created_by
+        FROM newsletter_issues i
+        JOIN newsletter_issue_revisions r
+            ON r.newsletter_issue_id = i.newsletter_issue_id
+            AND r.revision = i.current_revision
+        WHERE i.newsletter_issue_id = $1
+        FOR UPDATE OF i
+        "#,
+        issue_id
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+    .map_err(e500)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+    // Once an issue went out there is nothing left to decide, and decisions
+    // about a superseded revision are meaningless.
+    if !(issue.status == "draft" || issue.status == "scheduled")
+        || issue.current_revision != revision
+    {
+        return Err(StatusCode::CONFLICT);
+    }
+    Ok([issue.created_by, issue.settings_changed_by]
+        .into_iter()
+        .flatten()
+        .collect())
+}
@@ -0,0 +1,170 @@
+use std::sync::Arc;
+
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    attributes::normalize_tags,
+    authentication::AdminUser,
+    bulk_actions::{
+        BulkAction, BulkActionReport, BulkTargets, bulk_action_report, create_bulk_action,
+        run_bulk_action,
+    },
+    lists::find_lists,
+    segments::Filter,
+    startup::ApplicationState,
+    utils::e500,
+};
+
+/// A bulk action and the subscribers it applies to: exactly one of
+/// `subscriber_ids`, `segment_id` and `filter`.
+#[derive(Deserialize)]
+pub struct BulkActionRequest {
+    #[serde(flatten)]
+    pub action: BulkAction,
+    pub subscriber_ids: Option<Vec<Uuid>>,
+    /// The segment whose filter selects the subscribers.
+    pub segment_id: Option<Uuid>,
+    /// A filter selecting the subscribers, see [`Filter`] for the language.
+    pub filter: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct BulkActionStarted {
+    pub bulk_action_id: Uuid,
+}
+
+#[derive(Serialize)]
+pub struct SubscriberAuditEntry {
+    pub actor: Option<String>,
+    pub action: String,
+    pub note: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+fn parse_filter(filter: &str) -> Result<Filter, StatusCode> {
+    Filter::parse(filter).map_err(|e| {
+        tracing::info!(error = %e, "Invalid segment filter");
+        StatusCode::BAD_REQUEST
+    })
+}
+
+/// Start applying an action to many subscribers in the background. Follow
+/// its progress with [`get_bulk_action`].
+#[tracing::instrument(
+    name = "Start a bulk action",
+    skip(admin, app_state, body),
+    fields(username = %admin.username, action = body.action.name())
+)]
+pub async fn start_bulk_action(
+    admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Json(body): Json<BulkActionRequest>,
+) -> Result<(StatusCode, Json<BulkActionStarted>), StatusCode> {
+    let action = match body.action {
+        BulkAction::Tag { tag } => BulkAction::Tag {
+            tag: normalize_tag(tag)?,
+        },
+        BulkAction::Untag { tag } => BulkAction::Untag {
+            tag: normalize_tag(tag)?,
+        },
+        BulkAction::MoveToList { list } => {
+            let lists = find_lists(&app_state.pool, std::slice::from_ref(&list))
+                .await
+                .map_err(e500)?;
+            if lists.is_empty() {
+                return Err(StatusCode::BAD_REQUEST);
+            }
+            BulkAction::MoveToList { list }
+        }
+        action => action,
+    };
+    let targets = match (body.subscriber_ids, body.segment_id, body.filter) {
+        (Some(ids), None, None) => BulkTargets::Ids(ids),
+        (None, Some(segment_id), None) => {
+            let filter = sqlx::query_scalar!(
+                "SELECT filter FROM segments WHERE segment_id = $1",
+                segment_id
+            )
+            .fetch_optional(&app_state.pool)
+            .await
+            .map_err(e500)?
+            .ok_or(StatusCode::BAD_REQUEST)?;
+            BulkTargets::Filter(parse_filter(&filter)?)
+        }
+        (None, None, Some(filter)) => BulkTargets::Filter(parse_filter(&filter)?),
+        _ => return Err(StatusCode::BAD_REQUEST),
+    };
+    let bulk_action_id = create_bulk_action(&app_state.pool, admin.user_id, &action, &targets)
+        .await
+        .map_err(e500)?;
+
+    tokio::spawn(async move {
+        if let Err(e) = run_bulk_action(
+            &app_state.pool,
+            &app_state.email_client,
+            &app_state.base_url.0,
+            bulk_action_id,
+            &action,
+        )
+        .await
+        {
+            tracing::error!("Failed to record the outcome of a bulk action: {:?}", e);
+        }
+    });
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(BulkActionStarted { bulk_action_id }),
+    ))
+}
+
+#[tracing::instrument(name = "Get a bulk action", skip(_admin, app_state))]
+pub async fn get_bulk_action(
+    _admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Path(bulk_action_id): Path<Uuid>,
+) -> Result<Json<BulkActionReport>, StatusCode> {
+    let report = bulk_action_report(&app_state.pool, bulk_action_id)
+        .await
+        .map_err(e500)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(report))
+}
+
+/// What was done to a subscriber, oldest first. The trail outlives the
+/// subscriber.
+#[tracing::instrument(name = "Get the audit trail of a subscriber", skip(_admin, app_state))]
+pub async fn get_subscriber_audit_trail(
+    _admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Path(subscriber_id): Path<Uuid>,
+) -> Result<Json<Vec<SubscriberAuditEntry>>, StatusCode> {
+    let entries = sqlx::query_as!(
+        SubscriberAuditEntry,
+        r#"
+        SELECT u.username AS "actor?", a.action, a.note, a.created_at
+        FROM subscriber_audit_log a
+        LEFT JOIN users u ON u.user_id = a.actor
+        WHERE a.subscriber_id = $1
+        ORDER BY a.created_at, a.id
+        "#,
+        subscriber_id
+    )
+    .fetch_all(&app_state.pool)
+    .await
+    .map_err(e500)?;
+    Ok(Json(entries))
+}
+
+fn normalize_tag(tag: String) -> Result<String, StatusCode> {
+    normalize_tags(vec![tag])
+        .map_err(|_| StatusCode::BAD_REQUEST)?
+        .pop()
+        .ok_or(StatusCode::BAD_REQUEST)
+}
@@ -0,0 +1,166 @@
+use std::sync::Arc;
+
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{authentication::AdminUser, startup::ApplicationState, utils::e500};
+
+/// How many recipients an issue reached, by delivery status.
+#[derive(Serialize)]
+pub struct DeliveryStats {
+    pub newsletter_issue_id: Uuid,
+    pub total: i64,
+    pub queued: i64,
+    pub sent: i64,
+    pub failed: i64,
+    pub bounced: i64,
+    pub suppressed: i64,
+    /// Recipients who opened the issue at least once. Only tracked
+    /// deliveries count.
+    pub opened: i64,
+    pub total_opens: i64,
+    /// Recipients who clicked at least one link. Only tracked deliveries
+    /// count.
+    pub clicked: i64,
+    pub total_clicks: i64,
+}
+
+/// How often a link of an issue was clicked.
+#[derive(Serialize)]
+pub struct LinkClicks {
+    pub url: String,
+    pub clicks: i64,
+    pub unique_clicks: i64,
+}
+
+#[derive(Serialize)]
+pub struct DeliveryRecord {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub subscriber_email: String,
+    pub status: String,
+    pub provider_message_id: Option<String>,
+    pub queued_at: DateTime<Utc>,
+    pub sent_at: Option<DateTime<Utc>>,
+    pub failed_at: Option<DateTime<Utc>>,
+    pub bounced_at: Option<DateTime<Utc>>,
+    pub first_opened_at: Option<DateTime<Utc>>,
+    pub open_count: i32,
+}
+
+#[tracing::instrument(name = "Get the delivery stats of an issue", skip(_admin, app_state))]
+pub async fn issue_delivery_stats(
+    _admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Path(issue_id): Path<Uuid>,
+) -> Result<Json<DeliveryStats>, StatusCode> {
+    let stats = sqlx::query_as!(
+        DeliveryStats,
+        r#"
+        SELECT
+            i.newsletter_issue_id,
+            COUNT(d.status) AS "total!",
+            COUNT(*) FILTER (WHERE d.status = 'queued') AS "queued!",
+            COUNT(*) FILTER (WHERE d.status = 'sent') AS "sent!",
+            COUNT(*) FILTER (WHERE d.status = 'failed') AS "failed!",
+            COUNT(*) FILTER (WHERE d.status = 'bounced') AS "bounced!",
+            COUNT(*) FILTER (WHERE d.status = 'suppressed') AS "suppressed!",
+            COUNT(d.first_opened_at) AS "opened!",
+            COALESCE(SUM(d.open_count), 0) AS "total_opens!",
+            COUNT(*) FILTER (WHERE d.clicks > 0) AS "clicked!",
+            COALESCE(SUM(d.clicks), 0)::BIGINT AS "total_clicks!"
+        FROM newsletter_issues i
+        LEFT JOIN (
+            SELECT
+                d.*,
+                (
+                    SELECT COUNT(*) FROM link_clicks c
+                    WHERE
+                        c.newsletter_issue_id = d.newsletter_issue_id AND
+                        c.subscriber_email = d.subscriber_email
+                ) AS clicks
+            FROM issue_deliveries d
+        ) d ON d.newsletter_issue_id = i.newsletter_issue_id
+        WHERE i.newsletter_issue_id = $1
+        GROUP BY i.newsletter_issue_id
+        "#,
+        issue_id
+    )
+    .fetch_optional(&app_state.pool)
+    .await
+    .map_err(e500)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(stats))
+}
+
+#[tracing::instrument(name = "Get the link clicks of an issue", skip(_admin, app_state))]
+pub async fn issue_link_clicks(
+    _admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Path(issue_id): Path<Uuid>,
+) -> Result<Json<Vec<LinkClicks>>, StatusCode> {
+    let clicks = sqlx::query_as!(
+        LinkClicks,
+        r#"
+        SELECT
+            url,
+            COUNT(*) AS "clicks!",
+            COUNT(DISTINCT subscriber_email) AS "unique_clicks!"
+        FROM link_clicks
+        WHERE newsletter_issue_id = $1
+        GROUP BY url
+        ORDER BY COUNT(*) DESC, url
+        "#,
+        issue_id
+    )
+    .fetch_all(&app_state.pool)
+    .await
+    .map_err(e500)?;
+    Ok(Json(clicks))
+}
+
+#[tracing::instrument(
+    name = "Get the delivery history of a subscriber",
+    skip(_admin, app_state)
+)]
+pub async fn subscriber_delivery_history(
+    _admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Path(subscriber_id): Path<Uuid>,
+) -> Result<Json<Vec<DeliveryRecord>>, StatusCode> {
+    let known = sqlx::query_scalar!(
+        r#"SELECT EXISTS(SELECT 1 FROM subscriptions WHERE id = $1) AS "known!""#,
+        subscriber_id
+    )
+    .fetch_one(&app_state.pool)
+    .await
+    .map_err(e500)?;
+    if !known {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let history = sqlx::query_as!(
+        DeliveryRecord,
+        r#"
+        SELECT
+            d.newsletter_issue_id, i.title, d.subscriber_email, d.status,
+            d.provider_message_id, d.queued_at, d.sent_at, d.failed_at, d.bounced_at,
+            d.first_opened_at, d.open_count
+        FROM issue_deliveries d
+        JOIN newsletter_issues i ON i.newsletter_issue_id = d.newsletter_issue_id
+        WHERE d.subscriber_id = $1
+        ORDER BY d.queued_at DESC
+        "#,
+        subscriber_id
+    )
+    .fetch_all(&app_state.pool)
+    .await
+    .map_err(e500)?;
+    Ok(Json(history))
+}
@@ -0,0 +1,238 @@
+use std::sync::Arc;
+
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    authentication::AdminUser, delivery_log::record_requeued, startup::ApplicationState,
+    utils::e500,
+};
+
+#[derive(Serialize)]
+pub struct FailedDelivery {
+    pub failed_delivery_id: Uuid,
+    pub newsletter_issue_id: Uuid,
+    pub subscriber_email: String,
+    pub error_kind: String,
+    pub last_error: String,
+    pub n_attempts: i16,
+    pub failed_at: DateTime<Utc>,
+}
+
+/// Which failed deliveries a listing or a bulk action applies to. Missing
+/// criteria match everything.
+#[derive(Deserialize, Debug, Default)]
+pub struct FailedDeliveryFilter {
+    pub newsletter_issue_id: Option<Uuid>,
+    pub error_kind: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct BulkOutcome {
+    pub affected: usize,
+}
+
+#[tracing::instrument(name = "List failed deliveries", skip(_admin, app_state))]
+pub async fn list_failed_deliveries(
+    _admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Query(filter): Query<FailedDeliveryFilter>,
+) -> Result<Json<Vec<FailedDelivery>>, StatusCode> {
+    let failed = sqlx::query_as!(
+        FailedDelivery,
+        r#"
+        SELECT
+            failed_delivery_id, newsletter_issue_id, subscriber_email,
+            error_kind, last_error, n_attempts, failed_at
+        FROM failed_deliveries
+        WHERE
+            ($1::uuid IS NULL OR newsletter_issue_id = $1) AND
+            ($2::TEXT IS NULL OR error_kind = $2)
+        ORDER BY failed_at DESC
+        "#,
+        filter.newsletter_issue_id,
+        filter.error_kind
+    )
+    .fetch_all(&app_state.pool)
+    .await
+    .map_err(e500)?;
+    Ok(Json(failed))
+}
+
+#[tracing::instrument(name = "Retry a failed delivery", skip(_admin, app_state))]
+pub async fn retry_failed_delivery(
+    _admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Path(failed_delivery_id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let mut transaction = app_state.pool.begin().await.map_err(e500)?;
+    let exists = sqlx::query_scalar!(
+        r#"
+        SELECT EXISTS(
+            SELECT 1 FROM failed_deliveries WHERE failed_delivery_id = $1
+        ) AS "exists!"
+        "#,
+        failed_delivery_id
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .map_err(e500)?;
+    if !exists {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let removed = remove_failed_deliveries(
+        &mut transaction,
+        Some(failed_delivery_id),
+        &FailedDeliveryFilter::default(),
+        true,
+    )
+    .await
+    .map_err(e500)?;
+    // The recipient left the issue's audience since: there is nothing to
+    // retry, only to discard.
+    if removed.is_empty() {
+        return Err(StatusCode::CONFLICT);
+    }
+    requeue(&mut transaction, removed).await.map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[tracing::instrument(name = "Retry failed deliveries in bulk", skip(_admin, app_state))]
+pub async fn retry_failed_deliveries(
+    _admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Json(filter): Json<FailedDeliveryFilter>,
+) -> Result<Json<BulkOutcome>, StatusCode> {
+    let mut transaction = app_state.pool.begin().await.map_err(e500)?;
+    let removed = remove_failed_deliveries(&mut transaction, None, &filter, true)
+        .await
+        .map_err(e500)?;
+    let affected = removed.len();
+    requeue(&mut transaction, removed).await.map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    Ok(Json(BulkOutcome { affected }))
+}
+
+#[tracing::instrument(name = "Discard a failed delivery", skip(_admin, app_state))]
+pub async fn discard_failed_delivery(
+    _admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Path(failed_delivery_id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let mut transaction = app_state.pool.begin().await.map_err(e500)?;
+    let removed = remove_failed_deliveries(
+        &mut transaction,
+        Some(failed_delivery_id),
+        &FailedDeliveryFilter::default(),
+        false,
+    )
+    .await
+    .map_err(e500)?;
+    if removed.is_empty() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    transaction.commit().await.map_err(e500)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[tracing::instrument(name = "Discard failed deliveries in bulk", skip(_admin, app_state))]
+pub async fn discard_failed_deliveries(
+    _admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Json(filter): Json<FailedDeliveryFilter>,
+) -> Result<Json<BulkOutcome>, StatusCode> {
+    let mut transaction = app_state.pool.begin().await.map_err(e500)?;
+    let removed = remove_failed_deliveries(&mut transaction, None, &filter, false)
+        .await
+        .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    Ok(Json(BulkOutcome {
+        affected: removed.len(),
+    }))
+}
+
+struct Recipient {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+}
+
+/// Remove the matching failed deliveries, returning their recipients.
+///
+/// With `recipients_only`, the ones whose recipient left the issue's audience
+/// since (unsubscribed, erased, suppressed, ...) are kept: they are not to be
+/// retried.
+async fn remove_failed_deliveries(
+    transaction: &mut Transaction<'_, Postgres>,
+    failed_delivery_id: Option<Uuid>,
+    filter: &FailedDeliveryFilter,
+    recipients_only: bool,
+) -> Result<Vec<Recipient>, sqlx::Error> {
+    sqlx::query_as!(
+        Recipient,
+        r#"
+        DELETE FROM failed_deliveries f
+        WHERE
+            ($1::uuid IS NULL OR f.failed_delivery_id = $1) AND
+            ($2::uuid IS NULL OR f.newsletter_issue_id = $2) AND
+            ($3::TEXT IS NULL OR f.error_kind = $3) AND
+            (
+                NOT $4 OR
+                f.subscriber_email IN (
+                    SELECT subscriber_email FROM issue_recipients(f.newsletter_issue_id)
+                )
+            )
+        RETURNING f.newsletter_issue_id, f.subscriber_email
+        "#,
+        failed_delivery_id,
+        filter.newsletter_issue_id,
+        filter.error_kind,
+        recipients_only
+    )
+    .fetch_all(&mut **transaction)
+    .await
+}
+
+/// Queue the deliveries again, with a fresh retry budget. Issues already
+/// marked as sent go back to sending until the queue drains again.
+async fn requeue(
+    transaction: &mut Transaction<'_, Postgres>,
+    recipients: Vec<Recipient>,
+) -> Result<(), sqlx::Error> {
+    let (issue_ids, emails): (Vec<Uuid>, Vec<String>) = recipients
+        .into_iter()
+        .map(|r| (r.newsletter_issue_id, r.subscriber_email))
+        .unzip();
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email, variant)
+        SELECT r.newsletter_issue_id, r.subscriber_email, d.variant
+        FROM UNNEST($1::uuid[], $2::TEXT[]) AS r (newsletter_issue_id, subscriber_email)
+        LEFT JOIN issue_deliveries d USING (newsletter_issue_id, subscriber_email)
+        ON CONFLICT DO NOTHING
+        "#,
+        &issue_ids,
+        &emails
+    )
+    .execute(&mut **transaction)
+    .await?;
+    record_requeued(&mut **transaction, &issue_ids, &emails).await?;
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'sending'
+        WHERE newsletter_issue_id = ANY($1) AND status = 'sent'
+        "#,
+        &issue_ids
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,101 @@
+use std::sync::Arc;
+
+use axum::{
+    Json, RequestExt,
+    extract::{Path, Query, Request, State},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    authentication::AdminUser,
+    lists::{DEFAULT_LIST, find_lists},
+    startup::ApplicationState,
+    subscriber_import::{
+        BodyReader, ImportReport, create_import, csv_reader, import_report, run_import,
+    },
+    utils::e500,
+};
+
+/// The largest CSV file an import accepts, in bytes.
+pub const MAX_IMPORT_SIZE: usize = 64 * 1024 * 1024;
+
+#[derive(Deserialize)]
+pub struct ImportOptions {
+    /// Whether the subscribers confirmed their address with the previous
+    /// provider already. They are sent a confirmation email otherwise.
+    #[serde(default)]
+    pub confirmed: bool,
+    /// The slug of the list to import into, the default list when missing.
+    pub list: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ImportStarted {
+    pub import_id: Uuid,
+}
+
+/// Start importing a CSV file of subscribers, with `email` and `name`
+/// columns, in the background. Follow its progress with [`get_import`].
+///
+/// The file is read as it is uploaded, never held in memory whole.
+#[tracing::instrument(
+    name = "Import subscribers",
+    skip(admin, app_state, options, request),
+    fields(username = %admin.username, confirmed = options.confirmed)
+)]
+pub async fn import_subscribers(
+    admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Query(options): Query<ImportOptions>,
+    request: Request,
+) -> Result<(StatusCode, Json<ImportStarted>), StatusCode> {
+    let slug = options.list.unwrap_or_else(|| DEFAULT_LIST.to_owned());
+    let list = find_lists(&app_state.pool, &[slug])
+        .await
+        .map_err(e500)?
+        .pop()
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    // Still bound by the size limit of the route.
+    let body = BodyReader::new(request.into_limited_body());
+    let reader = tokio::task::spawn_blocking(move || csv_reader(body))
+        .await
+        .map_err(e500)?
+        .map_err(|e| {
+            tracing::warn!("Rejected import: {}", e);
+            StatusCode::BAD_REQUEST
+        })?;
+    let import_id = create_import(&app_state.pool, &list, options.confirmed)
+        .await
+        .map_err(e500)?;
+
+    tokio::spawn(async move {
+        if let Err(e) = run_import(
+            &app_state.pool,
+            &app_state.email_client,
+            &app_state.base_url.0,
+            import_id,
+            &list,
+            reader,
+        )
+        .await
+        {
+            tracing::error!("Failed to record the outcome of an import: {:?}", e);
+        }
+    });
+    Ok((StatusCode::ACCEPTED, Json(ImportStarted { import_id })))
+}
+
+#[tracing::instrument(name = "Get an import", skip(_admin, app_state))]
+pub async fn get_import(
+    _admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Path(import_id): Path<Uuid>,
+) -> Result<Json<ImportReport>, StatusCode> {
+    let report = import_report(&app_state.pool, import_id)
+        .await
+        .map_err(e500)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(report))
+}
@@ -0,0 +1,756 @@
+use std::sync::Arc;
+
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    ab_testing::AbTestMetric,
+    audit::record_issue_event,
+    authentication::AdminUser,
+    domain::IssueSlug,
+    lists::{DEFAULT_LIST, find_lists},
+    routes::admin::revisions::append_revision,
+    scheduler::lock_issue,
+    startup::ApplicationState,
+    utils::e500,
+};
+
+#[derive(Deserialize)]
+pub struct IssueContent {
+    pub title: String,
+    pub text_content: String,
+    pub html_content: String,
+}
+
+impl IssueContent {
+    pub fn is_valid(&self) -> bool {
+        !(self.title.trim().is_empty()
+            || self.text_content.trim().is_empty()
+            || self.html_content.trim().is_empty())
+    }
+}
+
+#[derive(Deserialize)]
+pub struct NewIssue {
+    #[serde(flatten)]
+    pub content: IssueContent,
+    /// Schedule the issue straight away instead of saving it as a draft.
+    pub send_at: Option<DateTime<Utc>>,
+    /// The slug of the list the issue goes out to, the default list when
+    /// unset.
+    pub list: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct Schedule {
+    pub send_at: DateTime<Utc>,
+}
+
+/// What is tracked about the recipients of an issue.
+#[derive(Deserialize, Serialize)]
+pub struct IssueTracking {
+    #[serde(default)]
+    pub track_opens: bool,
+    #[serde(default)]
+    pub track_clicks: bool,
+}
+
+/// Whether the outbound links of an issue get UTM parameters. Unset
+/// parameters fall back to `newsletter`, `email` and the issue slug.
+#[derive(Deserialize, Serialize)]
+pub struct IssueUtm {
+    pub utm_tagging: bool,
+    pub utm_source: Option<String>,
+    pub utm_medium: Option<String>,
+    pub utm_campaign: Option<String>,
+}
+
+impl IssueUtm {
+    pub fn is_valid(&self) -> bool {
+        [&self.utm_source, &self.utm_medium, &self.utm_campaign]
+            .into_iter()
+            .flatten()
+            .all(|value| !value.trim().is_empty())
+    }
+}
+
+/// The segment of its list an issue goes out to, the whole list when unset.
+#[derive(Deserialize, Serialize)]
+pub struct IssueSegment {
+    pub segment_id: Option<Uuid>,
+}
+
+#[derive(Deserialize)]
+pub struct ListFilter {
+    pub status: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct IssueSummary {
+    pub newsletter_issue_id: Uuid,
+    pub slug: String,
+    pub title: String,
+    pub status: String,
+    pub current_revision: i32,
+    pub approval_status: String,
+    pub approved_revision: Option<i32>,
+    pub send_at: Option<DateTime<Utc>>,
+    pub published_at: Option<DateTime<Utc>>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct IssueDetails {
+    pub newsletter_issue_id: Uuid,
+    pub list_id: Uuid,
+    pub segment_id: Option<Uuid>,
+    pub slug: String,
+    pub title: String,
+    pub text_content: String,
+    pub html_content: String,
+    pub status: String,
+    pub current_revision: i32,
+    pub approval_status: String,
+    pub approved_revision: Option<i32>,
+    pub send_at: Option<DateTime<Utc>>,
+    pub published_at: Option<DateTime<Utc>>,
+    pub track_opens: bool,
+    pub track_clicks: bool,
+    pub utm_tagging: bool,
+    pub utm_source: Option<String>,
+    pub utm_medium: Option<String>,
+    pub utm_campaign: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(
+    name = "Create a newsletter issue",
+    skip(admin, app_state, body),
+    fields(username = %admin.username, title = %body.content.title)
+)]
+pub async fn create_issue(
+    admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Json(body): Json<NewIssue>,
+) -> Result<impl IntoResponse, StatusCode> {
+    if !body.content.is_valid() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let mut transaction = app_state.pool.begin().await.map_err(e500)?;
+    let list_id = match &body.list {
+        Some(slug) => {
+            let lists = find_lists(&mut *transaction, std::slice::from_ref(slug))
+                .await
+                .map_err(e500)?;
+            Some(lists.first().ok_or(StatusCode::BAD_REQUEST)?.list_id)
+        }
+        None => None,
+    };
+    let issue_id = insert_issue(
+        &mut transaction,
+        &body.content,
+        list_id,
+        body.send_at,
+        Some(admin.user_id),
+    )
+    .await
+    .map_err(e500)?;
+    let issue = fetch_issue_summary(&mut transaction, issue_id)
+        .await
+        .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    Ok((StatusCode::CREATED, Json(issue)))
+}
+
+#[tracing::instrument(name = "List newsletter issues", skip(_admin, app_state, filter))]
+pub async fn list_issues(
+    _admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Query(filter): Query<ListFilter>,
+) -> Result<Json<Vec<IssueSummary>>, StatusCode> {
+    let issues = sqlx::query_as!(
+        IssueSummary,
+        r#"
+        SELECT
+            newsletter_issue_id, slug, title, status, current_revision,
+            approval_status, approved_revision, send_at, published_at, updated_at
+        FROM newsletter_issues
+        WHERE $1::TEXT IS NULL OR status = $1
+        ORDER BY updated_at DESC
+        "#,
+        filter.status
+    )
+    .fetch_all(&app_state.pool)
+    .await
+    .map_err(e500)?;
+    Ok(Json(issues))
+}
+
+#[tracing::instrument(name = "Get a newsletter issue", skip(_admin, app_state))]
+pub async fn get_issue(
+    _admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Path(issue_id): Path<Uuid>,
+) -> Result<Json<IssueDetails>, StatusCode> {
+    let issue = sqlx::query_as!(
+        IssueDetails,
+        r#"
+        SELECT
+            newsletter_issue_id, list_id, segment_id, slug, title, text_content, html_content, status,
+            current_revision, approval_status, approved_revision,
+            send_at, published_at, track_opens, track_clicks,
+            utm_tagging, utm_source, utm_medium, utm_campaign, created_at, updated_at
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_optional(&app_state.pool)
+    .await
+    .map_err(e500)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(issue))
+}
+
+#[tracing::instrument(
+    name = "Update a draft newsletter issue",
+    skip(admin, app_state, body),
+    fields(username = %admin.username)
+)]
+pub async fn update_issue(
+    admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Path(issue_id): Path<Uuid>,
+    Json(body): Json<IssueContent>,
+) -> Result<Json<IssueSummary>, StatusCode> {
+    if !body.is_valid() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let mut transaction = app_state.pool.begin().await.map_err(e500)?;
+    ensure_status(&mut transaction, issue_id, "draft").await?;
+    let issue = save_content(&mut transaction, issue_id, &body, admin.user_id, None)
+        .await
+        .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    Ok(Json(issue))
+}
+
+#[tracing::instrument(
+    name = "Delete a draft newsletter issue",
+    skip(admin, app_state),
+    fields(username = %admin.username)
+)]
+pub async fn delete_issue(
+    admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Path(issue_id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let mut transaction = app_state.pool.begin().await.map_err(e500)?;
+    ensure_status(&mut transaction, issue_id, "draft").await?;
+    sqlx::query!(
+        r#"DELETE FROM newsletter_issues WHERE newsletter_issue_id = $1"#,
+        issue_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[tracing::instrument(
+    name = "Schedule a draft newsletter issue",
+    skip(admin, app_state, body),
+    fields(username = %admin.username, send_at = %body.send_at)
+)]
+pub async fn schedule_issue(
+    admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Path(issue_id): Path<Uuid>,
+    Json(body): Json<Schedule>,
+) -> Result<Json<IssueSummary>, StatusCode> {
+    let mut transaction = app_state.pool.begin().await.map_err(e500)?;
+    ensure_status(&mut transaction, issue_id, "draft").await?;
+    let issue = sqlx::query_as!(
+        IssueSummary,
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'scheduled', send_at = $2, updated_at = now()
+        WHERE newsletter_issue_id = $1
+        RETURNING
+            newsletter_issue_id, slug, title, status, current_revision,
+            approval_status, approved_revision, send_at, published_at, updated_at
+        "#,
+        issue_id,
+        body.send_at
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    Ok(Json(issue))
+}
+
+#[tracing::instrument(
+    name = "Cancel a scheduled newsletter issue",
+    skip(admin, app_state),
+    fields(username = %admin.username)
+)]
+pub async fn cancel_issue(
+    admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Path(issue_id): Path<Uuid>,
+) -> Result<Json<IssueSummary>, StatusCode> {
+    let mut transaction = app_state.pool.begin().await.map_err(e500)?;
+    ensure_status(&mut transaction, issue_id, "scheduled").await?;
+    let issue = sqlx::query_as!(
+        IssueSummary,
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'draft', send_at = NULL, updated_at = now()
+        WHERE newsletter_issue_id = $1
+        RETURNING
+            newsletter_issue_id, slug, title, status, current_revision,
+            approval_status, approved_revision, send_at, published_at, updated_at
+        "#,
+        issue_id
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    Ok(Json(issue))
+}
+
+#[tracing::instrument(
+    name = "Change the tracking of a newsletter issue",
+    skip(admin, app_state, body),
+    fields(
+        username = %admin.username,
+        track_opens = body.track_opens,
+        track_clicks = body.track_clicks
+    )
+)]
+pub async fn set_issue_tracking(
+    admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Path(issue_id): Path<Uuid>,
+    Json(body): Json<IssueTracking>,
+) -> Result<Json<IssueTracking>, StatusCode> {
+    let mut transaction = app_state.pool.begin().await.map_err(e500)?;
+    ensure_unpublished(&mut transaction, issue_id).await?;
+    // An A/B test of the issue must still be able to measure its metric.
+    let ab_test_metric = sqlx::query_scalar!(
+        "SELECT ab_test_metric FROM newsletter_issues WHERE newsletter_issue_id = $1",
+        issue_id
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .map_err(e500)?;
+    if ab_test_metric
+        .as_deref()
+        .and_then(AbTestMetric::parse)
+        .is_some_and(|metric| !metric.is_measured(body.track_opens, body.track_clicks))
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let tracking = sqlx::query_as!(
+        IssueTracking,
+        r#"
+        UPDATE newsletter_issues
+        SET track_opens = $2, track_clicks = $3, updated_at = now()
+        WHERE newsletter_issue_id = $1
+        RETURNING track_opens, track_clicks
+        "#,
+        issue_id,
+        body.track_opens,
+        body.track_clicks
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .map_err(e500)?;
+    record_settings_change(&mut transaction, issue_id, admin.user_id, "tracking")
+        .await
+        .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    Ok(Json(tracking))
+}
+
+#[tracing::instrument(
+    name = "Change the UTM tagging of a newsletter issue",
+    skip(admin, app_state, body),
+    fields(username = %admin.username, utm_tagging = body.utm_tagging)
+)]
+pub async fn set_issue_utm(
+    admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Path(issue_id): Path<Uuid>,
+    Json(body): Json<IssueUtm>,
+) -> Result<Json<IssueUtm>, StatusCode> {
+    if !body.is_valid() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let mut transaction = app_state.pool.begin().await.map_err(e500)?;
+    ensure_unpublished(&mut transaction, issue_id).await?;
+    let utm = sqlx::query_as!(
+        IssueUtm,
+        r#"
+        UPDATE newsletter_issues
+        SET utm_tagging = $2, utm_source = $3, utm_medium = $4, utm_campaign = $5, updated_at = now()
+        WHERE newsletter_issue_id = $1
+        RETURNING utm_tagging, utm_source, utm_medium, utm_campaign
+        "#,
+        issue_id,
+        body.utm_tagging,
+        body.utm_source.as_deref().map(str::trim),
+        body.utm_medium.as_deref().map(str::trim),
+        body.utm_campaign.as_deref().map(str::trim)
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .map_err(e500)?;
+    record_settings_change(&mut transaction, issue_id, admin.user_id, "UTM tagging")
+        .await
+        .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    Ok(Json(utm))
+}
+
+#[tracing::instrument(
+    name = "Change the segment of a newsletter issue",
+    skip(admin, app_state, body),
+    fields(username = %admin.username, segment_id = ?body.segment_id)
+)]
+pub async fn set_issue_segment(
+    admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Path(issue_id): Path<Uuid>,
+    Json(body): Json<IssueSegment>,
+) -> Result<Json<IssueSegment>, StatusCode> {
+    let mut transaction = app_state.pool.begin().await.map_err(e500)?;
+    ensure_unpublished(&mut transaction, issue_id).await?;
+    if let Some(segment_id) = body.segment_id {
+        let exists = sqlx::query_scalar!(
+            r#"SELECT EXISTS(SELECT 1 FROM segments WHERE segment_id = $1) AS "exists!""#,
+            segment_id
+        )
+        .fetch_one(&mut *transaction)
+        .await
+        .map_err(e500)?;
+        if !exists {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    }
+    let segment = sqlx::query_as!(
+        IssueSegment,
+        r#"
+        UPDATE newsletter_issues
+        SET segment_id = $2, updated_at = now()
+        WHERE newsletter_issue_id = $1
+        RETURNING segment_id
+        "#,
+        issue_id,
+        body.segment_id
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .map_err(e500)?;
+    record_settings_change(&mut transaction, issue_id, admin.user_id, "segment")
+        .await
+        .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    Ok(Json(segment))
+}
+
+#[tracing::instrument(
+    name = "Reschedule a newsletter issue",
+    skip(admin, app_state, body),
+    fields(username = %admin.username, send_at = %body.send_at)
+)]
+pub async fn reschedule_issue(
+    admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Path(issue_id): Path<Uuid>,
+    Json(body): Json<Schedule>,
+) -> Result<Json<IssueSummary>, StatusCode> {
+    let mut transaction = app_state.pool.begin().await.map_err(e500)?;
+    ensure_status(&mut transaction, issue_id, "scheduled").await?;
+    let issue = sqlx::query_as!(
+        IssueSummary,
+        r#"
+        UPDATE newsletter_issues
+        SET send_at = $2, updated_at = now()
+        WHERE newsletter_issue_id = $1
+        RETURNING
+            newsletter_issue_id, slug, title, status, current_revision,
+            approval_status, approved_revision, send_at, published_at, updated_at
+        "#,
+        issue_id,
+        body.send_at
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    Ok(Json(issue))
+}
+
+/// Take the issue's advisory lock, so the scheduler cannot promote it while we
+/// change it, and check that it is in the `expected` state.
+pub async fn ensure_status(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    expected: &str,
+) -> Result<(), StatusCode> {
+    lock_issue(transaction, issue_id).await.map_err(e500)?;
+    let status = sqlx::query_scalar!(
+        r#"SELECT status FROM newsletter_issues WHERE newsletter_issue_id = $1 FOR UPDATE"#,
+        issue_id
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+    .map_err(e500)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+    if status != expected {
+        return Err(StatusCode::CONFLICT);
+    }
+    Ok(())
+}
+
+/// Like [`ensure_status`], for settings that may change until the issue is
+/// published: recipients of an issue in delivery must all be treated alike.
+pub async fn ensure_unpublished(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+) -> Result<(), StatusCode> {
+    lock_issue(transaction, issue_id).await.map_err(e500)?;
+    let status = sqlx::query_scalar!(
+        r#"SELECT status FROM newsletter_issues WHERE newsletter_issue_id = $1 FOR UPDATE"#,
+        issue_id
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+    .map_err(e500)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+    if status != "draft" && status != "scheduled" {
+        return Err(StatusCode::CONFLICT);
+    }
+    Ok(())
+}
+
+/// Replace the current content of an issue, recording it as a new revision.
+///
+/// Approvals are tied to a revision, so any standing approval is withdrawn.
+pub async fn save_content(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    content: &IssueContent,
+    created_by: Uuid,
+    restored_from: Option<i32>,
+) -> Result<IssueSummary, sqlx::Error> {
+    withdraw_approval(
+        transaction,
+        issue_id,
+        created_by,
+        "The content changed after it was approved",
+    )
+    .await?;
+    let issue = sqlx::query_as!(
+        IssueSummary,
+        r#"
+        UPDATE newsletter_issues
+        SET
+            title = $2,
+            text_content = $3,
+            html_content = $4,
+            current_revision = current_revision + 1,
+            approval_status = 'pending',
+            approved_revision = NULL,
+            approved_by = NULL,
+            updated_at = now()
+        WHERE newsletter_issue_id = $1
+        RETURNING
+            newsletter_issue_id, slug, title, status, current_revision,
+            approval_status, approved_revision, send_at, published_at, updated_at
+        "#,
+        issue_id,
+        content.title,
+        content.text_content,
+        content.html_content
+    )
+    .fetch_one(&mut **transaction)
+    .await?;
+    append_revision(
+        transaction,
+        issue_id,
+        issue.current_revision,
+        content,
+        Some(created_by),
+        restored_from,
+    )
+    .await?;
+    Ok(issue)
+}
+
+/// Record that `changed_by` changed how or to whom the issue is sent.
+///
+/// The standing approval, if any, does not cover the change, and whoever made
+/// it cannot be the one to approve the issue again.
+pub async fn record_settings_change(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    changed_by: Uuid,
+    setting: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET settings_changed_by = $2, updated_at = now()
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id,
+        changed_by
+    )
+    .execute(&mut **transaction)
+    .await?;
+    withdraw_approval(
+        transaction,
+        issue_id,
+        changed_by,
+        &format!("The {} changed after it was approved", setting),
+    )
+    .await
+}
+
+/// Withdraw the standing approval of an issue, if it has one.
+async fn withdraw_approval(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    actor: Uuid,
+    note: &str,
+) -> Result<(), sqlx::Error> {
+    // Approvals are always about the current revision.
+    let approved_revision = sqlx::query_scalar!(
+        r#"
+        UPDATE newsletter_issues
+        SET approval_status = 'pending', approved_revision = NULL, approved_by = NULL
+        WHERE newsletter_issue_id = $1 AND approval_status = 'approved'
+        RETURNING current_revision
+        "#,
+        issue_id
+    )
+    .fetch_optional(&mut **transaction)
+    .await?;
+    if let Some(revision) = approved_revision {
+        record_issue_event(
+            &mut **transaction,
+            issue_id,
+            Some(revision),
+            Some(actor),
+            "approval_invalidated",
+            Some(note),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Persist a new issue along with its first revision. It is scheduled when
+/// `send_at` is set and saved as a draft otherwise.
+///
+/// The issue goes out to the default list when `list_id` is `None`.
+pub async fn insert_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    content: &IssueContent,
+    list_id: Option<Uuid>,
+    send_at: Option<DateTime<Utc>>,
+    created_by: Option<Uuid>,
+) -> Result<Uuid, sqlx::Error> {
+    let status = if send_at.is_some() {
+        "scheduled"
+    } else {
+        "draft"
+    };
+    let issue_id = Uuid::new_v4();
+    let mut slug = IssueSlug::from_title(&content.title);
+    if slug_is_taken(transaction, &slug).await? {
+        slug = slug.with_suffix(&issue_id.simple().to_string()[..8]);
+    }
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id,
+            list_id,
+            slug,
+            title,
+            text_content,
+            html_content,
+            status,
+            send_at,
+            current_revision,
+            approval_status,
+            created_at,
+            updated_at
+        )
+        VALUES (
+            $1,
+            COALESCE($8, (SELECT list_id FROM lists WHERE slug = $9)),
+            $2, $3, $4, $5, $6, $7, 1, 'pending', now(), now()
+        )
+        "#,
+        issue_id,
+        slug.as_ref(),
+        content.title,
+        content.text_content,
+        content.html_content,
+        status,
+        send_at,
+        list_id,
+        DEFAULT_LIST
+    )
+    .execute(&mut **transaction)
+    .await?;
+    append_revision(transaction, issue_id, 1, content, created_by, None).await?;
+    Ok(issue_id)
+}
+
+async fn slug_is_taken(
+    transaction: &mut Transaction<'_, Postgres>,
+    slug: &IssueSlug,
+) -> Result<bool, sqlx::Error> {
+    let taken = sqlx::query_scalar!(
+        r#"SELECT EXISTS(SELECT 1 FROM newsletter_issues WHERE slug = $1) AS "taken!""#,
+        slug.as_ref()
+    )
+    .fetch_one(&mut **transaction)
+    .await?;
+    Ok(taken)
+}
+
+pub async fn fetch_issue_summary(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+) -> Result<IssueSummary, sqlx::Error> {
+    sqlx::query_as!(
+        IssueSummary,
+        r#"
+        SELECT
+            newsletter_issue_id, slug, title, status, current_revision,
+            approval_status, approved_revision, send_at, published_at, updated_at
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_one(&mut **transaction)
+    .await
+}
@@ -0,0 +1,163 @@
+use std::sync::Arc;
+
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    attributes::{AttributeSchema, is_valid_schema},
+    authentication::AdminUser,
+    domain::SubscriberEmail,
+    lists::List,
+    startup::ApplicationState,
+    utils::e500,
+};
+
+#[derive(Deserialize)]
+pub struct NewList {
+    pub slug: String,
+    pub name: String,
+    pub sender_email: Option<String>,
+    pub sender_name: Option<String>,
+    #[serde(default = "double_opt_in_by_default")]
+    pub double_opt_in: bool,
+    pub confirmation_subject: Option<String>,
+    #[serde(default)]
+    pub attribute_schema: AttributeSchema,
+}
+
+fn double_opt_in_by_default() -> bool {
+    true
+}
+
+impl NewList {
+    pub fn is_valid(&self) -> bool {
+        let slug_is_valid = !self.slug.is_empty()
+            && self.slug.len() <= 60
+            && self
+                .slug
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+        // The name ends up in a `From` header.
+        let sender_is_valid = match (&self.sender_email, &self.sender_name) {
+            (None, None) => true,
+            (None, Some(_)) => false,
+            (Some(email), name) => {
+                SubscriberEmail::parse(email.clone()).is_ok()
+                    && name.as_ref().is_none_or(|name| {
+                        !name.trim().is_empty() && !name.contains(['<', '>', '"', ',', '\n'])
+                    })
+            }
+        };
+        slug_is_valid
+            && !self.name.trim().is_empty()
+            && sender_is_valid
+            && self
+                .confirmation_subject
+                .as_ref()
+                .is_none_or(|subject| !subject.trim().is_empty())
+            && is_valid_schema(&self.attribute_schema)
+    }
+}
+
+#[tracing::instrument(
+    name = "Create a list",
+    skip(admin, app_state, body),
+    fields(username = %admin.username, slug = %body.slug)
+)]
+pub async fn create_list(
+    admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Json(body): Json<NewList>,
+) -> Result<impl IntoResponse, StatusCode> {
+    if !body.is_valid() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let list = sqlx::query_as!(
+        List,
+        r#"
+        INSERT INTO lists (
+            list_id, slug, name, sender_email, sender_name,
+            double_opt_in, confirmation_subject, attribute_schema, created_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, COALESCE($7, 'Welcome'), $8, now())
+        ON CONFLICT (slug) DO NOTHING
+        RETURNING
+            list_id, slug, name, sender_email, sender_name, double_opt_in, confirmation_subject,
+            attribute_schema AS "attribute_schema: sqlx::types::Json<AttributeSchema>", created_at
+        "#,
+        Uuid::new_v4(),
+        body.slug,
+        body.name.trim(),
+        body.sender_email.as_deref().map(str::trim),
+        body.sender_name.as_deref().map(str::trim),
+        body.double_opt_in,
+        body.confirmation_subject.as_deref().map(str::trim),
+        sqlx::types::Json(&body.attribute_schema) as _
+    )
+    .fetch_optional(&app_state.pool)
+    .await
+    .map_err(e500)?
+    .ok_or(StatusCode::CONFLICT)?;
+    Ok((StatusCode::CREATED, Json(list)))
+}
+
+#[tracing::instrument(name = "List the lists", skip(_admin, app_state))]
+pub async fn list_lists(
+    _admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+) -> Result<Json<Vec<List>>, StatusCode> {
+    let lists = sqlx::query_as!(
+        List,
+        r#"
+        SELECT
+            list_id, slug, name, sender_email, sender_name,
+            double_opt_in, confirmation_subject,
+            attribute_schema AS "attribute_schema: sqlx::types::Json<AttributeSchema>", created_at
+        FROM lists
+        ORDER BY created_at, slug
+        "#
+    )
+    .fetch_all(&app_state.pool)
+    .await
+    .map_err(e500)?;
+    Ok(Json(lists))
+}
+
+/// Replace the custom attributes a list collects. Values stored under
+/// attributes dropped from the schema are kept.
+#[tracing::instrument(
+    name = "Change the attribute schema of a list",
+    skip(admin, app_state, body),
+    fields(username = %admin.username)
+)]
+pub async fn set_attribute_schema(
+    admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Path(list_id): Path<Uuid>,
+    Json(body): Json<AttributeSchema>,
+) -> Result<Json<AttributeSchema>, StatusCode> {
+    if !is_valid_schema(&body) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let schema = sqlx::query_scalar!(
+        r#"
+        UPDATE lists
+        SET attribute_schema = $2
+        WHERE list_id = $1
+        RETURNING attribute_schema AS "attribute_schema: sqlx::types::Json<AttributeSchema>"
+        "#,
+        list_id,
+        sqlx::types::Json(&body) as _
+    )
+    .fetch_optional(&app_state.pool)
+    .await
+    .map_err(e500)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(schema.0))
+}
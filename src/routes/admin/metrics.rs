@@ -0,0 +1,21 @@
+use std::sync::Arc;
+
+use axum::{Json, extract::State};
+use serde::Serialize;
+
+use crate::{authentication::AdminUser, startup::ApplicationState, throttle::ThrottleMetrics};
+
+#[derive(Serialize)]
+pub struct Metrics {
+    pub email_throttle: ThrottleMetrics,
+}
+
+#[tracing::instrument(name = "Get the delivery metrics", skip(_admin, app_state))]
+pub async fn get_metrics(
+    _admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+) -> Json<Metrics> {
+    Json(Metrics {
+        email_throttle: app_state.email_client.throttle().metrics(),
+    })
+}
@@ -0,0 +1,13 @@
+pub mod ab_tests;
+pub mod approvals;
+pub mod bulk_actions;
+pub mod deliveries;
+pub mod failed_deliveries;
+pub mod imports;
+pub mod issues;
+pub mod lists;
+pub mod metrics;
+pub mod password;
+pub mod revisions;
+pub mod segments;
+pub mod subscribers;
@@ -0,0 +1,38 @@
+use std::sync::Arc;
+
+use axum::{Json, extract::State, http::StatusCode};
+use secrecy::SecretString;
+use serde::Deserialize;
+
+use crate::{
+    authentication::{AdminUser, change_password, validate_new_password},
+    startup::ApplicationState,
+    utils::e500,
+};
+
+#[derive(Deserialize)]
+pub struct NewPassword {
+    pub new_password: SecretString,
+}
+
+/// Change the password of the admin making the request. The credentials of
+/// the request are the old password.
+#[tracing::instrument(
+    name = "Change the password of an admin",
+    skip(admin, app_state, body),
+    fields(username = %admin.username)
+)]
+pub async fn change_admin_password(
+    admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Json(body): Json<NewPassword>,
+) -> Result<StatusCode, StatusCode> {
+    validate_new_password(&body.new_password).map_err(|e| {
+        tracing::warn!("Rejected the new password: {}", e);
+        StatusCode::BAD_REQUEST
+    })?;
+    change_password(admin.user_id, body.new_password, &app_state.pool)
+        .await
+        .map_err(e500)?;
+    Ok(StatusCode::NO_CONTENT)
+}
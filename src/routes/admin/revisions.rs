@@ -0,0 +1,169 @@
+use std::sync::Arc;
+
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    authentication::AdminUser,
+    routes::admin::issues::{IssueContent, IssueSummary, ensure_status, save_content},
+    startup::ApplicationState,
+    utils::e500,
+};
+
+#[derive(Serialize)]
+pub struct RevisionSummary {
+    pub revision: i32,
+    pub title: String,
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub restored_from: Option<i32>,
+}
+
+#[derive(Serialize)]
+pub struct Revision {
+    pub revision: i32,
+    pub title: String,
+    pub text_content: String,
+    pub html_content: String,
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub restored_from: Option<i32>,
+}
+
+#[tracing::instrument(name = "List the revisions of an issue", skip(_admin, app_state))]
+pub async fn list_revisions(
+    _admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Path(issue_id): Path<Uuid>,
+) -> Result<Json<Vec<RevisionSummary>>, StatusCode> {
+    let revisions = sqlx::query_as!(
+        RevisionSummary,
+        r#"
+        SELECT r.revision, r.title, u.username AS "created_by?", r.created_at, r.restored_from
+        FROM newsletter_issue_revisions r
+        LEFT JOIN users u ON u.user_id = r.created_by
+        WHERE r.newsletter_issue_id = $1
+        ORDER BY r.revision DESC
+        "#,
+        issue_id
+    )
+    .fetch_all(&app_state.pool)
+    .await
+    .map_err(e500)?;
+    // Every issue has at least one revision, so none means no such issue.
+    if revisions.is_empty() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(Json(revisions))
+}
+
+#[tracing::instrument(name = "Get a revision of an issue", skip(_admin, app_state))]
+pub async fn get_revision(
+    _admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Path((issue_id, revision)): Path<(Uuid, i32)>,
+) -> Result<Json<Revision>, StatusCode> {
+    let revision = fetch_revision(&app_state, issue_id, revision)
+        .await?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(revision))
+}
+
+#[tracing::instrument(
+    name = "Restore an earlier revision of a draft",
+    skip(admin, app_state),
+    fields(username = %admin.username)
+)]
+pub async fn restore_revision(
+    admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Path((issue_id, revision)): Path<(Uuid, i32)>,
+) -> Result<Json<IssueSummary>, StatusCode> {
+    let restored = fetch_revision(&app_state, issue_id, revision)
+        .await?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let content = IssueContent {
+        title: restored.title,
+        text_content: restored.text_content,
+        html_content: restored.html_content,
+    };
+
+    let mut transaction = app_state.pool.begin().await.map_err(e500)?;
+    ensure_status(&mut transaction, issue_id, "draft").await?;
+    let issue = save_content(
+        &mut transaction,
+        issue_id,
+        &content,
+        admin.user_id,
+        Some(revision),
+    )
+    .await
+    .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    Ok(Json(issue))
+}
+
+pub async fn append_revision(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    revision: i32,
+    content: &IssueContent,
+    created_by: Option<Uuid>,
+    restored_from: Option<i32>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issue_revisions (
+            newsletter_issue_id,
+            revision,
+            title,
+            text_content,
+            html_content,
+            created_by,
+            created_at,
+            restored_from
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, now(), $7)
+        "#,
+        issue_id,
+        revision,
+        content.title,
+        content.text_content,
+        content.html_content,
+        created_by,
+        restored_from
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+async fn fetch_revision(
+    app_state: &ApplicationState,
+    issue_id: Uuid,
+    revision: i32,
+) -> Result<Option<Revision>, StatusCode> {
+    sqlx::query_as!(
+        Revision,
+        r#"
+        SELECT
+            r.revision, r.title, r.text_content, r.html_content,
+            u.username AS "created_by?", r.created_at, r.restored_from
+        FROM newsletter_issue_revisions r
+        LEFT JOIN users u ON u.user_id = r.created_by
+        WHERE r.newsletter_issue_id = $1 AND r.revision = $2
+        "#,
+        issue_id,
+        revision
+    )
+    .fetch_optional(&app_state.pool)
+    .await
+    .map_err(e500)
+}
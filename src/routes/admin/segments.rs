@@ -0,0 +1,108 @@
+use std::sync::Arc;
+
+use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    authentication::AdminUser,
+    segments::{Filter, count_matching},
+    startup::ApplicationState,
+    utils::e500,
+};
+
+#[derive(Deserialize)]
+pub struct NewSegment {
+    pub name: String,
+    pub filter: String,
+}
+
+/// A named filter over subscribers, see [`Filter`] for the language.
+#[derive(Serialize)]
+pub struct Segment {
+    pub segment_id: Uuid,
+    pub name: String,
+    pub filter: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+pub struct SegmentPreview {
+    pub filter: String,
+}
+
+#[derive(Serialize)]
+pub struct SegmentSize {
+    pub count: i64,
+}
+
+fn parse_filter(filter: &str) -> Result<Filter, StatusCode> {
+    Filter::parse(filter).map_err(|e| {
+        tracing::info!(error = %e, "Invalid segment filter");
+        StatusCode::BAD_REQUEST
+    })
+}
+
+#[tracing::instrument(
+    name = "Create a segment",
+    skip(admin, app_state, body),
+    fields(username = %admin.username, name = %body.name)
+)]
+pub async fn create_segment(
+    admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Json(body): Json<NewSegment>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let name = body.name.trim();
+    if name.is_empty() || name.len() > 100 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    parse_filter(&body.filter)?;
+    let segment = sqlx::query_as!(
+        Segment,
+        r#"
+        INSERT INTO segments (segment_id, name, filter, created_at)
+        VALUES ($1, $2, $3, now())
+        ON CONFLICT (name) DO NOTHING
+        RETURNING segment_id, name, filter, created_at
+        "#,
+        Uuid::new_v4(),
+        name,
+        body.filter.trim()
+    )
+    .fetch_optional(&app_state.pool)
+    .await
+    .map_err(e500)?
+    .ok_or(StatusCode::CONFLICT)?;
+    Ok((StatusCode::CREATED, Json(segment)))
+}
+
+#[tracing::instrument(name = "List the segments", skip(_admin, app_state))]
+pub async fn list_segments(
+    _admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+) -> Result<Json<Vec<Segment>>, StatusCode> {
+    let segments = sqlx::query_as!(
+        Segment,
+        "SELECT segment_id, name, filter, created_at FROM segments ORDER BY name"
+    )
+    .fetch_all(&app_state.pool)
+    .await
+    .map_err(e500)?;
+    Ok(Json(segments))
+}
+
+/// Count the subscribers a filter matches, to try it before saving it.
+#[tracing::instrument(name = "Preview a segment", skip(_admin, app_state, body))]
+pub async fn preview_segment(
+    _admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Json(body): Json<SegmentPreview>,
+) -> Result<Json<SegmentSize>, StatusCode> {
+    let filter = parse_filter(&body.filter)?;
+    let count = count_matching(&app_state.pool, &filter)
+        .await
+        .map_err(e500)?;
+    Ok(Json(SegmentSize { count }))
+}
@@ -0,0 +1,588 @@
+use std::sync::Arc;
+
+use axum::{
+    Json,
+    body::Body,
+    extract::{Path, Query, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sqlx::{Postgres, QueryBuilder, types::Json as JsonColumn};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use uuid::Uuid;
+
+use crate::{
+    attributes::{AttributeSchema, normalize_tags, validate_attributes},
+    audit::record_subscriber_events,
+    authentication::AdminUser,
+    consent::{ConsentRecord, consent_records},
+    erasure::erase_subscribers,
+    lists::find_lists,
+    routes::preferences::data_download,
+    startup::ApplicationState,
+    subscriber_data::compile_subscriber_data,
+    subscriber_export::{ExportFilter, ExportFormat, stream_subscribers},
+    utils::e500,
+};
+
+/// The default, and maximum, number of subscribers per page.
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 200;
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct SubscriberDetails {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+    pub frequency: String,
+    pub tracking_opt_out: bool,
+    pub tags: Vec<String>,
+    pub attributes: Value,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortColumn {
+    #[default]
+    SubscribedAt,
+    Email,
+    Name,
+}
+
+impl SortColumn {
+    fn as_sql(self) -> &'static str {
+        match self {
+            Self::SubscribedAt => "s.subscribed_at",
+            Self::Email => "s.email",
+            Self::Name => "s.name",
+        }
+    }
+
+    /// The value of the column for `subscriber`, as a cursor carries it.
+    fn key_of(self, subscriber: &SubscriberDetails) -> String {
+        match self {
+            Self::SubscribedAt => subscriber
+                .subscribed_at
+                .to_rfc3339_opts(SecondsFormat::Micros, true),
+            Self::Email => subscriber.email.clone(),
+            Self::Name => subscriber.name.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+/// Which subscribers to list, and in what order. Filters combine with AND.
+#[derive(Deserialize)]
+pub struct SubscriberQuery {
+    /// The status of the subscriber, or of their membership of `list` when
+    /// one is given.
+    pub status: Option<String>,
+    /// The slug of a list the subscriber is, or was, a member of.
+    pub list: Option<String>,
+    pub tag: Option<String>,
+    pub subscribed_after: Option<DateTime<Utc>>,
+    pub subscribed_before: Option<DateTime<Utc>>,
+    /// Matched, case-insensitively, against any part of the email or name.
+    pub search: Option<String>,
+    #[serde(default)]
+    pub sort: SortColumn,
+    #[serde(default)]
+    pub order: SortOrder,
+    pub limit: Option<i64>,
+    /// Where the previous page stopped.
+    pub cursor: Option<String>,
+}
+
+/// The position of the last subscriber of a page, in the order it was
+/// listed in. Ties on the sort column are broken by id.
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+struct Cursor {
+    sort: SortColumn,
+    order: SortOrder,
+    key: String,
+    id: Uuid,
+}
+
+impl Cursor {
+    fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).expect("Cursors are serializable"))
+    }
+
+    fn decode(s: &str) -> Result<Self, String> {
+        let bytes = URL_SAFE_NO_PAD
+            .decode(s)
+            .map_err(|_| "The cursor is not valid base64.")?;
+        serde_json::from_slice(&bytes).map_err(|_| "The cursor is malformed.".into())
+    }
+}
+
+#[derive(Serialize)]
+pub struct SubscriberPage {
+    pub subscribers: Vec<SubscriberDetails>,
+    /// Pass it back as `cursor` to get the next page, unset on the last one.
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct SubscriberTags {
+    pub tags: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct SubscriberAttributes {
+    pub attributes: Map<String, Value>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct TrackingPreference {
+    pub opt_out: bool,
+}
+
+#[tracing::instrument(
+    name = "Change the tracking preference of a subscriber",
+    skip(_admin, app_state, body),
+    fields(opt_out = body.opt_out)
+)]
+pub async fn set_tracking_preference(
+    _admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Path(subscriber_id): Path<Uuid>,
+    Json(body): Json<TrackingPreference>,
+) -> Result<Json<TrackingPreference>, StatusCode> {
+    let preference = sqlx::query_as!(
+        TrackingPreference,
+        r#"
+        UPDATE subscriptions
+        SET tracking_opt_out = $2
+        WHERE id = $1
+        RETURNING tracking_opt_out AS opt_out
+        "#,
+        subscriber_id,
+        body.opt_out
+    )
+    .fetch_optional(&app_state.pool)
+    .await
+    .map_err(e500)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(preference))
+}
+
+/// Build the query listing a page of subscribers, fetching one more than
+/// `limit` to tell whether there is a next page.
+fn listing_query(
+    query: &SubscriberQuery,
+    cursor: Option<&Cursor>,
+    limit: i64,
+) -> QueryBuilder<'static, Postgres> {
+    let mut sql = QueryBuilder::new(
+        "SELECT s.id, s.email, s.name, s.status, s.subscribed_at, s.frequency, \
+        s.tracking_opt_out, s.tags, s.attributes FROM subscriptions s ",
+    );
+    if let Some(list) = &query.list {
+        sql.push(
+            "JOIN list_subscriptions ls ON ls.subscriber_id = s.id \
+            JOIN lists l ON l.list_id = ls.list_id AND l.slug = ",
+        )
+        .push_bind(list.clone());
+    }
+    sql.push(" WHERE TRUE");
+    if let Some(status) = &query.status {
+        sql.push(if query.list.is_some() {
+            " AND ls.status = "
+        } else {
+            " AND s.status = "
+        })
+        .push_bind(status.clone());
+    }
+    if let Some(tag) = &query.tag {
+        sql.push(" AND ")
+            .push_bind(tag.trim().to_lowercase())
+            .push(" = ANY(s.tags)");
+    }
+    if let Some(after) = query.subscribed_after {
+        sql.push(" AND s.subscribed_at >= ").push_bind(after);
+    }
+    if let Some(before) = query.subscribed_before {
+        sql.push(" AND s.subscribed_at < ").push_bind(before);
+    }
+    if let Some(search) = query.search.as_deref().map(str::trim) {
+        let pattern = format!(
+            "%{}%",
+            search
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_")
+        );
+        sql.push(" AND (s.email ILIKE ")
+            .push_bind(pattern.clone())
+            .push(" OR s.name ILIKE ")
+            .push_bind(pattern)
+            .push(")");
+    }
+    let column = query.sort.as_sql();
+    let (comparison, direction) = match query.order {
+        SortOrder::Asc => (" > ", " ASC"),
+        SortOrder::Desc => (" < ", " DESC"),
+    };
+    if let Some(cursor) = cursor {
+        sql.push(format!(" AND ({}, s.id){}(", column, comparison));
+        match query.sort {
+            SortColumn::SubscribedAt => sql.push_bind(cursor.key.clone()).push("::timestamptz"),
+            SortColumn::Email | SortColumn::Name => sql.push_bind(cursor.key.clone()),
+        };
+        sql.push(", ").push_bind(cursor.id).push(")");
+    }
+    sql.push(format!(
+        " ORDER BY {}{}, s.id{} LIMIT ",
+        column, direction, direction
+    ))
+    .push_bind(limit + 1);
+    sql
+}
+
+/// List subscribers a page at a time, most recent first by default.
+#[tracing::instrument(name = "List subscribers", skip(_admin, app_state, query))]
+pub async fn list_subscribers(
+    _admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Query(query): Query<SubscriberQuery>,
+) -> Result<Json<SubscriberPage>, StatusCode> {
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let cursor = query
+        .cursor
+        .as_deref()
+        .map(Cursor::decode)
+        .transpose()
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    // A cursor only makes sense in the order it was created for.
+    if let Some(cursor) = &cursor {
+        let valid_key = match cursor.sort {
+            SortColumn::SubscribedAt => DateTime::parse_from_rfc3339(&cursor.key).is_ok(),
+            SortColumn::Email | SortColumn::Name => true,
+        };
+        if cursor.sort != query.sort || cursor.order != query.order || !valid_key {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    }
+
+    let mut subscribers: Vec<SubscriberDetails> = listing_query(&query, cursor.as_ref(), limit)
+        .build_query_as()
+        .fetch_all(&app_state.pool)
+        .await
+        .map_err(e500)?;
+    let next_cursor = if subscribers.len() as i64 > limit {
+        subscribers.truncate(limit as usize);
+        subscribers.last().map(|last| {
+            Cursor {
+                sort: query.sort,
+                order: query.order,
+                key: query.sort.key_of(last),
+                id: last.id,
+            }
+            .encode()
+        })
+    } else {
+        None
+    };
+    Ok(Json(SubscriberPage {
+        subscribers,
+        next_cursor,
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct ExportQuery {
+    #[serde(default)]
+    pub format: ExportFormat,
+    #[serde(flatten)]
+    pub filter: ExportFilter,
+}
+
+/// Download every subscriber matching the filter, as CSV or JSON lines. The
+/// export is streamed straight from the database.
+#[tracing::instrument(
+    name = "Export subscribers",
+    skip(admin, app_state, query),
+    fields(username = %admin.username, format = ?query.format)
+)]
+pub async fn export_subscribers(
+    admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, StatusCode> {
+    if let Some(list) = &query.filter.list {
+        let lists = find_lists(&app_state.pool, std::slice::from_ref(list))
+            .await
+            .map_err(e500)?;
+        if lists.is_empty() {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    }
+    let format = query.format;
+    // A few chunks in flight at most: the database is read no faster than
+    // the client downloads.
+    let (sender, receiver) = mpsc::channel(4);
+    tokio::spawn(async move {
+        stream_subscribers(&app_state.pool, &query.filter, format, sender).await;
+    });
+    let disposition = format!(
+        "attachment; filename=\"subscribers.{}\"",
+        format.extension()
+    );
+    Ok((
+        [
+            (header::CONTENT_TYPE, format.content_type().to_owned()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        Body::from_stream(ReceiverStream::new(receiver)),
+    )
+        .into_response())
+}
+
+#[tracing::instrument(name = "Get a subscriber", skip(_admin, app_state))]
+pub async fn get_subscriber(
+    _admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Path(subscriber_id): Path<Uuid>,
+) -> Result<Json<SubscriberDetails>, StatusCode> {
+    let subscriber = sqlx::query_as!(
+        SubscriberDetails,
+        r#"
+        SELECT
+            id, email, name, status, subscribed_at, frequency,
+            tracking_opt_out, tags, attributes
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(&app_state.pool)
+    .await
+    .map_err(e500)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(subscriber))
+}
+
+/// How and when a subscriber consented to hear from us, oldest first. The
+/// records of erased subscribers are kept, anonymised.
+#[tracing::instrument(name = "Get the consents of a subscriber", skip(_admin, app_state))]
+pub async fn get_subscriber_consents(
+    _admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Path(subscriber_id): Path<Uuid>,
+) -> Result<Json<Vec<ConsentRecord>>, StatusCode> {
+    let consents = consent_records(&app_state.pool, subscriber_id)
+        .await
+        .map_err(e500)?;
+    if consents.is_empty() {
+        let known = sqlx::query_scalar!(
+            r#"SELECT EXISTS(SELECT 1 FROM subscriptions WHERE id = $1) AS "known!""#,
+            subscriber_id
+        )
+        .fetch_one(&app_state.pool)
+        .await
+        .map_err(e500)?;
+        if !known {
+            return Err(StatusCode::NOT_FOUND);
+        }
+    }
+    Ok(Json(consents))
+}
+
+/// Everything we store about a subscriber, as a JSON file to hand over to
+/// them.
+#[tracing::instrument(
+    name = "Export the data of a subscriber",
+    skip(admin, app_state),
+    fields(username = %admin.username)
+)]
+pub async fn export_subscriber_data(
+    admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Path(subscriber_id): Path<Uuid>,
+) -> Result<Response, StatusCode> {
+    let data = compile_subscriber_data(&app_state.pool, subscriber_id)
+        .await
+        .map_err(e500)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    record_subscriber_events(
+        &app_state.pool,
+        &[subscriber_id],
+        Some(admin.user_id),
+        "export_data",
+        None,
+    )
+    .await
+    .map_err(e500)?;
+    Ok(data_download(data))
+}
+
+/// Erase a subscriber and their personal data, see [`erase_subscribers`].
+#[tracing::instrument(
+    name = "Erase a subscriber",
+    skip(admin, app_state),
+    fields(username = %admin.username)
+)]
+pub async fn erase_subscriber(
+    admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Path(subscriber_id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let mut transaction = app_state.pool.begin().await.map_err(e500)?;
+    let erased = erase_subscribers(&mut transaction, &[subscriber_id])
+        .await
+        .map_err(e500)?;
+    if erased.is_empty() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    record_subscriber_events(
+        &mut *transaction,
+        &erased,
+        Some(admin.user_id),
+        "erase",
+        None,
+    )
+    .await
+    .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[tracing::instrument(
+    name = "Change the tags of a subscriber",
+    skip(admin, app_state, body),
+    fields(username = %admin.username)
+)]
+pub async fn set_subscriber_tags(
+    admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Path(subscriber_id): Path<Uuid>,
+    Json(body): Json<SubscriberTags>,
+) -> Result<Json<SubscriberTags>, StatusCode> {
+    let tags = normalize_tags(body.tags).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let mut transaction = app_state.pool.begin().await.map_err(e500)?;
+    let tags = sqlx::query_as!(
+        SubscriberTags,
+        "UPDATE subscriptions SET tags = $2 WHERE id = $1 RETURNING tags",
+        subscriber_id,
+        &tags
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .map_err(e500)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+    record_subscriber_events(
+        &mut *transaction,
+        &[subscriber_id],
+        Some(admin.user_id),
+        "set_tags",
+        Some(&tags.tags.join(", ")),
+    )
+    .await
+    .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    Ok(Json(tags))
+}
+
+/// Replace the custom attributes of a subscriber. Each must be declared by
+/// one of the lists the subscriber belongs, or belonged, to.
+#[tracing::instrument(
+    name = "Change the attributes of a subscriber",
+    skip(admin, app_state, body),
+    fields(username = %admin.username)
+)]
+pub async fn set_subscriber_attributes(
+    admin: AdminUser,
+    State(app_state): State<Arc<ApplicationState>>,
+    Path(subscriber_id): Path<Uuid>,
+    Json(body): Json<SubscriberAttributes>,
+) -> Result<Json<SubscriberAttributes>, StatusCode> {
+    let mut transaction = app_state.pool.begin().await.map_err(e500)?;
+    let exists = sqlx::query_scalar!(
+        "SELECT id FROM subscriptions WHERE id = $1 FOR UPDATE",
+        subscriber_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .map_err(e500)?;
+    if exists.is_none() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let schemas: Vec<AttributeSchema> = sqlx::query_scalar!(
+        r#"
+        SELECT l.attribute_schema AS "attribute_schema: JsonColumn<AttributeSchema>"
+        FROM lists l
+        JOIN list_subscriptions ls ON ls.list_id = l.list_id
+        WHERE ls.subscriber_id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .map_err(e500)?
+    .into_iter()
+    .map(|schema| schema.0)
+    .collect();
+    validate_attributes(&body.attributes, &schemas).map_err(|_| StatusCode::BAD_REQUEST)?;
+    sqlx::query!(
+        "UPDATE subscriptions SET attributes = $2 WHERE id = $1",
+        subscriber_id,
+        Value::Object(body.attributes.clone())
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(e500)?;
+    record_subscriber_events(
+        &mut *transaction,
+        &[subscriber_id],
+        Some(admin.user_id),
+        "set_attributes",
+        Some(&Value::Object(body.attributes.clone()).to_string()),
+    )
+    .await
+    .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    Ok(Json(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::assert_err;
+    use uuid::Uuid;
+
+    use super::{Cursor, SortColumn, SortOrder};
+
+    #[test]
+    fn cursors_round_trip() {
+        let cursor = Cursor {
+            sort: SortColumn::Email,
+            order: SortOrder::Asc,
+            key: "ursula@example.com".into(),
+            id: Uuid::new_v4(),
+        };
+        assert_eq!(Cursor::decode(&cursor.encode()), Ok(cursor));
+    }
+
+    #[test]
+    fn malformed_cursors_are_rejected() {
+        assert_err!(Cursor::decode("not base64!"));
+        assert_err!(Cursor::decode("bm90IGpzb24"));
+    }
+}
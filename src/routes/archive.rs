@@ -0,0 +1,415 @@
+use std::{fmt::Write, sync::Arc};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use sqlx::{PgPool, types::Json};
+
+use crate::{
+    attributes::{AttributeSchema, template_variables},
+    issue_delivery_worker::personalise,
+    startup::ApplicationState,
+    utils::{e500, escape_html},
+};
+
+const PAGE_SIZE: i64 = 10;
+const FEED_SIZE: i64 = 20;
+
+#[derive(Debug, Deserialize)]
+pub struct Pagination {
+    page: Option<i64>,
+}
+
+struct PublishedIssue {
+    slug: String,
+    title: String,
+    html_content: String,
+    published_at: DateTime<Utc>,
+    /// The attributes declared by the list the issue went to.
+    attribute_schema: Json<AttributeSchema>,
+}
+
+impl PublishedIssue {
+    /// The content of the issue addressed to nobody in particular: the
+    /// subscriber's details render as empty.
+    fn content(&self) -> String {
+        personalise(
+            &self.html_content,
+            template_variables("", "", &Default::default(), &self.attribute_schema, true),
+        )
+    }
+}
+
+/// What a cache needs to know to decide whether its copy is still fresh.
+struct Validators {
+    etag: String,
+    last_modified: Option<DateTime<Utc>>,
+}
+
+#[tracing::instrument(name = "Show the archive of past issues", skip(app_state, headers))]
+pub async fn archive_index(
+    State(app_state): State<Arc<ApplicationState>>,
+    Query(pagination): Query<Pagination>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let page = pagination.page.unwrap_or(1);
+    if page < 1 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    // No archive is long enough to reach pages this far.
+    let offset = (page - 1)
+        .checked_mul(PAGE_SIZE)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let validators = archive_validators(&app_state.pool, &format!("page-{}", page))
+        .await
+        .map_err(e500)?;
+    if is_fresh(&headers, &validators) {
+        return Ok(not_modified(&validators));
+    }
+
+    let mut issues = published_issues(&app_state.pool, PAGE_SIZE + 1, offset)
+        .await
+        .map_err(e500)?;
+    let has_older = issues.len() as i64 > PAGE_SIZE;
+    issues.truncate(PAGE_SIZE as usize);
+    if issues.is_empty() && page > 1 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let title = escape_html(&app_state.archive.title);
+    let mut body = String::new();
+    for issue in &issues {
+        writeln!(
+            body,
+            r#"<li><a href="/archive/{}">{}</a> <time datetime="{}">{}</time></li>"#,
+            escape_html(&issue.slug),
+            escape_html(&issue.title),
+            issue.published_at.to_rfc3339(),
+            issue.published_at.format("%B %-d, %Y"),
+        )
+        .unwrap();
+    }
+    let mut navigation = String::new();
+    if page > 1 {
+        write!(
+            navigation,
+            r#"<a rel="prev" href="/archive?page={}">Newer issues</a> "#,
+            page - 1
+        )
+        .unwrap();
+    }
+    if has_older {
+        write!(
+            navigation,
+            r#"<a rel="next" href="/archive?page={}">Older issues</a>"#,
+            page + 1
+        )
+        .unwrap();
+    }
+    let html = format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<link rel="alternate" type="application/rss+xml" href="/archive/feed.rss">
+<link rel="alternate" type="application/atom+xml" href="/archive/feed.atom">
+</head>
+<body>
+<h1>{title}</h1>
+<p>{description}</p>
+<ul>
+{body}</ul>
+<nav>{navigation}</nav>
+</body>
+</html>
+"#,
+        description = escape_html(&app_state.archive.description),
+    );
+    Ok(cacheable("text/html; charset=utf-8", &validators, html))
+}
+
+#[tracing::instrument(name = "Show a past issue", skip(app_state, headers))]
+pub async fn archived_issue(
+    State(app_state): State<Arc<ApplicationState>>,
+    Path(slug): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let issue = sqlx::query_as!(
+        PublishedIssue,
+        r#"
+        SELECT
+            i.slug, i.title, i.html_content, i.published_at AS "published_at!",
+            l.attribute_schema AS "attribute_schema: Json<AttributeSchema>"
+        FROM newsletter_issues i
+        JOIN lists l ON l.list_id = i.list_id
+        WHERE i.slug = $1 AND i.sent_at IS NOT NULL AND i.segment_id IS NULL
+        "#,
+        slug
+    )
+    .fetch_optional(&app_state.pool)
+    .await
+    .map_err(e500)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    // A published issue never changes.
+    let validators = Validators {
+        etag: format!(r#""{}-{}""#, issue.slug, issue.published_at.timestamp()),
+        last_modified: Some(issue.published_at),
+    };
+    if is_fresh(&headers, &validators) {
+        return Ok(not_modified(&validators));
+    }
+
+    let html = format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>{title} - {archive}</title>
+</head>
+<body>
+<p><a href="/archive">{archive}</a></p>
+<h1>{title}</h1>
+<p><time datetime="{datetime}">{date}</time></p>
+<article>
+{content}
+</article>
+</body>
+</html>
+"#,
+        title = escape_html(&issue.title),
+        archive = escape_html(&app_state.archive.title),
+        datetime = issue.published_at.to_rfc3339(),
+        date = issue.published_at.format("%B %-d, %Y"),
+        content = issue.content(),
+    );
+    Ok(cacheable("text/html; charset=utf-8", &validators, html))
+}
+
+#[tracing::instrument(name = "Serve the RSS feed of past issues", skip(app_state, headers))]
+pub async fn rss_feed(
+    State(app_state): State<Arc<ApplicationState>>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let validators = archive_validators(&app_state.pool, "rss")
+        .await
+        .map_err(e500)?;
+    if is_fresh(&headers, &validators) {
+        return Ok(not_modified(&validators));
+    }
+
+    let base_url = &app_state.base_url.0;
+    let issues = published_issues(&app_state.pool, FEED_SIZE, 0)
+        .await
+        .map_err(e500)?;
+    let mut items = String::new();
+    for issue in &issues {
+        let link = escape_html(&format!("{}/archive/{}", base_url, issue.slug));
+        writeln!(
+            items,
+            "<item><title>{}</title><link>{link}</link><guid isPermaLink=\"true\">{link}</guid>\
+            <pubDate>{}</pubDate><description>{}</description></item>",
+            escape_html(&issue.title),
+            issue.published_at.to_rfc2822(),
+            escape_html(&issue.content()),
+        )
+        .unwrap();
+    }
+    let last_build_date = validators
+        .last_modified
+        .map(|d| format!("<lastBuildDate>{}</lastBuildDate>", d.to_rfc2822()))
+        .unwrap_or_default();
+    let xml = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom">
+<channel>
+<title>{title}</title>
+<link>{link}</link>
+<description>{description}</description>
+<atom:link href="{self_link}" rel="self" type="application/rss+xml"/>
+{last_build_date}
+{items}</channel>
+</rss>
+"#,
+        title = escape_html(&app_state.archive.title),
+        link = escape_html(&format!("{}/archive", base_url)),
+        description = escape_html(&app_state.archive.description),
+        self_link = escape_html(&format!("{}/archive/feed.rss", base_url)),
+    );
+    Ok(cacheable(
+        "application/rss+xml; charset=utf-8",
+        &validators,
+        xml,
+    ))
+}
+
+#[tracing::instrument(name = "Serve the Atom feed of past issues", skip(app_state, headers))]
+pub async fn atom_feed(
+    State(app_state): State<Arc<ApplicationState>>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let validators = archive_validators(&app_state.pool, "atom")
+        .await
+        .map_err(e500)?;
+    if is_fresh(&headers, &validators) {
+        return Ok(not_modified(&validators));
+    }
+
+    let base_url = &app_state.base_url.0;
+    let issues = published_issues(&app_state.pool, FEED_SIZE, 0)
+        .await
+        .map_err(e500)?;
+    let mut entries = String::new();
+    for issue in &issues {
+        let link = escape_html(&format!("{}/archive/{}", base_url, issue.slug));
+        writeln!(
+            entries,
+            "<entry><id>{link}</id><title>{}</title><link href=\"{link}\"/>\
+            <updated>{published}</updated><published>{published}</published>\
+            <content type=\"html\">{}</content></entry>",
+            escape_html(&issue.title),
+            escape_html(&issue.content()),
+            published = issue.published_at.to_rfc3339(),
+        )
+        .unwrap();
+    }
+    // Atom requires `updated` even on an empty feed.
+    let updated = validators
+        .last_modified
+        .unwrap_or(DateTime::UNIX_EPOCH)
+        .to_rfc3339();
+    let xml = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+<id>{link}</id>
+<title>{title}</title>
+<subtitle>{description}</subtitle>
+<link href="{link}"/>
+<link href="{self_link}" rel="self"/>
+<updated>{updated}</updated>
+<author><name>{title}</name></author>
+{entries}</feed>
+"#,
+        title = escape_html(&app_state.archive.title),
+        link = escape_html(&format!("{}/archive", base_url)),
+        description = escape_html(&app_state.archive.description),
+        self_link = escape_html(&format!("{}/archive/feed.atom", base_url)),
+    );
+    Ok(cacheable(
+        "application/atom+xml; charset=utf-8",
+        &validators,
+        xml,
+    ))
+}
+
+/// The issues sent to a whole list, newest first. Issues still being
+/// delivered, or sent to a segment only, are left out.
+async fn published_issues(
+    pool: &PgPool,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<PublishedIssue>, sqlx::Error> {
+    sqlx::query_as!(
+        PublishedIssue,
+        r#"
+        SELECT
+            i.slug, i.title, i.html_content, i.published_at AS "published_at!",
+            l.attribute_schema AS "attribute_schema: Json<AttributeSchema>"
+        FROM newsletter_issues i
+        JOIN lists l ON l.list_id = i.list_id
+        WHERE i.sent_at IS NOT NULL AND i.segment_id IS NULL
+        ORDER BY i.published_at DESC, i.newsletter_issue_id
+        LIMIT $1 OFFSET $2
+        "#,
+        limit,
+        offset
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Published issues never change, so the archive only changes when an issue
+/// is done being sent: the count and the latest time one was identify its
+/// current version.
+async fn archive_validators(
+    pool: &PgPool,
+    representation: &str,
+) -> Result<Validators, sqlx::Error> {
+    let version = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "count!", MAX(sent_at) AS last_modified
+        FROM newsletter_issues
+        WHERE sent_at IS NOT NULL AND segment_id IS NULL
+        "#
+    )
+    .fetch_one(pool)
+    .await?;
+    let timestamp = version
+        .last_modified
+        .map(|d| d.timestamp_micros())
+        .unwrap_or_default();
+    Ok(Validators {
+        etag: format!(r#""{}-{}-{}""#, representation, version.count, timestamp),
+        last_modified: version.last_modified,
+    })
+}
+
+/// Evaluate the conditional request headers, giving `If-None-Match`
+/// precedence over `If-Modified-Since` as RFC 9110 requires.
+fn is_fresh(headers: &HeaderMap, validators: &Validators) -> bool {
+    if let Some(if_none_match) = headers.get(header::IF_NONE_MATCH) {
+        let Ok(if_none_match) = if_none_match.to_str() else {
+            return false;
+        };
+        return if_none_match
+            .split(',')
+            .map(str::trim)
+            .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == validators.etag);
+    }
+    let if_modified_since = headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| DateTime::parse_from_rfc2822(value).ok());
+    match (if_modified_since, validators.last_modified) {
+        // HTTP dates have a one second resolution.
+        (Some(since), Some(last_modified)) => last_modified.timestamp() <= since.timestamp(),
+        _ => false,
+    }
+}
+
+fn not_modified(validators: &Validators) -> Response {
+    let mut response = StatusCode::NOT_MODIFIED.into_response();
+    set_cache_headers(response.headers_mut(), validators);
+    response
+}
+
+fn cacheable(content_type: &'static str, validators: &Validators, body: String) -> Response {
+    let mut response = body.into_response();
+    let headers = response.headers_mut();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    set_cache_headers(headers, validators);
+    response
+}
+
+fn set_cache_headers(headers: &mut HeaderMap, validators: &Validators) {
+    headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static("public, max-age=60"),
+    );
+    if let Ok(etag) = HeaderValue::from_str(&validators.etag) {
+        headers.insert(header::ETAG, etag);
+    }
+    if let Some(last_modified) = validators.last_modified {
+        let http_date = last_modified
+            .format("%a, %d %b %Y %H:%M:%S GMT")
+            .to_string();
+        if let Ok(last_modified) = HeaderValue::from_str(&http_date) {
+            headers.insert(header::LAST_MODIFIED, last_modified);
+        }
+    }
+}
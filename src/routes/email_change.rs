@@ -0,0 +1,262 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Form, Query, State},
+    http::StatusCode,
+    response::Html,
+};
+use chrono::Utc;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    domain::SubscriberEmail,
+    preferences::PreferenceToken,
+    startup::ApplicationState,
+    utils::{e500, escape_html, generate_token},
+};
+
+/// The fields of the change-of-address form: the preference link it was
+/// reached from, and the new address.
+#[derive(Deserialize)]
+pub struct EmailChangeForm {
+    pub subscriber_id: Uuid,
+    pub expires: i64,
+    pub sig: String,
+    pub email: String,
+}
+
+/// Email a link confirming the new address of a subscriber to that address.
+/// Nothing changes until the link is followed.
+///
+/// Addresses belonging to another subscriber get the same answer and no
+/// email, so that the form does not tell who subscribes.
+#[tracing::instrument(
+    name = "Request a change of address",
+    skip(form, app_state),
+    fields(subscriber_id = %form.subscriber_id)
+)]
+pub async fn request_email_change(
+    State(app_state): State<Arc<ApplicationState>>,
+    Form(form): Form<EmailChangeForm>,
+) -> Result<Html<String>, StatusCode> {
+    let token = PreferenceToken {
+        subscriber_id: form.subscriber_id,
+        expires: form.expires,
+        sig: form.sig,
+    };
+    if !app_state.preference_links.verify(&token, Utc::now()) {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    let new_email = SubscriberEmail::parse(form.email).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let current_email = sqlx::query_scalar!(
+        "SELECT email FROM subscriptions WHERE id = $1",
+        token.subscriber_id
+    )
+    .fetch_optional(&app_state.pool)
+    .await
+    .map_err(e500)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+    if current_email == new_email.as_ref() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let taken = sqlx::query_scalar!(
+        r#"SELECT EXISTS(SELECT 1 FROM subscriptions WHERE email = $1) AS "taken!""#,
+        new_email.as_ref()
+    )
+    .fetch_one(&app_state.pool)
+    .await
+    .map_err(e500)?;
+    if taken {
+        tracing::info!("The new address belongs to another subscriber");
+        return Ok(message_page(CHECK_INBOX));
+    }
+
+    let email_change_token = generate_token();
+    sqlx::query!(
+        r#"
+        INSERT INTO email_change_tokens (
+            email_change_token, subscriber_id, new_email, requested_at
+        )
+        VALUES ($1, $2, $3, now())
+        "#,
+        email_change_token,
+        token.subscriber_id,
+        new_email.as_ref()
+    )
+    .execute(&app_state.pool)
+    .await
+    .map_err(e500)?;
+
+    let link = format!(
+        "{}/preferences/email/confirm?email_change_token={}",
+        app_state.base_url.0, email_change_token
+    );
+    let html_body = format!(
+        "Click <a href=\"{}\">here</a> to receive our emails at this address.<br />\
+        The link is valid for 24 hours.",
+        escape_html(&link)
+    );
+    let text_body = format!(
+        "Visit {} to receive our emails at this address.\nThe link is valid for 24 hours.",
+        link
+    );
+    app_state
+        .email_client
+        .send_email(
+            new_email,
+            "Confirm your new address",
+            &html_body,
+            &text_body,
+        )
+        .await
+        .map_err(e500)?;
+    Ok(message_page(CHECK_INBOX))
+}
+
+const CHECK_INBOX: &str = "Check the inbox of your new address to confirm it.";
+
+#[derive(Deserialize)]
+pub struct Parameters {
+    email_change_token: String,
+}
+
+/// Move a subscriber to the address a change token was sent to, and let the
+/// old address know. Every other pending change is dropped.
+#[tracing::instrument(
+    name = "Confirm a change of address",
+    skip(parameters, app_state),
+    fields(subscriber_id = tracing::field::Empty)
+)]
+pub async fn confirm_email_change(
+    State(app_state): State<Arc<ApplicationState>>,
+    Query(parameters): Query<Parameters>,
+) -> Result<Html<String>, StatusCode> {
+    let mut transaction = app_state.pool.begin().await.map_err(e500)?;
+    let change = sqlx::query!(
+        r#"
+        SELECT t.subscriber_id, t.new_email, s.email AS old_email
+        FROM email_change_tokens t
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        WHERE
+            t.email_change_token = $1 AND
+            t.requested_at > now() - INTERVAL '24 hours'
+        FOR UPDATE OF s
+        "#,
+        parameters.email_change_token
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .map_err(e500)?
+    .ok_or(StatusCode::UNAUTHORIZED)?;
+    tracing::Span::current().record(
+        "subscriber_id",
+        tracing::field::display(change.subscriber_id),
+    );
+
+    // Someone else may have subscribed with the new address in the meantime.
+    let updated = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET email = $2, status = 'confirmed'
+        WHERE
+            id = $1 AND
+            NOT EXISTS (SELECT 1 FROM subscriptions WHERE email = $2)
+        "#,
+        change.subscriber_id,
+        change.new_email
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(e500)?;
+    if updated.rows_affected() == 0 {
+        return Err(StatusCode::CONFLICT);
+    }
+    // Issues on their way to the old address follow the subscriber.
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET subscriber_email = $2
+        WHERE subscriber_email = $1
+        "#,
+        change.old_email,
+        change.new_email
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(e500)?;
+    // Along with their record, for the worker to find it once it sends them.
+    sqlx::query!(
+        r#"
+        UPDATE issue_deliveries
+        SET subscriber_email = $2
+        WHERE subscriber_id = $1 AND status = 'queued'
+        "#,
+        change.subscriber_id,
+        change.new_email
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(e500)?;
+    sqlx::query!(
+        "DELETE FROM email_change_tokens WHERE subscriber_id = $1",
+        change.subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+
+    notify_old_address(&app_state, &change.old_email, &change.new_email).await;
+    Ok(message_page("Your new address has been confirmed."))
+}
+
+/// Tell the previous owner of the subscription where it went, in case they
+/// did not ask for the change. The change stands if this fails.
+async fn notify_old_address(app_state: &ApplicationState, old_email: &str, new_email: &str) {
+    let old_email = match SubscriberEmail::parse(old_email.to_owned()) {
+        Ok(email) => email,
+        Err(e) => {
+            tracing::warn!("Not notifying an invalid address: {}", e);
+            return;
+        }
+    };
+    let html_body = format!(
+        "Your subscription has moved to {}.<br />\
+        If you did not ask for this, reply to this email.",
+        escape_html(new_email)
+    );
+    let text_body = format!(
+        "Your subscription has moved to {}.\nIf you did not ask for this, reply to this email.",
+        new_email
+    );
+    if let Err(e) = app_state
+        .email_client
+        .send_email(
+            old_email,
+            "Your address has been changed",
+            &html_body,
+            &text_body,
+        )
+        .await
+    {
+        tracing::error!("Failed to notify the old address: {:?}", e);
+    }
+}
+
+fn message_page(message: &str) -> Html<String> {
+    Html(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>Your address</title>
+</head>
+<body>
+<p>{}</p>
+</body>
+</html>
+"#,
+        message
+    ))
+}
@@ -1,3 +1,9 @@
+pub mod admin;
+pub mod archive;
+pub mod email_change;
 pub mod health_check;
+pub mod preferences;
 pub mod subscriptions;
 pub mod subscriptions_confirm;
+pub mod tracking;
+pub mod webhooks;
@@ -0,0 +1,487 @@
+use std::{fmt::Write, sync::Arc};
+
+use axum::{
+    Json,
+    extract::{Form, Query, State},
+    http::{StatusCode, header},
+    response::{Html, IntoResponse, Response},
+};
+use chrono::Utc;
+use serde::Deserialize;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    audit::record_subscriber_events,
+    domain::{DeliveryFrequency, SubscriberEmail, SubscriberName},
+    erasure::erase_subscribers,
+    lists::find_lists,
+    preferences::PreferenceToken,
+    startup::ApplicationState,
+    subscriber_data::{SubscriberData, compile_subscriber_data},
+    utils::{e500, escape_html},
+};
+
+#[derive(Deserialize)]
+pub struct LinkRequest {
+    pub email: String,
+}
+
+/// Email a subscriber a link to their preferences.
+///
+/// The answer is the same whether the address is known or not, so that the
+/// endpoint does not tell who subscribes.
+#[tracing::instrument(name = "Send a link to the preference center", skip(form, app_state))]
+pub async fn request_preferences_link(
+    State(app_state): State<Arc<ApplicationState>>,
+    Form(form): Form<LinkRequest>,
+) -> Result<StatusCode, StatusCode> {
+    let email = SubscriberEmail::parse(form.email).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let subscriber_id = sqlx::query_scalar!(
+        "SELECT id FROM subscriptions WHERE email = $1",
+        email.as_ref()
+    )
+    .fetch_optional(&app_state.pool)
+    .await
+    .map_err(e500)?;
+    let Some(subscriber_id) = subscriber_id else {
+        tracing::info!("No subscriber to send a preference link to");
+        return Ok(StatusCode::OK);
+    };
+
+    let link = app_state.preference_links.link(subscriber_id, Utc::now());
+    let html_body = format!(
+        "Click <a href=\"{}\">here</a> to manage your subscription.<br />\
+        The link is valid for 24 hours.",
+        escape_html(&link)
+    );
+    let text_body = format!(
+        "Visit {} to manage your subscription.\nThe link is valid for 24 hours.",
+        link
+    );
+    app_state
+        .email_client
+        .send_email(email, "Manage your subscription", &html_body, &text_body)
+        .await
+        .map_err(e500)?;
+    Ok(StatusCode::OK)
+}
+
+#[tracing::instrument(
+    name = "Show the preferences of a subscriber",
+    skip(token, app_state),
+    fields(subscriber_id = %token.subscriber_id)
+)]
+pub async fn preferences_page(
+    State(app_state): State<Arc<ApplicationState>>,
+    Query(token): Query<PreferenceToken>,
+) -> Result<Html<String>, StatusCode> {
+    if !app_state.preference_links.verify(&token, Utc::now()) {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    let subscriber = sqlx::query!(
+        "SELECT name, frequency FROM subscriptions WHERE id = $1",
+        token.subscriber_id
+    )
+    .fetch_optional(&app_state.pool)
+    .await
+    .map_err(e500)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+    let lists = list_choices(&app_state.pool, token.subscriber_id)
+        .await
+        .map_err(e500)?;
+
+    let mut list_inputs = String::new();
+    for list in &lists {
+        writeln!(
+            list_inputs,
+            r#"<label><input type="checkbox" name="list" value="{}"{}> {}</label><br>"#,
+            escape_html(&list.slug),
+            if list.subscribed { " checked" } else { "" },
+            escape_html(&list.name),
+        )
+        .unwrap();
+    }
+    let mut frequency_options = String::new();
+    for frequency in DeliveryFrequency::ALL {
+        writeln!(
+            frequency_options,
+            r#"<option value="{}"{}>{}</option>"#,
+            frequency.as_str(),
+            if frequency.as_str() == subscriber.frequency {
+                " selected"
+            } else {
+                ""
+            },
+            frequency.label(),
+        )
+        .unwrap();
+    }
+    let html = format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>Your preferences</title>
+</head>
+<body>
+<h1>Your preferences</h1>
+<form method="post" action="/preferences">
+<input type="hidden" name="subscriber_id" value="{subscriber_id}">
+<input type="hidden" name="expires" value="{expires}">
+<input type="hidden" name="sig" value="{sig}">
+<p><label>Name <input name="name" value="{name}"></label></p>
+<fieldset>
+<legend>Lists</legend>
+{list_inputs}</fieldset>
+<p><label>Frequency <select name="frequency">
+{frequency_options}</select></label></p>
+<p><label><input type="checkbox" name="unsubscribe" value="true"> Unsubscribe from everything</label></p>
+<button type="submit">Save</button>
+</form>
+<h2>Your address</h2>
+<form method="post" action="/preferences/email">
+<input type="hidden" name="subscriber_id" value="{subscriber_id}">
+<input type="hidden" name="expires" value="{expires}">
+<input type="hidden" name="sig" value="{sig}">
+<p><label>New address <input type="email" name="email"></label></p>
+<button type="submit">Change address</button>
+</form>
+<h2>Your data</h2>
+<p><a href="/preferences/data?subscriber_id={subscriber_id}&amp;expires={expires}&amp;sig={sig}">Download everything we store about you</a></p>
+<form method="post" action="/preferences/erase">
+<input type="hidden" name="subscriber_id" value="{subscriber_id}">
+<input type="hidden" name="expires" value="{expires}">
+<input type="hidden" name="sig" value="{sig}">
+<p><label><input type="checkbox" name="confirm" value="true" required> I understand that this cannot be undone</label></p>
+<button type="submit">Erase everything about me</button>
+</form>
+</body>
+</html>
+"#,
+        subscriber_id = token.subscriber_id,
+        expires = token.expires,
+        sig = escape_html(&token.sig),
+        name = escape_html(&subscriber.name),
+    );
+    Ok(Html(html))
+}
+
+/// The fields of the preference form. Lists come as repeated `list` fields,
+/// hence the manual parsing.
+struct PreferencesForm {
+    token: PreferenceToken,
+    name: Option<String>,
+    frequency: Option<String>,
+    lists: Vec<String>,
+    unsubscribe: bool,
+}
+
+impl TryFrom<Vec<(String, String)>> for PreferencesForm {
+    type Error = String;
+
+    fn try_from(fields: Vec<(String, String)>) -> Result<Self, Self::Error> {
+        let (mut subscriber_id, mut expires, mut sig) = (None, None, None);
+        let (mut name, mut frequency) = (None, None);
+        let mut lists = Vec::new();
+        let mut unsubscribe = false;
+        for (key, value) in fields {
+            match key.as_str() {
+                "subscriber_id" => subscriber_id = Some(value),
+                "expires" => expires = Some(value),
+                "sig" => sig = Some(value),
+                "name" => name = Some(value),
+                "frequency" => frequency = Some(value),
+                "list" => lists.push(value),
+                "unsubscribe" => unsubscribe = value == "true",
+                _ => {}
+            }
+        }
+        let token = PreferenceToken {
+            subscriber_id: subscriber_id
+                .ok_or("Missing subscriber_id")?
+                .parse()
+                .map_err(|_| "Invalid subscriber_id")?,
+            expires: expires
+                .ok_or("Missing expires")?
+                .parse()
+                .map_err(|_| "Invalid expires")?,
+            sig: sig.ok_or("Missing sig")?,
+        };
+        Ok(Self {
+            token,
+            name,
+            frequency,
+            lists,
+            unsubscribe,
+        })
+    }
+}
+
+/// Validated changes to the preferences of a subscriber.
+struct PreferenceUpdate {
+    name: SubscriberName,
+    frequency: DeliveryFrequency,
+    lists: Vec<String>,
+}
+
+impl TryFrom<PreferencesForm> for PreferenceUpdate {
+    type Error = String;
+
+    fn try_from(form: PreferencesForm) -> Result<Self, Self::Error> {
+        let name = SubscriberName::parse(form.name.ok_or("Missing name")?)?;
+        let frequency = DeliveryFrequency::parse(form.frequency.ok_or("Missing frequency")?)?;
+        let mut lists = form.lists;
+        lists.sort();
+        lists.dedup();
+        Ok(Self {
+            name,
+            frequency,
+            lists,
+        })
+    }
+}
+
+#[tracing::instrument(
+    name = "Update the preferences of a subscriber",
+    skip_all,
+    fields(subscriber_id = tracing::field::Empty)
+)]
+pub async fn update_preferences(
+    State(app_state): State<Arc<ApplicationState>>,
+    Form(fields): Form<Vec<(String, String)>>,
+) -> Result<Html<String>, StatusCode> {
+    let form = PreferencesForm::try_from(fields).map_err(|_| StatusCode::BAD_REQUEST)?;
+    if !app_state.preference_links.verify(&form.token, Utc::now()) {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    let subscriber_id = form.token.subscriber_id;
+    tracing::Span::current().record("subscriber_id", tracing::field::display(subscriber_id));
+
+    let mut transaction = app_state.pool.begin().await.map_err(e500)?;
+    let message = if form.unsubscribe {
+        leave_lists(&mut transaction, subscriber_id, &[])
+            .await
+            .map_err(e500)?;
+        "You have been unsubscribed from everything."
+    } else {
+        let update = PreferenceUpdate::try_from(form).map_err(|_| StatusCode::BAD_REQUEST)?;
+        let lists = find_lists(&mut *transaction, &update.lists)
+            .await
+            .map_err(e500)?;
+        if lists.len() != update.lists.len() {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        let list_ids: Vec<Uuid> = lists.iter().map(|list| list.list_id).collect();
+        // The subscriber followed a link sent to their address: it is theirs.
+        let updated = sqlx::query!(
+            r#"
+            UPDATE subscriptions
+            SET name = $2, frequency = $3, status = 'confirmed'
+            WHERE id = $1
+            "#,
+            subscriber_id,
+            update.name.as_ref(),
+            update.frequency.as_str()
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(e500)?;
+        if updated.rows_affected() == 0 {
+            return Err(StatusCode::NOT_FOUND);
+        }
+        sqlx::query!(
+            r#"
+            INSERT INTO list_subscriptions (
+                list_id, subscriber_id, status, subscribed_at, confirmed_at
+            )
+            SELECT list_id, $1, 'confirmed', now(), now()
+            FROM UNNEST($2::uuid[]) AS list_id
+            ON CONFLICT (list_id, subscriber_id) DO UPDATE
+            SET
+                status = 'confirmed',
+                confirmed_at = CASE
+                    WHEN list_subscriptions.status = 'confirmed' THEN list_subscriptions.confirmed_at
+                    ELSE now()
+                END
+            "#,
+            subscriber_id,
+            &list_ids
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(e500)?;
+        leave_lists(&mut transaction, subscriber_id, &list_ids)
+            .await
+            .map_err(e500)?;
+        "Your preferences have been saved."
+    };
+    transaction.commit().await.map_err(e500)?;
+    Ok(Html(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>Your preferences</title>
+</head>
+<body>
+<p>{}</p>
+</body>
+</html>
+"#,
+        message
+    )))
+}
+
+/// Everything we store about the subscriber, as a JSON file.
+#[tracing::instrument(
+    name = "Download the data of a subscriber",
+    skip(token, app_state),
+    fields(subscriber_id = %token.subscriber_id)
+)]
+pub async fn download_data(
+    State(app_state): State<Arc<ApplicationState>>,
+    Query(token): Query<PreferenceToken>,
+) -> Result<Response, StatusCode> {
+    if !app_state.preference_links.verify(&token, Utc::now()) {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    let data = compile_subscriber_data(&app_state.pool, token.subscriber_id)
+        .await
+        .map_err(e500)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    record_subscriber_events(
+        &app_state.pool,
+        &[token.subscriber_id],
+        None,
+        "export_data",
+        Some("Requested by the subscriber"),
+    )
+    .await
+    .map_err(e500)?;
+    Ok(data_download(data))
+}
+
+/// The fields of the erasure form: the preference link it was reached from,
+/// and the box the subscriber ticked to confirm.
+#[derive(Deserialize)]
+pub struct ErasureForm {
+    pub subscriber_id: Uuid,
+    pub expires: i64,
+    pub sig: String,
+    #[serde(default)]
+    pub confirm: bool,
+}
+
+/// Erase the subscriber and their personal data, see [`erase_subscribers`].
+#[tracing::instrument(
+    name = "Erase a subscriber at their request",
+    skip(form, app_state),
+    fields(subscriber_id = %form.subscriber_id)
+)]
+pub async fn erase_data(
+    State(app_state): State<Arc<ApplicationState>>,
+    Form(form): Form<ErasureForm>,
+) -> Result<Html<String>, StatusCode> {
+    let token = PreferenceToken {
+        subscriber_id: form.subscriber_id,
+        expires: form.expires,
+        sig: form.sig,
+    };
+    if !app_state.preference_links.verify(&token, Utc::now()) {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    if !form.confirm {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let mut transaction = app_state.pool.begin().await.map_err(e500)?;
+    let erased = erase_subscribers(&mut transaction, &[token.subscriber_id])
+        .await
+        .map_err(e500)?;
+    if erased.is_empty() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    record_subscriber_events(
+        &mut *transaction,
+        &erased,
+        None,
+        "erase",
+        Some("Requested by the subscriber"),
+    )
+    .await
+    .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    Ok(Html(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>Your data</title>
+</head>
+<body>
+<p>Everything we stored about you has been erased.</p>
+</body>
+</html>
+"#
+        .to_owned(),
+    ))
+}
+
+/// Serve the data of a subscriber as a file to save.
+pub fn data_download(data: SubscriberData) -> Response {
+    let disposition = format!(
+        r#"attachment; filename="subscriber-{}.json""#,
+        data.profile.id
+    );
+    ([(header::CONTENT_DISPOSITION, disposition)], Json(data)).into_response()
+}
+
+/// Unsubscribe from every list but the ones in `kept`.
+async fn leave_lists(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    kept: &[Uuid],
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE list_subscriptions
+        SET status = 'unsubscribed'
+        WHERE
+            subscriber_id = $1 AND
+            status <> 'unsubscribed' AND
+            NOT (list_id = ANY($2))
+        "#,
+        subscriber_id,
+        kept
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+struct ListChoice {
+    slug: String,
+    name: String,
+    subscribed: bool,
+}
+
+/// Every list, and whether the subscriber receives it.
+async fn list_choices(pool: &PgPool, subscriber_id: Uuid) -> Result<Vec<ListChoice>, sqlx::Error> {
+    sqlx::query_as!(
+        ListChoice,
+        r#"
+        SELECT
+            l.slug,
+            l.name,
+            COALESCE(ls.status = 'confirmed', false) AS "subscribed!"
+        FROM lists l
+        LEFT JOIN list_subscriptions ls ON
+            ls.list_id = l.list_id AND
+            ls.subscriber_id = $1
+        ORDER BY l.created_at, l.slug
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+}
@@ -1,8 +1,13 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
 use crate::{
+    attributes::AttributeType,
+    consent::{ConsentContext, record_consent},
     domain::{NewSubscriber, SubscriberEmail, SubscriberName},
+    email_client::EmailClient,
+    lists::{DEFAULT_LIST, List, find_lists},
     startup::ApplicationState,
+    utils::{escape_html, generate_token},
 };
 use axum::{
     extract::{Form, State},
@@ -11,12 +16,63 @@ use axum::{
 };
 use chrono::Utc;
 use serde::Deserialize;
+use sqlx::{Postgres, Transaction};
 use uuid::Uuid;
 
+/// The longest `source` a subscription form can give.
+const MAX_SOURCE_LENGTH: usize = 100;
+
 #[derive(Deserialize)]
 pub struct FormData {
     pub email: String,
     pub name: String,
+    /// Comma-separated slugs of the lists to join, the default list when
+    /// missing.
+    pub lists: Option<String>,
+    /// Where the form is, e.g. `homepage`, kept with the consent record.
+    pub source: Option<String>,
+    /// Any other field, kept as a custom attribute if one of the lists
+    /// declares it.
+    #[serde(flatten)]
+    pub fields: HashMap<String, String>,
+}
+
+impl FormData {
+    fn list_slugs(&self) -> Vec<String> {
+        let mut slugs: Vec<String> = match &self.lists {
+            Some(lists) => lists
+                .split(',')
+                .map(|slug| slug.trim().to_owned())
+                .filter(|slug| !slug.is_empty())
+                .collect(),
+            None => vec![DEFAULT_LIST.to_owned()],
+        };
+        slugs.sort();
+        slugs.dedup();
+        slugs
+    }
+}
+
+/// The custom attributes `lists` collect, parsed from the extra `fields` of
+/// the form. Undeclared fields are dropped, empty ones skipped.
+fn collect_attributes(
+    fields: &HashMap<String, String>,
+    lists: &[List],
+) -> Result<serde_json::Map<String, serde_json::Value>, String> {
+    let mut attributes = serde_json::Map::new();
+    for (name, value) in fields {
+        let declared: Option<AttributeType> = lists
+            .iter()
+            .find_map(|list| list.attribute_schema.get(name).copied());
+        let Some(attribute_type) = declared else {
+            continue;
+        };
+        if value.trim().is_empty() {
+            continue;
+        }
+        attributes.insert(name.clone(), attribute_type.parse(value)?);
+    }
+    Ok(attributes)
 }
 
 impl TryFrom<FormData> for NewSubscriber {
@@ -31,7 +87,7 @@ impl TryFrom<FormData> for NewSubscriber {
 
 #[tracing::instrument(
     name= "Adding a new subscriber",
-    skip(form, app_state),
+    skip(form, app_state, consent),
     fields(
         subscriber_email = %form.email,
         subscriber_name = %form.name
@@ -39,74 +95,302 @@ impl TryFrom<FormData> for NewSubscriber {
 )]
 pub async fn subscribe(
     State(app_state): State<Arc<ApplicationState>>,
-    Form(form): Form<FormData>,
+    consent: ConsentContext,
+    Form(mut form): Form<FormData>,
 ) -> impl IntoResponse {
-    let new_subscriber = match form.try_into() {
+    let list_slugs = form.list_slugs();
+    let fields = std::mem::take(&mut form.fields);
+    let source = form
+        .source
+        .take()
+        .filter(|source| !source.trim().is_empty());
+    if source
+        .as_ref()
+        .is_some_and(|source| source.len() > MAX_SOURCE_LENGTH)
+    {
+        return StatusCode::BAD_REQUEST;
+    }
+    let new_subscriber: NewSubscriber = match form.try_into() {
         Ok(form) => form,
         Err(_) => return StatusCode::BAD_REQUEST,
     };
+    if list_slugs.is_empty() {
+        return StatusCode::BAD_REQUEST;
+    }
+
+    let mut transaction = match app_state.pool.begin().await {
+        Ok(transaction) => transaction,
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR,
+    };
+
+    let lists = match find_lists(&mut *transaction, &list_slugs).await {
+        Ok(lists) if lists.len() == list_slugs.len() => lists,
+        Ok(_) => return StatusCode::BAD_REQUEST,
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    let attributes = match collect_attributes(&fields, &lists) {
+        Ok(attributes) => attributes,
+        Err(_) => return StatusCode::BAD_REQUEST,
+    };
 
-    if insert_subscriber(&app_state, &new_subscriber)
+    let subscriber_id = match find_subscriber(&mut transaction, &new_subscriber).await {
+        Ok(Some(subscriber_id)) => subscriber_id,
+        Ok(None) => match insert_subscriber(&mut transaction, &new_subscriber).await {
+            Ok(subscriber_id) => subscriber_id,
+            Err(_) => return StatusCode::INTERNAL_SERVER_ERROR,
+        },
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR,
+    };
+
+    if add_attributes(&mut transaction, subscriber_id, attributes)
         .await
         .is_err()
     {
         return StatusCode::INTERNAL_SERVER_ERROR;
     }
 
-    if send_confirmation_email(&app_state, new_subscriber, &app_state.base_url.0)
+    let pending_lists = match join_lists(&mut transaction, subscriber_id, lists).await {
+        Ok(pending_lists) => pending_lists,
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    if record_consent(
+        &mut *transaction,
+        subscriber_id,
+        "subscribe",
+        &list_slugs,
+        source.as_deref(),
+        &consent,
+    )
+    .await
+    .is_err()
+    {
+        return StatusCode::INTERNAL_SERVER_ERROR;
+    }
+    // Nothing left for the subscriber to confirm.
+    if pending_lists.is_empty() {
+        return match transaction.commit().await {
+            Ok(_) => StatusCode::OK,
+            Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+    }
+
+    let subscription_token = generate_token();
+    if store_token(&mut transaction, subscriber_id, &subscription_token)
         .await
         .is_err()
     {
         return StatusCode::INTERNAL_SERVER_ERROR;
     }
 
+    if transaction.commit().await.is_err() {
+        return StatusCode::INTERNAL_SERVER_ERROR;
+    }
+
+    if send_confirmation_email(
+        &app_state.email_client,
+        new_subscriber,
+        &pending_lists,
+        &app_state.base_url.0,
+        &subscription_token,
+    )
+    .await
+    .is_err()
+    {
+        return StatusCode::INTERNAL_SERVER_ERROR;
+    }
+
     StatusCode::OK
 }
 
+/// Send the link confirming every list in `pending_lists`. The email goes out
+/// as the first of them.
 #[tracing::instrument(
     name = "Send a confirmation email to a new subscriber",
-    skip(app_state, new_subscriber)
+    skip(email_client, new_subscriber, pending_lists, subscription_token)
 )]
 pub async fn send_confirmation_email(
-    app_state: &Arc<ApplicationState>,
+    email_client: &EmailClient,
     new_subscriber: NewSubscriber,
+    pending_lists: &[List],
     base_url: &str,
+    subscription_token: &str,
 ) -> Result<(), reqwest::Error> {
-    let confirmation_link = format!("{}/subscriptions/confirm", base_url);
+    let confirmation_link = format!(
+        "{}/subscriptions/confirm?subscription_token={}",
+        base_url, subscription_token
+    );
+    let list_names = pending_lists
+        .iter()
+        .map(|list| list.name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
 
     let plain_body = &format!(
-        "Welcome to our newsletter!<br />\
+        "Welcome to {}!<br />\
         Click <a href=\"{}\">here</a> to confirm your subscription.",
+        escape_html(&list_names),
         confirmation_link
     );
 
     let html_body = &format!(
-        "Welcome to our newsletter!\nVisit {} to confirm your subscription.",
-        confirmation_link
+        "Welcome to {}!\nVisit {} to confirm your subscription.",
+        list_names, confirmation_link
     );
 
-    app_state
-        .email_client
-        .send_email(new_subscriber.email, "Welcome", html_body, plain_body)
+    let list = &pending_lists[0];
+    email_client
+        .send_email_from(
+            list.sender().as_deref(),
+            new_subscriber.email,
+            &list.confirmation_subject,
+            html_body,
+            plain_body,
+        )
         .await
+        .map(|_| ())
+}
+
+#[tracing::instrument(name = "Looking up an existing subscriber", skip_all)]
+pub async fn find_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    new_subscriber: &NewSubscriber,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let subscriber_id = sqlx::query_scalar!(
+        "SELECT id FROM subscriptions WHERE email = $1",
+        new_subscriber.email.as_ref()
+    )
+    .fetch_optional(&mut **transaction)
+    .await?;
+    Ok(subscriber_id)
+}
+
+/// Add the subscriber to `lists`, returning the ones where their
+/// subscription still has to be confirmed.
+///
+/// Anyone can submit the form for any address, so joining a list with double
+/// opt-in always takes a confirmation, even for a subscriber who confirmed
+/// their address for another list. Lists the subscriber is a member of
+/// already are left as they are, unless the subscriber had left them.
+#[tracing::instrument(name = "Adding a subscriber to lists", skip_all)]
+pub async fn join_lists(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    lists: Vec<List>,
+) -> Result<Vec<List>, sqlx::Error> {
+    let mut pending_lists = Vec::new();
+    for list in lists {
+        let status = if list.double_opt_in {
+            "pending_confirmation"
+        } else {
+            "confirmed"
+        };
+        let status = sqlx::query_scalar!(
+            r#"
+            INSERT INTO list_subscriptions (
+                list_id, subscriber_id, status, subscribed_at, confirmed_at
+            )
+            VALUES ($1, $2, $3, now(), CASE WHEN $3 = 'confirmed' THEN now() END)
+            ON CONFLICT (list_id, subscriber_id) DO UPDATE
+            SET
+                status = EXCLUDED.status,
+                subscribed_at = EXCLUDED.subscribed_at,
+                confirmed_at = EXCLUDED.confirmed_at
+            WHERE list_subscriptions.status = 'unsubscribed'
+            RETURNING status
+            "#,
+            list.list_id,
+            subscriber_id,
+            status
+        )
+        .fetch_optional(&mut **transaction)
+        .await?;
+        let status = match status {
+            Some(status) => status,
+            // A member already: they might not have confirmed yet.
+            None => {
+                sqlx::query_scalar!(
+                    r#"
+                SELECT status FROM list_subscriptions
+                WHERE list_id = $1 AND subscriber_id = $2
+                "#,
+                    list.list_id,
+                    subscriber_id
+                )
+                .fetch_one(&mut **transaction)
+                .await?
+            }
+        };
+        if status == "pending_confirmation" {
+            pending_lists.push(list);
+        }
+    }
+    Ok(pending_lists)
+}
+
+/// Record custom attributes, keeping the values already known: anyone can
+/// submit the form for any address.
+#[tracing::instrument(
+    name = "Adding attributes to a subscriber",
+    skip(transaction, attributes)
+)]
+pub async fn add_attributes(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    attributes: serde_json::Map<String, serde_json::Value>,
+) -> Result<(), sqlx::Error> {
+    if attributes.is_empty() {
+        return Ok(());
+    }
+    sqlx::query!(
+        "UPDATE subscriptions SET attributes = $2 || attributes WHERE id = $1",
+        subscriber_id,
+        serde_json::Value::Object(attributes)
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
 }
 
 #[tracing::instrument(
     name = "Saving new subscriber details in the database",
-    skip(new_subscriber, pool)
+    skip(new_subscriber, transaction)
 )]
 pub async fn insert_subscriber(
-    pool: &Arc<ApplicationState>,
+    transaction: &mut Transaction<'_, Postgres>,
     new_subscriber: &NewSubscriber,
-) -> Result<(), sqlx::Error> {
+) -> Result<Uuid, sqlx::Error> {
+    let subscriber_id = Uuid::new_v4();
     sqlx::query!(
         r#"INSERT INTO subscriptions (id, email, name, subscribed_at, status) VALUES($1, $2, $3, $4, 'pending_confirmation')"#,
-        Uuid::new_v4(),
+        subscriber_id,
         new_subscriber.email.as_ref(),
         new_subscriber.name.as_ref(),
         Utc::now(),
     )
-    .execute(&pool.pool)
+    .execute(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(subscriber_id)
+}
+
+#[tracing::instrument(
+    name = "Store subscription token in the database",
+    skip(subscription_token, transaction)
+)]
+pub async fn store_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    subscription_token: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id) VALUES ($1, $2)"#,
+        subscription_token,
+        subscriber_id
+    )
+    .execute(&mut **transaction)
     .await
     .map_err(|e| {
         tracing::error!("Failed to execute query: {:?}", e);
@@ -1,12 +1,123 @@
-use axum::{extract::Query, response::IntoResponse};
+use std::sync::Arc;
+
+use axum::{
+    extract::{Query, State},
+    response::IntoResponse,
+};
 use reqwest::StatusCode;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    consent::{ConsentContext, record_consent},
+    startup::ApplicationState,
+};
 
 #[derive(serde::Deserialize)]
 pub struct Parameters {
     subscription_token: String,
 }
 
-#[tracing::instrument(name = "Confirm a pending subscriber", skip(parameters))]
-pub async fn confirm(Query(parameters): Query<Parameters>) -> impl IntoResponse {
-    StatusCode::OK
+#[tracing::instrument(
+    name = "Confirm a pending subscriber",
+    skip(parameters, app_state, consent)
+)]
+pub async fn confirm(
+    State(app_state): State<Arc<ApplicationState>>,
+    consent: ConsentContext,
+    Query(parameters): Query<Parameters>,
+) -> impl IntoResponse {
+    let id =
+        match get_subscriber_id_from_token(&app_state.pool, &parameters.subscription_token).await {
+            Ok(id) => id,
+            Err(_) => return StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+    match id {
+        // Non-existing token!
+        None => StatusCode::UNAUTHORIZED,
+        Some(subscriber_id) => {
+            if confirm_subscriber(&app_state.pool, subscriber_id, &consent)
+                .await
+                .is_err()
+            {
+                return StatusCode::INTERNAL_SERVER_ERROR;
+            }
+            StatusCode::OK
+        }
+    }
+}
+
+/// Confirm the subscriber's address, along with every list subscription
+/// waiting on it, and record their consent to those lists.
+#[tracing::instrument(
+    name = "Mark subscriber as confirmed",
+    skip(subscriber_id, pool, consent)
+)]
+pub async fn confirm_subscriber(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    consent: &ConsentContext,
+) -> Result<(), sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
+        subscriber_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    let confirmed_lists = sqlx::query_scalar!(
+        r#"
+        UPDATE list_subscriptions ls
+        SET status = 'confirmed', confirmed_at = now()
+        FROM lists l
+        WHERE
+            l.list_id = ls.list_id AND
+            ls.subscriber_id = $1 AND
+            ls.status = 'pending_confirmation'
+        RETURNING l.slug
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    // Following the link again confirms nothing new.
+    if !confirmed_lists.is_empty() {
+        record_consent(
+            &mut *transaction,
+            subscriber_id,
+            "confirm",
+            &confirmed_lists,
+            None,
+            consent,
+        )
+        .await?;
+    }
+    transaction.commit().await
+}
+
+#[tracing::instrument(name = "Get subscriber_id from token", skip(subscription_token, pool))]
+pub async fn get_subscriber_id_from_token(
+    pool: &PgPool,
+    subscription_token: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"SELECT subscriber_id FROM subscription_tokens WHERE subscription_token = $1"#,
+        subscription_token,
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(result.map(|r| r.subscriber_id))
 }
@@ -0,0 +1,128 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{StatusCode, header},
+    response::IntoResponse,
+};
+use serde::Deserialize;
+
+use crate::{links::is_web_url, startup::ApplicationState};
+
+/// A transparent 1x1 GIF.
+const PIXEL: &[u8] = &[
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+/// Record that a recipient opened an issue.
+///
+/// The pixel is served whatever happens, so that a tracking failure never
+/// shows up as a broken image in the email.
+#[tracing::instrument(name = "Record an open", skip_all)]
+pub async fn track_open(
+    State(app_state): State<Arc<ApplicationState>>,
+    Path(open_token): Path<String>,
+) -> impl IntoResponse {
+    // Subscribers who opted out after the issue was sent are not tracked
+    // either.
+    let recorded = sqlx::query!(
+        r#"
+        UPDATE issue_deliveries d
+        SET
+            open_count = open_count + 1,
+            first_opened_at = COALESCE(first_opened_at, now()),
+            last_opened_at = now()
+        WHERE
+            open_token = $1 AND
+            NOT EXISTS (
+                SELECT 1 FROM subscriptions s
+                WHERE s.id = d.subscriber_id AND s.tracking_opt_out
+            )
+        "#,
+        open_token
+    )
+    .execute(&app_state.pool)
+    .await;
+    if let Err(e) = recorded {
+        tracing::error!("Failed to record an open: {:?}", e);
+    }
+    (
+        [
+            (header::CONTENT_TYPE, "image/gif"),
+            (header::CACHE_CONTROL, "no-store, max-age=0"),
+        ],
+        PIXEL,
+    )
+}
+
+#[derive(Deserialize)]
+pub struct ClickParameters {
+    url: String,
+    sig: String,
+}
+
+/// Record a click on a link of an issue, then send the recipient on to it.
+///
+/// Only destinations signed for this delivery are followed, so that the
+/// route cannot be used as an open redirect.
+#[tracing::instrument(name = "Record a click", skip_all)]
+pub async fn track_click(
+    State(app_state): State<Arc<ApplicationState>>,
+    Path(click_token): Path<String>,
+    Query(parameters): Query<ClickParameters>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let ClickParameters { url, sig } = parameters;
+    if !app_state.tracker.verify_click(&click_token, &url, &sig) || !is_web_url(&url) {
+        tracing::warn!("Rejected a click with an invalid signature");
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    match record_click(&app_state, &click_token, &url).await {
+        Ok(true) => {}
+        Ok(false) => return Err(StatusCode::NOT_FOUND),
+        // The link is genuine: do not get in the way of the recipient.
+        Err(e) => tracing::error!("Failed to record a click: {:?}", e),
+    }
+    Ok((StatusCode::FOUND, [(header::LOCATION, url)]))
+}
+
+/// Returns whether the delivery exists. Clicks of subscribers who opted out
+/// of tracking after the issue was sent are not recorded.
+async fn record_click(
+    app_state: &ApplicationState,
+    click_token: &str,
+    url: &str,
+) -> Result<bool, sqlx::Error> {
+    let delivery = sqlx::query!(
+        r#"
+        SELECT
+            d.newsletter_issue_id,
+            d.subscriber_email,
+            COALESCE(s.tracking_opt_out, false) AS "opted_out!"
+        FROM issue_deliveries d
+        LEFT JOIN subscriptions s ON s.id = d.subscriber_id
+        WHERE d.click_token = $1
+        "#,
+        click_token
+    )
+    .fetch_optional(&app_state.pool)
+    .await?;
+    let Some(delivery) = delivery else {
+        return Ok(false);
+    };
+    if !delivery.opted_out {
+        sqlx::query!(
+            r#"
+            INSERT INTO link_clicks (newsletter_issue_id, subscriber_email, url, clicked_at)
+            VALUES ($1, $2, $3, now())
+            "#,
+            delivery.newsletter_issue_id,
+            delivery.subscriber_email,
+            url
+        )
+        .execute(&app_state.pool)
+        .await?;
+    }
+    Ok(true)
+}
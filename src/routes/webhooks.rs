@@ -0,0 +1,161 @@
+use std::sync::Arc;
+
+use axum::{Json, extract::State, http::StatusCode};
+use serde::Deserialize;
+use sqlx::{Postgres, Transaction};
+
+use crate::{authentication::PostmarkWebhook, startup::ApplicationState, utils::e500};
+
+/// The subset of Postmark's webhook payloads we act upon.
+#[derive(Deserialize, Debug)]
+#[serde(tag = "RecordType")]
+pub enum PostmarkEvent {
+    Bounce(BounceEvent),
+    SpamComplaint(BounceEvent),
+    /// Deliveries, opens, clicks...: acknowledged and ignored.
+    #[serde(other)]
+    Other,
+}
+
+/// Postmark describes bounces and spam complaints with the same fields.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct BounceEvent {
+    #[serde(rename = "MessageID")]
+    pub message_id: Option<String>,
+    #[serde(rename = "Type")]
+    pub kind: String,
+    pub email: String,
+    pub description: Option<String>,
+}
+
+impl BounceEvent {
+    /// Hard bounces mean the address will never accept our email; soft
+    /// bounces (full mailbox, greylisting...) might clear on their own.
+    pub fn is_hard_bounce(&self) -> bool {
+        matches!(
+            self.kind.as_str(),
+            "HardBounce" | "BadEmailAddress" | "ManuallyDeactivated" | "Blocked"
+        )
+    }
+}
+
+#[tracing::instrument(name = "Handle a Postmark webhook", skip(_webhook, app_state))]
+pub async fn postmark_webhook(
+    _webhook: PostmarkWebhook,
+    State(app_state): State<Arc<ApplicationState>>,
+    Json(event): Json<PostmarkEvent>,
+) -> Result<StatusCode, StatusCode> {
+    let mut transaction = app_state.pool.begin().await.map_err(e500)?;
+    match &event {
+        PostmarkEvent::Bounce(bounce) => {
+            mark_delivery_as_bounced(&mut transaction, bounce)
+                .await
+                .map_err(e500)?;
+            if bounce.is_hard_bounce() {
+                suppress_email(&mut transaction, bounce, "hard_bounce")
+                    .await
+                    .map_err(e500)?;
+            } else {
+                tracing::info!("Ignoring a soft bounce");
+            }
+        }
+        PostmarkEvent::SpamComplaint(complaint) => {
+            suppress_email(&mut transaction, complaint, "spam_complaint")
+                .await
+                .map_err(e500)?;
+        }
+        PostmarkEvent::Other => {}
+    }
+    transaction.commit().await.map_err(e500)?;
+    Ok(StatusCode::OK)
+}
+
+async fn mark_delivery_as_bounced(
+    transaction: &mut Transaction<'_, Postgres>,
+    bounce: &BounceEvent,
+) -> Result<(), sqlx::Error> {
+    let Some(message_id) = &bounce.message_id else {
+        return Ok(());
+    };
+    sqlx::query!(
+        r#"
+        UPDATE issue_deliveries
+        SET status = 'bounced', bounced_at = now()
+        WHERE provider_message_id = $1
+        "#,
+        message_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+/// Add the address to the suppression list. The first reason recorded for an
+/// address is kept.
+async fn suppress_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    event: &BounceEvent,
+    reason: &str,
+) -> Result<(), sqlx::Error> {
+    tracing::info!(reason, "Suppressing an email address");
+    sqlx::query!(
+        r#"
+        INSERT INTO suppressed_emails (email, reason, detail, provider_message_id, suppressed_at)
+        VALUES ($1, $2, $3, $4, now())
+        ON CONFLICT (email) DO NOTHING
+        "#,
+        event.email,
+        reason,
+        event.description,
+        event.message_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PostmarkEvent;
+
+    #[test]
+    fn bounces_are_classified_as_hard_or_soft() {
+        let event: PostmarkEvent = serde_json::from_value(serde_json::json!({
+            "RecordType": "Bounce",
+            "MessageID": "883953f4-6105-42a2-a16a-77a8eac79483",
+            "Type": "HardBounce",
+            "TypeCode": 1,
+            "Email": "john@example.com",
+            "Description": "The server was unable to deliver your message",
+        }))
+        .unwrap();
+        let PostmarkEvent::Bounce(bounce) = event else {
+            panic!("Expected a bounce, got {:?}", event);
+        };
+        assert!(bounce.is_hard_bounce());
+
+        let event: PostmarkEvent = serde_json::from_value(serde_json::json!({
+            "RecordType": "Bounce",
+            "Type": "SoftBounce",
+            "TypeCode": 4096,
+            "Email": "john@example.com",
+        }))
+        .unwrap();
+        let PostmarkEvent::Bounce(bounce) = event else {
+            panic!("Expected a bounce, got {:?}", event);
+        };
+        assert!(!bounce.is_hard_bounce());
+    }
+
+    #[test]
+    fn other_record_types_are_ignored() {
+        let event: PostmarkEvent = serde_json::from_value(serde_json::json!({
+            "RecordType": "Delivery",
+            "MessageID": "883953f4-6105-42a2-a16a-77a8eac79483",
+            "Recipient": "john@example.com",
+        }))
+        .unwrap();
+        assert!(matches!(event, PostmarkEvent::Other));
+    }
+}
@@ -0,0 +1,182 @@
+use std::time::Duration;
+
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    ab_testing::{decide_due_ab_tests, queue_test_sample},
+    audit::record_issue_event,
+    bulk_actions::fail_stale_bulk_actions,
+    delivery_log::record_queued_issue,
+    issue_delivery_worker::mark_issue_as_sent_if_done,
+    segments::snapshot_segment,
+    subscriber_import::fail_stale_imports,
+};
+
+/// How often the scheduler looks for issues whose `send_at` has passed, for
+/// A/B tests whose window has closed, and for imports and bulk actions whose
+/// heartbeat stopped.
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+pub async fn run_scheduler_until_stopped(pool: PgPool) {
+    loop {
+        if let Err(e) = promote_due_issues(&pool).await {
+            tracing::error!("Failed to promote due newsletter issues: {:?}", e);
+        }
+        if let Err(e) = decide_due_ab_tests(&pool).await {
+            tracing::error!("Failed to decide due A/B tests: {:?}", e);
+        }
+        if let Err(e) = fail_stale_imports(&pool).await {
+            tracing::error!("Failed to look for stale imports: {:?}", e);
+        }
+        if let Err(e) = fail_stale_bulk_actions(&pool).await {
+            tracing::error!("Failed to look for stale bulk actions: {:?}", e);
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// Move every approved, scheduled issue whose `send_at` has passed into
+/// delivery, for the confirmed subscribers of its list who belong to its
+/// segment, if any (see the `issue_recipients` SQL function). Issues still
+/// waiting for a sign-off stay scheduled.
+///
+/// Returns the number of issues promoted by this call. Issues promoted
+/// concurrently by another replica are not counted, nor are the ones that
+/// failed: they are logged and tried again on the next call.
+#[tracing::instrument(skip_all)]
+pub async fn promote_due_issues(pool: &PgPool) -> Result<usize, sqlx::Error> {
+    let due_issues = sqlx::query_scalar!(
+        r#"
+        SELECT newsletter_issue_id
+        FROM newsletter_issues
+        WHERE
+            status = 'scheduled' AND
+            send_at <= now() AND
+            approval_status = 'approved' AND
+            approved_revision = current_revision
+        ORDER BY send_at
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut promoted = 0;
+    for issue_id in due_issues {
+        // One issue that cannot be promoted must not hold back the others.
+        match promote_issue(pool, issue_id).await {
+            Ok(true) => promoted += 1,
+            Ok(false) => {}
+            Err(e) => tracing::error!(
+                newsletter_issue_id = %issue_id,
+                "Failed to promote a newsletter issue: {:?}",
+                e
+            ),
+        }
+    }
+    Ok(promoted)
+}
+
+#[tracing::instrument(skip(pool), fields(newsletter_issue_id = %issue_id))]
+async fn promote_issue(pool: &PgPool, issue_id: Uuid) -> Result<bool, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    // Another replica holding the lock is already promoting (or cancelling)
+    // this issue: leave it alone rather than waiting on it.
+    if !try_lock_issue(&mut transaction, issue_id).await? {
+        return Ok(false);
+    }
+
+    // The status might have changed between listing the due issues and
+    // acquiring the lock, so it must be checked again.
+    let updated = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            status = 'sending',
+            published_at = now(),
+            ab_test_decide_at = now() + make_interval(mins => ab_test_wait_minutes)
+        WHERE
+            newsletter_issue_id = $1 AND
+            status = 'scheduled' AND
+            send_at <= now() AND
+            approval_status = 'approved' AND
+            approved_revision = current_revision
+        RETURNING current_revision, ab_test_percentage
+        "#,
+        issue_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    let Some(updated) = updated else {
+        return Ok(false);
+    };
+
+    snapshot_segment(&mut transaction, issue_id).await?;
+    match updated.ab_test_percentage {
+        // The rest of the list waits for the test to be decided.
+        Some(test_percentage) => {
+            queue_test_sample(&mut transaction, issue_id, test_percentage).await?;
+        }
+        None => {
+            sqlx::query!(
+                r#"
+                INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email)
+                SELECT $1, subscriber_email FROM issue_recipients($1)
+                "#,
+                issue_id
+            )
+            .execute(&mut *transaction)
+            .await?;
+        }
+    }
+    record_queued_issue(&mut *transaction, issue_id).await?;
+    // Nobody to send the issue to: it is done already.
+    mark_issue_as_sent_if_done(&mut transaction, issue_id).await?;
+    record_issue_event(
+        &mut *transaction,
+        issue_id,
+        Some(updated.current_revision),
+        None,
+        "published",
+        None,
+    )
+    .await?;
+    transaction.commit().await?;
+    tracing::info!("Newsletter issue promoted into delivery");
+    Ok(true)
+}
+
+/// Try to take the advisory lock guarding state transitions of an issue.
+///
+/// The lock is released when the transaction commits or rolls back.
+pub async fn try_lock_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let locked = sqlx::query_scalar!(
+        "SELECT pg_try_advisory_xact_lock($1)",
+        issue_lock_key(issue_id)
+    )
+    .fetch_one(&mut **transaction)
+    .await?;
+    Ok(locked.unwrap_or(false))
+}
+
+/// Wait for the advisory lock guarding state transitions of an issue.
+///
+/// The lock is released when the transaction commits or rolls back.
+pub async fn lock_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!("SELECT pg_advisory_xact_lock($1)", issue_lock_key(issue_id))
+        .execute(&mut **transaction)
+        .await?;
+    Ok(())
+}
+
+/// Postgres advisory locks are keyed by a `bigint`: fold the issue id into one.
+fn issue_lock_key(issue_id: Uuid) -> i64 {
+    let (high, low) = issue_id.as_u64_pair();
+    (high ^ low) as i64
+}
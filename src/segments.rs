@@ -0,0 +1,605 @@
+use std::fmt;
+
+use chrono::NaiveDate;
+use sqlx::{PgPool, Postgres, QueryBuilder, Transaction};
+use uuid::Uuid;
+
+/// Filters longer than this are rejected, as are ones nested deeper than
+/// [`MAX_DEPTH`]: both would only be mistakes or abuse.
+const MAX_LENGTH: usize = 2_000;
+const MAX_DEPTH: usize = 32;
+
+/// The statuses a subscriber can have.
+const STATUSES: [&str; 2] = ["pending_confirmation", "confirmed"];
+
+/// A condition on subscribers, written in a small filter language:
+///
+/// ```text
+/// filter     := filter "or" filter | filter "and" filter | "not" filter
+///             | "(" filter ")" | comparison
+/// comparison := field operator value
+/// operator   := "=" | "!=" | "<" | "<=" | ">" | ">="
+/// ```
+///
+/// `and` binds tighter than `or`. The fields are:
+///
+/// - `status`, compared with `=` or `!=` to `"pending_confirmation"` or
+///   `"confirmed"`;
+/// - `tag`, where `tag = "beta"` holds for subscribers tagged `beta` and
+///   `tag != "beta"` for the others;
+/// - `signed_up`, the day of the subscription, e.g. `signed_up >= "2026-03-01"`;
+/// - `opens` and `clicks`, the number of issues opened and links clicked;
+/// - `attributes.<name>`, a custom attribute compared to a string, a number
+///   or `true`/`false`. Comparisons with a missing attribute, or one of
+///   another type, do not hold.
+///
+/// For instance `status = "confirmed" and tag = "beta" and signed_up > "2026-03-01"`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+    Not(Box<Filter>),
+    Status(Comparison, String),
+    Tag(Comparison, String),
+    SignedUp(Comparison, NaiveDate),
+    Opens(Comparison, i64),
+    Clicks(Comparison, i64),
+    Attribute(String, Comparison, Value),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    fn as_sql(self) -> &'static str {
+        match self {
+            Self::Equal => " = ",
+            Self::NotEqual => " <> ",
+            Self::Less => " < ",
+            Self::LessOrEqual => " <= ",
+            Self::Greater => " > ",
+            Self::GreaterOrEqual => " >= ",
+        }
+    }
+
+    fn is_equality(self) -> bool {
+        matches!(self, Self::Equal | Self::NotEqual)
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Equal => "=",
+            Self::NotEqual => "!=",
+            Self::Less => "<",
+            Self::LessOrEqual => "<=",
+            Self::Greater => ">",
+            Self::GreaterOrEqual => ">=",
+        })
+    }
+}
+
+/// A literal of the filter language.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Text(String),
+    Number(f64),
+    Boolean(bool),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Text(text) => write!(f, "{:?}", text),
+            Self::Number(number) => write!(f, "{}", number),
+            Self::Boolean(boolean) => write!(f, "{}", boolean),
+        }
+    }
+}
+
+impl Filter {
+    pub fn parse(s: &str) -> Result<Filter, String> {
+        if s.len() > MAX_LENGTH {
+            return Err(format!("Filters are limited to {} characters.", MAX_LENGTH));
+        }
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            position: 0,
+            depth: 0,
+        };
+        let filter = parser.or()?;
+        match parser.next() {
+            None => Ok(filter),
+            Some(token) => Err(format!("Unexpected {}.", token)),
+        }
+    }
+
+    /// Append the filter to `query` as a condition on `s`, which must be the
+    /// alias of `subscriptions`. Every literal is bound as a parameter.
+    pub fn push_sql(&self, query: &mut QueryBuilder<'_, Postgres>) {
+        match self {
+            Self::And(left, right) | Self::Or(left, right) => {
+                query.push("(");
+                left.push_sql(query);
+                query.push(if matches!(self, Self::And(..)) {
+                    " AND "
+                } else {
+                    " OR "
+                });
+                right.push_sql(query);
+                query.push(")");
+            }
+            Self::Not(filter) => {
+                query.push("NOT (");
+                filter.push_sql(query);
+                query.push(")");
+            }
+            Self::Status(comparison, status) => {
+                query
+                    .push("s.status")
+                    .push(comparison.as_sql())
+                    .push_bind(status.clone());
+            }
+            Self::Tag(comparison, tag) => {
+                if *comparison == Comparison::NotEqual {
+                    query.push("NOT ");
+                }
+                query
+                    .push("(")
+                    .push_bind(tag.clone())
+                    .push(" = ANY(s.tags))");
+            }
+            Self::SignedUp(comparison, day) => {
+                query
+                    .push("(s.subscribed_at AT TIME ZONE 'UTC')::date")
+                    .push(comparison.as_sql())
+                    .push_bind(*day);
+            }
+            Self::Opens(comparison, count) => {
+                query
+                    .push(
+                        "(SELECT COUNT(*) FROM issue_deliveries d \
+                        WHERE d.subscriber_email = s.email AND d.first_opened_at IS NOT NULL)",
+                    )
+                    .push(comparison.as_sql())
+                    .push_bind(*count);
+            }
+            Self::Clicks(comparison, count) => {
+                query
+                    .push("(SELECT COUNT(*) FROM link_clicks c WHERE c.subscriber_email = s.email)")
+                    .push(comparison.as_sql())
+                    .push_bind(*count);
+            }
+            Self::Attribute(name, comparison, value) => {
+                let (json_type, cast) = match value {
+                    Value::Text(_) => ("string", ""),
+                    Value::Number(_) => ("number", "::float8"),
+                    Value::Boolean(_) => ("boolean", "::boolean"),
+                };
+                query
+                    .push("COALESCE(CASE WHEN jsonb_typeof(s.attributes -> ")
+                    .push_bind(name.clone())
+                    .push(format!(") = '{}' THEN (s.attributes ->> ", json_type))
+                    .push_bind(name.clone())
+                    .push(format!("){}", cast))
+                    .push(comparison.as_sql());
+                match value {
+                    Value::Text(text) => query.push_bind(text.clone()),
+                    Value::Number(number) => query.push_bind(*number),
+                    Value::Boolean(boolean) => query.push_bind(*boolean),
+                };
+                query.push(" END, false)");
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Text(String),
+    Number(f64),
+    Operator(Comparison),
+    Open,
+    Close,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Word(word) => write!(f, "`{}`", word),
+            Self::Text(text) => write!(f, "{:?}", text),
+            Self::Number(number) => write!(f, "{}", number),
+            Self::Operator(comparison) => write!(f, "`{}`", comparison),
+            Self::Open => f.write_str("`(`"),
+            Self::Close => f.write_str("`)`"),
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = s.char_indices().peekable();
+    while let Some((position, c)) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '=' => Token::Operator(Comparison::Equal),
+            '!' if chars.next_if(|(_, c)| *c == '=').is_some() => {
+                Token::Operator(Comparison::NotEqual)
+            }
+            '<' if chars.next_if(|(_, c)| *c == '=').is_some() => {
+                Token::Operator(Comparison::LessOrEqual)
+            }
+            '<' => Token::Operator(Comparison::Less),
+            '>' if chars.next_if(|(_, c)| *c == '=').is_some() => {
+                Token::Operator(Comparison::GreaterOrEqual)
+            }
+            '>' => Token::Operator(Comparison::Greater),
+            '"' => {
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, c @ ('"' | '\\'))) => text.push(c),
+                            _ => {
+                                return Err(format!(
+                                    "Invalid escape in the string at {}.",
+                                    position
+                                ));
+                            }
+                        },
+                        Some((_, c)) => text.push(c),
+                        None => return Err(format!("Unterminated string at {}.", position)),
+                    }
+                }
+                Token::Text(text)
+            }
+            c if c == '-' || c.is_ascii_digit() => {
+                let mut number = String::from(c);
+                while let Some((_, c)) = chars.next_if(|(_, c)| c.is_ascii_digit() || *c == '.') {
+                    number.push(c);
+                }
+                let number = number
+                    .parse()
+                    .map_err(|_| format!("Invalid number at {}.", position))?;
+                Token::Number(number)
+            }
+            c if c.is_ascii_alphabetic() => {
+                let mut word = String::from(c);
+                while let Some((_, c)) =
+                    chars.next_if(|(_, c)| c.is_ascii_alphanumeric() || *c == '_' || *c == '.')
+                {
+                    word.push(c);
+                }
+                Token::Word(word)
+            }
+            c => return Err(format!("Unexpected `{}` at {}.", c, position)),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn next_if_word(&mut self, keyword: &str) -> bool {
+        match self.tokens.get(self.position) {
+            Some(Token::Word(word)) if word == keyword => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn or(&mut self) -> Result<Filter, String> {
+        let mut filter = self.and()?;
+        while self.next_if_word("or") {
+            filter = Filter::Or(Box::new(filter), Box::new(self.and()?));
+        }
+        Ok(filter)
+    }
+
+    fn and(&mut self) -> Result<Filter, String> {
+        let mut filter = self.unary()?;
+        while self.next_if_word("and") {
+            filter = Filter::And(Box::new(filter), Box::new(self.unary()?));
+        }
+        Ok(filter)
+    }
+
+    fn unary(&mut self) -> Result<Filter, String> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err("The filter is nested too deeply.".into());
+        }
+        let filter = if self.next_if_word("not") {
+            Filter::Not(Box::new(self.unary()?))
+        } else {
+            match self.next() {
+                Some(Token::Open) => {
+                    let filter = self.or()?;
+                    match self.next() {
+                        Some(Token::Close) => filter,
+                        _ => return Err("Missing `)`.".into()),
+                    }
+                }
+                Some(Token::Word(field)) => self.comparison(field)?,
+                Some(token) => return Err(format!("Expected a field, found {}.", token)),
+                None => return Err("Expected a field, found the end of the filter.".into()),
+            }
+        };
+        self.depth -= 1;
+        Ok(filter)
+    }
+
+    fn comparison(&mut self, field: String) -> Result<Filter, String> {
+        let comparison = match self.next() {
+            Some(Token::Operator(comparison)) => comparison,
+            _ => return Err(format!("Expected an operator after `{}`.", field)),
+        };
+        let value = match self.next() {
+            Some(Token::Text(text)) => Value::Text(text),
+            Some(Token::Number(number)) => Value::Number(number),
+            Some(Token::Word(word)) if word == "true" => Value::Boolean(true),
+            Some(Token::Word(word)) if word == "false" => Value::Boolean(false),
+            _ => {
+                return Err(format!(
+                    "Expected a value after `{} {}`.",
+                    field, comparison
+                ));
+            }
+        };
+        let invalid = format!(
+            "`{} {} {}` is not a valid comparison.",
+            field, comparison, value
+        );
+        let filter = match (field.as_str(), value) {
+            ("status", Value::Text(status))
+                if comparison.is_equality() && STATUSES.contains(&status.as_str()) =>
+            {
+                Filter::Status(comparison, status)
+            }
+            ("tag", Value::Text(tag)) if comparison.is_equality() => Filter::Tag(comparison, tag),
+            ("signed_up", Value::Text(day)) => {
+                let day = NaiveDate::parse_from_str(&day, "%Y-%m-%d")
+                    .map_err(|_| format!("{:?} is not a YYYY-MM-DD date.", day))?;
+                Filter::SignedUp(comparison, day)
+            }
+            ("opens" | "clicks", Value::Number(count))
+                if count >= 0.0 && count.fract() == 0.0 && count <= i64::MAX as f64 =>
+            {
+                if field == "opens" {
+                    Filter::Opens(comparison, count as i64)
+                } else {
+                    Filter::Clicks(comparison, count as i64)
+                }
+            }
+            (field, value) => match field.strip_prefix("attributes.") {
+                Some(name) if is_attribute_name(name) => {
+                    if matches!(value, Value::Boolean(_)) && !comparison.is_equality() {
+                        return Err(invalid);
+                    }
+                    Filter::Attribute(name.to_owned(), comparison, value)
+                }
+                _ if is_field(field) => return Err(invalid),
+                _ => return Err(format!("Unknown field `{}`.", field)),
+            },
+        };
+        Ok(filter)
+    }
+}
+
+fn is_field(field: &str) -> bool {
+    ["status", "tag", "signed_up", "opens", "clicks"].contains(&field)
+        || field.starts_with("attributes.")
+}
+
+/// Custom attribute names are made of lowercase letters, digits and
+/// underscores.
+pub fn is_attribute_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+/// The number of subscribers matching a filter.
+pub async fn count_matching(pool: &PgPool, filter: &Filter) -> Result<i64, sqlx::Error> {
+    let mut query = QueryBuilder::new("SELECT COUNT(*) FROM subscriptions s WHERE ");
+    filter.push_sql(&mut query);
+    query.build_query_scalar().fetch_one(pool).await
+}
+
+/// Record who belongs to the segment of an issue, if it has one, for
+/// `issue_recipients` to send it to them only.
+#[tracing::instrument(skip(transaction))]
+pub async fn snapshot_segment(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let filter = sqlx::query_scalar!(
+        r#"
+        SELECT s.filter
+        FROM newsletter_issues i
+        JOIN segments s ON s.segment_id = i.segment_id
+        WHERE i.newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_optional(&mut **transaction)
+    .await?;
+    let Some(filter) = filter else {
+        return Ok(());
+    };
+    // Filters are checked when segments are saved: this only fails if the
+    // language lost a feature since.
+    let filter = Filter::parse(&filter).map_err(|e| sqlx::Error::Decode(e.into()))?;
+    let mut query = QueryBuilder::new(
+        "INSERT INTO issue_segment_members (newsletter_issue_id, subscriber_id) SELECT ",
+    );
+    query
+        .push_bind(issue_id)
+        .push(", s.id FROM subscriptions s WHERE ");
+    filter.push_sql(&mut query);
+    query.push(" ON CONFLICT DO NOTHING");
+    query.build().execute(&mut **transaction).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use claim::{assert_err, assert_ok};
+    use sqlx::{Postgres, QueryBuilder};
+
+    use super::{Comparison, Filter, Value};
+
+    fn sql_of(filter: &str) -> String {
+        let mut query = QueryBuilder::<Postgres>::new("");
+        Filter::parse(filter).unwrap().push_sql(&mut query);
+        query.sql().to_owned()
+    }
+
+    #[test]
+    fn comparisons_are_parsed() {
+        assert_eq!(
+            Filter::parse(r#"status = "confirmed""#),
+            Ok(Filter::Status(Comparison::Equal, "confirmed".into()))
+        );
+        assert_eq!(
+            Filter::parse(r#"tag != "beta""#),
+            Ok(Filter::Tag(Comparison::NotEqual, "beta".into()))
+        );
+        assert_eq!(
+            Filter::parse(r#"signed_up > "2026-03-01""#),
+            Ok(Filter::SignedUp(
+                Comparison::Greater,
+                NaiveDate::from_ymd_opt(2026, 3, 1).unwrap()
+            ))
+        );
+        assert_eq!(
+            Filter::parse("opens >= 2"),
+            Ok(Filter::Opens(Comparison::GreaterOrEqual, 2))
+        );
+        assert_eq!(
+            Filter::parse("attributes.seats <= 10.5"),
+            Ok(Filter::Attribute(
+                "seats".into(),
+                Comparison::LessOrEqual,
+                Value::Number(10.5)
+            ))
+        );
+        assert_eq!(
+            Filter::parse("attributes.vip = true"),
+            Ok(Filter::Attribute(
+                "vip".into(),
+                Comparison::Equal,
+                Value::Boolean(true)
+            ))
+        );
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let status = || Box::new(Filter::Status(Comparison::Equal, "confirmed".into()));
+        let tag = |t: &str| Box::new(Filter::Tag(Comparison::Equal, t.into()));
+        assert_eq!(
+            Filter::parse(r#"tag = "a" or tag = "b" and status = "confirmed""#),
+            Ok(Filter::Or(
+                tag("a"),
+                Box::new(Filter::And(tag("b"), status()))
+            ))
+        );
+        assert_eq!(
+            Filter::parse(r#"(tag = "a" or tag = "b") and not status = "confirmed""#),
+            Ok(Filter::And(
+                Box::new(Filter::Or(tag("a"), tag("b"))),
+                Box::new(Filter::Not(status()))
+            ))
+        );
+    }
+
+    #[test]
+    fn strings_may_contain_escaped_quotes() {
+        assert_eq!(
+            Filter::parse(r#"attributes.motto = "say \"hi\"""#),
+            Ok(Filter::Attribute(
+                "motto".into(),
+                Comparison::Equal,
+                Value::Text(r#"say "hi""#.into())
+            ))
+        );
+    }
+
+    #[test]
+    fn invalid_filters_are_rejected() {
+        for filter in [
+            "",
+            "status",
+            "status =",
+            r#"status = "gone""#,
+            r#"status < "confirmed""#,
+            r#"tag > "beta""#,
+            r#"signed_up > "March""#,
+            "opens > -1",
+            "clicks = 1.5",
+            "attributes.vip > true",
+            "attributes.Plan = 1",
+            r#"email = "a@example.com""#,
+            r#"tag = "a" tag = "b""#,
+            r#"(tag = "a""#,
+            r#"tag = "a")"#,
+            r#"tag = "unterminated"#,
+            "opens = 1; DROP TABLE subscriptions",
+        ] {
+            assert_err!(Filter::parse(filter), "{} was accepted", filter);
+        }
+    }
+
+    #[test]
+    fn deep_or_long_filters_are_rejected() {
+        assert_err!(Filter::parse(&"not ".repeat(100).to_string()));
+        assert_err!(Filter::parse(&format!("{}opens = 1", "(".repeat(40))));
+        let long = vec![r#"tag = "a""#; 300].join(" or ");
+        assert_err!(Filter::parse(&long));
+        let short = vec![r#"tag = "a""#; 50].join(" or ");
+        assert_ok!(Filter::parse(&short));
+    }
+
+    #[test]
+    fn literals_are_bound_as_parameters() {
+        assert_eq!(
+            sql_of(r#"tag = "x' OR 1=1 --" and attributes.plan != "pro""#),
+            "(($1 = ANY(s.tags)) AND COALESCE(CASE WHEN jsonb_typeof(s.attributes -> $2) = 'string' \
+            THEN (s.attributes ->> $3) <> $4 END, false))"
+        );
+        assert_eq!(
+            sql_of(r#"not (status = "confirmed" or signed_up <= "2026-01-31")"#),
+            "NOT ((s.status = $1 OR (s.subscribed_at AT TIME ZONE 'UTC')::date <= $2))"
+        );
+    }
+}
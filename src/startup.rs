@@ -1,17 +1,60 @@
-use std::{pin::Pin, sync::Arc};
+use std::{net::SocketAddr, pin::Pin, sync::Arc};
 
 use crate::{
-    configuration::{DatabaseSettings, Settings},
+    configuration::{
+        ArchiveSettings, ConsentSettings, DatabaseSettings, Settings, WebhookCredentials,
+    },
     email_client::EmailClient,
+    feed_poller::{ConfiguredFetcher, FeedPoller, run_feed_poller_until_stopped},
+    issue_delivery_worker::run_worker_until_stopped,
+    preferences::PreferenceLinks,
     routes::{
-        health_check::health_check, subscriptions::subscribe, subscriptions_confirm::confirm,
+        admin::{
+            ab_tests::{get_ab_test, remove_ab_test, set_ab_test},
+            approvals::{approve_issue, get_audit_trail, reject_issue},
+            bulk_actions::{get_bulk_action, get_subscriber_audit_trail, start_bulk_action},
+            deliveries::{issue_delivery_stats, issue_link_clicks, subscriber_delivery_history},
+            failed_deliveries::{
+                discard_failed_deliveries, discard_failed_delivery, list_failed_deliveries,
+                retry_failed_deliveries, retry_failed_delivery,
+            },
+            imports::{MAX_IMPORT_SIZE, get_import, import_subscribers},
+            issues::{
+                cancel_issue, create_issue, delete_issue, get_issue, list_issues, reschedule_issue,
+                schedule_issue, set_issue_segment, set_issue_tracking, set_issue_utm, update_issue,
+            },
+            lists::{create_list, list_lists, set_attribute_schema},
+            metrics::get_metrics,
+            password::change_admin_password,
+            revisions::{get_revision, list_revisions, restore_revision},
+            segments::{create_segment, list_segments, preview_segment},
+            subscribers::{
+                erase_subscriber, export_subscriber_data, export_subscribers, get_subscriber,
+                get_subscriber_consents, list_subscribers, set_subscriber_attributes,
+                set_subscriber_tags, set_tracking_preference,
+            },
+        },
+        archive::{archive_index, archived_issue, atom_feed, rss_feed},
+        email_change::{confirm_email_change, request_email_change},
+        health_check::health_check,
+        preferences::{
+            download_data, erase_data, preferences_page, request_preferences_link,
+            update_preferences,
+        },
+        subscriptions::subscribe,
+        subscriptions_confirm::confirm,
+        tracking::{track_click, track_open},
+        webhooks::postmark_webhook,
     },
+    scheduler::run_scheduler_until_stopped,
+    signing::Signer,
+    tracking::Tracker,
 };
 use axum::{
     Router,
     body::Body,
-    extract::Request,
-    routing::{get, post},
+    extract::{DefaultBodyLimit, Request},
+    routing::{delete, get, post, put},
     serve,
 };
 use sqlx::{PgPool, Pool, Postgres, postgres::PgPoolOptions};
@@ -26,28 +69,31 @@ pub struct ApplicationState {
     pub pool: Pool<Postgres>,
     pub email_client: EmailClient,
     pub base_url: ApplicationBaseUrl,
+    pub archive: ArchiveSettings,
+    pub postmark_webhook: WebhookCredentials,
+    pub tracker: Tracker,
+    pub preference_links: PreferenceLinks,
+    pub consent: ConsentSettings,
 }
 
 pub struct Application {
     port: u16,
     server: Pin<Box<dyn Future<Output = ()> + Send + 'static>>,
+    pool: PgPool,
+    email_client: EmailClient,
+    tracker: Tracker,
+    feed_poller: Option<FeedPoller<ConfiguredFetcher>>,
 }
 
 impl Application {
     pub async fn build(configuration: Settings) -> Result<Self, std::io::Error> {
         let pool = get_connection_pool(&configuration.database);
 
-        let sender_email = configuration
-            .email_client
-            .sender()
-            .expect("Invalid sender email address");
-        let timeout = configuration.email_client.timeout();
-        let email_client = EmailClient::new(
-            configuration.email_client.base_url.clone(),
-            sender_email,
-            configuration.email_client.authorization_token.clone(),
-            timeout,
-        );
+        let email_client = configuration.email_client.client();
+
+        let feed_poller = configuration.feed_poller.map(|settings| {
+            FeedPoller::new(ConfiguredFetcher::from_source(&settings.source), settings)
+        });
 
         let addr = format!(
             "{}:{}",
@@ -55,16 +101,29 @@ impl Application {
         );
         let listener = TcpListener::bind(addr).await.unwrap();
         let port = listener.local_addr().unwrap().port();
-        let server = run(
-            listener,
-            pool,
-            email_client,
-            configuration.application.base_url,
-        );
+        let signer = Signer::new(configuration.application.hmac_secret.clone());
+        let tracker = Tracker::new(configuration.application.base_url.clone(), signer.clone());
+        let preference_links =
+            PreferenceLinks::new(configuration.application.base_url.clone(), signer);
+        let app_state = ApplicationState {
+            pool: pool.clone(),
+            email_client: email_client.clone(),
+            base_url: ApplicationBaseUrl(configuration.application.base_url),
+            archive: configuration.archive,
+            postmark_webhook: configuration.postmark_webhook,
+            tracker: tracker.clone(),
+            preference_links,
+            consent: configuration.consent,
+        };
+        let server = run(listener, app_state);
 
         Ok(Self {
             port,
             server: Box::pin(server),
+            pool,
+            email_client,
+            tracker,
+            feed_poller,
         })
     }
 
@@ -72,8 +131,25 @@ impl Application {
         self.port
     }
 
+    /// Serve HTTP requests while the scheduler, the delivery worker and, when
+    /// configured, the feed poller run in the background. Returns as soon as
+    /// any of them stops.
     pub async fn run_until_stopped(self) {
-        self.server.await
+        let scheduler = run_scheduler_until_stopped(self.pool.clone());
+        let feed_poller = async {
+            match self.feed_poller {
+                Some(poller) => run_feed_poller_until_stopped(self.pool.clone(), poller).await,
+                None => std::future::pending().await,
+            }
+        };
+        let delivery_worker =
+            run_worker_until_stopped(self.pool.clone(), self.email_client, self.tracker);
+        tokio::select! {
+            _ = self.server => tracing::error!("HTTP server stopped"),
+            _ = scheduler => tracing::error!("Newsletter scheduler stopped"),
+            _ = delivery_worker => tracing::error!("Delivery worker stopped"),
+            _ = feed_poller => tracing::error!("Feed poller stopped"),
+        }
     }
 }
 
@@ -83,21 +159,133 @@ pub fn get_connection_pool(configuration: &DatabaseSettings) -> PgPool {
         .connect_lazy_with(configuration.with_db())
 }
 
-pub async fn run(
-    listener: TcpListener,
-    pool: Pool<Postgres>,
-    email_client: EmailClient,
-    base_url: String,
-) {
-    let app_state = Arc::new(ApplicationState {
-        pool,
-        email_client,
-        base_url: ApplicationBaseUrl(base_url),
-    });
+pub async fn run(listener: TcpListener, app_state: ApplicationState) {
+    let app_state = Arc::new(app_state);
     let app = Router::new()
         .route("/health_check", get(health_check))
         .route("/subscriptions", post(subscribe))
         .route("/subscriptions/confirm", get(confirm))
+        .route(
+            "/preferences",
+            get(preferences_page).post(update_preferences),
+        )
+        .route("/preferences/link", post(request_preferences_link))
+        .route("/preferences/data", get(download_data))
+        .route("/preferences/erase", post(erase_data))
+        .route("/preferences/email", post(request_email_change))
+        .route("/preferences/email/confirm", get(confirm_email_change))
+        .route("/webhooks/postmark", post(postmark_webhook))
+        .route("/t/open/{open_token}", get(track_open))
+        .route("/t/click/{click_token}", get(track_click))
+        .route("/archive", get(archive_index))
+        .route("/archive/feed.rss", get(rss_feed))
+        .route("/archive/feed.atom", get(atom_feed))
+        .route("/archive/{slug}", get(archived_issue))
+        .route("/admin/metrics", get(get_metrics))
+        .route("/admin/password", put(change_admin_password))
+        .route("/admin/failed-deliveries", get(list_failed_deliveries))
+        .route(
+            "/admin/failed-deliveries/retry",
+            post(retry_failed_deliveries),
+        )
+        .route(
+            "/admin/failed-deliveries/discard",
+            post(discard_failed_deliveries),
+        )
+        .route(
+            "/admin/failed-deliveries/{failed_delivery_id}",
+            delete(discard_failed_delivery),
+        )
+        .route(
+            "/admin/failed-deliveries/{failed_delivery_id}/retry",
+            post(retry_failed_delivery),
+        )
+        .route("/admin/issues", get(list_issues).post(create_issue))
+        .route("/admin/lists", get(list_lists).post(create_list))
+        .route(
+            "/admin/lists/{list_id}/attributes",
+            put(set_attribute_schema),
+        )
+        .route("/admin/segments", get(list_segments).post(create_segment))
+        .route("/admin/segments/preview", post(preview_segment))
+        .route(
+            "/admin/issues/{issue_id}",
+            get(get_issue).put(update_issue).delete(delete_issue),
+        )
+        .route("/admin/issues/{issue_id}/schedule", post(schedule_issue))
+        .route("/admin/issues/{issue_id}/cancel", post(cancel_issue))
+        .route(
+            "/admin/issues/{issue_id}/reschedule",
+            post(reschedule_issue),
+        )
+        .route("/admin/issues/{issue_id}/approve", post(approve_issue))
+        .route("/admin/issues/{issue_id}/reject", post(reject_issue))
+        .route("/admin/issues/{issue_id}/audit", get(get_audit_trail))
+        .route("/admin/issues/{issue_id}/tracking", put(set_issue_tracking))
+        .route("/admin/issues/{issue_id}/utm", put(set_issue_utm))
+        .route("/admin/issues/{issue_id}/segment", put(set_issue_segment))
+        .route(
+            "/admin/issues/{issue_id}/ab-test",
+            get(get_ab_test).put(set_ab_test).delete(remove_ab_test),
+        )
+        .route(
+            "/admin/issues/{issue_id}/deliveries",
+            get(issue_delivery_stats),
+        )
+        .route("/admin/issues/{issue_id}/clicks", get(issue_link_clicks))
+        .route(
+            "/admin/subscribers/{subscriber_id}/deliveries",
+            get(subscriber_delivery_history),
+        )
+        .route("/admin/subscribers", get(list_subscribers))
+        .route("/admin/subscribers/export", get(export_subscribers))
+        .route("/admin/subscribers/bulk", post(start_bulk_action))
+        .route(
+            "/admin/subscribers/bulk/{bulk_action_id}",
+            get(get_bulk_action),
+        )
+        .route(
+            "/admin/imports",
+            post(import_subscribers).layer(DefaultBodyLimit::max(MAX_IMPORT_SIZE)),
+        )
+        .route("/admin/imports/{import_id}", get(get_import))
+        .route(
+            "/admin/subscribers/{subscriber_id}",
+            get(get_subscriber).delete(erase_subscriber),
+        )
+        .route(
+            "/admin/subscribers/{subscriber_id}/tags",
+            put(set_subscriber_tags),
+        )
+        .route(
+            "/admin/subscribers/{subscriber_id}/attributes",
+            put(set_subscriber_attributes),
+        )
+        .route(
+            "/admin/subscribers/{subscriber_id}/tracking",
+            put(set_tracking_preference),
+        )
+        .route(
+            "/admin/subscribers/{subscriber_id}/consents",
+            get(get_subscriber_consents),
+        )
+        .route(
+            "/admin/subscribers/{subscriber_id}/data",
+            get(export_subscriber_data),
+        )
+        .route(
+            "/admin/subscribers/{subscriber_id}/audit",
+            get(get_subscriber_audit_trail),
+        )
+        .route("/admin/issues/{issue_id}/revisions", get(list_revisions))
+        .route(
+            "/admin/issues/{issue_id}/revisions/{revision}",
+            get(get_revision),
+        )
+        .route(
+            "/admin/issues/{issue_id}/revisions/{revision}/restore",
+            post(restore_revision),
+        )
         .with_state(app_state)
         .layer(ServiceBuilder::new().layer(RequestIdLayer).layer(
             TraceLayer::new_for_http().make_span_with(|request: &Request<Body>| {
@@ -117,5 +305,11 @@ pub async fn run(
             }),
         ));
 
-    serve(listener, app).await.unwrap();
+    // Consent records need the address of the client.
+    serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap();
 }
@@ -0,0 +1,247 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::consent::{ConsentRecord, consent_records};
+
+/// Everything we store about a subscriber, for them to take away.
+#[derive(Serialize)]
+pub struct SubscriberData {
+    pub exported_at: DateTime<Utc>,
+    pub profile: Profile,
+    pub lists: Vec<Membership>,
+    /// How and when the subscriber consented to hear from us, oldest first.
+    pub consents: Vec<ConsentRecord>,
+    /// What was done to the subscriber, oldest first.
+    pub history: Vec<HistoryEntry>,
+    /// The tokens we sent the subscriber. Their values are left out: they
+    /// are credentials.
+    pub tokens: Vec<IssuedToken>,
+    pub deliveries: Vec<Delivery>,
+    /// Why we stopped sending issues to the address, if we did.
+    pub suppression: Option<Suppression>,
+}
+
+#[derive(Serialize)]
+pub struct Profile {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+    pub frequency: String,
+    pub tracking_opt_out: bool,
+    pub tags: Vec<String>,
+    pub attributes: Value,
+}
+
+#[derive(Serialize)]
+pub struct Membership {
+    pub list: String,
+    pub name: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+    pub confirmed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+pub struct HistoryEntry {
+    pub action: String,
+    pub note: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct IssuedToken {
+    /// `subscription_confirmation` or `email_change`.
+    pub kind: String,
+    /// The address an email change token confirms.
+    pub new_email: Option<String>,
+    pub issued_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+pub struct Delivery {
+    #[serde(skip)]
+    pub newsletter_issue_id: Uuid,
+    pub issue: String,
+    /// The address the issue was sent to.
+    pub email: String,
+    pub status: String,
+    pub queued_at: DateTime<Utc>,
+    pub sent_at: Option<DateTime<Utc>>,
+    pub failed_at: Option<DateTime<Utc>>,
+    pub bounced_at: Option<DateTime<Utc>>,
+    pub first_opened_at: Option<DateTime<Utc>>,
+    pub last_opened_at: Option<DateTime<Utc>>,
+    pub open_count: i32,
+    pub clicks: Vec<Click>,
+}
+
+#[derive(Serialize)]
+pub struct Click {
+    pub url: String,
+    pub clicked_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct Suppression {
+    pub reason: String,
+    pub suppressed_at: DateTime<Utc>,
+}
+
+/// Compile everything we store about a subscriber, `None` if we do not know
+/// them.
+#[tracing::instrument(skip(pool))]
+pub async fn compile_subscriber_data(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<SubscriberData>, sqlx::Error> {
+    let profile = sqlx::query_as!(
+        Profile,
+        r#"
+        SELECT
+            id, email, name, status, subscribed_at, frequency,
+            tracking_opt_out, tags, attributes
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .await?;
+    let Some(profile) = profile else {
+        return Ok(None);
+    };
+    let lists = sqlx::query_as!(
+        Membership,
+        r#"
+        SELECT l.slug AS list, l.name, ls.status, ls.subscribed_at, ls.confirmed_at
+        FROM list_subscriptions ls
+        JOIN lists l ON l.list_id = ls.list_id
+        WHERE ls.subscriber_id = $1
+        ORDER BY ls.subscribed_at, l.slug
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await?;
+    let consents = consent_records(pool, subscriber_id).await?;
+    let history = sqlx::query_as!(
+        HistoryEntry,
+        r#"
+        SELECT action, note, created_at
+        FROM subscriber_audit_log
+        WHERE subscriber_id = $1
+        ORDER BY created_at, id
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await?;
+    let tokens = sqlx::query_as!(
+        IssuedToken,
+        r#"
+        SELECT
+            'subscription_confirmation' AS "kind!",
+            NULL::TEXT AS new_email,
+            NULL::TIMESTAMPTZ AS issued_at
+        FROM subscription_tokens
+        WHERE subscriber_id = $1
+        UNION ALL
+        SELECT 'email_change', new_email, requested_at
+        FROM email_change_tokens
+        WHERE subscriber_id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await?;
+    let deliveries = deliveries(pool, subscriber_id).await?;
+    let suppression = sqlx::query_as!(
+        Suppression,
+        "SELECT reason, suppressed_at FROM suppressed_emails WHERE email = $1",
+        profile.email
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(Some(SubscriberData {
+        exported_at: Utc::now(),
+        profile,
+        lists,
+        consents,
+        history,
+        tokens,
+        deliveries,
+        suppression,
+    }))
+}
+
+/// The issues sent to the subscriber, newest first, with the links they
+/// clicked in each.
+async fn deliveries(pool: &PgPool, subscriber_id: Uuid) -> Result<Vec<Delivery>, sqlx::Error> {
+    let mut deliveries = sqlx::query!(
+        r#"
+        SELECT
+            d.newsletter_issue_id, i.title, d.subscriber_email, d.status,
+            d.queued_at, d.sent_at, d.failed_at, d.bounced_at,
+            d.first_opened_at, d.last_opened_at, d.open_count
+        FROM issue_deliveries d
+        JOIN newsletter_issues i ON i.newsletter_issue_id = d.newsletter_issue_id
+        WHERE d.subscriber_id = $1
+        ORDER BY d.queued_at DESC
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|r| Delivery {
+        newsletter_issue_id: r.newsletter_issue_id,
+        issue: r.title,
+        email: r.subscriber_email,
+        status: r.status,
+        queued_at: r.queued_at,
+        sent_at: r.sent_at,
+        failed_at: r.failed_at,
+        bounced_at: r.bounced_at,
+        first_opened_at: r.first_opened_at,
+        last_opened_at: r.last_opened_at,
+        open_count: r.open_count,
+        clicks: Vec::new(),
+    })
+    .collect::<Vec<_>>();
+
+    let clicks = sqlx::query!(
+        r#"
+        SELECT c.newsletter_issue_id, c.subscriber_email, c.url, c.clicked_at
+        FROM link_clicks c
+        JOIN issue_deliveries d ON
+            d.newsletter_issue_id = c.newsletter_issue_id AND
+            d.subscriber_email = c.subscriber_email
+        WHERE d.subscriber_id = $1
+        ORDER BY c.clicked_at, c.link_click_id
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await?;
+    let by_delivery: HashMap<(Uuid, String), usize> = deliveries
+        .iter()
+        .enumerate()
+        .map(|(i, d)| ((d.newsletter_issue_id, d.email.clone()), i))
+        .collect();
+    for click in clicks {
+        if let Some(&i) = by_delivery.get(&(click.newsletter_issue_id, click.subscriber_email)) {
+            deliveries[i].clicks.push(Click {
+                url: click.url,
+                clicked_at: click.clicked_at,
+            });
+        }
+    }
+    Ok(deliveries)
+}
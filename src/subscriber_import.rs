@@ -0,0 +1,618 @@
+use std::{
+    collections::HashSet,
+    io::{self, Read},
+};
+
+use axum::body::{Body, Bytes};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, Transaction};
+use tokio::sync::mpsc;
+use tokio_stream::StreamExt;
+use uuid::Uuid;
+
+use crate::{
+    domain::{NewSubscriber, SubscriberEmail, SubscriberName},
+    email_client::EmailClient,
+    erasure::erased_addresses,
+    heartbeat::{HEARTBEAT_TIMEOUT, keep_alive},
+    lists::List,
+    routes::subscriptions::send_confirmation_email,
+    utils::generate_token,
+};
+
+/// How many rows are written, and reported as progress, at once.
+const BATCH_SIZE: usize = 500;
+
+/// The columns an import needs. Any other column is ignored.
+#[derive(Deserialize)]
+struct ImportRow {
+    email: String,
+    name: String,
+}
+
+/// A row that could not be imported, by its line in the file.
+#[derive(Debug, Serialize)]
+pub struct RowError {
+    pub line: i64,
+    pub error: String,
+}
+
+#[derive(Serialize)]
+pub struct ImportReport {
+    pub import_id: Uuid,
+    pub list_id: Uuid,
+    pub confirmed: bool,
+    /// `running`, `completed` or `failed`.
+    pub status: String,
+    pub processed_rows: i32,
+    pub imported_rows: i32,
+    pub duplicate_rows: i32,
+    pub failed_rows: i32,
+    /// Why the import stopped, when it failed.
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    /// The rows that could not be imported, by line.
+    pub errors: Vec<RowError>,
+}
+
+/// A file being uploaded, read as its chunks arrive rather than held in
+/// memory whole.
+///
+/// Reading blocks until the next chunk comes in: only read it from a
+/// blocking thread.
+pub struct BodyReader {
+    chunks: mpsc::Receiver<Result<Bytes, axum::Error>>,
+    chunk: Bytes,
+}
+
+impl BodyReader {
+    pub fn new(body: Body) -> Self {
+        let (sender, chunks) = mpsc::channel(4);
+        tokio::spawn(async move {
+            let mut stream = body.into_data_stream();
+            while let Some(chunk) = stream.next().await {
+                if sender.send(chunk).await.is_err() {
+                    break;
+                }
+            }
+        });
+        Self {
+            chunks,
+            chunk: Bytes::new(),
+        }
+    }
+}
+
+impl Read for BodyReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.chunk.is_empty() {
+            match self.chunks.blocking_recv() {
+                Some(chunk) => self.chunk = chunk.map_err(io::Error::other)?,
+                None => return Ok(0),
+            }
+        }
+        let read = buf.len().min(self.chunk.len());
+        buf[..read].copy_from_slice(&self.chunk.split_to(read));
+        Ok(read)
+    }
+}
+
+/// Open a CSV file of subscribers, checking it has the columns we need.
+pub fn csv_reader<R: Read>(source: R) -> Result<csv::Reader<R>, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(source);
+    let headers = reader
+        .headers()
+        .map_err(|e| format!("Failed to read the header row: {}", e))?;
+    for column in ["email", "name"] {
+        if !headers.iter().any(|header| header == column) {
+            return Err(format!("The `{}` column is missing.", column));
+        }
+    }
+    Ok(reader)
+}
+
+/// Record an import into `list`, to be run with [`run_import`].
+///
+/// Imported subscribers are either `confirmed` already, or sent the usual
+/// confirmation email when the list asks for one.
+#[tracing::instrument(skip(pool, list), fields(list = %list.slug))]
+pub async fn create_import(
+    pool: &PgPool,
+    list: &List,
+    confirmed: bool,
+) -> Result<Uuid, sqlx::Error> {
+    let import_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_imports (import_id, list_id, confirmed, status, created_at)
+        VALUES ($1, $2, $3, 'running', now())
+        "#,
+        import_id,
+        list.list_id,
+        confirmed
+    )
+    .execute(pool)
+    .await?;
+    Ok(import_id)
+}
+
+/// Import every row of `reader` a batch at a time, recording progress and
+/// the rows that could not be imported as it goes. Addresses we know already
+/// are skipped.
+///
+/// The import is marked as failed if it cannot complete; the batches written
+/// until then stay. It keeps a heartbeat while it runs, see
+/// [`fail_stale_imports`].
+#[tracing::instrument(skip(pool, email_client, base_url, list, reader))]
+pub async fn run_import<R: Read + Send + 'static>(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    import_id: Uuid,
+    list: &List,
+    reader: csv::Reader<R>,
+) -> Result<(), sqlx::Error> {
+    let importer = Importer {
+        pool,
+        email_client,
+        base_url,
+        import_id,
+        list,
+    };
+    let error = tokio::select! {
+        result = importer.import_rows(reader) => result.err(),
+        () = keep_alive(|| heartbeat(pool, import_id)) => {
+            tracing::warn!("Stopped an import that was failed in the meantime");
+            return Ok(());
+        }
+    };
+    if let Some(e) = &error {
+        tracing::error!("Failed to import subscribers: {}", e);
+    }
+    // The import may have been failed in the meantime, and must stay so.
+    sqlx::query!(
+        r#"
+        UPDATE subscriber_imports
+        SET
+            status = CASE WHEN $2::TEXT IS NULL THEN 'completed' ELSE 'failed' END,
+            error = $2,
+            finished_at = now()
+        WHERE import_id = $1 AND status = 'running'
+        "#,
+        import_id,
+        error.map(|e| e.to_string())
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Record that an import is still running, returning whether it is.
+async fn heartbeat(pool: &PgPool, import_id: Uuid) -> Result<bool, sqlx::Error> {
+    let updated = sqlx::query!(
+        r#"
+        UPDATE subscriber_imports
+        SET heartbeat_at = now()
+        WHERE import_id = $1 AND status = 'running'
+        "#,
+        import_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(updated.rows_affected() > 0)
+}
+
+/// Mark the running imports whose heartbeat stopped as failed: the process
+/// running them stopped. Their file went with it, so they cannot be resumed;
+/// the batches they wrote stay.
+#[tracing::instrument(skip(pool))]
+pub async fn fail_stale_imports(pool: &PgPool) -> Result<(), sqlx::Error> {
+    let stale = sqlx::query!(
+        r#"
+        UPDATE subscriber_imports
+        SET
+            status = 'failed',
+            error = 'The application stopped before the import completed.',
+            finished_at = now()
+        WHERE
+            status = 'running' AND
+            heartbeat_at < now() - make_interval(secs => $1)
+        "#,
+        HEARTBEAT_TIMEOUT.as_secs_f64()
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    if stale > 0 {
+        tracing::warn!(stale, "Failed the imports whose heartbeat stopped");
+    }
+    Ok(())
+}
+
+/// The progress of an import, or its outcome once it is over.
+pub async fn import_report(
+    pool: &PgPool,
+    import_id: Uuid,
+) -> Result<Option<ImportReport>, sqlx::Error> {
+    let import = sqlx::query!(
+        r#"
+        SELECT
+            import_id, list_id, confirmed, status, processed_rows, imported_rows,
+            duplicate_rows, failed_rows, error, created_at, finished_at
+        FROM subscriber_imports
+        WHERE import_id = $1
+        "#,
+        import_id
+    )
+    .fetch_optional(pool)
+    .await?;
+    let Some(import) = import else {
+        return Ok(None);
+    };
+    let errors = sqlx::query_as!(
+        RowError,
+        r#"
+        SELECT line, error
+        FROM subscriber_import_errors
+        WHERE import_id = $1
+        ORDER BY line
+        "#,
+        import_id
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(Some(ImportReport {
+        import_id: import.import_id,
+        list_id: import.list_id,
+        confirmed: import.confirmed,
+        status: import.status,
+        processed_rows: import.processed_rows,
+        imported_rows: import.imported_rows,
+        duplicate_rows: import.duplicate_rows,
+        failed_rows: import.failed_rows,
+        error: import.error,
+        created_at: import.created_at,
+        finished_at: import.finished_at,
+        errors,
+    }))
+}
+
+struct Importer<'a> {
+    pool: &'a PgPool,
+    email_client: &'a EmailClient,
+    base_url: &'a str,
+    import_id: Uuid,
+    list: &'a List,
+}
+
+/// The rows read since the last batch was written.
+#[derive(Default)]
+struct Batch {
+    rows: usize,
+    subscribers: Vec<(i64, NewSubscriber)>,
+    errors: Vec<RowError>,
+}
+
+/// What a batch adds to the counts of an import.
+#[derive(Default, Clone, Copy)]
+struct Progress {
+    rows: usize,
+    imported: usize,
+    duplicates: usize,
+    failed: usize,
+}
+
+impl Importer<'_> {
+    async fn import_rows<R: Read + Send + 'static>(
+        &self,
+        reader: csv::Reader<R>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let confirmed = sqlx::query_scalar!(
+            "SELECT confirmed FROM subscriber_imports WHERE import_id = $1",
+            self.import_id
+        )
+        .fetch_one(self.pool)
+        .await?;
+        let mut batches = read_batches(reader);
+        while let Some(batch) = batches.recv().await {
+            self.write_batch(batch?, confirmed).await?;
+        }
+        Ok(())
+    }
+
+    /// Store a batch, then send the confirmation emails it calls for. Emails
+    /// that cannot be sent are reported with the other errors.
+    async fn write_batch(&self, mut batch: Batch, confirmed: bool) -> Result<(), sqlx::Error> {
+        let mut transaction = self.pool.begin().await?;
+        refuse_erased(&mut transaction, &mut batch).await?;
+        let ids: Vec<Uuid> = batch.subscribers.iter().map(|_| Uuid::new_v4()).collect();
+        let inserted = insert_subscribers(
+            &mut transaction,
+            self.list,
+            confirmed,
+            &ids,
+            &batch.subscribers,
+        )
+        .await?;
+        let duplicates = batch.subscribers.len() - inserted.len();
+
+        let mut confirmations = Vec::new();
+        if !confirmed && self.list.double_opt_in {
+            confirmations = batch
+                .subscribers
+                .into_iter()
+                .zip(ids)
+                .filter(|(_, id)| inserted.contains(id))
+                .map(|((line, subscriber), id)| (line, subscriber, id, generate_token()))
+                .collect();
+            let (subscriber_ids, tokens): (Vec<Uuid>, Vec<String>) = confirmations
+                .iter()
+                .map(|(_, _, id, token)| (*id, token.clone()))
+                .unzip();
+            sqlx::query!(
+                r#"
+                INSERT INTO subscription_tokens (subscription_token, subscriber_id)
+                SELECT * FROM UNNEST($1::TEXT[], $2::uuid[])
+                "#,
+                &tokens,
+                &subscriber_ids
+            )
+            .execute(&mut *transaction)
+            .await?;
+        }
+        let progress = Progress {
+            rows: batch.rows,
+            imported: inserted.len(),
+            duplicates,
+            failed: batch.errors.len(),
+        };
+        self.record_progress(&mut transaction, progress, &batch.errors)
+            .await?;
+        transaction.commit().await?;
+
+        let mut email_errors = Vec::new();
+        for (line, subscriber, _, token) in confirmations {
+            if let Err(e) = send_confirmation_email(
+                self.email_client,
+                subscriber,
+                std::slice::from_ref(self.list),
+                self.base_url,
+                &token,
+            )
+            .await
+            {
+                email_errors.push(RowError {
+                    line,
+                    error: format!("Failed to send the confirmation email: {}", e),
+                });
+            }
+        }
+        if !email_errors.is_empty() {
+            let mut transaction = self.pool.begin().await?;
+            // The subscribers are stored: only their email is missing.
+            self.record_progress(&mut transaction, Progress::default(), &email_errors)
+                .await?;
+            transaction.commit().await?;
+        }
+        tracing::info!(
+            import_id = %self.import_id,
+            rows = progress.rows,
+            "Imported a batch of subscribers"
+        );
+        Ok(())
+    }
+
+    async fn record_progress(
+        &self,
+        transaction: &mut Transaction<'_, Postgres>,
+        progress: Progress,
+        errors: &[RowError],
+    ) -> Result<(), sqlx::Error> {
+        let (lines, messages): (Vec<i64>, Vec<String>) =
+            errors.iter().map(|e| (e.line, e.error.clone())).unzip();
+        sqlx::query!(
+            r#"
+            INSERT INTO subscriber_import_errors (import_id, line, error)
+            SELECT $1, * FROM UNNEST($2::BIGINT[], $3::TEXT[])
+            "#,
+            self.import_id,
+            &lines,
+            &messages
+        )
+        .execute(&mut **transaction)
+        .await?;
+        sqlx::query!(
+            r#"
+            UPDATE subscriber_imports
+            SET
+                processed_rows = processed_rows + $2,
+                imported_rows = imported_rows + $3,
+                duplicate_rows = duplicate_rows + $4,
+                failed_rows = failed_rows + $5
+            WHERE import_id = $1
+            "#,
+            self.import_id,
+            progress.rows as i32,
+            progress.imported as i32,
+            progress.duplicates as i32,
+            progress.failed as i32
+        )
+        .execute(&mut **transaction)
+        .await?;
+        Ok(())
+    }
+}
+
+/// Parse the rows of `reader` on a blocking thread, handing them over a batch
+/// at a time as the file comes in. The last batch is always sent, even when
+/// empty, unless the file can no longer be read.
+fn read_batches<R: Read + Send + 'static>(
+    mut reader: csv::Reader<R>,
+) -> mpsc::Receiver<Result<Batch, csv::Error>> {
+    let (sender, batches) = mpsc::channel(1);
+    tokio::task::spawn_blocking(move || {
+        let headers = match reader.headers() {
+            Ok(headers) => headers.clone(),
+            Err(e) => {
+                let _ = sender.blocking_send(Err(e));
+                return;
+            }
+        };
+        let mut record = csv::StringRecord::new();
+        let mut batch = Batch::default();
+        loop {
+            let more = match reader.read_record(&mut record) {
+                Ok(false) => false,
+                Ok(true) => {
+                    let line = record.position().map_or(0, |p| p.line()) as i64;
+                    match parse_row(&record, &headers) {
+                        Ok(subscriber) => batch.subscribers.push((line, subscriber)),
+                        Err(error) => batch.errors.push(RowError { line, error }),
+                    }
+                    batch.rows += 1;
+                    true
+                }
+                // The file can no longer be read.
+                Err(e) if e.is_io_error() => {
+                    let _ = sender.blocking_send(Err(e));
+                    return;
+                }
+                Err(e) => {
+                    let line = e.position().map_or(0, |p| p.line()) as i64;
+                    batch.errors.push(RowError {
+                        line,
+                        error: e.to_string(),
+                    });
+                    batch.rows += 1;
+                    true
+                }
+            };
+            if (batch.rows >= BATCH_SIZE || !more)
+                && sender
+                    .blocking_send(Ok(std::mem::take(&mut batch)))
+                    .is_err()
+            {
+                // The import stopped.
+                return;
+            }
+            if !more {
+                return;
+            }
+        }
+    });
+    batches
+}
+
+/// Turn the rows whose owner had their data erased into errors: they must
+/// not come back through an import.
+async fn refuse_erased(
+    transaction: &mut Transaction<'_, Postgres>,
+    batch: &mut Batch,
+) -> Result<(), sqlx::Error> {
+    let emails: Vec<&str> = batch
+        .subscribers
+        .iter()
+        .map(|(_, s)| s.email.as_ref())
+        .collect();
+    let erased = erased_addresses(transaction, &emails).await?;
+    if erased.is_empty() {
+        return Ok(());
+    }
+    let (refused, kept) = std::mem::take(&mut batch.subscribers)
+        .into_iter()
+        .partition(|(_, s)| erased.contains(s.email.as_ref()));
+    batch.subscribers = kept;
+    batch
+        .errors
+        .extend(refused.into_iter().map(|(line, _): (i64, _)| RowError {
+            line,
+            error: "The owner of the address had their data erased.".into(),
+        }));
+    Ok(())
+}
+
+fn parse_row(
+    record: &csv::StringRecord,
+    headers: &csv::StringRecord,
+) -> Result<NewSubscriber, String> {
+    let row: ImportRow = record
+        .deserialize(Some(headers))
+        .map_err(|e| e.to_string())?;
+    Ok(NewSubscriber {
+        email: SubscriberEmail::parse(row.email)?,
+        name: SubscriberName::parse(row.name)?,
+    })
+}
+
+/// Store the subscribers we do not know yet, as members of `list`, and
+/// return the ids of the ones stored.
+async fn insert_subscribers(
+    transaction: &mut Transaction<'_, Postgres>,
+    list: &List,
+    confirmed: bool,
+    ids: &[Uuid],
+    subscribers: &[(i64, NewSubscriber)],
+) -> Result<HashSet<Uuid>, sqlx::Error> {
+    let emails: Vec<&str> = subscribers.iter().map(|(_, s)| s.email.as_ref()).collect();
+    let names: Vec<&str> = subscribers.iter().map(|(_, s)| s.name.as_ref()).collect();
+    let status = if confirmed {
+        "confirmed"
+    } else {
+        "pending_confirmation"
+    };
+    let inserted = sqlx::query_scalar!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        SELECT id, email, name, now(), $4
+        FROM UNNEST($1::uuid[], $2::TEXT[], $3::TEXT[]) AS t(id, email, name)
+        ON CONFLICT (email) DO NOTHING
+        RETURNING id
+        "#,
+        ids,
+        &emails as &[&str],
+        &names as &[&str],
+        status
+    )
+    .fetch_all(&mut **transaction)
+    .await?;
+    let list_status = if confirmed || !list.double_opt_in {
+        "confirmed"
+    } else {
+        "pending_confirmation"
+    };
+    sqlx::query!(
+        r#"
+        INSERT INTO list_subscriptions (
+            list_id, subscriber_id, status, subscribed_at, confirmed_at
+        )
+        SELECT $1, id, $3, now(), CASE WHEN $3 = 'confirmed' THEN now() END
+        FROM UNNEST($2::uuid[]) AS id
+        "#,
+        list.list_id,
+        &inserted,
+        list_status
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(inserted.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::{assert_err, assert_ok};
+
+    use super::csv_reader;
+
+    #[test]
+    fn files_need_an_email_and_a_name_column() {
+        assert_ok!(csv_reader("name,email,plan\n".as_bytes()));
+        assert_ok!(csv_reader(" email , name \n".as_bytes()));
+        assert_err!(csv_reader("email\n".as_bytes()));
+        assert_err!(csv_reader("".as_bytes()));
+    }
+}
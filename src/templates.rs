@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+
+/// Substitute `{{ name }}` placeholders in `template`.
+///
+/// Placeholders without a matching variable render as an empty string, and
+/// an unterminated `{{` is kept as is. Values are inserted verbatim: escape
+/// them beforehand when rendering HTML.
+pub fn render(template: &str, variables: &HashMap<&str, String>) -> String {
+    expand(template, variables, false)
+}
+
+/// Like [`render`], but placeholders without a matching variable are kept as
+/// written: content such as code samples may contain `{{ ... }}` of its own.
+pub fn substitute(template: &str, variables: &HashMap<&str, String>) -> String {
+    expand(template, variables, true)
+}
+
+fn expand(template: &str, variables: &HashMap<&str, String>, keep_unknown: bool) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+        let after_open = &rest[start + 2..];
+        let Some(end) = after_open.find("}}") else {
+            rendered.push_str(&rest[start..]);
+            return rendered;
+        };
+        let name = after_open[..end].trim();
+        match variables.get(name) {
+            Some(value) => rendered.push_str(value),
+            None if keep_unknown => rendered.push_str(&rest[start..start + end + 4]),
+            None => {}
+        }
+        rest = &after_open[end + 2..];
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+/// Reduce an HTML fragment to its text, for plain-text email bodies.
+pub fn strip_tags(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+        .trim()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{render, strip_tags, substitute};
+
+    #[test]
+    fn placeholders_are_replaced_with_their_values() {
+        let variables = HashMap::from([("title", "Hello".to_string())]);
+        assert_eq!(render("<h1>{{ title }}</h1>", &variables), "<h1>Hello</h1>");
+        assert_eq!(render("{{title}}{{title}}", &variables), "HelloHello");
+    }
+
+    #[test]
+    fn unknown_placeholders_render_as_empty() {
+        assert_eq!(render("a{{ missing }}b", &HashMap::new()), "ab");
+    }
+
+    #[test]
+    fn substitution_keeps_unknown_placeholders() {
+        let variables = HashMap::from([("name", "Ursula".to_string())]);
+        assert_eq!(
+            substitute("{{ name }}: {{ not_a_var }}{{name}}", &variables),
+            "Ursula: {{ not_a_var }}Ursula"
+        );
+    }
+
+    #[test]
+    fn unterminated_placeholders_are_kept() {
+        assert_eq!(render("a {{ title", &HashMap::new()), "a {{ title");
+    }
+
+    #[test]
+    fn tags_are_stripped_and_entities_decoded() {
+        assert_eq!(strip_tags("<p>Fish &amp; <b>chips</b></p>"), "Fish & chips");
+    }
+}
@@ -0,0 +1,158 @@
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use serde::Serialize;
+use tokio::time::Instant;
+
+/// A token bucket shared by every clone of the handle.
+///
+/// Tokens refill at `rate_per_second` up to `burst`. Callers reserve a token
+/// before doing any work; when the bucket is empty they wait for their turn,
+/// in the order they asked.
+#[derive(Clone)]
+pub struct Throttle {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    rate_per_second: f64,
+    burst: f64,
+    bucket: Mutex<Bucket>,
+    metrics: Metrics,
+}
+
+struct Bucket {
+    /// Negative while callers are waiting on tokens not refilled yet.
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+#[derive(Default)]
+struct Metrics {
+    acquired: AtomicU64,
+    throttled: AtomicU64,
+    waited_micros: AtomicU64,
+}
+
+/// What the throttle did since the application started.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ThrottleMetrics {
+    pub rate_per_second: f64,
+    pub burst: u32,
+    /// Tokens handed out.
+    pub acquired: u64,
+    /// How many of them had to wait for the bucket to refill.
+    pub throttled: u64,
+    pub wait_seconds_total: f64,
+}
+
+impl Throttle {
+    pub fn new(rate_per_second: f64, burst: u32) -> Self {
+        assert!(rate_per_second > 0.0, "The rate must be positive");
+        let burst = f64::from(burst.max(1));
+        Self {
+            inner: Arc::new(Inner {
+                rate_per_second,
+                burst,
+                bucket: Mutex::new(Bucket {
+                    tokens: burst,
+                    refilled_at: Instant::now(),
+                }),
+                metrics: Metrics::default(),
+            }),
+        }
+    }
+
+    /// Wait until a token is available and take it.
+    pub async fn acquire(&self) {
+        let wait = self.reserve();
+        let metrics = &self.inner.metrics;
+        metrics.acquired.fetch_add(1, Ordering::Relaxed);
+        if wait.is_zero() {
+            return;
+        }
+        metrics.throttled.fetch_add(1, Ordering::Relaxed);
+        metrics
+            .waited_micros
+            .fetch_add(wait.as_micros() as u64, Ordering::Relaxed);
+        tracing::debug!(wait_ms = wait.as_millis() as u64, "Throttled");
+        tokio::time::sleep(wait).await;
+    }
+
+    /// Take a token, possibly borrowed from the future, and return how long
+    /// the caller must wait before it is really theirs.
+    fn reserve(&self) -> Duration {
+        let mut bucket = self.inner.bucket.lock().unwrap();
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+        bucket.tokens =
+            (bucket.tokens + elapsed * self.inner.rate_per_second).min(self.inner.burst);
+        bucket.refilled_at = now;
+        bucket.tokens -= 1.0;
+        if bucket.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-bucket.tokens / self.inner.rate_per_second)
+        }
+    }
+
+    pub fn metrics(&self) -> ThrottleMetrics {
+        let metrics = &self.inner.metrics;
+        ThrottleMetrics {
+            rate_per_second: self.inner.rate_per_second,
+            burst: self.inner.burst as u32,
+            acquired: metrics.acquired.load(Ordering::Relaxed),
+            throttled: metrics.throttled.load(Ordering::Relaxed),
+            wait_seconds_total: metrics.waited_micros.load(Ordering::Relaxed) as f64 / 1e6,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Throttle;
+
+    #[tokio::test(start_paused = true)]
+    async fn a_full_bucket_lets_a_burst_through_without_waiting() {
+        let throttle = Throttle::new(1.0, 3);
+        let start = tokio::time::Instant::now();
+        for _ in 0..3 {
+            throttle.acquire().await;
+        }
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        assert_eq!(throttle.metrics().throttled, 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn callers_wait_for_the_bucket_to_refill_once_it_is_empty() {
+        let throttle = Throttle::new(2.0, 1);
+        let start = tokio::time::Instant::now();
+        for _ in 0..5 {
+            throttle.acquire().await;
+        }
+        // The first token is free, the four others come every 500ms.
+        assert!(start.elapsed() >= Duration::from_secs(2));
+        let metrics = throttle.metrics();
+        assert_eq!(metrics.acquired, 5);
+        assert_eq!(metrics.throttled, 4);
+        assert!((metrics.wait_seconds_total - 2.0).abs() < 0.01);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn clones_share_the_same_bucket() {
+        let throttle = Throttle::new(1.0, 1);
+        let clone = throttle.clone();
+        throttle.acquire().await;
+        let start = tokio::time::Instant::now();
+        clone.acquire().await;
+        assert!(start.elapsed() >= Duration::from_secs(1));
+        assert_eq!(throttle.metrics().acquired, 2);
+    }
+}
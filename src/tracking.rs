@@ -0,0 +1,142 @@
+use url::Url;
+
+use crate::{
+    links::{is_web_url, rewrite_hrefs},
+    signing::Signer,
+};
+
+/// Builds the tracking URLs embedded in issues, and checks the ones that
+/// come back.
+#[derive(Clone)]
+pub struct Tracker {
+    base_url: String,
+    signer: Signer,
+}
+
+impl Tracker {
+    /// `base_url` is where recipients reach the tracking routes.
+    pub fn new(base_url: String, signer: Signer) -> Self {
+        Self { base_url, signer }
+    }
+
+    /// Where recipients reach the application.
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// The URL of the open-tracking pixel of a delivery.
+    pub fn open_pixel_url(&self, open_token: &str) -> String {
+        format!("{}/t/open/{}", self.base_url, open_token)
+    }
+
+    /// Route every web link of an HTML body through the click-tracking
+    /// redirect of a delivery.
+    pub fn track_clicks(&self, html: &str, click_token: &str) -> String {
+        rewrite_hrefs(html, |url| {
+            is_web_url(url).then(|| self.click_url(click_token, url))
+        })
+    }
+
+    /// The signature binds the destination to the delivery: without it, the
+    /// redirect route would send people anywhere it is asked to.
+    pub fn click_url(&self, click_token: &str, url: &str) -> String {
+        let mut click_url = Url::parse(&format!("{}/t/click/{}", self.base_url, click_token))
+            .expect("The base URL is not a valid URL");
+        click_url
+            .query_pairs_mut()
+            .append_pair("url", url)
+            .append_pair("sig", &self.signer.sign(&click_message(click_token, url)));
+        click_url.into()
+    }
+
+    pub fn verify_click(&self, click_token: &str, url: &str, signature: &str) -> bool {
+        self.signer
+            .verify(&click_message(click_token, url), signature)
+    }
+}
+
+fn click_message(click_token: &str, url: &str) -> String {
+    format!("click\n{}\n{}", click_token, url)
+}
+
+/// Add a 1x1 tracking image to an HTML body, right before `</body>` when
+/// there is one.
+pub fn inject_open_pixel(html: &str, pixel_url: &str) -> String {
+    let pixel = format!(
+        r#"<img src="{}" width="1" height="1" alt="" style="display:none">"#,
+        pixel_url
+    );
+    match html.to_ascii_lowercase().rfind("</body>") {
+        Some(index) => format!("{}{}{}", &html[..index], pixel, &html[index..]),
+        None => format!("{}{}", html, pixel),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::SecretString;
+
+    use super::{Tracker, inject_open_pixel};
+    use crate::signing::Signer;
+
+    fn tracker() -> Tracker {
+        Tracker::new(
+            "https://news.example".into(),
+            Signer::new(SecretString::from("secret")),
+        )
+    }
+
+    const PIXEL: &str =
+        r#"<img src="https://t.example/p" width="1" height="1" alt="" style="display:none">"#;
+
+    #[test]
+    fn the_pixel_goes_at_the_end_of_the_body() {
+        let html = "<html><body><p>Hi</p></BODY></html>";
+        assert_eq!(
+            inject_open_pixel(html, "https://t.example/p"),
+            format!("<html><body><p>Hi</p>{}</BODY></html>", PIXEL)
+        );
+    }
+
+    #[test]
+    fn the_pixel_is_appended_to_fragments() {
+        assert_eq!(
+            inject_open_pixel("<p>Hi</p>", "https://t.example/p"),
+            format!("<p>Hi</p>{}", PIXEL)
+        );
+    }
+
+    #[test]
+    fn web_links_are_routed_through_signed_redirects() {
+        let tracker = tracker();
+        let html = r#"<a href="https://blog.example/?a=1&amp;b=2">Post</a> <a href="mailto:x@y.z">Mail</a>"#;
+
+        let tracked = tracker.track_clicks(html, "token");
+
+        let url = tracker.click_url("token", "https://blog.example/?a=1&b=2");
+        assert!(url.starts_with("https://news.example/t/click/token?url=https%3A%2F%2Fblog"));
+        assert_eq!(
+            tracked,
+            format!(
+                r#"<a href="{}">Post</a> <a href="mailto:x@y.z">Mail</a>"#,
+                url.replace('&', "&amp;")
+            )
+        );
+    }
+
+    #[test]
+    fn clicks_are_verified_against_the_destination_and_the_delivery() {
+        let tracker = tracker();
+        let url = url::Url::parse(&tracker.click_url("token", "https://blog.example")).unwrap();
+        let signature = url
+            .query_pairs()
+            .find(|(name, _)| name == "sig")
+            .unwrap()
+            .1
+            .into_owned();
+
+        assert!(tracker.verify_click("token", "https://blog.example", &signature));
+        assert!(!tracker.verify_click("token", "https://evil.example", &signature));
+        assert!(!tracker.verify_click("other", "https://blog.example", &signature));
+    }
+}
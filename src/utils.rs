@@ -0,0 +1,33 @@
+use axum::http::StatusCode;
+use rand::{Rng, distr::Alphanumeric, rng};
+
+/// Log an unexpected error and turn it into a `500 Internal Server Error`.
+pub fn e500<T: std::fmt::Debug>(e: T) -> StatusCode {
+    tracing::error!("Unexpected error: {:?}", e);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+/// Escape text for inclusion in HTML or XML content and attribute values.
+pub fn escape_html(input: &str) -> String {
+    let mut escaped = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Generate a random 25-characters-long case-sensitive token.
+pub fn generate_token() -> String {
+    let mut rng = rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(25)
+        .collect()
+}
@@ -0,0 +1,108 @@
+use url::Url;
+
+use crate::links::{is_web_url, rewrite_hrefs, rewrite_text_urls};
+
+pub const DEFAULT_SOURCE: &str = "newsletter";
+pub const DEFAULT_MEDIUM: &str = "email";
+
+/// The UTM parameters appended to the outbound links of an issue, so that
+/// the visits they bring show up as such in web analytics.
+pub struct UtmParameters {
+    pub source: String,
+    pub medium: String,
+    pub campaign: String,
+}
+
+impl UtmParameters {
+    /// Tag the links of an HTML body. Links to `base_url` are ours, and are
+    /// left alone.
+    pub fn tag_html(&self, html: &str, base_url: &str) -> String {
+        rewrite_hrefs(html, |url| self.tag(url, base_url))
+    }
+
+    /// Tag the links of a plain-text body. Links to `base_url` are ours, and
+    /// are left alone.
+    pub fn tag_text(&self, text: &str, base_url: &str) -> String {
+        rewrite_text_urls(text, |url| self.tag(url, base_url))
+    }
+
+    /// The tagged URL, or `None` when the link must be kept as is. Parameters
+    /// already set on a link win over ours.
+    fn tag(&self, url: &str, base_url: &str) -> Option<String> {
+        if !is_web_url(url)
+            || url.to_ascii_lowercase().contains("unsubscribe")
+            || is_internal(url, base_url)
+        {
+            return None;
+        }
+        let mut url = Url::parse(url.trim()).ok()?;
+        let existing: Vec<String> = url
+            .query_pairs()
+            .map(|(name, _)| name.into_owned())
+            .collect();
+        let missing: Vec<(&str, &str)> = [
+            ("utm_source", self.source.as_str()),
+            ("utm_medium", self.medium.as_str()),
+            ("utm_campaign", self.campaign.as_str()),
+        ]
+        .into_iter()
+        .filter(|(name, _)| !existing.iter().any(|e| e == name))
+        .collect();
+        if missing.is_empty() {
+            return None;
+        }
+        url.query_pairs_mut().extend_pairs(missing);
+        Some(url.into())
+    }
+}
+
+/// Links to the host serving the application, whatever the scheme or port.
+fn is_internal(url: &str, base_url: &str) -> bool {
+    match (Url::parse(url.trim()), Url::parse(base_url)) {
+        (Ok(url), Ok(base_url)) => url.host_str() == base_url.host_str(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UtmParameters;
+
+    const BASE_URL: &str = "https://news.example";
+
+    fn parameters() -> UtmParameters {
+        UtmParameters {
+            source: "newsletter".into(),
+            medium: "email".into(),
+            campaign: "issue-42".into(),
+        }
+    }
+
+    #[test]
+    fn parameters_are_appended_to_existing_query_strings() {
+        let html = r#"<a href="https://blog.example/post?id=1&amp;ref=x#top">Post</a>"#;
+        assert_eq!(
+            parameters().tag_html(html, BASE_URL),
+            r#"<a href="https://blog.example/post?id=1&amp;ref=x&amp;utm_source=newsletter&amp;utm_medium=email&amp;utm_campaign=issue-42#top">Post</a>"#
+        );
+    }
+
+    #[test]
+    fn parameters_set_on_a_link_are_kept() {
+        let text = "See https://blog.example/?utm_campaign=spring.";
+        assert_eq!(
+            parameters().tag_text(text, BASE_URL),
+            "See https://blog.example/?utm_campaign=spring&utm_source=newsletter&utm_medium=email."
+        );
+    }
+
+    #[test]
+    fn mailto_unsubscribe_and_internal_links_are_skipped() {
+        let html = concat!(
+            r#"<a href="mailto:editor@example.com">Reply</a>"#,
+            r#"<a href="https://esp.example/Unsubscribe?id=1">Leave</a>"#,
+            r#"<a href="https://news.example/archive/issue-41">Previous issue</a>"#,
+        );
+        assert_eq!(parameters().tag_html(html, BASE_URL), html);
+    }
+}
@@ -0,0 +1,335 @@
+use std::collections::HashSet;
+
+use wiremock::{
+    Mock, ResponseTemplate,
+    matchers::{method, path},
+};
+
+use crate::helpers::{TestApp, spawn_app};
+
+/// Send everything queued, returning the emails sent by this call.
+async fn deliver(app: &TestApp) -> Vec<serde_json::Value> {
+    let already_sent = app.email_server.received_requests().await.unwrap().len();
+    app.dispatch_all_pending_emails().await;
+    app.email_server.received_requests().await.unwrap()[already_sent..]
+        .iter()
+        .map(|r| serde_json::from_slice(&r.body).unwrap())
+        .collect()
+}
+
+async fn issue_status(app: &TestApp, issue_id: &str) -> String {
+    let issue: serde_json::Value = app.get_issue(issue_id).await.json().await.unwrap();
+    issue["status"].as_str().unwrap().to_owned()
+}
+
+async fn setup(app: &TestApp, n_subscribers: usize) {
+    for _ in 0..n_subscribers {
+        app.create_confirmed_subscriber().await;
+    }
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+#[tokio::test]
+async fn only_the_test_sample_gets_the_variants_at_first() {
+    // Arrange
+    let app = spawn_app().await;
+    setup(&app, 6).await;
+    let issue_id = app
+        .create_ab_tested_issue(&["Subject A", "Subject B"])
+        .await;
+
+    // Act
+    app.promote_due_issues().await;
+    let emails = deliver(&app).await;
+
+    // Assert
+    assert_eq!(emails.len(), 3);
+    let subjects: HashSet<_> = emails.iter().map(|e| e["Subject"].clone()).collect();
+    assert_eq!(
+        subjects,
+        HashSet::from([
+            serde_json::json!("Subject A"),
+            serde_json::json!("Subject B")
+        ])
+    );
+    // The rest of the list waits for the winner.
+    assert_eq!(issue_status(&app, &issue_id).await, "sending");
+    let report: serde_json::Value = app
+        .admin_get(&format!("/admin/issues/{}/ab-test", issue_id))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(report["winning_variant"], serde_json::Value::Null);
+    assert!(report["decide_at"].is_string());
+    assert_eq!(report["variants"][0]["sent"], 2);
+    assert_eq!(report["variants"][1]["sent"], 1);
+}
+
+#[tokio::test]
+async fn the_winning_subject_goes_to_the_rest_of_the_list() {
+    // Arrange
+    let app = spawn_app().await;
+    setup(&app, 6).await;
+    let issue_id = app
+        .create_ab_tested_issue(&["Subject A", "Subject B"])
+        .await;
+    app.promote_due_issues().await;
+    let sample = deliver(&app).await;
+    let opened = sample.iter().find(|e| e["Subject"] == "Subject B").unwrap();
+    let html = opened["HtmlBody"].as_str().unwrap();
+    let (_, rest) = html.split_once("/t/open/").unwrap();
+    let token = &rest[..rest.find('"').unwrap()];
+    reqwest::get(format!("{}/t/open/{}", app.address, token))
+        .await
+        .unwrap();
+
+    // Act
+    app.decide_ab_tests_now().await;
+    let rest = deliver(&app).await;
+
+    // Assert
+    assert_eq!(rest.len(), 3);
+    assert!(rest.iter().all(|e| e["Subject"] == "Subject B"));
+    let sample_recipients: HashSet<_> = sample.iter().map(|e| e["To"].clone()).collect();
+    assert!(rest.iter().all(|e| !sample_recipients.contains(&e["To"])));
+    assert_eq!(issue_status(&app, &issue_id).await, "sent");
+    let report: serde_json::Value = app
+        .admin_get(&format!("/admin/issues/{}/ab-test", issue_id))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(report["winning_variant"], 1);
+    assert_eq!(report["variants"][1]["opened"], 1);
+}
+
+#[tokio::test]
+async fn the_first_subject_wins_without_any_signal() {
+    // Arrange
+    let app = spawn_app().await;
+    setup(&app, 4).await;
+    app.create_ab_tested_issue(&["Subject A", "Subject B", "Subject C"])
+        .await;
+    app.promote_due_issues().await;
+    deliver(&app).await;
+
+    // Act
+    app.decide_ab_tests_now().await;
+    let rest = deliver(&app).await;
+
+    // Assert
+    assert_eq!(rest.len(), 1);
+    assert_eq!(rest[0]["Subject"], "Subject A");
+}
+
+#[tokio::test]
+async fn tests_are_not_decided_before_their_window_closes() {
+    // Arrange
+    let app = spawn_app().await;
+    setup(&app, 4).await;
+    let issue_id = app
+        .create_ab_tested_issue(&["Subject A", "Subject B"])
+        .await;
+    app.promote_due_issues().await;
+    deliver(&app).await;
+
+    // Act
+    email_newsletter::ab_testing::decide_due_ab_tests(&app.db)
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(deliver(&app).await.is_empty());
+    assert_eq!(issue_status(&app, &issue_id).await, "sending");
+}
+
+#[tokio::test]
+async fn invalid_ab_tests_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let response = app
+        .post_issue(&serde_json::json!({
+            "title": "Issue",
+            "text_content": "text",
+            "html_content": "<p>html</p>",
+        }))
+        .await;
+    let body: serde_json::Value = response.json().await.unwrap();
+    let issue_id = body["newsletter_issue_id"].as_str().unwrap();
+    let test_cases = vec![
+        (
+            serde_json::json!({
+                "subjects": ["Only one"],
+                "test_percentage": 20,
+                "wait_minutes": 60,
+                "metric": "open_rate",
+            }),
+            "a single subject",
+        ),
+        (
+            serde_json::json!({
+                "subjects": ["A", " "],
+                "test_percentage": 20,
+                "wait_minutes": 60,
+                "metric": "open_rate",
+            }),
+            "a blank subject",
+        ),
+        (
+            serde_json::json!({
+                "subjects": ["A", "B"],
+                "test_percentage": 100,
+                "wait_minutes": 60,
+                "metric": "click_rate",
+            }),
+            "the whole list as a sample",
+        ),
+        (
+            serde_json::json!({
+                "subjects": ["A", "B"],
+                "test_percentage": 20,
+                "wait_minutes": 0,
+                "metric": "click_rate",
+            }),
+            "no wait window",
+        ),
+    ];
+
+    for (body, description) in test_cases {
+        // Act
+        let response = app
+            .admin_put(&format!("/admin/issues/{}/ab-test", issue_id), &body)
+            .await;
+
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not reject {}",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn ab_tests_cannot_change_once_an_issue_is_published() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = app.create_published_issue("Issue").await;
+
+    // Act
+    let response = app
+        .admin_delete(&format!("/admin/issues/{}/ab-test", issue_id))
+        .await;
+
+    // Assert
+    assert_eq!(409, response.status().as_u16());
+    let response = app
+        .admin_get(&format!("/admin/issues/{}/ab-test", issue_id))
+        .await;
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn ab_tests_need_the_tracking_their_metric_relies_on() {
+    // Arrange
+    let app = spawn_app().await;
+    let response = app
+        .post_issue(&serde_json::json!({
+            "title": "Issue",
+            "text_content": "text",
+            "html_content": "<p>html</p>",
+        }))
+        .await;
+    let body: serde_json::Value = response.json().await.unwrap();
+    let issue_id = body["newsletter_issue_id"].as_str().unwrap();
+    let ab_test = |metric: &str| {
+        serde_json::json!({
+            "subjects": ["A", "B"],
+            "test_percentage": 20,
+            "wait_minutes": 60,
+            "metric": metric,
+        })
+    };
+    let tracking = |track_opens: bool, track_clicks: bool| serde_json::json!({ "track_opens": track_opens, "track_clicks": track_clicks });
+    let ab_test_path = format!("/admin/issues/{}/ab-test", issue_id);
+    let tracking_path = format!("/admin/issues/{}/tracking", issue_id);
+
+    // Act
+    app.admin_put(&tracking_path, &tracking(true, false))
+        .await
+        .error_for_status()
+        .unwrap();
+    let unmeasured = app.admin_put(&ab_test_path, &ab_test("click_rate")).await;
+    let measured = app.admin_put(&ab_test_path, &ab_test("open_rate")).await;
+    let untracked = app.admin_put(&tracking_path, &tracking(false, true)).await;
+
+    // Assert
+    assert_eq!(400, unmeasured.status().as_u16());
+    assert_eq!(200, measured.status().as_u16());
+    assert_eq!(400, untracked.status().as_u16());
+}
+
+#[tokio::test]
+async fn changing_the_ab_test_invalidates_the_approval() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = app.create_ab_tested_issue(&["A", "B"]).await;
+
+    // Act
+    let response = app
+        .admin_delete(&format!("/admin/issues/{}/ab-test", issue_id))
+        .await;
+    app.promote_due_issues().await;
+
+    // Assert
+    assert_eq!(204, response.status().as_u16());
+    let issue: serde_json::Value = app.get_issue(&issue_id).await.json().await.unwrap();
+    assert_eq!(issue["status"], "scheduled");
+    assert_eq!(issue["approval_status"], "pending");
+}
+
+#[tokio::test]
+async fn a_test_that_cannot_be_decided_does_not_hold_back_the_others() {
+    // Arrange
+    let app = spawn_app().await;
+    setup(&app, 2).await;
+    let broken_issue_id = app
+        .create_ab_tested_issue(&["Subject A", "Subject B"])
+        .await;
+    let issue_id = app
+        .create_ab_tested_issue(&["Subject C", "Subject D"])
+        .await;
+    app.promote_due_issues().await;
+    deliver(&app).await;
+    // Picking a winner for the first issue fails every time.
+    sqlx::raw_sql(&format!(
+        r#"
+        CREATE FUNCTION refuse_winner() RETURNS trigger AS $$
+        BEGIN
+            RAISE EXCEPTION 'refused';
+        END;
+        $$ LANGUAGE plpgsql;
+        CREATE TRIGGER refuse_winner
+            BEFORE UPDATE OF winning_variant ON newsletter_issues
+            FOR EACH ROW WHEN (NEW.newsletter_issue_id = '{}')
+            EXECUTE FUNCTION refuse_winner();
+        "#,
+        broken_issue_id
+    ))
+    .execute(&app.db)
+    .await
+    .unwrap();
+
+    // Act
+    app.decide_ab_tests_now().await;
+
+    // Assert
+    assert_eq!(issue_status(&app, &broken_issue_id).await, "sending");
+    assert_eq!(issue_status(&app, &issue_id).await, "sent");
+}
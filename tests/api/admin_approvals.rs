@@ -0,0 +1,273 @@
+use chrono::Utc;
+use email_newsletter::routes::admin::issues::{IssueContent, insert_issue};
+use wiremock::{
+    Mock, ResponseTemplate,
+    matchers::{any, method, path},
+};
+
+use crate::helpers::{TestApp, spawn_app};
+
+fn content(title: &str) -> serde_json::Value {
+    serde_json::json!({
+        "title": title,
+        "text_content": format!("{} as plain text", title),
+        "html_content": format!("<p>{} as HTML</p>", title),
+    })
+}
+
+/// Create an issue, due immediately, as `test_user`.
+async fn due_issue(app: &TestApp) -> String {
+    let mut body = content("Issue");
+    body["send_at"] = serde_json::json!(Utc::now());
+    let response = app.post_issue(&body).await;
+    assert_eq!(201, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    body["newsletter_issue_id"].as_str().unwrap().to_owned()
+}
+
+#[tokio::test]
+async fn unapproved_issues_are_not_published() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let issue_id = due_issue(&app).await;
+
+    // Act
+    app.promote_due_issues().await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let issue: serde_json::Value = app.get_issue(&issue_id).await.json().await.unwrap();
+    assert_eq!(issue["status"], "scheduled");
+    assert_eq!(issue["approval_status"], "pending");
+}
+
+#[tokio::test]
+async fn approved_issues_are_published() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let issue_id = due_issue(&app).await;
+
+    // Act
+    let response = app.approve_issue(&issue_id, 1).await;
+    app.promote_due_issues().await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let issue: serde_json::Value = app.get_issue(&issue_id).await.json().await.unwrap();
+    assert_eq!(issue["status"], "sent");
+    assert_eq!(issue["approved_revision"], 1);
+}
+
+#[tokio::test]
+async fn authors_cannot_approve_their_own_revision() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = due_issue(&app).await;
+
+    // Act
+    let response = app
+        .admin_post(
+            &format!("/admin/issues/{}/approve", issue_id),
+            &serde_json::json!({ "revision": 1 }),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(403, response.status().as_u16());
+    let issue: serde_json::Value = app.get_issue(&issue_id).await.json().await.unwrap();
+    assert_eq!(issue["approval_status"], "pending");
+}
+
+#[tokio::test]
+async fn approving_a_stale_revision_is_rejected_with_a_409() {
+    // Arrange
+    let app = spawn_app().await;
+    let response = app.post_issue(&content("First draft")).await;
+    let body: serde_json::Value = response.json().await.unwrap();
+    let issue_id = body["newsletter_issue_id"].as_str().unwrap().to_owned();
+    app.admin_put(
+        &format!("/admin/issues/{}", issue_id),
+        &content("Second draft"),
+    )
+    .await;
+
+    // Act
+    let response = app.approve_issue(&issue_id, 1).await;
+
+    // Assert
+    assert_eq!(409, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn editing_an_approved_draft_invalidates_the_approval() {
+    // Arrange
+    let app = spawn_app().await;
+    let response = app.post_issue(&content("First draft")).await;
+    let body: serde_json::Value = response.json().await.unwrap();
+    let issue_id = body["newsletter_issue_id"].as_str().unwrap().to_owned();
+    app.approve_issue(&issue_id, 1).await;
+
+    // Act
+    app.admin_put(
+        &format!("/admin/issues/{}", issue_id),
+        &content("Second draft"),
+    )
+    .await;
+
+    // Assert
+    let issue: serde_json::Value = app.get_issue(&issue_id).await.json().await.unwrap();
+    assert_eq!(issue["approval_status"], "pending");
+    assert!(issue["approved_revision"].is_null());
+    let trail: serde_json::Value = app
+        .admin_get(&format!("/admin/issues/{}/audit", issue_id))
+        .await
+        .json()
+        .await
+        .unwrap();
+    let actions: Vec<_> = trail
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["action"].as_str().unwrap())
+        .collect();
+    assert_eq!(actions, vec!["approved", "approval_invalidated"]);
+}
+
+#[tokio::test]
+async fn rejected_issues_are_not_published_and_the_decision_is_audited() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let issue_id = due_issue(&app).await;
+    app.approve_issue(&issue_id, 1).await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/admin/issues/{}/reject", app.address, issue_id))
+        .basic_auth(&app.approver.username, Some(&app.approver.password))
+        .json(&serde_json::json!({ "revision": 1, "note": "Typo in the title" }))
+        .send()
+        .await
+        .unwrap();
+    app.promote_due_issues().await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let issue: serde_json::Value = app.get_issue(&issue_id).await.json().await.unwrap();
+    assert_eq!(issue["status"], "scheduled");
+    assert_eq!(issue["approval_status"], "rejected");
+    let trail: serde_json::Value = app
+        .admin_get(&format!("/admin/issues/{}/audit", issue_id))
+        .await
+        .json()
+        .await
+        .unwrap();
+    let last = trail.as_array().unwrap().last().unwrap().clone();
+    assert_eq!(last["action"], "rejected");
+    assert_eq!(last["note"], "Typo in the title");
+    assert_eq!(last["actor"], app.approver.username.as_str());
+}
+
+#[tokio::test]
+async fn changing_the_settings_of_an_approved_issue_invalidates_the_approval() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = due_issue(&app).await;
+    app.approve_issue(&issue_id, 1)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    let response = app
+        .admin_put(
+            &format!("/admin/issues/{}/tracking", issue_id),
+            &serde_json::json!({ "track_opens": false, "track_clicks": false }),
+        )
+        .await;
+    app.promote_due_issues().await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let issue: serde_json::Value = app.get_issue(&issue_id).await.json().await.unwrap();
+    assert_eq!(issue["status"], "scheduled");
+    assert_eq!(issue["approval_status"], "pending");
+    let trail: serde_json::Value = app
+        .admin_get(&format!("/admin/issues/{}/audit", issue_id))
+        .await
+        .json()
+        .await
+        .unwrap();
+    let last = trail.as_array().unwrap().last().unwrap().clone();
+    assert_eq!(last["action"], "approval_invalidated");
+    assert_eq!(last["note"], "The tracking changed after it was approved");
+}
+
+#[tokio::test]
+async fn admins_cannot_approve_settings_they_changed() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = due_issue(&app).await;
+    app.api_client
+        .put(format!("{}/admin/issues/{}/segment", app.address, issue_id))
+        .basic_auth(&app.approver.username, Some(&app.approver.password))
+        .json(&serde_json::json!({ "segment_id": null }))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    let response = app.approve_issue(&issue_id, 1).await;
+
+    // Assert
+    assert_eq!(403, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn issues_written_by_the_application_need_a_single_approval() {
+    // Arrange
+    let app = spawn_app().await;
+    let content = IssueContent {
+        title: "From the feed".into(),
+        text_content: "Plain text".into(),
+        html_content: "<p>HTML</p>".into(),
+    };
+    let mut transaction = app.db.begin().await.unwrap();
+    let issue_id = insert_issue(&mut transaction, &content, None, None, None)
+        .await
+        .unwrap();
+    transaction.commit().await.unwrap();
+
+    // Act
+    let response = app
+        .admin_post(
+            &format!("/admin/issues/{}/approve", issue_id),
+            &serde_json::json!({ "revision": 1 }),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+}
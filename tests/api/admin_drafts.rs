@@ -0,0 +1,205 @@
+use chrono::{Duration, Utc};
+
+use crate::helpers::{TestApp, spawn_app};
+
+fn content(title: &str) -> serde_json::Value {
+    serde_json::json!({
+        "title": title,
+        "text_content": format!("{} as plain text", title),
+        "html_content": format!("<p>{} as HTML</p>", title),
+    })
+}
+
+async fn create_draft(app: &TestApp, title: &str) -> String {
+    let response = app.post_issue(&content(title)).await;
+    assert_eq!(201, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "draft");
+    body["newsletter_issue_id"].as_str().unwrap().to_owned()
+}
+
+#[tokio::test]
+async fn issues_without_send_at_are_saved_as_drafts() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let issue_id = create_draft(&app, "First draft").await;
+
+    // Assert
+    let issue: serde_json::Value = app.get_issue(&issue_id).await.json().await.unwrap();
+    assert_eq!(issue["status"], "draft");
+    assert_eq!(issue["current_revision"], 1);
+    assert!(issue["send_at"].is_null());
+    let drafts: serde_json::Value = app
+        .admin_get("/admin/issues?status=draft")
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(drafts.as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn invalid_drafts_are_rejected_with_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (
+            serde_json::json!({"title": "", "text_content": "t", "html_content": "h"}),
+            "empty title",
+        ),
+        (
+            serde_json::json!({"title": "T", "text_content": " ", "html_content": "h"}),
+            "empty text content",
+        ),
+    ];
+
+    for (body, description) in test_cases {
+        // Act
+        let response = app.post_issue(&body).await;
+
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 Bad Request when the payload had an {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn editing_a_draft_appends_a_revision() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = create_draft(&app, "First draft").await;
+
+    // Act
+    let response = app
+        .admin_put(
+            &format!("/admin/issues/{}", issue_id),
+            &content("Second draft"),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let issue: serde_json::Value = app.get_issue(&issue_id).await.json().await.unwrap();
+    assert_eq!(issue["title"], "Second draft");
+    assert_eq!(issue["current_revision"], 2);
+
+    let revisions: serde_json::Value = app
+        .admin_get(&format!("/admin/issues/{}/revisions", issue_id))
+        .await
+        .json()
+        .await
+        .unwrap();
+    let revisions = revisions.as_array().unwrap();
+    assert_eq!(revisions.len(), 2);
+    assert_eq!(revisions[0]["title"], "Second draft");
+    assert_eq!(revisions[1]["title"], "First draft");
+    assert_eq!(revisions[1]["created_by"], app.test_user.username.as_str());
+}
+
+#[tokio::test]
+async fn restoring_a_revision_creates_a_new_one_with_its_content() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = create_draft(&app, "First draft").await;
+    app.admin_put(
+        &format!("/admin/issues/{}", issue_id),
+        &content("Second draft"),
+    )
+    .await;
+
+    // Act
+    let response = app
+        .admin_post(
+            &format!("/admin/issues/{}/revisions/1/restore", issue_id),
+            &serde_json::json!({}),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let issue: serde_json::Value = app.get_issue(&issue_id).await.json().await.unwrap();
+    assert_eq!(issue["title"], "First draft");
+    assert_eq!(issue["current_revision"], 3);
+    let revision: serde_json::Value = app
+        .admin_get(&format!("/admin/issues/{}/revisions/3", issue_id))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(revision["restored_from"], 1);
+    assert_eq!(revision["html_content"], "<p>First draft as HTML</p>");
+}
+
+#[tokio::test]
+async fn scheduled_issues_cannot_be_edited_or_deleted() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = create_draft(&app, "First draft").await;
+    let response = app
+        .post_issue_action(
+            &issue_id,
+            "schedule",
+            &serde_json::json!({ "send_at": Utc::now() + Duration::days(1) }),
+        )
+        .await;
+    assert_eq!(200, response.status().as_u16());
+
+    // Act
+    let edit = app
+        .admin_put(&format!("/admin/issues/{}", issue_id), &content("Too late"))
+        .await;
+    let restore = app
+        .admin_post(
+            &format!("/admin/issues/{}/revisions/1/restore", issue_id),
+            &serde_json::json!({}),
+        )
+        .await;
+    let delete = app
+        .admin_delete(&format!("/admin/issues/{}", issue_id))
+        .await;
+
+    // Assert
+    assert_eq!(409, edit.status().as_u16());
+    assert_eq!(409, restore.status().as_u16());
+    assert_eq!(409, delete.status().as_u16());
+}
+
+#[tokio::test]
+async fn drafts_can_be_deleted() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = create_draft(&app, "First draft").await;
+
+    // Act
+    let response = app
+        .admin_delete(&format!("/admin/issues/{}", issue_id))
+        .await;
+
+    // Assert
+    assert_eq!(204, response.status().as_u16());
+    assert_eq!(404, app.get_issue(&issue_id).await.status().as_u16());
+}
+
+#[tokio::test]
+async fn revisions_cannot_be_modified_in_place() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id: uuid::Uuid = create_draft(&app, "First draft").await.parse().unwrap();
+
+    // Act
+    let outcome = sqlx::query!(
+        "UPDATE newsletter_issue_revisions SET title = 'Rewritten' WHERE newsletter_issue_id = $1",
+        issue_id
+    )
+    .execute(&app.db)
+    .await;
+
+    // Assert
+    assert!(outcome.is_err());
+}
@@ -0,0 +1,243 @@
+use chrono::{Duration, Utc};
+use wiremock::{
+    Mock, ResponseTemplate,
+    matchers::{any, method, path},
+};
+
+use crate::helpers::spawn_app;
+
+fn issue_body(send_at: chrono::DateTime<Utc>) -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "send_at": send_at,
+    })
+}
+
+/// Create a scheduled issue signed off by a second admin.
+async fn scheduled_issue_id(
+    app: &crate::helpers::TestApp,
+    send_at: chrono::DateTime<Utc>,
+) -> String {
+    let response = app.post_issue(&issue_body(send_at)).await;
+    assert_eq!(201, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    let issue_id = body["newsletter_issue_id"].as_str().unwrap().to_owned();
+    let response = app.approve_issue(&issue_id, 1).await;
+    assert_eq!(200, response.status().as_u16());
+    issue_id
+}
+
+#[tokio::test]
+async fn requests_missing_authorization_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/admin/issues", &app.address))
+        .json(&issue_body(Utc::now()))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+    assert_eq!(
+        r#"Basic realm="admin""#,
+        response.headers()["WWW-Authenticate"]
+    );
+}
+
+#[tokio::test]
+async fn invalid_password_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/admin/issues", &app.address))
+        .basic_auth(&app.test_user.username, Some("not-the-password"))
+        .json(&issue_body(Utc::now()))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn issues_are_not_delivered_before_send_at() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let issue_id = scheduled_issue_id(&app, Utc::now() + Duration::hours(1)).await;
+    app.promote_due_issues().await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let issue: serde_json::Value = app.get_issue(&issue_id).await.json().await.unwrap();
+    assert_eq!(issue["status"], "scheduled");
+}
+
+#[tokio::test]
+async fn due_issues_are_delivered_to_confirmed_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_unconfirmed_subscriber().await;
+    app.create_confirmed_subscriber().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let issue_id = scheduled_issue_id(&app, Utc::now()).await;
+    app.promote_due_issues().await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let issue: serde_json::Value = app.get_issue(&issue_id).await.json().await.unwrap();
+    assert_eq!(issue["status"], "sent");
+    assert!(issue["published_at"].is_string());
+}
+
+#[tokio::test]
+async fn cancelled_issues_are_never_delivered() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let issue_id = scheduled_issue_id(&app, Utc::now() + Duration::seconds(1)).await;
+
+    // Act
+    let response = app
+        .post_issue_action(&issue_id, "cancel", &serde_json::json!({}))
+        .await;
+    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    app.promote_due_issues().await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let issue: serde_json::Value = app.get_issue(&issue_id).await.json().await.unwrap();
+    assert_eq!(issue["status"], "draft");
+}
+
+#[tokio::test]
+async fn rescheduling_moves_the_send_time() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let issue_id = scheduled_issue_id(&app, Utc::now() + Duration::days(1)).await;
+
+    // Act
+    let response = app
+        .post_issue_action(
+            &issue_id,
+            "reschedule",
+            &serde_json::json!({ "send_at": Utc::now() }),
+        )
+        .await;
+    app.promote_due_issues().await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let issue: serde_json::Value = app.get_issue(&issue_id).await.json().await.unwrap();
+    assert_eq!(issue["status"], "sent");
+}
+
+#[tokio::test]
+async fn issues_that_went_out_cannot_be_cancelled_or_rescheduled() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let issue_id = scheduled_issue_id(&app, Utc::now()).await;
+    app.promote_due_issues().await;
+
+    // Act
+    let cancel = app
+        .post_issue_action(&issue_id, "cancel", &serde_json::json!({}))
+        .await;
+    let reschedule = app
+        .post_issue_action(
+            &issue_id,
+            "reschedule",
+            &serde_json::json!({ "send_at": Utc::now() + Duration::days(1) }),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(409, cancel.status().as_u16());
+    assert_eq!(409, reschedule.status().as_u16());
+}
+
+#[tokio::test]
+async fn unknown_issues_return_a_404() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_issue_action(
+            &uuid::Uuid::new_v4().to_string(),
+            "cancel",
+            &serde_json::json!({}),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn a_locked_issue_is_skipped_by_the_scheduler() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let issue_id = scheduled_issue_id(&app, Utc::now()).await;
+    let issue_uuid: uuid::Uuid = issue_id.parse().unwrap();
+    // Simulate another replica holding the issue's advisory lock
+    let mut transaction = app.db.begin().await.unwrap();
+    email_newsletter::scheduler::lock_issue(&mut transaction, issue_uuid)
+        .await
+        .unwrap();
+
+    // Act
+    let promoted = email_newsletter::scheduler::promote_due_issues(&app.db)
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(0, promoted);
+    transaction.rollback().await.unwrap();
+    let issue: serde_json::Value = app.get_issue(&issue_id).await.json().await.unwrap();
+    assert_eq!(issue["status"], "scheduled");
+}
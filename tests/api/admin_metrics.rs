@@ -0,0 +1,35 @@
+use crate::helpers::spawn_app;
+
+#[tokio::test]
+async fn metrics_require_authentication() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::get(format!("{}/admin/metrics", app.address))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn emails_sent_by_the_application_draw_from_the_throttle() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    app.create_unconfirmed_subscriber().await;
+    app.create_unconfirmed_subscriber().await;
+
+    // Assert
+    let response = app.admin_get("/admin/metrics").await;
+    assert_eq!(200, response.status().as_u16());
+    let metrics: serde_json::Value = response.json().await.unwrap();
+    let throttle = &metrics["email_throttle"];
+    assert_eq!(throttle["rate_per_second"], 10.0);
+    assert_eq!(throttle["burst"], 10);
+    assert_eq!(throttle["acquired"], 2);
+    assert_eq!(throttle["throttled"], 0);
+}
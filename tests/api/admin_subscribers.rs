@@ -0,0 +1,242 @@
+use chrono::{DateTime, Duration, Utc};
+use uuid::Uuid;
+
+use crate::helpers::{TestApp, spawn_app};
+
+/// Store a subscriber straight away, and make them a member of the default
+/// list.
+async fn insert_subscriber(
+    app: &TestApp,
+    email: &str,
+    name: &str,
+    status: &str,
+    tags: &[&str],
+    subscribed_at: DateTime<Utc>,
+) -> Uuid {
+    let id = Uuid::new_v4();
+    let tags: Vec<String> = tags.iter().map(|tag| tag.to_string()).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status, tags)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        id,
+        email,
+        name,
+        subscribed_at,
+        status,
+        &tags
+    )
+    .execute(&app.db)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO list_subscriptions (list_id, subscriber_id, status, subscribed_at)
+        SELECT list_id, $1, $2, $3 FROM lists WHERE slug = 'newsletter'
+        "#,
+        id,
+        status,
+        subscribed_at
+    )
+    .execute(&app.db)
+    .await
+    .unwrap();
+    id
+}
+
+async fn list_subscribers(app: &TestApp, query: &str) -> serde_json::Value {
+    let response = app
+        .admin_get(&format!("/admin/subscribers?{}", query))
+        .await;
+    assert_eq!(200, response.status().as_u16());
+    response.json().await.unwrap()
+}
+
+fn emails(page: &serde_json::Value) -> Vec<&str> {
+    page["subscribers"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|subscriber| subscriber["email"].as_str().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn requests_missing_authorization_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .get(format!("{}/admin/subscribers", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn pages_follow_each_other_without_gaps_or_repeats() {
+    // Arrange
+    let app = spawn_app().await;
+    let now = Utc::now();
+    for i in 0..5 {
+        // Ties on the sort column are broken by id.
+        insert_subscriber(
+            &app,
+            &format!("reader{}@example.com", i),
+            "Reader",
+            "confirmed",
+            &[],
+            now - Duration::days(i / 2),
+        )
+        .await;
+    }
+
+    // Act
+    let mut seen = Vec::new();
+    let mut query = "limit=2".to_owned();
+    let mut pages = 0;
+    loop {
+        let page = list_subscribers(&app, &query).await;
+        seen.extend(emails(&page).into_iter().map(str::to_owned));
+        pages += 1;
+        match page["next_cursor"].as_str() {
+            Some(cursor) => query = format!("limit=2&cursor={}", cursor),
+            None => break,
+        }
+    }
+
+    // Assert
+    assert_eq!(pages, 3);
+    let mut sorted = seen.clone();
+    sorted.sort();
+    sorted.dedup();
+    assert_eq!(sorted.len(), 5);
+    assert!(seen[0] == "reader0@example.com" || seen[0] == "reader1@example.com");
+    assert_eq!(seen[4], "reader4@example.com");
+}
+
+#[tokio::test]
+async fn subscribers_can_be_sorted_by_email() {
+    // Arrange
+    let app = spawn_app().await;
+    let now = Utc::now();
+    for email in ["carol@example.com", "alice@example.com", "bob@example.com"] {
+        insert_subscriber(&app, email, "Reader", "confirmed", &[], now).await;
+    }
+
+    // Act
+    let first = list_subscribers(&app, "sort=email&order=asc&limit=2").await;
+    let cursor = first["next_cursor"].as_str().unwrap();
+    let second = list_subscribers(
+        &app,
+        &format!("sort=email&order=asc&limit=2&cursor={}", cursor),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(emails(&first), vec!["alice@example.com", "bob@example.com"]);
+    assert_eq!(emails(&second), vec!["carol@example.com"]);
+    assert!(second["next_cursor"].is_null());
+}
+
+#[tokio::test]
+async fn subscribers_can_be_filtered() {
+    // Arrange
+    let app = spawn_app().await;
+    let now = Utc::now();
+    insert_subscriber(
+        &app,
+        "ursula@example.com",
+        "Ursula Le Guin",
+        "confirmed",
+        &["beta"],
+        now - Duration::days(10),
+    )
+    .await;
+    insert_subscriber(
+        &app,
+        "octavia@example.com",
+        "Octavia Butler",
+        "pending_confirmation",
+        &[],
+        now,
+    )
+    .await;
+    let test_cases = [
+        ("status=confirmed", vec!["ursula@example.com"]),
+        ("tag=beta", vec!["ursula@example.com"]),
+        ("search=LE%20GUIN", vec!["ursula@example.com"]),
+        ("search=octavia@", vec!["octavia@example.com"]),
+        ("search=%25", vec![]),
+        (
+            "list=newsletter&status=pending_confirmation",
+            vec!["octavia@example.com"],
+        ),
+        ("list=digest", vec![]),
+    ];
+
+    for (query, expected) in test_cases {
+        // Act
+        let page = list_subscribers(&app, query).await;
+
+        // Assert
+        assert_eq!(emails(&page), expected, "Unexpected result for {}", query);
+    }
+
+    // Date ranges
+    let after = (now - Duration::days(1)).to_rfc3339();
+    let page = list_subscribers(
+        &app,
+        &serde_urlencoded::to_string([("subscribed_after", &after)]).unwrap(),
+    )
+    .await;
+    assert_eq!(emails(&page), vec!["octavia@example.com"]);
+    let page = list_subscribers(
+        &app,
+        &serde_urlencoded::to_string([("subscribed_before", &after)]).unwrap(),
+    )
+    .await;
+    assert_eq!(emails(&page), vec!["ursula@example.com"]);
+}
+
+#[tokio::test]
+async fn invalid_listing_parameters_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let now = Utc::now();
+    for email in ["alice@example.com", "bob@example.com"] {
+        insert_subscriber(&app, email, "Reader", "confirmed", &[], now).await;
+    }
+    let page = list_subscribers(&app, "limit=1").await;
+    let cursor = page["next_cursor"].as_str().unwrap();
+    let test_cases = [
+        ("limit=0".to_owned(), "an empty page"),
+        ("limit=1000".to_owned(), "a page too large"),
+        ("cursor=garbage".to_owned(), "a malformed cursor"),
+        ("sort=age".to_owned(), "an unknown sort column"),
+        (
+            format!("sort=email&cursor={}", cursor),
+            "a cursor for another order",
+        ),
+    ];
+
+    for (query, description) in test_cases {
+        // Act
+        let response = app
+            .admin_get(&format!("/admin/subscribers?{}", query))
+            .await;
+
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not reject {}.",
+            description
+        );
+    }
+}
@@ -0,0 +1,109 @@
+use secrecy::SecretString;
+
+use crate::helpers::{TestApp, spawn_app};
+
+async fn change_password(app: &TestApp, new_password: &str) -> reqwest::Response {
+    app.admin_put(
+        "/admin/password",
+        &serde_json::json!({ "new_password": new_password }),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn admins_can_change_their_password() {
+    // Arrange
+    let app = spawn_app().await;
+    let new_password = uuid::Uuid::new_v4().to_string();
+
+    // Act
+    let response = change_password(&app, &new_password).await;
+
+    // Assert
+    assert_eq!(204, response.status().as_u16());
+    let old = app.admin_get("/admin/lists").await;
+    assert_eq!(401, old.status().as_u16());
+    let new = app
+        .api_client
+        .get(format!("{}/admin/lists", &app.address))
+        .basic_auth(&app.test_user.username, Some(&new_password))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, new.status().as_u16());
+}
+
+#[tokio::test]
+async fn passwords_must_have_a_reasonable_length() {
+    // Arrange
+    let app = spawn_app().await;
+
+    for (new_password, description) in [
+        ("short".to_owned(), "too short"),
+        ("a".repeat(129), "too long"),
+    ] {
+        // Act
+        let response = change_password(&app, &new_password).await;
+
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not reject a password {}.",
+            description
+        );
+    }
+    assert_eq!(200, app.admin_get("/admin/lists").await.status().as_u16());
+}
+
+#[tokio::test]
+async fn changing_a_password_requires_authentication() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .put(format!("{}/admin/password", &app.address))
+        .json(&serde_json::json!({ "new_password": "a fine new password" }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn created_admins_can_sign_in_under_a_free_username() {
+    // Arrange
+    let app = spawn_app().await;
+    let password = "a fine new password";
+
+    // Act
+    let created = email_newsletter::authentication::create_admin(
+        "ursula",
+        SecretString::from(password),
+        &app.db,
+    )
+    .await
+    .unwrap();
+    let duplicate = email_newsletter::authentication::create_admin(
+        "ursula",
+        SecretString::from("another password"),
+        &app.db,
+    )
+    .await
+    .unwrap();
+
+    // Assert
+    assert!(created.is_some());
+    assert!(duplicate.is_none());
+    let response = app
+        .api_client
+        .get(format!("{}/admin/lists", &app.address))
+        .basic_auth("ursula", Some(password))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, response.status().as_u16());
+}
@@ -0,0 +1,328 @@
+use chrono::{Duration, Utc};
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use uuid::Uuid;
+
+use wiremock::{
+    Mock, ResponseTemplate,
+    matchers::{method, path},
+};
+
+use crate::helpers::{TestApp, spawn_app};
+
+/// Insert `n` issues published a day apart, bypassing the admin API.
+async fn insert_published_issues(app: &TestApp, n: i64) {
+    for i in 0..n {
+        sqlx::query!(
+            r#"
+            INSERT INTO newsletter_issues (
+                newsletter_issue_id, list_id, slug, title, text_content, html_content, status,
+                current_revision, approval_status, published_at, sent_at, created_at, updated_at
+            )
+            VALUES (
+                $1, (SELECT list_id FROM lists WHERE slug = 'newsletter'), $2, $3,
+                'text', '<p>html</p>', 'sent', 1, 'approved', $4, $4, now(), now()
+            )
+            "#,
+            Uuid::new_v4(),
+            format!("issue-{}", i),
+            format!("Issue {}", i),
+            Utc::now() - Duration::days(n - i),
+        )
+        .execute(&app.db)
+        .await
+        .unwrap();
+    }
+}
+
+#[tokio::test]
+async fn the_archive_lists_published_issues_only() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_published_issue("Published issue").await;
+    app.post_issue(&serde_json::json!({
+        "title": "Draft issue",
+        "text_content": "text",
+        "html_content": "<p>html</p>",
+    }))
+    .await;
+
+    // Act
+    let response = reqwest::get(format!("{}/archive", app.address))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let html = response.text().await.unwrap();
+    assert!(html.contains(r#"<a href="/archive/published-issue">Published issue</a>"#));
+    assert!(!html.contains("Draft issue"));
+}
+
+#[tokio::test]
+async fn published_issues_are_rendered_as_web_pages() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_published_issue("Published issue").await;
+
+    // Act
+    let response = reqwest::get(format!("{}/archive/published-issue", app.address))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let html = response.text().await.unwrap();
+    assert!(html.contains("<h1>Published issue</h1>"));
+    assert!(html.contains("<p>Published issue as HTML</p>"));
+}
+
+#[tokio::test]
+async fn drafts_and_unknown_slugs_are_not_found() {
+    // Arrange
+    let app = spawn_app().await;
+    app.post_issue(&serde_json::json!({
+        "title": "Draft issue",
+        "text_content": "text",
+        "html_content": "<p>html</p>",
+    }))
+    .await;
+
+    for slug in ["draft-issue", "not-an-issue"] {
+        // Act
+        let response = reqwest::get(format!("{}/archive/{}", app.address, slug))
+            .await
+            .unwrap();
+
+        // Assert
+        assert_eq!(404, response.status().as_u16(), "slug: {}", slug);
+    }
+}
+
+#[tokio::test]
+async fn the_archive_is_paginated() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_published_issues(&app, 11).await;
+
+    // Act
+    let first = reqwest::get(format!("{}/archive", app.address))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let second = reqwest::get(format!("{}/archive?page=2", app.address))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let third = reqwest::get(format!("{}/archive?page=3", app.address))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(first.matches("<li>").count(), 10);
+    assert!(first.contains("Issue 10"));
+    assert!(first.contains(r#"href="/archive?page=2""#));
+    assert_eq!(second.matches("<li>").count(), 1);
+    assert!(second.contains(">Issue 0<"));
+    assert!(second.contains(r#"href="/archive?page=1""#));
+    assert_eq!(404, third.status().as_u16());
+}
+
+#[tokio::test]
+async fn pages_beyond_any_archive_are_not_found() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::get(format!("{}/archive?page={}", app.address, i64::MAX))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn issues_appear_once_delivered_to_the_whole_list() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let issue: serde_json::Value = app
+        .post_issue(&serde_json::json!({
+            "title": "Personal issue",
+            "text_content": "text",
+            "html_content": "<p>Hi {{ name }}, {{ not_a_var }}</p>",
+            "send_at": Utc::now(),
+        }))
+        .await
+        .json()
+        .await
+        .unwrap();
+    app.approve_issue(issue["newsletter_issue_id"].as_str().unwrap(), 1)
+        .await
+        .error_for_status()
+        .unwrap();
+    app.promote_due_issues().await;
+    let url = format!("{}/archive/personal-issue", app.address);
+
+    // Act
+    let sending = reqwest::get(&url).await.unwrap();
+    app.dispatch_all_pending_emails().await;
+    let sent = reqwest::get(&url).await.unwrap();
+
+    // Assert
+    assert_eq!(404, sending.status().as_u16());
+    assert_eq!(200, sent.status().as_u16());
+    let html = sent.text().await.unwrap();
+    assert!(html.contains("<p>Hi , {{ not_a_var }}</p>"));
+}
+
+#[tokio::test]
+async fn issues_sent_to_a_segment_are_not_archived() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_published_issues(&app, 1).await;
+    let segment_id = Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO segments (segment_id, name, filter, created_at) VALUES ($1, 'beta', 'tag = \"beta\"', now())",
+        segment_id
+    )
+    .execute(&app.db)
+    .await
+    .unwrap();
+    sqlx::query!("UPDATE newsletter_issues SET segment_id = $1", segment_id)
+        .execute(&app.db)
+        .await
+        .unwrap();
+
+    // Act
+    let index = reqwest::get(format!("{}/archive", app.address))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let page = reqwest::get(format!("{}/archive/issue-0", app.address))
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(!index.contains("Issue 0"));
+    assert_eq!(404, page.status().as_u16());
+}
+
+#[tokio::test]
+async fn the_rss_feed_lists_recent_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_published_issue("Published issue").await;
+
+    // Act
+    let response = reqwest::get(format!("{}/archive/feed.rss", app.address))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert!(
+        response.headers()["Content-Type"]
+            .to_str()
+            .unwrap()
+            .starts_with("application/rss+xml")
+    );
+    let xml = response.text().await.unwrap();
+    assert!(xml.contains(r#"<rss version="2.0""#));
+    assert!(xml.contains("<title>Published issue</title>"));
+    assert!(xml.contains("<link>http://127.0.0.1/archive/published-issue</link>"));
+    assert!(xml.contains("&lt;p&gt;Published issue as HTML&lt;/p&gt;"));
+}
+
+#[tokio::test]
+async fn the_atom_feed_lists_recent_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_published_issue("Published issue").await;
+
+    // Act
+    let response = reqwest::get(format!("{}/archive/feed.atom", app.address))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert!(
+        response.headers()["Content-Type"]
+            .to_str()
+            .unwrap()
+            .starts_with("application/atom+xml")
+    );
+    let xml = response.text().await.unwrap();
+    assert!(xml.contains(r#"<feed xmlns="http://www.w3.org/2005/Atom">"#));
+    assert!(xml.contains("<title>Published issue</title>"));
+    assert!(xml.contains(r#"<link href="http://127.0.0.1/archive/published-issue"/>"#));
+}
+
+#[tokio::test]
+async fn feeds_answer_conditional_requests_with_a_304() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_published_issues(&app, 2).await;
+    let client = reqwest::Client::new();
+
+    for feed in ["feed.rss", "feed.atom"] {
+        let url = format!("{}/archive/{}", app.address, feed);
+        let response = client.get(&url).send().await.unwrap();
+        let etag = response.headers()[ETAG].clone();
+        let last_modified = response.headers()[LAST_MODIFIED].clone();
+
+        // Act
+        let by_etag = client
+            .get(&url)
+            .header(IF_NONE_MATCH, etag)
+            .send()
+            .await
+            .unwrap();
+        let by_date = client
+            .get(&url)
+            .header(IF_MODIFIED_SINCE, last_modified)
+            .send()
+            .await
+            .unwrap();
+
+        // Assert
+        assert_eq!(304, by_etag.status().as_u16(), "feed: {}", feed);
+        assert!(by_etag.text().await.unwrap().is_empty());
+        assert_eq!(304, by_date.status().as_u16(), "feed: {}", feed);
+    }
+}
+
+#[tokio::test]
+async fn publishing_an_issue_changes_the_feed_etag() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_published_issues(&app, 1).await;
+    let client = reqwest::Client::new();
+    let url = format!("{}/archive/feed.rss", app.address);
+    let etag = client.get(&url).send().await.unwrap().headers()[ETAG].clone();
+
+    // Act
+    app.create_published_issue("Brand new issue").await;
+    let response = client
+        .get(&url)
+        .header(IF_NONE_MATCH, etag)
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert!(response.text().await.unwrap().contains("Brand new issue"));
+}
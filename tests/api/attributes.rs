@@ -0,0 +1,331 @@
+use crate::helpers::{TestApp, spawn_app};
+
+async fn default_list_id(app: &TestApp) -> String {
+    let lists: Vec<serde_json::Value> = app.admin_get("/admin/lists").await.json().await.unwrap();
+    lists[0]["list_id"].as_str().unwrap().to_owned()
+}
+
+async fn declare_attributes(app: &TestApp, schema: serde_json::Value) -> reqwest::Response {
+    let list_id = default_list_id(app).await;
+    app.admin_put(&format!("/admin/lists/{}/attributes", list_id), &schema)
+        .await
+}
+
+async fn subscriber_id(app: &TestApp) -> String {
+    sqlx::query_scalar!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db)
+        .await
+        .unwrap()
+        .to_string()
+}
+
+#[tokio::test]
+async fn lists_declare_the_attributes_they_collect() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = declare_attributes(
+        &app,
+        serde_json::json!({"company": "text", "seats": "number", "vip": "boolean"}),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let lists: Vec<serde_json::Value> = app.admin_get("/admin/lists").await.json().await.unwrap();
+    assert_eq!(
+        lists[0]["attribute_schema"],
+        serde_json::json!({"company": "text", "seats": "number", "vip": "boolean"})
+    );
+}
+
+#[tokio::test]
+async fn invalid_attribute_schemas_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let test_cases = [
+        (serde_json::json!({"Company": "text"}), "an uppercase name"),
+        (serde_json::json!({"company": "date"}), "an unknown type"),
+        (serde_json::json!({"": "text"}), "an empty name"),
+    ];
+
+    for (schema, description) in test_cases {
+        // Act
+        let response = declare_attributes(&app, schema).await;
+
+        // Assert
+        assert!(
+            response.status().is_client_error(),
+            "The API did not reject {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn subscribing_keeps_the_declared_fields_only() {
+    // Arrange
+    let app = spawn_app().await;
+    declare_attributes(
+        &app,
+        serde_json::json!({"company": "text", "seats": "number"}),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+    app.mock_email_api().await;
+    let body = serde_urlencoded::to_string([
+        ("name", "le guin"),
+        ("email", "ursula_le_guin@gmail.com"),
+        ("company", "Acme"),
+        ("seats", "12"),
+        ("is_admin", "true"),
+    ])
+    .unwrap();
+
+    // Act
+    let response = app.post_subscriptions(body).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let attributes = sqlx::query_scalar!("SELECT attributes FROM subscriptions")
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+    assert_eq!(
+        attributes,
+        serde_json::json!({"company": "Acme", "seats": 12})
+    );
+}
+
+#[tokio::test]
+async fn subscribing_with_a_mistyped_field_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    declare_attributes(&app, serde_json::json!({"seats": "number"}))
+        .await
+        .error_for_status()
+        .unwrap();
+    let body = serde_urlencoded::to_string([
+        ("name", "le guin"),
+        ("email", "ursula_le_guin@gmail.com"),
+        ("seats", "a dozen"),
+    ])
+    .unwrap();
+
+    // Act
+    let response = app.post_subscriptions(body).await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn admins_can_tag_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let subscriber_id = subscriber_id(&app).await;
+
+    // Act
+    let response = app
+        .admin_put(
+            &format!("/admin/subscribers/{}/tags", subscriber_id),
+            &serde_json::json!({"tags": ["Beta", "early-bird", "beta"]}),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let subscriber: serde_json::Value = app
+        .admin_get(&format!("/admin/subscribers/{}", subscriber_id))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(
+        subscriber["tags"],
+        serde_json::json!(["beta", "early-bird"])
+    );
+    let audit: Vec<serde_json::Value> = app
+        .admin_get(&format!("/admin/subscribers/{}/audit", subscriber_id))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(audit.last().unwrap()["action"], "set_tags");
+    assert_eq!(audit.last().unwrap()["note"], "beta, early-bird");
+}
+
+#[tokio::test]
+async fn invalid_tags_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let subscriber_id = subscriber_id(&app).await;
+
+    // Act
+    let invalid = app
+        .admin_put(
+            &format!("/admin/subscribers/{}/tags", subscriber_id),
+            &serde_json::json!({"tags": ["two words"]}),
+        )
+        .await;
+    let unknown = app
+        .admin_put(
+            &format!("/admin/subscribers/{}/tags", uuid::Uuid::new_v4()),
+            &serde_json::json!({"tags": ["beta"]}),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(400, invalid.status().as_u16());
+    assert_eq!(404, unknown.status().as_u16());
+}
+
+#[tokio::test]
+async fn admins_can_set_declared_attributes_only() {
+    // Arrange
+    let app = spawn_app().await;
+    declare_attributes(&app, serde_json::json!({"company": "text"}))
+        .await
+        .error_for_status()
+        .unwrap();
+    app.create_confirmed_subscriber().await;
+    let path = format!(
+        "/admin/subscribers/{}/attributes",
+        subscriber_id(&app).await
+    );
+
+    // Act
+    let declared = app
+        .admin_put(
+            &path,
+            &serde_json::json!({"attributes": {"company": "Acme"}}),
+        )
+        .await;
+    let mistyped = app
+        .admin_put(&path, &serde_json::json!({"attributes": {"company": 3}}))
+        .await;
+    let undeclared = app
+        .admin_put(&path, &serde_json::json!({"attributes": {"plan": "pro"}}))
+        .await;
+
+    // Assert
+    assert_eq!(200, declared.status().as_u16());
+    assert_eq!(400, mistyped.status().as_u16());
+    assert_eq!(400, undeclared.status().as_u16());
+    let attributes = sqlx::query_scalar!("SELECT attributes FROM subscriptions")
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+    assert_eq!(attributes, serde_json::json!({"company": "Acme"}));
+    let audit: Vec<serde_json::Value> = app
+        .admin_get(&format!(
+            "/admin/subscribers/{}/audit",
+            subscriber_id(&app).await
+        ))
+        .await
+        .json()
+        .await
+        .unwrap();
+    let changes: Vec<_> = audit
+        .iter()
+        .filter(|entry| entry["action"] == "set_attributes")
+        .collect();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0]["note"], r#"{"company":"Acme"}"#);
+}
+
+#[tokio::test]
+async fn issues_are_personalised_with_the_subscriber_details() {
+    // Arrange
+    let app = spawn_app().await;
+    declare_attributes(
+        &app,
+        serde_json::json!({"company": "text", "missing": "text"}),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+    app.create_confirmed_subscriber().await;
+    sqlx::query!(
+        r#"UPDATE subscriptions SET name = 'Ursula', attributes = '{"company": "<Acme>"}'"#
+    )
+    .execute(&app.db)
+    .await
+    .unwrap();
+    app.mock_email_api().await;
+    let response = app
+        .post_issue(&serde_json::json!({
+            "title": "Personal",
+            "text_content": "Hi {{ name }} from {{ attributes.company }}{{ attributes.missing }}!",
+            "html_content": "<p>Hi {{ name }} from {{ attributes.company }}!</p>",
+            "send_at": chrono::Utc::now(),
+        }))
+        .await;
+    let issue: serde_json::Value = response.json().await.unwrap();
+    let issue_id = issue["newsletter_issue_id"].as_str().unwrap();
+
+    // Act
+    app.approve_issue(issue_id, 1)
+        .await
+        .error_for_status()
+        .unwrap();
+    app.promote_due_issues().await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let requests = app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value = requests
+        .iter()
+        .map(|r| serde_json::from_slice::<serde_json::Value>(&r.body).unwrap())
+        .find(|body| body["Subject"] == "Personal")
+        .unwrap();
+    assert_eq!(body["TextBody"], "Hi Ursula from <Acme>!");
+    assert_eq!(body["HtmlBody"], "<p>Hi Ursula from &lt;Acme&gt;!</p>");
+}
+
+#[tokio::test]
+async fn text_that_names_no_variable_is_kept_as_written() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    sqlx::query!(r#"UPDATE subscriptions SET name = 'Ursula', attributes = '{"plan": "pro"}'"#)
+        .execute(&app.db)
+        .await
+        .unwrap();
+    app.mock_email_api().await;
+    let response = app
+        .post_issue(&serde_json::json!({
+            "title": "Templates",
+            "text_content": "Hi {{ name }}, write {{ not_a_var }} on {{ attributes.plan }}",
+            "html_content": "<pre>{{ not_a_var }}</pre>",
+            "send_at": chrono::Utc::now(),
+        }))
+        .await;
+    let issue: serde_json::Value = response.json().await.unwrap();
+    let issue_id = issue["newsletter_issue_id"].as_str().unwrap();
+
+    // Act
+    app.approve_issue(issue_id, 1)
+        .await
+        .error_for_status()
+        .unwrap();
+    app.promote_due_issues().await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let requests = app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value = requests
+        .iter()
+        .map(|r| serde_json::from_slice::<serde_json::Value>(&r.body).unwrap())
+        .find(|body| body["Subject"] == "Templates")
+        .unwrap();
+    assert_eq!(
+        body["TextBody"],
+        "Hi Ursula, write {{ not_a_var }} on {{ attributes.plan }}"
+    );
+    assert_eq!(body["HtmlBody"], "<pre>{{ not_a_var }}</pre>");
+}
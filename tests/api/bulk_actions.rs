@@ -0,0 +1,425 @@
+use std::time::Duration;
+
+use serde_json::json;
+use uuid::Uuid;
+use wiremock::{
+    Mock, ResponseTemplate,
+    matchers::{method, path},
+};
+
+use crate::helpers::{TestApp, spawn_app};
+
+/// Start a bulk action and wait for it to be over, returning its report.
+async fn bulk_action(app: &TestApp, body: serde_json::Value) -> serde_json::Value {
+    let response = app.admin_post("/admin/subscribers/bulk", &body).await;
+    assert_eq!(202, response.status().as_u16());
+    let started: serde_json::Value = response.json().await.unwrap();
+    let bulk_action_id = started["bulk_action_id"].as_str().unwrap();
+    for _ in 0..100 {
+        let report: serde_json::Value = app
+            .admin_get(&format!("/admin/subscribers/bulk/{}", bulk_action_id))
+            .await
+            .json()
+            .await
+            .unwrap();
+        if report["status"] != "running" {
+            return report;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("The bulk action did not finish in time.");
+}
+
+async fn subscriber_ids(app: &TestApp) -> Vec<Uuid> {
+    sqlx::query_scalar!("SELECT id FROM subscriptions ORDER BY email")
+        .fetch_all(&app.db)
+        .await
+        .unwrap()
+}
+
+async fn audit_actions(app: &TestApp, subscriber_id: Uuid) -> Vec<String> {
+    let entries: Vec<serde_json::Value> = app
+        .admin_get(&format!("/admin/subscribers/{}/audit", subscriber_id))
+        .await
+        .json()
+        .await
+        .unwrap();
+    entries
+        .iter()
+        .map(|entry| entry["action"].as_str().unwrap().to_owned())
+        .collect()
+}
+
+#[tokio::test]
+async fn bulk_actions_require_authentication() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/admin/subscribers/bulk", &app.address))
+        .json(&json!({"action": "delete", "subscriber_ids": []}))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn explicit_subscribers_can_be_tagged_and_untagged() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.create_confirmed_subscriber().await;
+    let ids = subscriber_ids(&app).await;
+
+    // Act
+    let tagged = bulk_action(
+        &app,
+        json!({"action": "tag", "tag": " VIP ", "subscriber_ids": [ids[0], Uuid::new_v4()]}),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(tagged["status"], "completed");
+    assert_eq!(tagged["total_subscribers"], 1);
+    assert_eq!(tagged["processed_subscribers"], 1);
+    assert_eq!(tagged["argument"], "vip");
+    let tags = sqlx::query_scalar!("SELECT tags FROM subscriptions ORDER BY email")
+        .fetch_all(&app.db)
+        .await
+        .unwrap();
+    assert_eq!(tags, vec![vec!["vip".to_owned()], vec![]]);
+
+    // Act
+    bulk_action(
+        &app,
+        json!({"action": "untag", "tag": "vip", "subscriber_ids": ids}),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(audit_actions(&app, ids[0]).await, vec!["tag", "untag"]);
+    assert!(audit_actions(&app, ids[1]).await.is_empty());
+}
+
+#[tokio::test]
+async fn subscribers_matching_a_filter_can_be_unsubscribed() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.create_unconfirmed_subscriber().await;
+
+    // Act
+    let report = bulk_action(
+        &app,
+        json!({"action": "unsubscribe", "filter": r#"status = "confirmed""#}),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(report["total_subscribers"], 1);
+    let statuses = sqlx::query!(
+        r#"
+        SELECT s.status, ls.status AS list_status
+        FROM subscriptions s
+        JOIN list_subscriptions ls ON ls.subscriber_id = s.id
+        ORDER BY s.status
+        "#
+    )
+    .fetch_all(&app.db)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| (r.status, r.list_status))
+    .collect::<Vec<_>>();
+    assert_eq!(
+        statuses,
+        vec![
+            ("confirmed".to_owned(), "unsubscribed".to_owned()),
+            (
+                "pending_confirmation".to_owned(),
+                "pending_confirmation".to_owned()
+            ),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn members_of_a_segment_can_be_deleted_leaving_their_audit_trail() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_unconfirmed_subscriber().await;
+    app.create_confirmed_subscriber().await;
+    let segment: serde_json::Value = app
+        .admin_post(
+            "/admin/segments",
+            &json!({"name": "Pending", "filter": r#"status = "pending_confirmation""#}),
+        )
+        .await
+        .json()
+        .await
+        .unwrap();
+    let pending_id =
+        sqlx::query_scalar!("SELECT id FROM subscriptions WHERE status = 'pending_confirmation'")
+            .fetch_one(&app.db)
+            .await
+            .unwrap();
+
+    // Act
+    let report = bulk_action(
+        &app,
+        json!({"action": "delete", "segment_id": segment["segment_id"]}),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(report["status"], "completed");
+    let statuses = sqlx::query_scalar!("SELECT status FROM subscriptions")
+        .fetch_all(&app.db)
+        .await
+        .unwrap();
+    assert_eq!(statuses, vec!["confirmed"]);
+    assert_eq!(audit_actions(&app, pending_id).await, vec!["delete"]);
+}
+
+#[tokio::test]
+async fn subscribers_moved_to_another_list_confirm_it() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.admin_post(
+        "/admin/lists",
+        &json!({"slug": "digest", "name": "The Digest"}),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+    let ids = subscriber_ids(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let memberships = || async {
+        sqlx::query!(
+            r#"
+            SELECT l.slug, ls.status
+            FROM list_subscriptions ls
+            JOIN lists l ON l.list_id = ls.list_id
+            ORDER BY l.slug
+            "#
+        )
+        .fetch_all(&app.db)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| (r.slug, r.status))
+        .collect::<Vec<_>>()
+    };
+
+    // Act
+    bulk_action(
+        &app,
+        json!({"action": "move_to_list", "list": "digest", "subscriber_ids": ids}),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(
+        memberships().await,
+        vec![
+            ("digest".to_owned(), "pending_confirmation".to_owned()),
+            ("newsletter".to_owned(), "unsubscribed".to_owned()),
+        ]
+    );
+    let requests = app.email_server.received_requests().await.unwrap();
+    let confirmation_links = app.get_confirmation_links(requests.last().unwrap());
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    assert_eq!(
+        memberships().await,
+        vec![
+            ("digest".to_owned(), "confirmed".to_owned()),
+            ("newsletter".to_owned(), "unsubscribed".to_owned()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn subscribers_with_the_most_tags_allowed_are_not_tagged() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let ids = subscriber_ids(&app).await;
+    sqlx::query!(
+        "UPDATE subscriptions SET tags = ARRAY(SELECT 'tag-' || n FROM generate_series(1, 50) AS n)"
+    )
+    .execute(&app.db)
+    .await
+    .unwrap();
+
+    // Act
+    let report = bulk_action(
+        &app,
+        json!({"action": "tag", "tag": "vip", "subscriber_ids": ids}),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(report["status"], "completed");
+    let tags = sqlx::query_scalar!("SELECT tags FROM subscriptions")
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+    assert_eq!(tags.len(), 50);
+    assert!(!tags.contains(&"vip".to_owned()));
+    assert!(audit_actions(&app, ids[0]).await.is_empty());
+}
+
+#[tokio::test]
+async fn confirmation_emails_are_resent_to_pending_subscribers_only() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.create_unconfirmed_subscriber().await;
+    let ids = subscriber_ids(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    bulk_action(
+        &app,
+        json!({"action": "resend_confirmation", "subscriber_ids": ids}),
+    )
+    .await;
+
+    // Assert
+    let requests = app.email_server.received_requests().await.unwrap();
+    let confirmation_links = app.get_confirmation_links(requests.last().unwrap());
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let statuses = sqlx::query_scalar!("SELECT status FROM subscriptions")
+        .fetch_all(&app.db)
+        .await
+        .unwrap();
+    assert_eq!(statuses, vec!["confirmed", "confirmed"]);
+}
+
+#[tokio::test]
+async fn invalid_bulk_actions_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let test_cases = [
+        (json!({"action": "delete"}), "no subscribers"),
+        (
+            json!({"action": "delete", "subscriber_ids": [], "filter": "tag = \"vip\""}),
+            "two ways to select subscribers",
+        ),
+        (
+            json!({"action": "delete", "filter": "nope"}),
+            "an invalid filter",
+        ),
+        (
+            json!({"action": "delete", "segment_id": Uuid::new_v4()}),
+            "an unknown segment",
+        ),
+        (
+            json!({"action": "tag", "tag": "not a tag", "subscriber_ids": []}),
+            "an invalid tag",
+        ),
+        (
+            json!({"action": "move_to_list", "list": "nope", "subscriber_ids": []}),
+            "an unknown list",
+        ),
+    ];
+
+    for (body, description) in test_cases {
+        // Act
+        let response = app.admin_post("/admin/subscribers/bulk", &body).await;
+
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not reject {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn unknown_bulk_actions_are_not_found() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .admin_get(&format!("/admin/subscribers/bulk/{}", Uuid::new_v4()))
+        .await;
+
+    // Assert
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn only_bulk_actions_whose_heartbeat_stopped_are_failed() {
+    // Arrange
+    let app = spawn_app().await;
+    let stale = Uuid::new_v4();
+    let alive = Uuid::new_v4();
+    for (bulk_action_id, silence) in [(stale, "1 hour"), (alive, "1 second")] {
+        sqlx::query!(
+            r#"
+            INSERT INTO bulk_actions (
+                bulk_action_id, actor, action, argument, status, total_subscribers,
+                created_at, heartbeat_at
+            )
+            VALUES ($1, $2, 'unsubscribe', NULL, 'running', 0, now(), now() - $3::TEXT::INTERVAL)
+            "#,
+            bulk_action_id,
+            app.test_user.user_id,
+            silence
+        )
+        .execute(&app.db)
+        .await
+        .unwrap();
+    }
+
+    // Act
+    email_newsletter::bulk_actions::fail_stale_bulk_actions(&app.db)
+        .await
+        .unwrap();
+
+    // Assert
+    let report: serde_json::Value = app
+        .admin_get(&format!("/admin/subscribers/bulk/{}", stale))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(report["status"], "failed");
+    assert!(report["error"].is_string());
+    assert!(report["finished_at"].is_string());
+    // Another instance may be running it.
+    let report: serde_json::Value = app
+        .admin_get(&format!("/admin/subscribers/bulk/{}", alive))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(report["status"], "running");
+}
@@ -0,0 +1,123 @@
+use uuid::Uuid;
+use wiremock::{
+    Mock, ResponseTemplate,
+    matchers::{method, path},
+};
+
+use crate::helpers::{TestApp, spawn_app};
+
+async fn subscribe(app: &TestApp, body: &str) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("User-Agent", "Mozilla/5.0 (Test)")
+        .header("X-Forwarded-For", "192.0.2.66, 203.0.113.7")
+        .body(body.to_owned())
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn consents(app: &TestApp, subscriber_id: Uuid) -> Vec<serde_json::Value> {
+    app.admin_get(&format!("/admin/subscribers/{}/consents", subscriber_id))
+        .await
+        .json()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn subscribing_records_how_the_subscriber_consented() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    subscribe(
+        &app,
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&source=footer",
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+
+    // Assert
+    let consents = consents(&app, app.the_subscriber().await.0).await;
+    assert_eq!(consents.len(), 1);
+    assert_eq!(consents[0]["action"], "subscribe");
+    assert_eq!(consents[0]["lists"], serde_json::json!(["newsletter"]));
+    assert_eq!(consents[0]["ip_address"], "203.0.113.7");
+    assert_eq!(consents[0]["user_agent"], "Mozilla/5.0 (Test)");
+    assert_eq!(consents[0]["source"], "footer");
+    assert_eq!(consents[0]["policy_version"], "2026-10-01");
+}
+
+#[tokio::test]
+async fn confirming_records_a_second_consent() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmation_links = app.create_unconfirmed_subscriber().await;
+
+    // Act
+    for _ in 0..2 {
+        reqwest::get(confirmation_links.html.clone())
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+    }
+
+    // Assert
+    let subscriber_id = app.the_subscriber().await.0;
+    let consents = consents(&app, subscriber_id).await;
+    let actions: Vec<&str> = consents
+        .iter()
+        .map(|consent| consent["action"].as_str().unwrap())
+        .collect();
+    assert_eq!(actions, vec!["subscribe", "confirm"]);
+    assert_eq!(consents[1]["lists"], serde_json::json!(["newsletter"]));
+    assert_eq!(consents[1]["ip_address"], "127.0.0.1");
+    assert_eq!(consents[1]["source"], serde_json::Value::Null);
+
+    let data: serde_json::Value = app
+        .admin_get(&format!("/admin/subscribers/{}/data", subscriber_id))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(data["consents"].as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn sources_longer_than_100_characters_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = format!(
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&source={}",
+        "a".repeat(101)
+    );
+
+    // Act
+    let response = subscribe(&app, &body).await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn consents_of_unknown_subscribers_are_not_found() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .admin_get(&format!("/admin/subscribers/{}/consents", Uuid::new_v4()))
+        .await;
+
+    // Assert
+    assert_eq!(404, response.status().as_u16());
+}
@@ -0,0 +1,118 @@
+use uuid::Uuid;
+use wiremock::{
+    Mock, ResponseTemplate,
+    matchers::{method, path},
+};
+
+use crate::helpers::{TestApp, spawn_app};
+
+async fn delivery_stats(app: &TestApp, issue_id: &str) -> serde_json::Value {
+    app.admin_get(&format!("/admin/issues/{}/deliveries", issue_id))
+        .await
+        .json()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn queued_deliveries_are_logged_when_an_issue_is_published() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.create_confirmed_subscriber().await;
+    app.create_unconfirmed_subscriber().await;
+
+    // Act
+    let issue_id = app.create_published_issue("Issue").await;
+
+    // Assert
+    let stats = delivery_stats(&app, &issue_id).await;
+    assert_eq!(stats["total"], 2);
+    assert_eq!(stats["queued"], 2);
+    assert_eq!(stats["sent"], 0);
+}
+
+#[tokio::test]
+async fn delivery_stats_count_sent_and_failed_deliveries() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.create_confirmed_subscriber().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .up_to_n_times(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(422))
+        .mount(&app.email_server)
+        .await;
+    let issue_id = app.create_published_issue("Issue").await;
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let stats = delivery_stats(&app, &issue_id).await;
+    assert_eq!(stats["total"], 2);
+    assert_eq!(stats["queued"], 0);
+    assert_eq!(stats["sent"], 1);
+    assert_eq!(stats["failed"], 1);
+    assert_eq!(stats["bounced"], 0);
+}
+
+#[tokio::test]
+async fn the_delivery_history_of_a_subscriber_lists_the_issues_they_received() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let subscriber_id = sqlx::query_scalar!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(serde_json::json!({ "MessageID": "message-1" })),
+        )
+        .mount(&app.email_server)
+        .await;
+    let issue_id = app.create_published_issue("Issue").await;
+    app.dispatch_all_pending_emails().await;
+
+    // Act
+    let response = app
+        .admin_get(&format!("/admin/subscribers/{}/deliveries", subscriber_id))
+        .await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let history: Vec<serde_json::Value> = response.json().await.unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0]["newsletter_issue_id"], issue_id.as_str());
+    assert_eq!(history[0]["title"], "Issue");
+    assert_eq!(history[0]["status"], "sent");
+    assert_eq!(history[0]["provider_message_id"], "message-1");
+    assert!(history[0]["sent_at"].is_string());
+}
+
+#[tokio::test]
+async fn unknown_issues_and_subscribers_are_not_found() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let issue = app
+        .admin_get(&format!("/admin/issues/{}/deliveries", Uuid::new_v4()))
+        .await;
+    let subscriber = app
+        .admin_get(&format!("/admin/subscribers/{}/deliveries", Uuid::new_v4()))
+        .await;
+
+    // Assert
+    assert_eq!(404, issue.status().as_u16());
+    assert_eq!(404, subscriber.status().as_u16());
+}
@@ -0,0 +1,246 @@
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+use wiremock::{Mock, ResponseTemplate, matchers::any};
+
+use crate::helpers::{TestApp, spawn_app};
+
+async fn request_change(app: &TestApp, link: &str, email: &str) -> reqwest::Response {
+    let mut form: Vec<(String, String)> = reqwest::Url::parse(link)
+        .unwrap()
+        .query_pairs()
+        .map(|(k, v)| (k.into_owned(), v.into_owned()))
+        .collect();
+    form.push(("email".into(), email.into()));
+    app.api_client
+        .post(format!("{}/preferences/email", app.address))
+        .form(&form)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+/// The bodies of the emails sent since the subscriber was created.
+async fn sent_emails(app: &TestApp) -> Vec<serde_json::Value> {
+    app.email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .skip(1)
+        .map(|r| serde_json::from_slice(&r.body).unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn the_new_address_is_emailed_a_confirmation_link() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let (subscriber_id, email) = app.the_subscriber().await;
+    let link = app.preference_links.link(subscriber_id, Utc::now());
+    app.mock_email_api().await;
+
+    // Act
+    let response = request_change(&app, &link, "ursula@example.com").await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let emails = sent_emails(&app).await;
+    assert_eq!(emails.len(), 1);
+    assert_eq!(emails[0]["To"], "ursula@example.com");
+    assert_eq!(emails[0]["Subject"], "Confirm your new address");
+    // Nothing changes until the new address is confirmed.
+    assert_eq!(app.the_subscriber().await.1, email);
+}
+
+#[tokio::test]
+async fn confirming_moves_the_subscription_and_notifies_the_old_address() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let (subscriber_id, old_email) = app.the_subscriber().await;
+    let link = app.preference_links.link(subscriber_id, Utc::now());
+    app.mock_email_api().await;
+    request_change(&app, &link, "ursula@example.com")
+        .await
+        .error_for_status()
+        .unwrap();
+    let requests = app.email_server.received_requests().await.unwrap();
+    let confirmation_link = app.get_confirmation_links(requests.last().unwrap());
+
+    // Act
+    let response = reqwest::get(confirmation_link.html).await.unwrap();
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(
+        app.the_subscriber().await,
+        (subscriber_id, "ursula@example.com".to_owned())
+    );
+    let emails = sent_emails(&app).await;
+    let notification = emails.last().unwrap();
+    assert_eq!(notification["To"], old_email);
+    assert_eq!(notification["Subject"], "Your address has been changed");
+    assert!(
+        notification["TextBody"]
+            .as_str()
+            .unwrap()
+            .contains("ursula@example.com")
+    );
+}
+
+#[tokio::test]
+async fn issues_queued_for_the_old_address_follow_the_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let (subscriber_id, _) = app.the_subscriber().await;
+    let link = app.preference_links.link(subscriber_id, Utc::now());
+    app.mock_email_api().await;
+    // A single subscriber is in the sample of the test.
+    let issue_id = app
+        .create_ab_tested_issue(&["Subject A", "Subject B"])
+        .await;
+    app.promote_due_issues().await;
+    request_change(&app, &link, "ursula@example.com")
+        .await
+        .error_for_status()
+        .unwrap();
+    let requests = app.email_server.received_requests().await.unwrap();
+    let confirmation_link = app.get_confirmation_links(requests.last().unwrap());
+    reqwest::get(confirmation_link.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+    let sent = sent_emails(&app).await.len();
+    app.decide_ab_tests_now().await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let delivery =
+        sqlx::query!("SELECT subscriber_email, status, open_token FROM issue_deliveries")
+            .fetch_one(&app.db)
+            .await
+            .unwrap();
+    assert_eq!(delivery.subscriber_email, "ursula@example.com");
+    assert_eq!(delivery.status, "sent");
+    assert!(delivery.open_token.is_some());
+    let stats: serde_json::Value = app
+        .admin_get(&format!("/admin/issues/{}/deliveries", issue_id))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(stats["sent"], 1);
+    assert_eq!(stats["queued"], 0);
+    // The subscriber got the sample: the winner is not sent to them again.
+    assert_eq!(sent_emails(&app).await.len(), sent);
+}
+
+#[tokio::test]
+async fn confirmation_links_work_once() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let (subscriber_id, _) = app.the_subscriber().await;
+    let link = app.preference_links.link(subscriber_id, Utc::now());
+    app.mock_email_api().await;
+    request_change(&app, &link, "ursula@example.com")
+        .await
+        .error_for_status()
+        .unwrap();
+    let requests = app.email_server.received_requests().await.unwrap();
+    let confirmation_link = app.get_confirmation_links(requests.last().unwrap());
+    reqwest::get(confirmation_link.html.clone())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    let response = reqwest::get(confirmation_link.html).await.unwrap();
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn expired_confirmation_links_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let (subscriber_id, email) = app.the_subscriber().await;
+    let link = app.preference_links.link(subscriber_id, Utc::now());
+    app.mock_email_api().await;
+    request_change(&app, &link, "ursula@example.com")
+        .await
+        .error_for_status()
+        .unwrap();
+    let requests = app.email_server.received_requests().await.unwrap();
+    let confirmation_link = app.get_confirmation_links(requests.last().unwrap());
+    sqlx::query!(
+        "UPDATE email_change_tokens SET requested_at = $1",
+        Utc::now() - Duration::days(2)
+    )
+    .execute(&app.db)
+    .await
+    .unwrap();
+
+    // Act
+    let response = reqwest::get(confirmation_link.html).await.unwrap();
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+    assert_eq!(app.the_subscriber().await.1, email);
+}
+
+#[tokio::test]
+async fn changes_need_a_valid_preference_link() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let (subscriber_id, _) = app.the_subscriber().await;
+    let link = app.preference_links.link(subscriber_id, Utc::now());
+    let tampered = link.replace(&subscriber_id.to_string(), &Uuid::new_v4().to_string());
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let tampered = request_change(&app, &tampered, "ursula@example.com").await;
+    let invalid = request_change(&app, &link, "not an email").await;
+
+    // Assert
+    assert_eq!(401, tampered.status().as_u16());
+    assert_eq!(400, invalid.status().as_u16());
+}
+
+#[tokio::test]
+async fn addresses_of_other_subscribers_get_the_same_answer_and_no_email() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.create_confirmed_subscriber().await;
+    let subscribers = sqlx::query!("SELECT id, email FROM subscriptions ORDER BY email")
+        .fetch_all(&app.db)
+        .await
+        .unwrap();
+    let link = app.preference_links.link(subscribers[0].id, Utc::now());
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = request_change(&app, &link, &subscribers[1].email).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+}
@@ -0,0 +1,302 @@
+use chrono::Utc;
+use uuid::Uuid;
+
+use crate::helpers::{TestApp, spawn_app};
+
+async fn count(app: &TestApp, table: &str) -> i64 {
+    sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", table))
+        .fetch_one(&app.db)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn erasures_require_authentication() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .delete(format!(
+            "{}/admin/subscribers/{}",
+            &app.address,
+            Uuid::new_v4()
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn unconfirmed_subscribers_can_be_erased() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_unconfirmed_subscriber().await;
+    let (subscriber_id, _) = app.the_subscriber().await;
+
+    // Act
+    let response = app
+        .admin_delete(&format!("/admin/subscribers/{}", subscriber_id))
+        .await;
+
+    // Assert
+    assert_eq!(204, response.status().as_u16());
+    assert_eq!(count(&app, "subscriptions").await, 0);
+    assert_eq!(count(&app, "subscription_tokens").await, 0);
+    assert_eq!(count(&app, "erased_subscribers").await, 1);
+    let audit: Vec<serde_json::Value> = app
+        .admin_get(&format!("/admin/subscribers/{}/audit", subscriber_id))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(audit[0]["action"], "erase");
+}
+
+#[tokio::test]
+async fn erasures_keep_anonymous_delivery_statistics() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let (subscriber_id, email) = app.the_subscriber().await;
+    app.mock_email_api().await;
+    let issue_id = app.create_published_issue("Issue one").await;
+    app.dispatch_all_pending_emails().await;
+
+    // Act
+    app.admin_delete(&format!("/admin/subscribers/{}", subscriber_id))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let delivery = sqlx::query!(
+        "SELECT subscriber_email, subscriber_id, status, provider_message_id FROM issue_deliveries"
+    )
+    .fetch_one(&app.db)
+    .await
+    .unwrap();
+    assert_ne!(delivery.subscriber_email, email);
+    assert!(delivery.subscriber_email.ends_with("@invalid"));
+    assert_eq!(delivery.subscriber_id, None);
+    assert_eq!(delivery.status, "sent");
+    assert_eq!(delivery.provider_message_id, None);
+    let stats: serde_json::Value = app
+        .admin_get(&format!("/admin/issues/{}/deliveries", issue_id))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(stats["sent"], 1);
+}
+
+#[tokio::test]
+async fn erasures_keep_anonymous_consent_records() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let (subscriber_id, _) = app.the_subscriber().await;
+
+    // Act
+    app.admin_delete(&format!("/admin/subscribers/{}", subscriber_id))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let consents: Vec<serde_json::Value> = app
+        .admin_get(&format!("/admin/subscribers/{}/consents", subscriber_id))
+        .await
+        .json()
+        .await
+        .unwrap();
+    let actions: Vec<&str> = consents
+        .iter()
+        .map(|consent| consent["action"].as_str().unwrap())
+        .collect();
+    assert_eq!(actions, vec!["subscribe", "confirm"]);
+    for consent in &consents {
+        assert_eq!(consent["lists"], serde_json::json!(["newsletter"]));
+        assert_eq!(consent["ip_address"], serde_json::Value::Null);
+        assert_eq!(consent["user_agent"], serde_json::Value::Null);
+    }
+}
+
+#[tokio::test]
+async fn erasures_keep_an_anonymous_audit_trail() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let (subscriber_id, _) = app.the_subscriber().await;
+    let lists: Vec<serde_json::Value> = app.admin_get("/admin/lists").await.json().await.unwrap();
+    app.admin_put(
+        &format!(
+            "/admin/lists/{}/attributes",
+            lists[0]["list_id"].as_str().unwrap()
+        ),
+        &serde_json::json!({"company": "text"}),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+    app.admin_put(
+        &format!("/admin/subscribers/{}/attributes", subscriber_id),
+        &serde_json::json!({"attributes": {"company": "Acme"}}),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+    app.admin_put(
+        &format!("/admin/subscribers/{}/tags", subscriber_id),
+        &serde_json::json!({"tags": ["vip"]}),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+
+    // Act
+    app.admin_delete(&format!("/admin/subscribers/{}", subscriber_id))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let audit: Vec<serde_json::Value> = app
+        .admin_get(&format!("/admin/subscribers/{}/audit", subscriber_id))
+        .await
+        .json()
+        .await
+        .unwrap();
+    let actions: Vec<&str> = audit
+        .iter()
+        .map(|entry| entry["action"].as_str().unwrap())
+        .collect();
+    assert!(actions.contains(&"set_attributes"));
+    assert!(actions.contains(&"set_tags"));
+    let leaked = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM subscriber_audit_log
+        WHERE note LIKE '%Acme%' OR note LIKE '%vip%'
+        "#
+    )
+    .fetch_one(&app.db)
+    .await
+    .unwrap();
+    assert_eq!(leaked, 0);
+}
+
+#[tokio::test]
+async fn issues_are_not_sent_to_erased_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let (subscriber_id, _) = app.the_subscriber().await;
+    let issue_id = app.create_published_issue("Issue one").await;
+
+    // Act
+    app.admin_delete(&format!("/admin/subscribers/{}", subscriber_id))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    assert_eq!(count(&app, "issue_delivery_queue").await, 0);
+    assert_eq!(count(&app, "issue_deliveries").await, 0);
+    let issue: serde_json::Value = app.get_issue(&issue_id).await.json().await.unwrap();
+    assert_eq!(issue["status"], "sent");
+}
+
+#[tokio::test]
+async fn erased_addresses_cannot_be_imported_again() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let (subscriber_id, email) = app.the_subscriber().await;
+    app.admin_delete(&format!("/admin/subscribers/{}", subscriber_id))
+        .await
+        .error_for_status()
+        .unwrap();
+    let csv = format!("email,name\n{},Ursula\n", email.to_uppercase());
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/admin/imports?confirmed=true", &app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .body(csv)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let import_id = response.json::<serde_json::Value>().await.unwrap()["import_id"]
+        .as_str()
+        .unwrap()
+        .to_owned();
+    let mut report = serde_json::Value::Null;
+    for _ in 0..100 {
+        report = app
+            .admin_get(&format!("/admin/imports/{}", import_id))
+            .await
+            .json()
+            .await
+            .unwrap();
+        if report["status"] != "running" {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+
+    // Assert
+    assert_eq!(report["status"], "completed");
+    assert_eq!(report["imported_rows"], 0);
+    assert_eq!(report["failed_rows"], 1);
+    assert_eq!(report["errors"][0]["line"], 2);
+    assert_eq!(count(&app, "subscriptions").await, 0);
+}
+
+#[tokio::test]
+async fn subscribers_can_erase_themselves_from_the_preference_center() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let (subscriber_id, _) = app.the_subscriber().await;
+    let link = app.preference_links.link(subscriber_id, Utc::now());
+    let mut form: Vec<(String, String)> = reqwest::Url::parse(&link)
+        .unwrap()
+        .query_pairs()
+        .map(|(k, v)| (k.into_owned(), v.into_owned()))
+        .collect();
+    let erase = |form: Vec<(String, String)>| {
+        app.api_client
+            .post(format!("{}/preferences/erase", app.address))
+            .form(&form)
+            .send()
+    };
+
+    // Act
+    let unconfirmed = erase(form.clone()).await.unwrap();
+    form.push(("confirm".into(), "true".into()));
+    let confirmed = erase(form).await.unwrap();
+
+    // Assert
+    assert_eq!(400, unconfirmed.status().as_u16());
+    assert_eq!(200, confirmed.status().as_u16());
+    assert_eq!(count(&app, "subscriptions").await, 0);
+}
+
+#[tokio::test]
+async fn unknown_subscribers_cannot_be_erased() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .admin_delete(&format!("/admin/subscribers/{}", Uuid::new_v4()))
+        .await;
+
+    // Assert
+    assert_eq!(404, response.status().as_u16());
+}
@@ -0,0 +1,132 @@
+use crate::helpers::{TestApp, spawn_app};
+
+async fn export(app: &TestApp, query: &str) -> reqwest::Response {
+    app.admin_get(&format!("/admin/subscribers/export?{}", query))
+        .await
+}
+
+async fn emails_by_status(app: &TestApp, status: &str) -> Vec<String> {
+    sqlx::query_scalar!(
+        "SELECT email FROM subscriptions WHERE status = $1 ORDER BY email",
+        status
+    )
+    .fetch_all(&app.db)
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn exports_require_authentication() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .get(format!("{}/admin/subscribers/export", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn every_subscriber_is_exported_as_csv_by_default() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.create_unconfirmed_subscriber().await;
+
+    // Act
+    let response = export(&app, "").await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "text/csv; charset=utf-8"
+    );
+    assert_eq!(
+        response.headers()["Content-Disposition"],
+        r#"attachment; filename="subscribers.csv""#
+    );
+    let body = response.text().await.unwrap();
+    let mut lines = body.lines();
+    assert_eq!(
+        lines.next(),
+        Some("id,email,name,status,subscribed_at,frequency,tags,attributes,lists")
+    );
+    assert_eq!(lines.count(), 2);
+}
+
+#[tokio::test]
+async fn json_lines_exports_can_be_filtered_by_status() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.create_unconfirmed_subscriber().await;
+
+    // Act
+    let response = export(&app, "format=jsonl&status=confirmed").await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(response.headers()["Content-Type"], "application/x-ndjson");
+    let body = response.text().await.unwrap();
+    let subscribers: Vec<serde_json::Value> = body
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(subscribers.len(), 1);
+    assert_eq!(
+        subscribers[0]["email"],
+        emails_by_status(&app, "confirmed").await[0]
+    );
+    assert_eq!(subscribers[0]["lists"], serde_json::json!(["newsletter"]));
+}
+
+#[tokio::test]
+async fn exports_can_be_filtered_by_list() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.admin_post(
+        "/admin/lists",
+        &serde_json::json!({"slug": "digest", "name": "The Digest"}),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+
+    // Act
+    let newsletter = export(&app, "format=jsonl&list=newsletter").await;
+    let digest = export(&app, "format=jsonl&list=digest").await;
+
+    // Assert
+    assert_eq!(newsletter.text().await.unwrap().lines().count(), 1);
+    assert_eq!(digest.text().await.unwrap(), "");
+}
+
+#[tokio::test]
+async fn invalid_export_parameters_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let test_cases = [
+        ("format=xml", "an unknown format"),
+        ("list=nope", "an unknown list"),
+    ];
+
+    for (query, description) in test_cases {
+        // Act
+        let response = export(&app, query).await;
+
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not reject {}.",
+            description
+        );
+    }
+}
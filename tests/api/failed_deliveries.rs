@@ -0,0 +1,270 @@
+use wiremock::{
+    Mock, ResponseTemplate,
+    matchers::{method, path},
+};
+
+use crate::helpers::{TestApp, spawn_app};
+
+/// Publish an issue to a single subscriber whose delivery keeps failing with
+/// `status`, and dispatch it until the delivery gives up.
+async fn failed_delivery(app: &TestApp, status: u16) -> String {
+    app.create_confirmed_subscriber().await;
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(status))
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let issue_id = app.create_published_issue("Issue").await;
+    for _ in 0..5 {
+        app.dispatch_all_pending_emails().await;
+        app.make_postponed_deliveries_due().await;
+    }
+    issue_id
+}
+
+async fn list_failed_deliveries(app: &TestApp) -> Vec<serde_json::Value> {
+    app.admin_get("/admin/failed-deliveries")
+        .await
+        .json()
+        .await
+        .unwrap()
+}
+
+async fn issue_status(app: &TestApp, issue_id: &str) -> String {
+    let issue: serde_json::Value = app.get_issue(issue_id).await.json().await.unwrap();
+    issue["status"].as_str().unwrap().to_owned()
+}
+
+#[tokio::test]
+async fn failed_deliveries_require_authentication() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::get(format!("{}/admin/failed-deliveries", app.address))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn transient_failures_are_retried_before_being_dead_lettered() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(503))
+        .expect(5)
+        .mount(&app.email_server)
+        .await;
+    let issue_id = app.create_published_issue("Issue").await;
+
+    // Act - Part 1 - The first failure only postpones the delivery
+    app.dispatch_all_pending_emails().await;
+
+    // Assert - Part 1
+    assert!(list_failed_deliveries(&app).await.is_empty());
+    assert_eq!(issue_status(&app, &issue_id).await, "sending");
+
+    // Act - Part 2 - Exhaust the retries
+    for _ in 0..5 {
+        app.make_postponed_deliveries_due().await;
+        app.dispatch_all_pending_emails().await;
+    }
+
+    // Assert - Part 2
+    let failed = list_failed_deliveries(&app).await;
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0]["newsletter_issue_id"], issue_id.as_str());
+    assert_eq!(failed[0]["error_kind"], "provider_error");
+    assert_eq!(failed[0]["n_attempts"], 5);
+    assert_eq!(issue_status(&app, &issue_id).await, "sent");
+}
+
+#[tokio::test]
+async fn rejected_deliveries_are_dead_lettered_without_retrying() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(422))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.create_published_issue("Issue").await;
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let failed = list_failed_deliveries(&app).await;
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0]["error_kind"], "rejected");
+    assert_eq!(failed[0]["n_attempts"], 1);
+}
+
+#[tokio::test]
+async fn failed_deliveries_can_be_filtered_by_issue() {
+    // Arrange
+    let app = spawn_app().await;
+    failed_delivery(&app, 422).await;
+
+    // Act
+    let response = app
+        .admin_get(&format!(
+            "/admin/failed-deliveries?newsletter_issue_id={}",
+            uuid::Uuid::new_v4()
+        ))
+        .await;
+
+    // Assert
+    let failed: Vec<serde_json::Value> = response.json().await.unwrap();
+    assert!(failed.is_empty());
+}
+
+#[tokio::test]
+async fn a_retried_delivery_is_sent_again() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = failed_delivery(&app, 422).await;
+    let failed = list_failed_deliveries(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .admin_post(
+            &format!(
+                "/admin/failed-deliveries/{}/retry",
+                failed[0]["failed_delivery_id"].as_str().unwrap()
+            ),
+            &serde_json::json!({}),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(202, response.status().as_u16());
+    assert_eq!(issue_status(&app, &issue_id).await, "sending");
+    app.dispatch_all_pending_emails().await;
+    assert_eq!(issue_status(&app, &issue_id).await, "sent");
+    assert!(list_failed_deliveries(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn failed_deliveries_can_be_retried_in_bulk() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = failed_delivery(&app, 422).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .admin_post(
+            "/admin/failed-deliveries/retry",
+            &serde_json::json!({ "newsletter_issue_id": issue_id }),
+        )
+        .await;
+
+    // Assert
+    let outcome: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(outcome["affected"], 1);
+    app.dispatch_all_pending_emails().await;
+    assert!(list_failed_deliveries(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn a_discarded_delivery_is_forgotten() {
+    // Arrange
+    let app = spawn_app().await;
+    failed_delivery(&app, 422).await;
+    let failed = list_failed_deliveries(&app).await;
+    let path = format!(
+        "/admin/failed-deliveries/{}",
+        failed[0]["failed_delivery_id"].as_str().unwrap()
+    );
+
+    // Act
+    let first = app.admin_delete(&path).await;
+    let second = app.admin_delete(&path).await;
+
+    // Assert
+    assert_eq!(204, first.status().as_u16());
+    assert_eq!(404, second.status().as_u16());
+    assert!(list_failed_deliveries(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn failed_deliveries_can_be_discarded_in_bulk() {
+    // Arrange
+    let app = spawn_app().await;
+    failed_delivery(&app, 422).await;
+
+    // Act
+    let response = app
+        .admin_post(
+            "/admin/failed-deliveries/discard",
+            &serde_json::json!({ "error_kind": "rejected" }),
+        )
+        .await;
+
+    // Assert
+    let outcome: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(outcome["affected"], 1);
+    assert!(list_failed_deliveries(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn deliveries_to_subscribers_who_left_are_not_retried() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = failed_delivery(&app, 422).await;
+    let failed = list_failed_deliveries(&app).await;
+    sqlx::query!("UPDATE list_subscriptions SET status = 'unsubscribed'")
+        .execute(&app.db)
+        .await
+        .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let single = app
+        .admin_post(
+            &format!(
+                "/admin/failed-deliveries/{}/retry",
+                failed[0]["failed_delivery_id"].as_str().unwrap()
+            ),
+            &serde_json::json!({}),
+        )
+        .await;
+    let bulk = app
+        .admin_post(
+            "/admin/failed-deliveries/retry",
+            &serde_json::json!({ "newsletter_issue_id": issue_id }),
+        )
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(409, single.status().as_u16());
+    let outcome: serde_json::Value = bulk.json().await.unwrap();
+    assert_eq!(outcome["affected"], 0);
+    assert_eq!(issue_status(&app, &issue_id).await, "sent");
+    assert_eq!(list_failed_deliveries(&app).await.len(), 1);
+}
@@ -0,0 +1,246 @@
+use email_newsletter::{
+    configuration::{FeedIssueMode, FeedPollerSettings},
+    feed_poller::{FeedError, FeedFetcher, FeedPoller, FileFeedFetcher, HttpFeedFetcher},
+};
+use std::sync::{Arc, Mutex};
+use wiremock::{
+    Mock, ResponseTemplate,
+    matchers::{method, path},
+};
+
+use crate::helpers::spawn_app;
+
+/// Serves whatever feed the test last set, through any of its clones.
+#[derive(Clone)]
+struct StubFetcher(Arc<Mutex<String>>);
+
+impl StubFetcher {
+    fn new(items: &[(&str, &str)]) -> Self {
+        Self(Arc::new(Mutex::new(feed(items))))
+    }
+
+    fn set(&self, items: &[(&str, &str)]) {
+        *self.0.lock().unwrap() = feed(items);
+    }
+}
+
+impl FeedFetcher for StubFetcher {
+    async fn fetch(&self) -> Result<String, FeedError> {
+        Ok(self.0.lock().unwrap().clone())
+    }
+}
+
+/// An RSS document listing `(guid, title)` items, most recent first.
+fn feed(items: &[(&str, &str)]) -> String {
+    let items: String = items
+        .iter()
+        .map(|(guid, title)| {
+            let title = title.replace('&', "&amp;");
+            format!(
+                "<item><guid>{guid}</guid><title>{title}</title>\
+                 <link>https://blog.example.com/{guid}</link>\
+                 <description>&lt;p&gt;About {title}&lt;/p&gt;</description></item>"
+            )
+        })
+        .collect();
+    format!(
+        r#"<?xml version="1.0"?><rss version="2.0"><channel><title>Blog</title><link>https://blog.example.com</link><description>Posts</description>{items}</channel></rss>"#
+    )
+}
+
+fn settings(mode: FeedIssueMode) -> FeedPollerSettings {
+    FeedPollerSettings {
+        source: "https://blog.example.com/feed.xml".into(),
+        interval_seconds: 60,
+        mode,
+        send_delay_minutes: 30,
+        import_existing_items: false,
+        html_template: "<h1>{{ title }}</h1>{{ content }}<a href=\"{{ link }}\">More</a>".into(),
+        text_template: "{{ title }}: {{ content }} {{ link }}".into(),
+    }
+}
+
+#[tokio::test]
+async fn the_first_poll_only_remembers_existing_posts() {
+    // Arrange
+    let app = spawn_app().await;
+    let poller = FeedPoller::new(
+        StubFetcher::new(&[("2", "Second"), ("1", "First")]),
+        settings(FeedIssueMode::Draft),
+    );
+
+    // Act
+    let created = poller.poll(&app.db).await.unwrap();
+
+    // Assert
+    assert!(created.is_empty());
+    let issues = app.admin_get("/admin/issues").await;
+    let issues: Vec<serde_json::Value> = issues.json().await.unwrap();
+    assert!(issues.is_empty());
+}
+
+#[tokio::test]
+async fn existing_posts_are_imported_when_configured() {
+    // Arrange
+    let app = spawn_app().await;
+    let mut settings = settings(FeedIssueMode::Draft);
+    settings.import_existing_items = true;
+    let poller = FeedPoller::new(
+        StubFetcher::new(&[("2", "Second"), ("1", "First")]),
+        settings,
+    );
+
+    // Act
+    let created = poller.poll(&app.db).await.unwrap();
+
+    // Assert
+    assert_eq!(created.len(), 2);
+    let first = app
+        .get_issue(&created[0].to_string())
+        .await
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    assert_eq!(first["title"], "First");
+}
+
+#[tokio::test]
+async fn new_posts_become_draft_issues_rendered_from_the_templates() {
+    // Arrange
+    let app = spawn_app().await;
+    let fetcher = StubFetcher::new(&[("1", "First")]);
+    let poller = FeedPoller::new(fetcher.clone(), settings(FeedIssueMode::Draft));
+    poller.poll(&app.db).await.unwrap();
+
+    // Act
+    fetcher.set(&[("2", "Fish & chips"), ("1", "First")]);
+    let created = poller.poll(&app.db).await.unwrap();
+
+    // Assert
+    assert_eq!(created.len(), 1);
+    let issue = app
+        .get_issue(&created[0].to_string())
+        .await
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    assert_eq!(issue["title"], "Fish & chips");
+    assert_eq!(issue["status"], "draft");
+    assert!(issue["send_at"].is_null());
+    assert_eq!(
+        issue["html_content"],
+        "<h1>Fish &amp; chips</h1><p>About Fish & chips</p>\
+         <a href=\"https://blog.example.com/2\">More</a>"
+    );
+    assert_eq!(
+        issue["text_content"],
+        "Fish & chips: About Fish & chips https://blog.example.com/2"
+    );
+}
+
+#[tokio::test]
+async fn posts_are_turned_into_issues_only_once() {
+    // Arrange
+    let app = spawn_app().await;
+    let fetcher = StubFetcher::new(&[("0", "Zeroth")]);
+    let poller = FeedPoller::new(fetcher.clone(), settings(FeedIssueMode::Draft));
+    poller.poll(&app.db).await.unwrap();
+    fetcher.set(&[("1", "First"), ("0", "Zeroth")]);
+
+    // Act
+    let first = poller.poll(&app.db).await.unwrap();
+    let second = poller.poll(&app.db).await.unwrap();
+
+    // Assert
+    assert_eq!(first.len(), 1);
+    assert!(second.is_empty());
+}
+
+#[tokio::test]
+async fn scheduled_mode_schedules_issues_after_the_configured_delay() {
+    // Arrange
+    let app = spawn_app().await;
+    let fetcher = StubFetcher::new(&[("1", "First")]);
+    let poller = FeedPoller::new(fetcher.clone(), settings(FeedIssueMode::Scheduled));
+    poller.poll(&app.db).await.unwrap();
+    fetcher.set(&[("2", "Second"), ("1", "First")]);
+
+    // Act
+    let created = poller.poll(&app.db).await.unwrap();
+
+    // Assert
+    let issue = app
+        .get_issue(&created[0].to_string())
+        .await
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    assert_eq!(issue["status"], "scheduled");
+    let send_at: chrono::DateTime<chrono::Utc> =
+        serde_json::from_value(issue["send_at"].clone()).unwrap();
+    assert!(send_at > chrono::Utc::now() + chrono::Duration::minutes(29));
+    // The issue still waits for an approval before it goes out.
+    assert_eq!(issue["approval_status"], "pending");
+}
+
+#[tokio::test]
+async fn feeds_can_be_fetched_over_http() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/feed.xml"))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(feed(&[("1", "First")])))
+        .mount(&app.email_server)
+        .await;
+    let mut settings = settings(FeedIssueMode::Draft);
+    settings.import_existing_items = true;
+    let fetcher = HttpFeedFetcher::new(
+        format!("{}/feed.xml", app.email_server.uri()),
+        std::time::Duration::from_secs(5),
+    );
+
+    // Act
+    let created = FeedPoller::new(fetcher, settings)
+        .poll(&app.db)
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(created.len(), 1);
+}
+
+#[tokio::test]
+async fn feeds_can_be_read_from_a_file() {
+    // Arrange
+    let app = spawn_app().await;
+    let file = std::env::temp_dir().join(format!("{}.xml", uuid::Uuid::new_v4()));
+    std::fs::write(&file, feed(&[("1", "First")])).unwrap();
+    let mut settings = settings(FeedIssueMode::Draft);
+    settings.import_existing_items = true;
+
+    // Act
+    let created = FeedPoller::new(FileFeedFetcher::new(&file), settings)
+        .poll(&app.db)
+        .await
+        .unwrap();
+
+    // Assert
+    std::fs::remove_file(&file).unwrap();
+    assert_eq!(created.len(), 1);
+}
+
+#[tokio::test]
+async fn an_invalid_feed_is_reported_as_a_parse_error() {
+    // Arrange
+    let app = spawn_app().await;
+    let poller = FeedPoller::new(
+        StubFetcher(Arc::new(Mutex::new("not a feed".into()))),
+        settings(FeedIssueMode::Draft),
+    );
+
+    // Act
+    let outcome = poller.poll(&app.db).await;
+
+    // Assert
+    assert!(matches!(outcome, Err(FeedError::Parse(_))));
+}
@@ -1,13 +1,25 @@
+use argon2::{Algorithm, Argon2, Params, PasswordHasher, Version, password_hash::SaltString};
 use email_newsletter::{
+    ab_testing::decide_due_ab_tests,
     configuration::{DatabaseSettings, get_configuration},
+    email_client::EmailClient,
+    issue_delivery_worker::{ExecutionOutcome, try_execute_task},
+    preferences::PreferenceLinks,
+    scheduler::promote_due_issues,
+    signing::Signer,
     startup::{Application, get_connection_pool},
     telemetry::{get_subscriber, init_subscriber},
+    tracking::Tracker,
 };
+use fake::Fake;
 use once_cell::sync::Lazy;
 use reqwest::header::CONTENT_TYPE;
 use sqlx::{Connection, Executor, PgConnection, Pool, Postgres, postgres::PgPoolOptions};
 use uuid::Uuid;
-use wiremock::MockServer;
+use wiremock::{
+    Mock, MockServer, ResponseTemplate,
+    matchers::{method, path},
+};
 
 static TRACING: Lazy<()> = Lazy::new(|| {
     let default_filter_level = "info".to_string();
@@ -29,9 +41,321 @@ pub struct TestApp {
     pub db: Pool<Postgres>,
     pub email_server: MockServer,
     pub port: u16,
+    pub test_user: TestUser,
+    /// A second admin, to sign off on issues written by `test_user`.
+    pub approver: TestUser,
+    pub api_client: reqwest::Client,
+    pub email_client: EmailClient,
+    pub tracker: Tracker,
+    pub preference_links: PreferenceLinks,
+}
+
+/// Confirmation links embedded in the request to the email API.
+pub struct ConfirmationLinks {
+    pub html: reqwest::Url,
+    pub plain_text: reqwest::Url,
+}
+
+pub struct TestUser {
+    pub user_id: Uuid,
+    pub username: String,
+    pub password: String,
+}
+
+impl TestUser {
+    pub fn generate() -> Self {
+        Self {
+            user_id: Uuid::new_v4(),
+            username: Uuid::new_v4().to_string(),
+            password: Uuid::new_v4().to_string(),
+        }
+    }
+
+    pub async fn store(&self, pool: &Pool<Postgres>) {
+        let salt = SaltString::generate(&mut argon2::password_hash::rand_core::OsRng);
+        // Match the parameters of the hashes the application computes
+        let password_hash = Argon2::new(
+            Algorithm::Argon2id,
+            Version::V0x13,
+            Params::new(15000, 2, 1, None).unwrap(),
+        )
+        .hash_password(self.password.as_bytes(), &salt)
+        .unwrap()
+        .to_string();
+        sqlx::query!(
+            "INSERT INTO users (user_id, username, password_hash) VALUES ($1, $2, $3)",
+            self.user_id,
+            self.username,
+            password_hash,
+        )
+        .execute(pool)
+        .await
+        .expect("Failed to store test user.");
+    }
 }
 
 impl TestApp {
+    pub async fn dispatch_all_pending_emails(&self) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue =
+                try_execute_task(&self.db, &self.email_client, &self.tracker)
+                    .await
+                    .unwrap()
+            {
+                break;
+            }
+        }
+    }
+
+    /// Skip the backoff of the deliveries waiting to be retried.
+    pub async fn make_postponed_deliveries_due(&self) {
+        sqlx::query!("UPDATE issue_delivery_queue SET execute_after = now()")
+            .execute(&self.db)
+            .await
+            .unwrap();
+    }
+
+    pub async fn promote_due_issues(&self) {
+        promote_due_issues(&self.db).await.unwrap();
+    }
+
+    /// Close the window of every running A/B test, then pick the winners.
+    pub async fn decide_ab_tests_now(&self) {
+        sqlx::query!(
+            "UPDATE newsletter_issues SET ab_test_decide_at = now() WHERE ab_test_decide_at > now()"
+        )
+        .execute(&self.db)
+        .await
+        .unwrap();
+        decide_due_ab_tests(&self.db).await.unwrap();
+    }
+
+    /// Extract the confirmation links embedded in the request to the email API.
+    pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+
+        let get_link = |s: &str| {
+            let links: Vec<_> = linkify::LinkFinder::new()
+                .links(s)
+                .filter(|l| *l.kind() == linkify::LinkKind::Url)
+                .collect();
+            assert_eq!(links.len(), 1);
+            let raw_link = links[0].as_str().to_owned();
+            let mut confirmation_link = reqwest::Url::parse(&raw_link).unwrap();
+            // Let's make sure we don't call random APIs on the web
+            assert_eq!(confirmation_link.host_str().unwrap(), "127.0.0.1");
+            confirmation_link.set_port(Some(self.port)).unwrap();
+            confirmation_link
+        };
+
+        let html = get_link(body["HtmlBody"].as_str().unwrap());
+        let plain_text = get_link(body["TextBody"].as_str().unwrap());
+        ConfirmationLinks { html, plain_text }
+    }
+
+    /// Subscribe a new user and return the links from their confirmation email.
+    pub async fn create_unconfirmed_subscriber(&self) -> ConfirmationLinks {
+        let name: String = fake::faker::name::en::Name().fake();
+        let email: String = fake::faker::internet::en::SafeEmail().fake();
+        let body = serde_urlencoded::to_string([("name", name), ("email", email)]).unwrap();
+
+        let _mock_guard = Mock::given(path("/email"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .named("Create unconfirmed subscriber")
+            .expect(1)
+            .mount_as_scoped(&self.email_server)
+            .await;
+        self.post_subscriptions(body)
+            .await
+            .error_for_status()
+            .unwrap();
+
+        let email_request = &self
+            .email_server
+            .received_requests()
+            .await
+            .unwrap()
+            .pop()
+            .unwrap();
+        self.get_confirmation_links(email_request)
+    }
+
+    pub async fn create_confirmed_subscriber(&self) {
+        let confirmation_link = self.create_unconfirmed_subscriber().await;
+        reqwest::get(confirmation_link.html)
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+    }
+
+    /// The id and address of the only subscriber.
+    pub async fn the_subscriber(&self) -> (Uuid, String) {
+        let subscriber = sqlx::query!("SELECT id, email FROM subscriptions")
+            .fetch_one(&self.db)
+            .await
+            .unwrap();
+        (subscriber.id, subscriber.email)
+    }
+
+    /// Accept every email sent from now on.
+    pub async fn mock_email_api(&self) {
+        Mock::given(path("/email"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&self.email_server)
+            .await;
+    }
+
+    pub async fn post_issue(&self, body: &serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/issues", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_issue(&self, issue_id: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/issues/{}", &self.address, issue_id))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Sign off on `revision` of an issue as `approver`.
+    pub async fn approve_issue(&self, issue_id: &str, revision: i64) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/issues/{}/approve",
+                &self.address, issue_id
+            ))
+            .basic_auth(&self.approver.username, Some(&self.approver.password))
+            .json(&serde_json::json!({ "revision": revision }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Write, approve and publish an issue, returning its id.
+    pub async fn create_published_issue(&self, title: &str) -> String {
+        let response = self
+            .post_issue(&serde_json::json!({
+                "title": title,
+                "text_content": format!("{} as plain text", title),
+                "html_content": format!("<p>{} as HTML</p>", title),
+                "send_at": chrono::Utc::now(),
+            }))
+            .await;
+        let body: serde_json::Value = response.json().await.unwrap();
+        let issue_id = body["newsletter_issue_id"].as_str().unwrap().to_owned();
+        self.approve_issue(&issue_id, 1)
+            .await
+            .error_for_status()
+            .unwrap();
+        self.promote_due_issues().await;
+        issue_id
+    }
+
+    /// Write and approve an issue A/B testing `subjects` on half of the list,
+    /// with open tracking on. Returns its id.
+    pub async fn create_ab_tested_issue(&self, subjects: &[&str]) -> String {
+        let response = self
+            .post_issue(&serde_json::json!({
+                "title": "Subject under test",
+                "text_content": "text",
+                "html_content": "<html><body><p>html</p></body></html>",
+                "send_at": chrono::Utc::now(),
+            }))
+            .await;
+        let body: serde_json::Value = response.json().await.unwrap();
+        let issue_id = body["newsletter_issue_id"].as_str().unwrap().to_owned();
+        self.admin_put(
+            &format!("/admin/issues/{}/tracking", issue_id),
+            &serde_json::json!({ "track_opens": true }),
+        )
+        .await
+        .error_for_status()
+        .unwrap();
+        let response = self
+            .admin_put(
+                &format!("/admin/issues/{}/ab-test", issue_id),
+                &serde_json::json!({
+                    "subjects": subjects,
+                    "test_percentage": 50,
+                    "wait_minutes": 60,
+                    "metric": "open_rate",
+                }),
+            )
+            .await;
+        assert_eq!(200, response.status().as_u16());
+        self.approve_issue(&issue_id, 1)
+            .await
+            .error_for_status()
+            .unwrap();
+        issue_id
+    }
+
+    pub async fn admin_get(&self, path: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}{}", &self.address, path))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn admin_post(&self, path: &str, body: &serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}{}", &self.address, path))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn admin_put(&self, path: &str, body: &serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .put(format!("{}{}", &self.address, path))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn admin_delete(&self, path: &str) -> reqwest::Response {
+        self.api_client
+            .delete(format!("{}{}", &self.address, path))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_issue_action(
+        &self,
+        issue_id: &str,
+        action: &str,
+        body: &serde_json::Value,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/issues/{}/{}",
+                &self.address, issue_id, action
+            ))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
         reqwest::Client::new()
             .post(format!("{}/subscriptions", &self.address))
@@ -54,6 +378,8 @@ pub async fn spawn_app() -> TestApp {
         // Use a random OS port
         c.application.port = 0;
         c.email_client.base_url = email_server.uri();
+        // Requests come from the test itself: let it pose as a proxy.
+        c.consent.trusted_proxies = vec!["127.0.0.1".parse().unwrap()];
         c
     };
     configure_database(&configuration.database).await;
@@ -64,12 +390,32 @@ pub async fn spawn_app() -> TestApp {
     let application_port = application.port();
     let address = format!("http://127.0.0.1:{}", application.port());
     tokio::spawn(application.run_until_stopped());
-    TestApp {
+
+    let email_client = EmailClient::new(
+        configuration.email_client.base_url.clone(),
+        configuration.email_client.sender().unwrap(),
+        configuration.email_client.authorization_token.clone(),
+        configuration.email_client.timeout(),
+        configuration.email_client.throttle(),
+    );
+    let signer = Signer::new(configuration.application.hmac_secret.clone());
+    let tracker = Tracker::new(address.clone(), signer.clone());
+    let preference_links = PreferenceLinks::new(address.clone(), signer);
+    let test_app = TestApp {
         address,
         db: get_connection_pool(&configuration.database),
         email_server,
         port: application_port,
-    }
+        test_user: TestUser::generate(),
+        approver: TestUser::generate(),
+        api_client: reqwest::Client::new(),
+        email_client,
+        tracker,
+        preference_links,
+    };
+    test_app.test_user.store(&test_app.db).await;
+    test_app.approver.store(&test_app.db).await;
+    test_app
 }
 
 pub async fn configure_database(config: &DatabaseSettings) -> Pool<Postgres> {
@@ -0,0 +1,285 @@
+use std::time::Duration;
+
+use wiremock::{
+    Mock, ResponseTemplate,
+    matchers::{any, method, path},
+};
+
+use crate::helpers::{TestApp, spawn_app};
+
+async fn post_import(app: &TestApp, query: &str, csv: &str) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/admin/imports?{}", &app.address, query))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .header("Content-Type", "text/csv")
+        .body(csv.to_owned())
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+/// Start an import and wait for it to be over, returning its report.
+async fn import(app: &TestApp, query: &str, csv: &str) -> serde_json::Value {
+    let response = post_import(app, query, csv).await;
+    assert_eq!(202, response.status().as_u16());
+    let started: serde_json::Value = response.json().await.unwrap();
+    let import_id = started["import_id"].as_str().unwrap();
+    for _ in 0..100 {
+        let report: serde_json::Value = app
+            .admin_get(&format!("/admin/imports/{}", import_id))
+            .await
+            .json()
+            .await
+            .unwrap();
+        if report["status"] != "running" {
+            return report;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("The import did not finish in time.");
+}
+
+async fn subscriber_statuses(app: &TestApp) -> Vec<(String, String, String)> {
+    sqlx::query!(
+        r#"
+        SELECT s.email, s.status, ls.status AS list_status
+        FROM subscriptions s
+        JOIN list_subscriptions ls ON ls.subscriber_id = s.id
+        ORDER BY s.email
+        "#
+    )
+    .fetch_all(&app.db)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| (r.email, r.status, r.list_status))
+    .collect()
+}
+
+#[tokio::test]
+async fn imports_require_authentication() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/admin/imports", &app.address))
+        .body("email,name\n")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn confirmed_imports_report_every_row_that_failed() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let csv = "\
+name,email,plan
+Ursula,ursula@example.com,pro
+Octavia,not-an-email,
+Ursula again,ursula@example.com,
+,nameless@example.com,
+";
+
+    // Act
+    let report = import(&app, "confirmed=true", csv).await;
+
+    // Assert
+    assert_eq!(report["status"], "completed");
+    assert_eq!(report["processed_rows"], 4);
+    assert_eq!(report["imported_rows"], 1);
+    assert_eq!(report["duplicate_rows"], 1);
+    assert_eq!(report["failed_rows"], 2);
+    let lines: Vec<i64> = report["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|error| error["line"].as_i64().unwrap())
+        .collect();
+    assert_eq!(lines, vec![3, 5]);
+    assert_eq!(
+        subscriber_statuses(&app).await,
+        vec![(
+            "ursula@example.com".to_owned(),
+            "confirmed".to_owned(),
+            "confirmed".to_owned()
+        )]
+    );
+}
+
+#[tokio::test]
+async fn existing_subscribers_are_left_alone() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_unconfirmed_subscriber().await;
+    let email = sqlx::query_scalar!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+
+    // Act
+    let report = import(
+        &app,
+        "confirmed=true",
+        &format!("email,name\n{},Someone else\n", email),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(report["duplicate_rows"], 1);
+    let subscriber = sqlx::query!("SELECT name, status FROM subscriptions")
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+    assert_ne!(subscriber.name, "Someone else");
+    assert_eq!(subscriber.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn unconfirmed_imports_send_confirmation_emails() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    let csv = "email,name\nursula@example.com,Ursula\noctavia@example.com,Octavia\n";
+
+    // Act
+    let report = import(&app, "", csv).await;
+
+    // Assert
+    assert_eq!(report["imported_rows"], 2);
+    assert_eq!(report["failed_rows"], 0);
+    let requests = app.email_server.received_requests().await.unwrap();
+    let confirmation_links = app.get_confirmation_links(&requests[0]);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let statuses = subscriber_statuses(&app).await;
+    assert_eq!(
+        statuses
+            .iter()
+            .filter(|(_, status, list_status)| status == "confirmed" && list_status == "confirmed")
+            .count(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn imports_that_cannot_start_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let test_cases = [
+        ("", "email\nursula@example.com\n", "a missing name column"),
+        ("", "", "an empty file"),
+        ("list=nope", "email,name\n", "an unknown list"),
+    ];
+
+    for (query, csv, description) in test_cases {
+        // Act
+        let response = post_import(&app, query, csv).await;
+
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not reject {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn unknown_imports_are_not_found() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .admin_get(&format!("/admin/imports/{}", uuid::Uuid::new_v4()))
+        .await;
+
+    // Assert
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn files_spanning_several_batches_are_imported_whole() {
+    // Arrange
+    let app = spawn_app().await;
+    let mut csv = String::from("email,name\n");
+    for i in 0..1200 {
+        csv.push_str(&format!("subscriber{}@example.com,Subscriber {}\n", i, i));
+    }
+
+    // Act
+    let report = import(&app, "confirmed=true", &csv).await;
+
+    // Assert
+    assert_eq!(report["status"], "completed");
+    assert_eq!(report["processed_rows"], 1200);
+    assert_eq!(report["imported_rows"], 1200);
+    assert_eq!(subscriber_statuses(&app).await.len(), 1200);
+}
+
+#[tokio::test]
+async fn only_imports_whose_heartbeat_stopped_are_failed() {
+    // Arrange
+    let app = spawn_app().await;
+    let stale = uuid::Uuid::new_v4();
+    let alive = uuid::Uuid::new_v4();
+    for (import_id, silence) in [(stale, "1 hour"), (alive, "1 second")] {
+        sqlx::query!(
+            r#"
+            INSERT INTO subscriber_imports (
+                import_id, list_id, confirmed, status, created_at, heartbeat_at
+            )
+            SELECT $1, list_id, true, 'running', now(), now() - $2::TEXT::INTERVAL
+            FROM lists LIMIT 1
+            "#,
+            import_id,
+            silence
+        )
+        .execute(&app.db)
+        .await
+        .unwrap();
+    }
+
+    // Act
+    email_newsletter::subscriber_import::fail_stale_imports(&app.db)
+        .await
+        .unwrap();
+
+    // Assert
+    let report: serde_json::Value = app
+        .admin_get(&format!("/admin/imports/{}", stale))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(report["status"], "failed");
+    assert!(report["error"].is_string());
+    assert!(report["finished_at"].is_string());
+    // Another instance may be running it.
+    let report: serde_json::Value = app
+        .admin_get(&format!("/admin/imports/{}", alive))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(report["status"], "running");
+}
@@ -0,0 +1,357 @@
+use crate::helpers::{TestApp, spawn_app};
+
+async fn create_list(app: &TestApp, body: serde_json::Value) -> reqwest::Response {
+    app.admin_post("/admin/lists", &body).await
+}
+
+async fn list_statuses(app: &TestApp, email: &str) -> Vec<(String, String)> {
+    sqlx::query!(
+        r#"
+        SELECT l.slug, ls.status
+        FROM list_subscriptions ls
+        JOIN lists l ON l.list_id = ls.list_id
+        JOIN subscriptions s ON s.id = ls.subscriber_id
+        WHERE s.email = $1
+        ORDER BY l.slug
+        "#,
+        email
+    )
+    .fetch_all(&app.db)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| (r.slug, r.status))
+    .collect()
+}
+
+fn subscription(email: &str, lists: &str) -> String {
+    serde_urlencoded::to_string([("name", "le guin"), ("email", email), ("lists", lists)]).unwrap()
+}
+
+async fn sent_emails(app: &TestApp) -> Vec<serde_json::Value> {
+    app.email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|r| serde_json::from_slice(&r.body).unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn lists_can_be_created() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = create_list(
+        &app,
+        serde_json::json!({
+            "slug": "weekly",
+            "name": "The Weekly",
+            "sender_email": "weekly@example.com",
+            "sender_name": "The Weekly",
+        }),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(201, response.status().as_u16());
+    let lists: Vec<serde_json::Value> = app.admin_get("/admin/lists").await.json().await.unwrap();
+    let slugs: Vec<_> = lists.iter().map(|l| l["slug"].clone()).collect();
+    assert_eq!(slugs, vec!["newsletter", "weekly"]);
+    assert_eq!(lists[1]["double_opt_in"], true);
+    assert_eq!(lists[1]["confirmation_subject"], "Welcome");
+}
+
+#[tokio::test]
+async fn invalid_lists_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (
+            serde_json::json!({ "slug": "Weekly News", "name": "Weekly" }),
+            "an invalid slug",
+        ),
+        (
+            serde_json::json!({ "slug": "weekly", "name": " " }),
+            "a blank name",
+        ),
+        (
+            serde_json::json!({ "slug": "weekly", "name": "Weekly", "sender_email": "nope" }),
+            "an invalid sender",
+        ),
+        (
+            serde_json::json!({ "slug": "weekly", "name": "Weekly", "sender_name": "Weekly" }),
+            "a sender name without an address",
+        ),
+    ];
+
+    for (body, description) in test_cases {
+        // Act
+        let response = create_list(&app, body).await;
+
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not reject {}",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn list_slugs_are_unique() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = create_list(
+        &app,
+        serde_json::json!({ "slug": "newsletter", "name": "Another newsletter" }),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(409, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn subscribers_confirm_several_lists_at_once() {
+    // Arrange
+    let app = spawn_app().await;
+    app.mock_email_api().await;
+    create_list(
+        &app,
+        serde_json::json!({ "slug": "weekly", "name": "The Weekly" }),
+    )
+    .await;
+    create_list(
+        &app,
+        serde_json::json!({ "slug": "releases", "name": "Releases" }),
+    )
+    .await;
+
+    // Act
+    let response = app
+        .post_subscriptions(subscription("ursula@example.com", "weekly,releases"))
+        .await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let emails = app.email_server.received_requests().await.unwrap();
+    assert_eq!(emails.len(), 1);
+    assert_eq!(
+        list_statuses(&app, "ursula@example.com").await,
+        vec![
+            ("releases".into(), "pending_confirmation".into()),
+            ("weekly".into(), "pending_confirmation".into()),
+        ]
+    );
+    let links = app.get_confirmation_links(&emails[0]);
+    reqwest::get(links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    assert_eq!(
+        list_statuses(&app, "ursula@example.com").await,
+        vec![
+            ("releases".into(), "confirmed".into()),
+            ("weekly".into(), "confirmed".into()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn subscribing_to_an_unknown_list_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_subscriptions(subscription("ursula@example.com", "newsletter,nope"))
+        .await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+    assert!(list_statuses(&app, "ursula@example.com").await.is_empty());
+}
+
+#[tokio::test]
+async fn confirmed_subscribers_confirm_the_lists_they_join() {
+    // Arrange
+    let app = spawn_app().await;
+    app.mock_email_api().await;
+    create_list(
+        &app,
+        serde_json::json!({ "slug": "weekly", "name": "The Weekly" }),
+    )
+    .await;
+    app.post_subscriptions(subscription("ursula@example.com", "newsletter"))
+        .await
+        .error_for_status()
+        .unwrap();
+    let email = &app.email_server.received_requests().await.unwrap()[0];
+    reqwest::get(app.get_confirmation_links(email).html)
+        .await
+        .unwrap();
+
+    // Act
+    let response = app
+        .post_subscriptions(subscription("ursula@example.com", "weekly"))
+        .await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(
+        list_statuses(&app, "ursula@example.com").await,
+        vec![
+            ("newsletter".into(), "confirmed".into()),
+            ("weekly".into(), "pending_confirmation".into()),
+        ]
+    );
+    let emails = app.email_server.received_requests().await.unwrap();
+    assert_eq!(emails.len(), 2);
+    reqwest::get(app.get_confirmation_links(&emails[1]).html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    assert_eq!(
+        list_statuses(&app, "ursula@example.com").await,
+        vec![
+            ("newsletter".into(), "confirmed".into()),
+            ("weekly".into(), "confirmed".into()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn subscribers_who_left_a_list_confirm_again_to_rejoin_it() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.mock_email_api().await;
+    sqlx::query!("UPDATE list_subscriptions SET status = 'unsubscribed'")
+        .execute(&app.db)
+        .await
+        .unwrap();
+    let email = sqlx::query_scalar!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+
+    // Act
+    let response = app
+        .post_subscriptions(subscription(&email, "newsletter"))
+        .await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(
+        list_statuses(&app, &email).await,
+        vec![("newsletter".into(), "pending_confirmation".into())]
+    );
+    let emails = sent_emails(&app).await;
+    assert_eq!(emails.last().unwrap()["To"], email.as_str());
+}
+
+#[tokio::test]
+async fn lists_without_double_opt_in_need_no_confirmation() {
+    // Arrange
+    let app = spawn_app().await;
+    create_list(
+        &app,
+        serde_json::json!({ "slug": "weekly", "name": "The Weekly", "double_opt_in": false }),
+    )
+    .await;
+
+    // Act
+    let response = app
+        .post_subscriptions(subscription("ursula@example.com", "weekly"))
+        .await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert!(
+        app.email_server
+            .received_requests()
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    assert_eq!(
+        list_statuses(&app, "ursula@example.com").await,
+        vec![("weekly".into(), "confirmed".into())]
+    );
+}
+
+#[tokio::test]
+async fn issues_go_to_the_subscribers_of_their_list_as_its_sender() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.mock_email_api().await;
+    create_list(
+        &app,
+        serde_json::json!({
+            "slug": "weekly",
+            "name": "The Weekly",
+            "sender_email": "weekly@example.com",
+            "sender_name": "The Weekly",
+            "double_opt_in": false,
+        }),
+    )
+    .await;
+    app.post_subscriptions(subscription("ursula@example.com", "weekly"))
+        .await
+        .error_for_status()
+        .unwrap();
+    let response = app
+        .post_issue(&serde_json::json!({
+            "title": "Weekly issue",
+            "text_content": "text",
+            "html_content": "<p>html</p>",
+            "send_at": chrono::Utc::now(),
+            "list": "weekly",
+        }))
+        .await;
+    let body: serde_json::Value = response.json().await.unwrap();
+    let issue_id = body["newsletter_issue_id"].as_str().unwrap();
+    app.approve_issue(issue_id, 1)
+        .await
+        .error_for_status()
+        .unwrap();
+    let already_sent = app.email_server.received_requests().await.unwrap().len();
+
+    // Act
+    app.promote_due_issues().await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let emails = sent_emails(&app).await.split_off(already_sent);
+    assert_eq!(emails.len(), 1);
+    assert_eq!(emails[0]["To"], "ursula@example.com");
+    assert_eq!(emails[0]["From"], "The Weekly <weekly@example.com>");
+}
+
+#[tokio::test]
+async fn issues_for_unknown_lists_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_issue(&serde_json::json!({
+            "title": "Issue",
+            "text_content": "text",
+            "html_content": "<p>html</p>",
+            "list": "nope",
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+}
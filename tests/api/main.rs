@@ -1,4 +1,29 @@
+mod ab_tests;
+mod admin_approvals;
+mod admin_drafts;
+mod admin_issues;
+mod admin_metrics;
+mod admin_subscribers;
+mod admin_users;
+mod archive;
+mod attributes;
+mod bulk_actions;
+mod consents;
+mod deliveries;
+mod email_change;
+mod erasure;
+mod exports;
+mod failed_deliveries;
+mod feed_poller;
 mod health_check;
 mod helpers;
+mod imports;
+mod lists;
+mod preferences;
+mod segments;
+mod subscriber_data;
 mod subscriptions;
 mod subscriptions_confirm;
+mod tracking;
+mod utm;
+mod webhooks;
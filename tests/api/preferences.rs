@@ -0,0 +1,295 @@
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+use wiremock::{
+    Mock, ResponseTemplate,
+    matchers::{any, method, path},
+};
+
+use crate::helpers::{TestApp, spawn_app};
+
+/// The fields of a preference link, as the form carries them back.
+fn token_fields(link: &str) -> Vec<(String, String)> {
+    reqwest::Url::parse(link)
+        .unwrap()
+        .query_pairs()
+        .map(|(k, v)| (k.into_owned(), v.into_owned()))
+        .collect()
+}
+
+async fn post_preferences(app: &TestApp, link: &str, fields: &[(&str, &str)]) -> reqwest::Response {
+    let mut form = token_fields(link);
+    form.extend(fields.iter().map(|(k, v)| (k.to_string(), v.to_string())));
+    app.api_client
+        .post(format!("{}/preferences", app.address))
+        .form(&form)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn list_statuses(app: &TestApp, subscriber_id: Uuid) -> Vec<(String, String)> {
+    sqlx::query!(
+        r#"
+        SELECT l.slug, ls.status
+        FROM list_subscriptions ls
+        JOIN lists l ON l.list_id = ls.list_id
+        WHERE ls.subscriber_id = $1
+        ORDER BY l.slug
+        "#,
+        subscriber_id
+    )
+    .fetch_all(&app.db)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| (r.slug, r.status))
+    .collect()
+}
+
+#[tokio::test]
+async fn known_subscribers_are_emailed_a_link_to_their_preferences() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let (subscriber_id, email) = app.the_subscriber().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/preferences/link", app.address))
+        .form(&[("email", email.as_str())])
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let requests = app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&requests.last().unwrap().body).unwrap();
+    assert_eq!(body["Subject"], "Manage your subscription");
+    let expected = format!("/preferences?subscriber_id={}", subscriber_id);
+    assert!(body["TextBody"].as_str().unwrap().contains(&expected));
+}
+
+#[tokio::test]
+async fn unknown_addresses_get_the_same_answer_and_no_email() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/preferences/link", app.address))
+        .form(&[("email", "nobody@example.com")])
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn the_preference_page_shows_the_current_preferences() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let (subscriber_id, _) = app.the_subscriber().await;
+    let link = app.preference_links.link(subscriber_id, Utc::now());
+
+    // Act
+    let response = reqwest::get(&link).await.unwrap();
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let html = response.text().await.unwrap();
+    assert!(html.contains(r#"name="list" value="newsletter" checked"#));
+    assert!(html.contains(r#"<option value="every_issue" selected>"#));
+}
+
+#[tokio::test]
+async fn tampered_or_expired_links_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let (subscriber_id, _) = app.the_subscriber().await;
+    let link = app.preference_links.link(subscriber_id, Utc::now());
+    let tampered = link.replace(&subscriber_id.to_string(), &Uuid::new_v4().to_string());
+    let expired = app
+        .preference_links
+        .link(subscriber_id, Utc::now() - Duration::days(2));
+
+    for link in [tampered, expired] {
+        // Act
+        let page = reqwest::get(&link).await.unwrap();
+        let update =
+            post_preferences(&app, &link, &[("name", "Ursula"), ("frequency", "weekly")]).await;
+
+        // Assert
+        assert_eq!(401, page.status().as_u16());
+        assert_eq!(401, update.status().as_u16());
+    }
+}
+
+#[tokio::test]
+async fn subscribers_can_change_their_name_frequency_and_lists() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let (subscriber_id, _) = app.the_subscriber().await;
+    app.admin_post(
+        "/admin/lists",
+        &serde_json::json!({"slug": "digest", "name": "The Digest"}),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+    let link = app.preference_links.link(subscriber_id, Utc::now());
+
+    // Act
+    let response = post_preferences(
+        &app,
+        &link,
+        &[
+            ("name", "Ursula"),
+            ("frequency", "weekly"),
+            ("list", "digest"),
+        ],
+    )
+    .await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let saved = sqlx::query!(
+        "SELECT name, frequency FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_one(&app.db)
+    .await
+    .unwrap();
+    assert_eq!(saved.name, "Ursula");
+    assert_eq!(saved.frequency, "weekly");
+    assert_eq!(
+        list_statuses(&app, subscriber_id).await,
+        vec![
+            ("digest".to_owned(), "confirmed".to_owned()),
+            ("newsletter".to_owned(), "unsubscribed".to_owned()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn invalid_preferences_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let (subscriber_id, _) = app.the_subscriber().await;
+    let link = app.preference_links.link(subscriber_id, Utc::now());
+    let test_cases = [
+        (vec![("name", ""), ("frequency", "weekly")], "an empty name"),
+        (
+            vec![("name", "Ursula"), ("frequency", "daily")],
+            "an unknown frequency",
+        ),
+        (
+            vec![
+                ("name", "Ursula"),
+                ("frequency", "weekly"),
+                ("list", "nope"),
+            ],
+            "an unknown list",
+        ),
+    ];
+
+    for (fields, description) in test_cases {
+        // Act
+        let response = post_preferences(&app, &link, &fields).await;
+
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not reject {}.",
+            description
+        );
+    }
+    assert_eq!(
+        list_statuses(&app, subscriber_id).await,
+        vec![("newsletter".to_owned(), "confirmed".to_owned())]
+    );
+}
+
+#[tokio::test]
+async fn unsubscribing_from_everything_stops_the_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let (subscriber_id, _) = app.the_subscriber().await;
+    let link = app.preference_links.link(subscriber_id, Utc::now());
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = post_preferences(&app, &link, &[("unsubscribe", "true")]).await;
+    app.create_published_issue("After leaving").await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(
+        list_statuses(&app, subscriber_id).await,
+        vec![("newsletter".to_owned(), "unsubscribed".to_owned())]
+    );
+}
+
+#[tokio::test]
+async fn weekly_subscribers_get_at_most_one_issue_a_week() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let (subscriber_id, _) = app.the_subscriber().await;
+    let link = app.preference_links.link(subscriber_id, Utc::now());
+    post_preferences(
+        &app,
+        &link,
+        &[
+            ("name", "Ursula"),
+            ("frequency", "weekly"),
+            ("list", "newsletter"),
+        ],
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+    app.mock_email_api().await;
+
+    // Act
+    app.create_published_issue("First").await;
+    app.dispatch_all_pending_emails().await;
+    app.create_published_issue("Second").await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let requests = app.email_server.received_requests().await.unwrap();
+    let subjects: Vec<String> = requests
+        .iter()
+        .map(|r| serde_json::from_slice::<serde_json::Value>(&r.body).unwrap())
+        .filter_map(|body| body["Subject"].as_str().map(str::to_owned))
+        .filter(|subject| subject == "First" || subject == "Second")
+        .collect();
+    assert_eq!(subjects, vec!["First"]);
+}
@@ -0,0 +1,284 @@
+use wiremock::{
+    Mock, ResponseTemplate,
+    matchers::{method, path},
+};
+
+use crate::helpers::{TestApp, spawn_app};
+
+async fn create_segment(app: &TestApp, name: &str, filter: &str) -> reqwest::Response {
+    app.admin_post(
+        "/admin/segments",
+        &serde_json::json!({"name": name, "filter": filter}),
+    )
+    .await
+}
+
+async fn preview(app: &TestApp, filter: &str) -> reqwest::Response {
+    app.admin_post(
+        "/admin/segments/preview",
+        &serde_json::json!({"filter": filter}),
+    )
+    .await
+}
+
+/// Create `n` confirmed subscribers, returning their addresses.
+async fn create_subscribers(app: &TestApp, n: usize) -> Vec<String> {
+    for _ in 0..n {
+        app.create_confirmed_subscriber().await;
+    }
+    sqlx::query_scalar!("SELECT email FROM subscriptions ORDER BY subscribed_at")
+        .fetch_all(&app.db)
+        .await
+        .unwrap()
+}
+
+async fn tag(app: &TestApp, email: &str, tag: &str) {
+    sqlx::query!(
+        "UPDATE subscriptions SET tags = array_append(tags, $2) WHERE email = $1",
+        email,
+        tag
+    )
+    .execute(&app.db)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn segments_can_be_created_and_listed() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = create_segment(&app, "Beta testers", r#"tag = "beta""#).await;
+
+    // Assert
+    assert_eq!(201, response.status().as_u16());
+    let segments: Vec<serde_json::Value> =
+        app.admin_get("/admin/segments").await.json().await.unwrap();
+    assert_eq!(segments.len(), 1);
+    assert_eq!(segments[0]["name"], "Beta testers");
+    assert_eq!(segments[0]["filter"], r#"tag = "beta""#);
+}
+
+#[tokio::test]
+async fn invalid_or_duplicate_segments_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    create_segment(&app, "Beta testers", r#"tag = "beta""#)
+        .await
+        .error_for_status()
+        .unwrap();
+    let test_cases = [
+        ("Beta testers", r#"tag = "other""#, 409, "a duplicate name"),
+        ("", r#"tag = "beta""#, 400, "an empty name"),
+        ("Broken", r#"tag = "#, 400, "an incomplete filter"),
+        (
+            "Unknown",
+            r#"email = "a@example.com""#,
+            400,
+            "an unknown field",
+        ),
+    ];
+
+    for (name, filter, expected, description) in test_cases {
+        // Act
+        let response = create_segment(&app, name, filter).await;
+
+        // Assert
+        assert_eq!(
+            expected,
+            response.status().as_u16(),
+            "The API did not reject {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn the_preview_counts_matching_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    let emails = create_subscribers(&app, 3).await;
+    tag(&app, &emails[0], "beta").await;
+    sqlx::query!(
+        r#"UPDATE subscriptions SET attributes = '{"plan": "pro", "seats": 12}' WHERE email = $1"#,
+        emails[1]
+    )
+    .execute(&app.db)
+    .await
+    .unwrap();
+    let test_cases = [
+        (r#"status = "confirmed""#, 3),
+        (r#"tag = "beta""#, 1),
+        (r#"not tag = "beta""#, 2),
+        (r#"attributes.plan = "pro" and attributes.seats > 10"#, 1),
+        (r#"attributes.seats > 20 or tag = "beta""#, 1),
+        (r#"signed_up < "2000-01-01""#, 0),
+        ("opens >= 1", 0),
+        (r#"tag = "x' OR '1'='1""#, 0),
+    ];
+
+    for (filter, expected) in test_cases {
+        // Act
+        let response = preview(&app, filter).await;
+
+        // Assert
+        assert_eq!(200, response.status().as_u16(), "{}", filter);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["count"], expected, "{}", filter);
+    }
+}
+
+#[tokio::test]
+async fn previewing_an_invalid_filter_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = preview(&app, "opens = 1; DROP TABLE subscriptions").await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn an_issue_with_a_segment_goes_to_its_members_only() {
+    // Arrange
+    let app = spawn_app().await;
+    let emails = create_subscribers(&app, 2).await;
+    tag(&app, &emails[1], "beta").await;
+    let segment: serde_json::Value = create_segment(&app, "Beta testers", r#"tag = "beta""#)
+        .await
+        .json()
+        .await
+        .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let issue: serde_json::Value = app
+        .post_issue(&serde_json::json!({
+            "title": "For beta testers",
+            "text_content": "Plain text",
+            "html_content": "<p>HTML</p>",
+            "send_at": chrono::Utc::now(),
+        }))
+        .await
+        .json()
+        .await
+        .unwrap();
+    let issue_id = issue["newsletter_issue_id"].as_str().unwrap();
+
+    // Act
+    let response = app
+        .admin_put(
+            &format!("/admin/issues/{}/segment", issue_id),
+            &serde_json::json!({"segment_id": segment["segment_id"]}),
+        )
+        .await;
+    app.approve_issue(issue_id, 1)
+        .await
+        .error_for_status()
+        .unwrap();
+    app.promote_due_issues().await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let recipients: Vec<String> = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|r| serde_json::from_slice::<serde_json::Value>(&r.body).unwrap())
+        .filter(|body| body["Subject"] == "For beta testers")
+        .map(|body| body["To"].as_str().unwrap().to_owned())
+        .collect();
+    assert_eq!(recipients, vec![emails[1].clone()]);
+}
+
+#[tokio::test]
+async fn an_unknown_segment_cannot_be_set() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue: serde_json::Value = app
+        .post_issue(&serde_json::json!({
+            "title": "Draft",
+            "text_content": "Plain text",
+            "html_content": "<p>HTML</p>",
+        }))
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    // Act
+    let response = app
+        .admin_put(
+            &format!(
+                "/admin/issues/{}/segment",
+                issue["newsletter_issue_id"].as_str().unwrap()
+            ),
+            &serde_json::json!({"segment_id": uuid::Uuid::new_v4()}),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn an_issue_that_cannot_be_promoted_does_not_hold_back_the_others() {
+    // Arrange
+    let app = spawn_app().await;
+    create_subscribers(&app, 1).await;
+    let segment: serde_json::Value = create_segment(&app, "Beta testers", r#"tag = "beta""#)
+        .await
+        .json()
+        .await
+        .unwrap();
+    let issue: serde_json::Value = app
+        .post_issue(&serde_json::json!({
+            "title": "For beta testers",
+            "text_content": "Plain text",
+            "html_content": "<p>HTML</p>",
+            "send_at": chrono::Utc::now(),
+        }))
+        .await
+        .json()
+        .await
+        .unwrap();
+    let broken_issue_id = issue["newsletter_issue_id"].as_str().unwrap();
+    app.admin_put(
+        &format!("/admin/issues/{}/segment", broken_issue_id),
+        &serde_json::json!({"segment_id": segment["segment_id"]}),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+    app.approve_issue(broken_issue_id, 1)
+        .await
+        .error_for_status()
+        .unwrap();
+    // Due first, with a filter that no longer parses.
+    sqlx::query!("UPDATE newsletter_issues SET send_at = now() - INTERVAL '1 hour'")
+        .execute(&app.db)
+        .await
+        .unwrap();
+    sqlx::query!("UPDATE segments SET filter = 'tag ='")
+        .execute(&app.db)
+        .await
+        .unwrap();
+
+    // Act
+    let issue_id = app.create_published_issue("For everyone").await;
+
+    // Assert
+    let status = |issue: serde_json::Value| issue["status"].as_str().unwrap().to_owned();
+    let broken = app.get_issue(broken_issue_id).await.json().await.unwrap();
+    assert_eq!(status(broken), "scheduled");
+    let promoted = app.get_issue(&issue_id).await.json().await.unwrap();
+    assert_ne!(status(promoted), "scheduled");
+}
@@ -0,0 +1,158 @@
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+use wiremock::{
+    Mock, ResponseTemplate,
+    matchers::{method, path},
+};
+
+use crate::helpers::spawn_app;
+
+#[tokio::test]
+async fn data_exports_require_authentication() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .get(format!(
+            "{}/admin/subscribers/{}/data",
+            &app.address,
+            Uuid::new_v4()
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn admins_can_export_everything_we_store_about_a_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let (subscriber_id, email) = app.the_subscriber().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.create_published_issue("Issue one").await;
+    app.dispatch_all_pending_emails().await;
+
+    // Act
+    let response = app
+        .admin_get(&format!("/admin/subscribers/{}/data", subscriber_id))
+        .await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(
+        response.headers()["Content-Disposition"],
+        format!(
+            r#"attachment; filename="subscriber-{}.json""#,
+            subscriber_id
+        )
+    );
+    let data: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(data["profile"]["email"], email);
+    assert_eq!(data["lists"][0]["list"], "newsletter");
+    assert_eq!(data["lists"][0]["status"], "confirmed");
+    assert_eq!(data["deliveries"].as_array().unwrap().len(), 1);
+    assert_eq!(data["deliveries"][0]["issue"], "Issue one");
+    assert_eq!(data["deliveries"][0]["status"], "sent");
+    assert_eq!(data["suppression"], serde_json::Value::Null);
+
+    // Act
+    let data: serde_json::Value = app
+        .admin_get(&format!("/admin/subscribers/{}/data", subscriber_id))
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(data["history"][0]["action"], "export_data");
+}
+
+#[tokio::test]
+async fn subscribers_can_download_their_data_from_the_preference_center() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_unconfirmed_subscriber().await;
+    let (subscriber_id, email) = app.the_subscriber().await;
+    let link = app.preference_links.link(subscriber_id, Utc::now());
+    let query = reqwest::Url::parse(&link)
+        .unwrap()
+        .query()
+        .unwrap()
+        .to_owned();
+
+    // Act
+    let page = app.api_client.get(&link).send().await.unwrap();
+    let response = app
+        .api_client
+        .get(format!("{}/preferences/data?{}", app.address, query))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(page.text().await.unwrap().contains("/preferences/data?"));
+    assert_eq!(200, response.status().as_u16());
+    let data: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(data["profile"]["email"], email);
+    assert_eq!(data["profile"]["status"], "pending_confirmation");
+    assert_eq!(data["tokens"][0]["kind"], "subscription_confirmation");
+    assert!(data["tokens"][0].get("subscription_token").is_none());
+}
+
+#[tokio::test]
+async fn data_downloads_need_a_valid_link() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let (subscriber_id, _) = app.the_subscriber().await;
+    let expired = app
+        .preference_links
+        .link(subscriber_id, Utc::now() - Duration::days(2));
+    let someone_else = Uuid::new_v4();
+    let tampered = app
+        .preference_links
+        .link(someone_else, Utc::now())
+        .replace(&someone_else.to_string(), &subscriber_id.to_string());
+
+    for link in [expired, tampered] {
+        let query = reqwest::Url::parse(&link)
+            .unwrap()
+            .query()
+            .unwrap()
+            .to_owned();
+
+        // Act
+        let response = app
+            .api_client
+            .get(format!("{}/preferences/data?{}", app.address, query))
+            .send()
+            .await
+            .unwrap();
+
+        // Assert
+        assert_eq!(401, response.status().as_u16());
+    }
+}
+
+#[tokio::test]
+async fn unknown_subscribers_have_no_data() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .admin_get(&format!("/admin/subscribers/{}/data", Uuid::new_v4()))
+        .await;
+
+    // Assert
+    assert_eq!(404, response.status().as_u16());
+}
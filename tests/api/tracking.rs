@@ -0,0 +1,324 @@
+use wiremock::{
+    Mock, ResponseTemplate,
+    matchers::{method, path},
+};
+
+use crate::helpers::{TestApp, spawn_app};
+
+/// Publish an issue with the given tracking options.
+async fn publish_issue(app: &TestApp, tracking: serde_json::Value) -> String {
+    let response = app
+        .post_issue(&serde_json::json!({
+            "title": "Tracked issue",
+            "text_content": "text",
+            "html_content": r#"<html><body><p><a href="https://blog.example/post?id=1&amp;ref=news">Read</a> <a href="mailto:editor@example.com">Reply</a></p></body></html>"#,
+            "send_at": chrono::Utc::now(),
+        }))
+        .await;
+    let body: serde_json::Value = response.json().await.unwrap();
+    let issue_id = body["newsletter_issue_id"].as_str().unwrap().to_owned();
+    let response = app
+        .admin_put(&format!("/admin/issues/{}/tracking", issue_id), &tracking)
+        .await;
+    assert_eq!(200, response.status().as_u16());
+    app.approve_issue(&issue_id, 1)
+        .await
+        .error_for_status()
+        .unwrap();
+    app.promote_due_issues().await;
+    issue_id
+}
+
+/// Deliver the pending issue and return the HTML body that was sent.
+async fn deliver(app: &TestApp) -> String {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+    let requests = app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&requests.last().unwrap().body).unwrap();
+    body["HtmlBody"].as_str().unwrap().to_owned()
+}
+
+/// The URL of the tracking pixel embedded in an email, if any.
+fn pixel_url(app: &TestApp, html: &str) -> Option<String> {
+    let (_, rest) = html.split_once("/t/open/")?;
+    let token = &rest[..rest.find('"').unwrap()];
+    Some(format!("{}/t/open/{}", app.address, token))
+}
+
+/// The click-tracking URLs of the links of an email.
+fn click_urls(app: &TestApp, html: &str) -> Vec<String> {
+    html.split("href=\"")
+        .skip(1)
+        .filter_map(|rest| {
+            let (_, rest) = rest.split_once("/t/click/")?;
+            let link = &rest[..rest.find('"').unwrap()];
+            Some(format!(
+                "{}/t/click/{}",
+                app.address,
+                link.replace("&amp;", "&")
+            ))
+        })
+        .collect()
+}
+
+async fn subscriber_id(app: &TestApp) -> uuid::Uuid {
+    sqlx::query_scalar!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db)
+        .await
+        .unwrap()
+}
+
+async fn delivery_stats(app: &TestApp, issue_id: &str) -> serde_json::Value {
+    app.admin_get(&format!("/admin/issues/{}/deliveries", issue_id))
+        .await
+        .json()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn issues_are_not_tracked_by_default() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.create_published_issue("Issue").await;
+
+    // Act
+    let html = deliver(&app).await;
+
+    // Assert
+    assert_eq!(pixel_url(&app, &html), None);
+}
+
+#[tokio::test]
+async fn opens_of_tracked_issues_are_recorded() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let issue_id = publish_issue(&app, serde_json::json!({ "track_opens": true })).await;
+    let html = deliver(&app).await;
+    let pixel_url = pixel_url(&app, &html).expect("No tracking pixel");
+    assert!(html.contains(r#"style="display:none"></body>"#));
+
+    // Act
+    let first = reqwest::get(&pixel_url).await.unwrap();
+    reqwest::get(&pixel_url).await.unwrap();
+
+    // Assert
+    assert_eq!(200, first.status().as_u16());
+    assert_eq!(first.headers()["Content-Type"], "image/gif");
+    let stats = delivery_stats(&app, &issue_id).await;
+    assert_eq!(stats["opened"], 1);
+    assert_eq!(stats["total_opens"], 2);
+    let history: Vec<serde_json::Value> = app
+        .admin_get(&format!(
+            "/admin/subscribers/{}/deliveries",
+            subscriber_id(&app).await
+        ))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(history[0]["open_count"], 2);
+    assert!(history[0]["first_opened_at"].is_string());
+}
+
+#[tokio::test]
+async fn subscribers_who_opted_out_get_no_tracking_pixel() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let response = app
+        .admin_put(
+            &format!("/admin/subscribers/{}/tracking", subscriber_id(&app).await),
+            &serde_json::json!({ "opt_out": true }),
+        )
+        .await;
+    assert_eq!(200, response.status().as_u16());
+    publish_issue(&app, serde_json::json!({ "track_opens": true })).await;
+
+    // Act
+    let html = deliver(&app).await;
+
+    // Assert
+    assert_eq!(pixel_url(&app, &html), None);
+}
+
+#[tokio::test]
+async fn opens_are_not_recorded_after_the_subscriber_opted_out() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let issue_id = publish_issue(&app, serde_json::json!({ "track_opens": true })).await;
+    let html = deliver(&app).await;
+    app.admin_put(
+        &format!("/admin/subscribers/{}/tracking", subscriber_id(&app).await),
+        &serde_json::json!({ "opt_out": true }),
+    )
+    .await;
+
+    // Act
+    let response = reqwest::get(pixel_url(&app, &html).unwrap()).await.unwrap();
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(delivery_stats(&app, &issue_id).await["opened"], 0);
+}
+
+#[tokio::test]
+async fn unknown_tokens_still_get_a_pixel() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::get(format!("{}/t/open/not-a-token", app.address))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(response.headers()["Content-Type"], "image/gif");
+}
+
+#[tokio::test]
+async fn tracking_cannot_change_once_an_issue_is_published() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = app.create_published_issue("Issue").await;
+
+    // Act
+    let response = app
+        .admin_put(
+            &format!("/admin/issues/{}/tracking", issue_id),
+            &serde_json::json!({ "track_opens": true }),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(409, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn links_of_issues_tracking_clicks_are_rewritten() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    publish_issue(&app, serde_json::json!({ "track_clicks": true })).await;
+
+    // Act
+    let html = deliver(&app).await;
+
+    // Assert
+    assert_eq!(click_urls(&app, &html).len(), 1);
+    assert!(!html.contains(r#"href="https://blog.example"#));
+    assert!(html.contains(r#"href="mailto:editor@example.com""#));
+    assert_eq!(pixel_url(&app, &html), None);
+}
+
+#[tokio::test]
+async fn clicks_redirect_to_the_link_and_are_recorded() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let issue_id = publish_issue(&app, serde_json::json!({ "track_clicks": true })).await;
+    let html = deliver(&app).await;
+    let click_url = click_urls(&app, &html).remove(0);
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+
+    // Act
+    let response = client.get(&click_url).send().await.unwrap();
+    client.get(&click_url).send().await.unwrap();
+
+    // Assert
+    assert_eq!(302, response.status().as_u16());
+    assert_eq!(
+        response.headers()["Location"],
+        "https://blog.example/post?id=1&ref=news"
+    );
+    let stats = delivery_stats(&app, &issue_id).await;
+    assert_eq!(stats["clicked"], 1);
+    assert_eq!(stats["total_clicks"], 2);
+    let links: Vec<serde_json::Value> = app
+        .admin_get(&format!("/admin/issues/{}/clicks", issue_id))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(
+        links,
+        vec![serde_json::json!({
+            "url": "https://blog.example/post?id=1&ref=news",
+            "clicks": 2,
+            "unique_clicks": 1,
+        })]
+    );
+}
+
+#[tokio::test]
+async fn tampered_clicks_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    publish_issue(&app, serde_json::json!({ "track_clicks": true })).await;
+    let html = deliver(&app).await;
+    let click_url = reqwest::Url::parse(&click_urls(&app, &html).remove(0)).unwrap();
+    let token = click_url.path_segments().unwrap().next_back().unwrap();
+    let signature = click_url
+        .query_pairs()
+        .find(|(name, _)| name == "sig")
+        .unwrap()
+        .1;
+    let test_cases = vec![
+        (
+            format!("url=https%3A%2F%2Fevil.example&sig={}", signature),
+            "a different destination",
+        ),
+        (
+            "url=https%3A%2F%2Fblog.example%2Fpost%3Fid%3D1%26ref%3Dnews&sig=forged".to_owned(),
+            "a forged signature",
+        ),
+        ("url=https%3A%2F%2Fevil.example".to_owned(), "no signature"),
+    ];
+
+    for (query, description) in test_cases {
+        // Act
+        let response = reqwest::get(format!("{}/t/click/{}?{}", app.address, token, query))
+            .await
+            .unwrap();
+
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The click was not rejected with {}",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn clicks_with_unknown_tokens_are_not_found() {
+    // Arrange
+    let app = spawn_app().await;
+    let click_url = app.tracker.click_url("not-a-token", "https://blog.example");
+
+    // Act
+    let response = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+        .get(click_url)
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(404, response.status().as_u16());
+}
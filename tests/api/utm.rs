@@ -0,0 +1,159 @@
+use wiremock::{
+    Mock, ResponseTemplate,
+    matchers::{method, path},
+};
+
+use crate::helpers::{TestApp, spawn_app};
+
+/// Publish an issue with links, after changing its UTM tagging to `utm`.
+async fn publish_issue_with_links(app: &TestApp, utm: serde_json::Value) -> String {
+    let response = app
+        .post_issue(&serde_json::json!({
+            "title": "Spring News",
+            "text_content": "Read https://blog.example/post?id=1. Reply to mailto:editor@example.com",
+            "html_content": concat!(
+                r#"<p><a href="https://blog.example/post?id=1">Read</a> "#,
+                r#"<a href="mailto:editor@example.com">Reply</a> "#,
+                r#"<a href="http://127.0.0.1/archive">Archive</a></p>"#,
+            ),
+            "send_at": chrono::Utc::now(),
+        }))
+        .await;
+    let body: serde_json::Value = response.json().await.unwrap();
+    let issue_id = body["newsletter_issue_id"].as_str().unwrap().to_owned();
+    let response = app
+        .admin_put(&format!("/admin/issues/{}/utm", issue_id), &utm)
+        .await;
+    assert_eq!(200, response.status().as_u16());
+    app.approve_issue(&issue_id, 1)
+        .await
+        .error_for_status()
+        .unwrap();
+    app.promote_due_issues().await;
+    issue_id
+}
+
+/// Deliver the pending issue and return the email that was sent.
+async fn deliver(app: &TestApp) -> serde_json::Value {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+    let requests = app.email_server.received_requests().await.unwrap();
+    serde_json::from_slice(&requests.last().unwrap().body).unwrap()
+}
+
+#[tokio::test]
+async fn outbound_links_are_tagged_with_the_default_parameters() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    publish_issue_with_links(&app, serde_json::json!({ "utm_tagging": true })).await;
+
+    // Act
+    let email = deliver(&app).await;
+
+    // Assert
+    let tagged = "https://blog.example/post?id=1&utm_source=newsletter&utm_medium=email&utm_campaign=spring-news";
+    assert!(
+        email["HtmlBody"]
+            .as_str()
+            .unwrap()
+            .contains(&tagged.replace('&', "&amp;"))
+    );
+    assert!(email["HtmlBody"].as_str().unwrap().contains(
+        r#"<a href="mailto:editor@example.com">Reply</a> <a href="http://127.0.0.1/archive">"#
+    ));
+    assert_eq!(
+        email["TextBody"],
+        format!("Read {}. Reply to mailto:editor@example.com", tagged)
+    );
+}
+
+#[tokio::test]
+async fn utm_parameters_can_be_customised() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let issue_id = publish_issue_with_links(
+        &app,
+        serde_json::json!({
+            "utm_tagging": true,
+            "utm_source": "weekly",
+            "utm_campaign": "spring-2026",
+        }),
+    )
+    .await;
+
+    // Act
+    let email = deliver(&app).await;
+
+    // Assert
+    assert!(email["TextBody"].as_str().unwrap().contains(
+        "https://blog.example/post?id=1&utm_source=weekly&utm_medium=email&utm_campaign=spring-2026"
+    ));
+    let issue: serde_json::Value = app.get_issue(&issue_id).await.json().await.unwrap();
+    assert_eq!(issue["utm_source"], "weekly");
+    assert_eq!(issue["utm_medium"], serde_json::Value::Null);
+}
+
+#[tokio::test]
+async fn links_are_not_tagged_by_default() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    publish_issue_with_links(&app, serde_json::json!({ "utm_tagging": false })).await;
+
+    // Act
+    let email = deliver(&app).await;
+
+    // Assert
+    assert!(!email["HtmlBody"].as_str().unwrap().contains("utm_"));
+    assert!(!email["TextBody"].as_str().unwrap().contains("utm_"));
+}
+
+#[tokio::test]
+async fn blank_utm_parameters_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let response = app
+        .post_issue(&serde_json::json!({
+            "title": "Issue",
+            "text_content": "text",
+            "html_content": "<p>html</p>",
+        }))
+        .await;
+    let body: serde_json::Value = response.json().await.unwrap();
+    let issue_id = body["newsletter_issue_id"].as_str().unwrap();
+
+    // Act
+    let response = app
+        .admin_put(
+            &format!("/admin/issues/{}/utm", issue_id),
+            &serde_json::json!({ "utm_tagging": true, "utm_campaign": " " }),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn utm_tagging_cannot_change_once_an_issue_is_published() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = app.create_published_issue("Issue").await;
+
+    // Act
+    let response = app
+        .admin_put(
+            &format!("/admin/issues/{}/utm", issue_id),
+            &serde_json::json!({ "utm_tagging": true }),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(409, response.status().as_u16());
+}
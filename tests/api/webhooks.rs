@@ -0,0 +1,188 @@
+use wiremock::{
+    Mock, ResponseTemplate,
+    matchers::{method, path},
+};
+
+use crate::helpers::{TestApp, spawn_app};
+
+async fn post_webhook(app: &TestApp, body: &serde_json::Value) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/webhooks/postmark", app.address))
+        .basic_auth("postmark", Some("my-webhook-secret"))
+        .json(body)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn subscriber_email(app: &TestApp) -> String {
+    sqlx::query_scalar!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db)
+        .await
+        .unwrap()
+}
+
+async fn delivery_stats(app: &TestApp, issue_id: &str) -> serde_json::Value {
+    app.admin_get(&format!("/admin/issues/{}/deliveries", issue_id))
+        .await
+        .json()
+        .await
+        .unwrap()
+}
+
+fn bounce(kind: &str, email: &str) -> serde_json::Value {
+    serde_json::json!({
+        "RecordType": "Bounce",
+        "MessageID": "message-1",
+        "Type": kind,
+        "Email": email,
+        "Description": "The server was unable to deliver your message",
+    })
+}
+
+/// Publish an issue to a single confirmed subscriber and deliver it, with
+/// `message-1` as the provider's MessageID.
+async fn deliver_an_issue(app: &TestApp) -> String {
+    app.create_confirmed_subscriber().await;
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(serde_json::json!({ "MessageID": "message-1" })),
+        )
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let issue_id = app.create_published_issue("First issue").await;
+    app.dispatch_all_pending_emails().await;
+    issue_id
+}
+
+#[tokio::test]
+async fn webhooks_require_the_configured_credentials() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = bounce("HardBounce", "john@example.com");
+
+    // Act
+    let missing = app
+        .api_client
+        .post(format!("{}/webhooks/postmark", app.address))
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+    let invalid = app
+        .api_client
+        .post(format!("{}/webhooks/postmark", app.address))
+        .basic_auth("postmark", Some("wrong-secret"))
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(401, missing.status().as_u16());
+    assert_eq!(401, invalid.status().as_u16());
+    let suppressed = sqlx::query_scalar!("SELECT COUNT(*) FROM suppressed_emails")
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+    assert_eq!(suppressed, Some(0));
+}
+
+#[tokio::test]
+async fn hard_bounced_addresses_no_longer_receive_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    let first_issue = deliver_an_issue(&app).await;
+    let email = subscriber_email(&app).await;
+
+    // Act
+    let response = post_webhook(&app, &bounce("HardBounce", &email)).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(delivery_stats(&app, &first_issue).await["bounced"], 1);
+    let reason = sqlx::query_scalar!(
+        "SELECT reason FROM suppressed_emails WHERE email = $1",
+        email
+    )
+    .fetch_one(&app.db)
+    .await
+    .unwrap();
+    assert_eq!(reason, "hard_bounce");
+    let second_issue = app.create_published_issue("Second issue").await;
+    assert_eq!(delivery_stats(&app, &second_issue).await["total"], 0);
+}
+
+#[tokio::test]
+async fn soft_bounces_are_logged_without_suppressing_the_address() {
+    // Arrange
+    let app = spawn_app().await;
+    let first_issue = deliver_an_issue(&app).await;
+    let email = subscriber_email(&app).await;
+
+    // Act
+    let response = post_webhook(&app, &bounce("SoftBounce", &email)).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(delivery_stats(&app, &first_issue).await["bounced"], 1);
+    let second_issue = app.create_published_issue("Second issue").await;
+    assert_eq!(delivery_stats(&app, &second_issue).await["queued"], 1);
+}
+
+#[tokio::test]
+async fn spam_complaints_suppress_queued_deliveries() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let email = subscriber_email(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let issue_id = app.create_published_issue("Issue").await;
+
+    // Act
+    let response = post_webhook(
+        &app,
+        &serde_json::json!({
+            "RecordType": "SpamComplaint",
+            "MessageID": "message-0",
+            "Type": "SpamComplaint",
+            "Email": email,
+        }),
+    )
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let stats = delivery_stats(&app, &issue_id).await;
+    assert_eq!(stats["suppressed"], 1);
+    assert_eq!(stats["sent"], 0);
+}
+
+#[tokio::test]
+async fn other_events_are_acknowledged() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = post_webhook(
+        &app,
+        &serde_json::json!({
+            "RecordType": "Delivery",
+            "MessageID": "message-1",
+            "Recipient": "john@example.com",
+        }),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+}